@@ -0,0 +1,153 @@
+use std::f64::consts::PI;
+
+/// The number of output samples each band-limited step is spread across
+const KERNEL_WIDTH: usize = 16;
+
+/// The number of sub-sample positions a step can be placed at
+const KERNEL_PHASES: usize = 32;
+
+/// The cutoff frequency of the band-limiting filter, as a fraction of the output sample rate
+const CUTOFF: f64 = 0.45;
+
+/// Converts a signal clocked at a high rate in to samples at a lower rate, using band-limited
+/// step synthesis
+///
+/// Rather than sampling the signal, the caller reports each change in its level with `add_delta`.
+/// Each change is added to the output as a band-limited step (the integral of a windowed sinc
+/// impulse), placed at its exact position between output samples, which removes the aliasing that
+/// naive sampling of square waves would produce.
+///
+/// Time is measured in input clocks from the start of the current frame. Calling `end_frame`
+/// makes the samples for the frame available through `read_samples`. Deltas are held until their samples are
+/// read, so frames should be ended and read regularly.
+pub struct BlipBuffer {
+    factor: f64,
+    offset: f64,
+    deltas: Vec<f32>,
+    integrator: f32,
+    kernel: Vec<[f32; KERNEL_WIDTH]>
+}
+
+impl BlipBuffer {
+    /// Creates a new `BlipBuffer` converting a signal at `clock_rate` to samples at `sample_rate`
+    pub fn new(clock_rate: f64, sample_rate: f64) -> BlipBuffer {
+        BlipBuffer {
+            factor: sample_rate / clock_rate,
+            offset: 0.0,
+            deltas: vec![0.0; KERNEL_WIDTH],
+            integrator: 0.0,
+            kernel: build_kernel()
+        }
+    }
+
+    /// Adds a change of `delta` in the level of the signal, at `time` clocks after the start of
+    /// the current frame
+    pub fn add_delta(&mut self, time: u64, delta: f32) {
+        let position = self.offset + (time as f64) * self.factor;
+        let index = position.floor() as usize;
+        let phase = ((position - position.floor()) * (KERNEL_PHASES as f64)) as usize;
+
+        if self.deltas.len() < index + KERNEL_WIDTH {
+            self.deltas.resize(index + KERNEL_WIDTH, 0.0);
+        }
+        for (i, k) in self.kernel[phase].iter().enumerate() {
+            self.deltas[index + i] += delta * k;
+        }
+    }
+
+    /// Ends the current frame after `clocks` clocks, and starts a new one
+    pub fn end_frame(&mut self, clocks: u64) {
+        self.offset += (clocks as f64) * self.factor;
+        let needed = self.offset.floor() as usize + KERNEL_WIDTH;
+        if self.deltas.len() < needed {
+            self.deltas.resize(needed, 0.0);
+        }
+    }
+
+    /// Gets the number of samples completed by the frames ended so far
+    pub fn samples_available(&self) -> usize {
+        self.offset.floor() as usize
+    }
+
+    /// Removes the available samples from the buffer and appends them to `out`
+    pub fn read_samples(&mut self, out: &mut Vec<f32>) {
+        let count = self.samples_available();
+        for delta in self.deltas.drain(0 .. count) {
+            self.integrator += delta;
+            out.push(self.integrator);
+        }
+        self.offset -= count as f64;
+    }
+}
+
+/// Builds the band-limited impulses for each phase, each normalized to sum to exactly 1 so that
+/// a step always settles at the correct level
+fn build_kernel() -> Vec<[f32; KERNEL_WIDTH]> {
+    let half = (KERNEL_WIDTH / 2) as f64;
+    (0 .. KERNEL_PHASES).map(|phase| {
+        let fraction = (phase as f64) / (KERNEL_PHASES as f64);
+        let mut impulse = [0.0f64; KERNEL_WIDTH];
+        for (i, value) in impulse.iter_mut().enumerate() {
+            // Distance from the center of the kernel to this sample, in output samples
+            let x = (i as f64) - half + 1.0 - fraction;
+            let sinc = if x == 0.0 { 1.0 } else { (2.0 * PI * CUTOFF * x).sin() / (2.0 * PI * CUTOFF * x) };
+
+            // Blackman window
+            let n = (x + half) / (2.0 * half);
+            let window = if n < 0.0 || n > 1.0 {
+                0.0
+            } else {
+                0.42 - 0.5 * (2.0 * PI * n).cos() + 0.08 * (4.0 * PI * n).cos()
+            };
+            *value = sinc * window;
+        }
+
+        let sum: f64 = impulse.iter().sum();
+        let mut normalized = [0.0f32; KERNEL_WIDTH];
+        for (i, value) in impulse.iter().enumerate() {
+            normalized[i] = (value / sum) as f32;
+        }
+        normalized
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use audio::BlipBuffer;
+
+    #[test]
+    pub fn produces_samples_at_output_rate() {
+        let mut blip = BlipBuffer::new(1000.0, 100.0);
+        blip.end_frame(1000);
+        let mut samples = Vec::new();
+        blip.read_samples(&mut samples);
+        assert_eq!(100, samples.len());
+        assert_eq!(0, blip.samples_available());
+    }
+
+    #[test]
+    pub fn step_settles_at_new_level() {
+        let mut blip = BlipBuffer::new(1000.0, 100.0);
+        blip.add_delta(105, 0.5);
+        blip.end_frame(1000);
+        let mut samples = Vec::new();
+        blip.read_samples(&mut samples);
+
+        assert!(samples[0].abs() < 0.0001);
+        assert!((samples[99] - 0.5).abs() < 0.0001);
+    }
+
+    #[test]
+    pub fn steps_carry_over_between_frames() {
+        let mut blip = BlipBuffer::new(1000.0, 100.0);
+        blip.add_delta(995, 1.0);
+        blip.end_frame(1000);
+        let mut samples = Vec::new();
+        blip.read_samples(&mut samples);
+        blip.end_frame(1000);
+        blip.read_samples(&mut samples);
+
+        assert_eq!(200, samples.len());
+        assert!((samples[199] - 1.0).abs() < 0.0001);
+    }
+}
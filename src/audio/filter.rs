@@ -0,0 +1,106 @@
+use std::f32::consts::PI;
+
+/// A first-order high-pass filter
+pub struct HighPass {
+    alpha: f32,
+    prev_input: f32,
+    prev_output: f32
+}
+
+impl HighPass {
+    /// Creates a new `HighPass` filter with the provided cutoff frequency, for samples at the
+    /// provided sample rate
+    pub fn new(cutoff: f32, sample_rate: f32) -> HighPass {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        HighPass {
+            alpha: rc / (rc + dt),
+            prev_input: 0.0,
+            prev_output: 0.0
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let output = self.alpha * (self.prev_output + input - self.prev_input);
+        self.prev_input = input;
+        self.prev_output = output;
+        output
+    }
+}
+
+/// A first-order low-pass filter
+pub struct LowPass {
+    alpha: f32,
+    prev_output: f32
+}
+
+impl LowPass {
+    /// Creates a new `LowPass` filter with the provided cutoff frequency, for samples at the
+    /// provided sample rate
+    pub fn new(cutoff: f32, sample_rate: f32) -> LowPass {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        LowPass {
+            alpha: dt / (rc + dt),
+            prev_output: 0.0
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let output = self.prev_output + self.alpha * (input - self.prev_output);
+        self.prev_output = output;
+        output
+    }
+}
+
+/// The filters applied to the audio output by the NES hardware
+///
+/// The NES (NTSC front-loader) passes the mixed output through a 90Hz high-pass filter, a 440Hz
+/// high-pass filter and a 14KHz low-pass filter. The high-pass filters remove the DC offset of
+/// the mixer, so the filtered output is centered on zero.
+pub struct NesFilters {
+    high_pass_90: HighPass,
+    high_pass_440: HighPass,
+    low_pass_14k: LowPass
+}
+
+impl NesFilters {
+    pub fn new(sample_rate: f32) -> NesFilters {
+        NesFilters {
+            high_pass_90: HighPass::new(90.0, sample_rate),
+            high_pass_440: HighPass::new(440.0, sample_rate),
+            low_pass_14k: LowPass::new(14000.0, sample_rate)
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let val = self.high_pass_90.process(input);
+        let val = self.high_pass_440.process(val);
+        self.low_pass_14k.process(val)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use audio::{HighPass,LowPass};
+
+    #[test]
+    pub fn high_pass_removes_dc_offset() {
+        let mut filter = HighPass::new(90.0, 44100.0);
+        let mut output = 1.0;
+        for _ in 0 .. 44100 {
+            output = filter.process(1.0);
+        }
+        assert!(output.abs() < 0.001);
+    }
+
+    #[test]
+    pub fn low_pass_passes_dc() {
+        let mut filter = LowPass::new(14000.0, 44100.0);
+        let mut output = 0.0;
+        for _ in 0 .. 1000 {
+            output = filter.process(1.0);
+        }
+        assert!((output - 1.0).abs() < 0.001);
+    }
+}
@@ -0,0 +1,7 @@
+pub use self::blip::BlipBuffer;
+pub use self::filter::{HighPass,LowPass,NesFilters};
+pub use self::wav::WavWriter;
+
+mod blip;
+mod filter;
+mod wav;
@@ -0,0 +1,101 @@
+use std::io::{self,Seek,SeekFrom,Write};
+
+use byteorder::{LittleEndian,WriteBytesExt};
+
+/// The size of the RIFF and format headers, and the data chunk header
+const HEADER_SIZE: u32 = 44;
+
+/// Writes mono, 16-bit PCM WAV files
+///
+/// Samples are provided as floating point values from -1.0 to 1.0, and are clamped to that range.
+/// The sizes in the header are only known once all the samples have been written, so `finish`
+/// must be called to complete the file.
+pub struct WavWriter<W> where W: Write + Seek {
+    writer: W,
+    samples_written: u32
+}
+
+impl<W> WavWriter<W> where W: Write + Seek {
+    /// Creates a new `WavWriter`, writing the header for a file with the provided sample rate
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<WavWriter<W>> {
+        try!(write_header(&mut writer, sample_rate, 0));
+        Ok(WavWriter {
+            writer: writer,
+            samples_written: 0
+        })
+    }
+
+    /// Appends the provided samples to the file
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples {
+            let clamped = sample.max(-1.0).min(1.0);
+            try!(self.writer.write_i16::<LittleEndian>((clamped * 32767.0) as i16));
+        }
+        self.samples_written += samples.len() as u32;
+        Ok(())
+    }
+
+    /// Completes the file by filling in the sizes in the header, and returns the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_size = self.samples_written * 2;
+        try!(self.writer.seek(SeekFrom::Start(4)));
+        try!(self.writer.write_u32::<LittleEndian>(HEADER_SIZE - 8 + data_size));
+        try!(self.writer.seek(SeekFrom::Start(40)));
+        try!(self.writer.write_u32::<LittleEndian>(data_size));
+        try!(self.writer.seek(SeekFrom::End(0)));
+        try!(self.writer.flush());
+        Ok(self.writer)
+    }
+}
+
+fn write_header<W>(writer: &mut W, sample_rate: u32, data_size: u32) -> io::Result<()> where W: Write {
+    let channels = 1;
+    let bits_per_sample = 16;
+    let block_align = channels * bits_per_sample / 8;
+
+    try!(writer.write_all(b"RIFF"));
+    try!(writer.write_u32::<LittleEndian>(HEADER_SIZE - 8 + data_size));
+    try!(writer.write_all(b"WAVE"));
+
+    try!(writer.write_all(b"fmt "));
+    try!(writer.write_u32::<LittleEndian>(16));
+    try!(writer.write_u16::<LittleEndian>(1)); // PCM
+    try!(writer.write_u16::<LittleEndian>(channels));
+    try!(writer.write_u32::<LittleEndian>(sample_rate));
+    try!(writer.write_u32::<LittleEndian>(sample_rate * (block_align as u32)));
+    try!(writer.write_u16::<LittleEndian>(block_align));
+    try!(writer.write_u16::<LittleEndian>(bits_per_sample));
+
+    try!(writer.write_all(b"data"));
+    try!(writer.write_u32::<LittleEndian>(data_size));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use byteorder::{LittleEndian,ReadBytesExt};
+
+    use audio::WavWriter;
+
+    #[test]
+    pub fn writes_header_and_samples() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44100).unwrap();
+        wav.write_samples(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+        let data = wav.finish().unwrap().into_inner();
+
+        assert_eq!(44 + 8, data.len());
+        assert_eq!(b"RIFF", &data[0 .. 4]);
+        assert_eq!(b"WAVE", &data[8 .. 12]);
+        assert_eq!(44, (&data[4 .. 8]).read_u32::<LittleEndian>().unwrap());
+        assert_eq!(44100, (&data[24 .. 28]).read_u32::<LittleEndian>().unwrap());
+        assert_eq!(8, (&data[40 .. 44]).read_u32::<LittleEndian>().unwrap());
+
+        let mut samples = &data[44 ..];
+        assert_eq!(0, samples.read_i16::<LittleEndian>().unwrap());
+        assert_eq!(32767, samples.read_i16::<LittleEndian>().unwrap());
+        assert_eq!(-32767, samples.read_i16::<LittleEndian>().unwrap());
+        assert_eq!(32767, samples.read_i16::<LittleEndian>().unwrap());
+    }
+}
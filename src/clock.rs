@@ -1,7 +1,7 @@
 use std::sync::{Arc, atomic};
 
 pub struct Clock {
-    cycles: u64,
+    cycles: Arc<atomic::AtomicU64>,
     suspend_count: Arc<atomic::AtomicIsize>
 }
 
@@ -9,26 +9,37 @@ impl Clock {
     /// Creates a new Clock initialized to zero
     pub fn new() -> Clock {
         Clock {
-            cycles: 0,
+            cycles: Arc::new(atomic::AtomicU64::new(0)),
             suspend_count: Arc::new(atomic::AtomicIsize::new(0))
         }
     }
 
     /// Sets the total number of cycles on the clock. Ignores the paused state of the clock.
     pub fn set(&mut self, value: u64) {
-        self.cycles = value;
+        self.cycles.store(value, atomic::Ordering::Release);
     }
 
     /// Gets the current number of cycles on the clock.
     pub fn get(&self) -> u64 {
-        self.cycles
+        self.cycles.load(atomic::Ordering::Acquire)
     }
 
     /// Advances the clock forward by `amount` cycles
     pub fn tick(&mut self, amount: u64) {
         let suspend_count = self.suspend_count.load(atomic::Ordering::Acquire);
         if suspend_count == 0 {
-            self.cycles += amount;
+            let cycles = self.get();
+            self.set(cycles + amount);
+        }
+    }
+
+    /// Creates a `ClockReader` that sees the cycles on this clock as it is ticked
+    ///
+    /// This allows the cycles to be read while the clock itself is borrowed, such as by hardware
+    /// that needs to know the CPU cycle while the CPU is executing an instruction
+    pub fn reader(&self) -> ClockReader {
+        ClockReader {
+            cycles: self.cycles.clone()
         }
     }
 
@@ -41,6 +52,19 @@ impl Clock {
     }
 }
 
+/// Reads the cycles on a `Clock`, without being able to change them
+#[derive(Clone)]
+pub struct ClockReader {
+    cycles: Arc<atomic::AtomicU64>
+}
+
+impl ClockReader {
+    /// Gets the current number of cycles on the clock.
+    pub fn get(&self) -> u64 {
+        self.cycles.load(atomic::Ordering::Acquire)
+    }
+}
+
 #[must_use]
 pub struct ClockSuspendGuard {
     suspend_count: Arc<atomic::AtomicIsize>
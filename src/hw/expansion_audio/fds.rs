@@ -0,0 +1,303 @@
+use hw::expansion_audio::ExpansionAudio;
+
+/// The output at full volume, with the master volume at 100%
+const LEVEL: f32 = 0.27;
+
+/// The master volume levels selected by $4089, as fractions of the full output
+const MASTER_VOLUME: [f32; 4] = [1.0, 2.0 / 3.0, 2.0 / 4.0, 2.0 / 5.0];
+
+/// The changes to the modulation counter made by each entry of the modulation table, `None`
+/// resets the counter
+const MOD_ADJUSTMENTS: [Option<i8>; 8] = [Some(0), Some(1), Some(2), Some(4), None, Some(-4), Some(-2), Some(-1)];
+
+/// Emulates one of the two envelopes, which control the volume and the modulation depth
+struct Envelope {
+    enabled: bool,
+    increase: bool,
+    speed: u8,
+    gain: u8,
+    counter: u32
+}
+
+impl Envelope {
+    fn new() -> Envelope {
+        Envelope {
+            enabled: false,
+            increase: false,
+            speed: 0,
+            gain: 0,
+            counter: 0
+        }
+    }
+
+    fn write(&mut self, val: u8) {
+        self.enabled = val & 0x80 == 0;
+        self.increase = val & 0x40 != 0;
+        self.speed = val & 0x3F;
+        if !self.enabled {
+            self.gain = val & 0x3F;
+        }
+        self.counter = 0;
+    }
+
+    fn clock(&mut self, master_speed: u8) {
+        if !self.enabled {
+            return;
+        }
+        self.counter += 1;
+        if self.counter < 8 * ((self.speed as u32) + 1) * (master_speed as u32) {
+            return;
+        }
+        self.counter = 0;
+        if self.increase && self.gain < 32 {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+    }
+}
+
+/// Emulates the audio of the Famicom Disk System
+///
+/// The FDS plays a 64-step wavetable of 6-bit samples, with its pitch modulated by a second
+/// 64-step table of adjustments. Both the volume and modulation depth have envelopes.
+pub struct FdsAudio {
+    wave: [u8; 64],
+    wave_write: bool,
+    wave_halt: bool,
+    wave_freq: u16,
+    wave_accumulator: u32,
+    wave_position: u8,
+    mod_table: [u8; 64],
+    mod_halt: bool,
+    mod_freq: u16,
+    mod_accumulator: u32,
+    mod_position: u8,
+    mod_counter: i8,
+    envelopes_halted: bool,
+    volume: Envelope,
+    modulation: Envelope,
+    master_speed: u8,
+    master_volume: u8,
+    output: u8
+}
+
+impl FdsAudio {
+    pub fn new() -> FdsAudio {
+        FdsAudio {
+            wave: [0; 64],
+            wave_write: false,
+            wave_halt: true,
+            wave_freq: 0,
+            wave_accumulator: 0,
+            wave_position: 0,
+            mod_table: [0; 64],
+            mod_halt: true,
+            mod_freq: 0,
+            mod_accumulator: 0,
+            mod_position: 0,
+            mod_counter: 0,
+            envelopes_halted: false,
+            volume: Envelope::new(),
+            modulation: Envelope::new(),
+            master_speed: 0xE8,
+            master_volume: 0,
+            output: 0
+        }
+    }
+
+    /// Calculates the wave frequency after applying the modulation unit
+    ///
+    /// This follows the integer arithmetic of the hardware, as described on the NesDev wiki
+    fn modulated_freq(&self) -> u16 {
+        let counter = self.mod_counter as i32;
+        let mut temp = counter * (self.modulation.gain as i32);
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        let mut temp = (self.wave_freq as i32) * temp;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        ((self.wave_freq as i32) + temp).max(0) as u16
+    }
+
+    fn clock_modulation(&mut self) {
+        if self.mod_halt || self.mod_freq == 0 {
+            return;
+        }
+        self.mod_accumulator += self.mod_freq as u32;
+        if self.mod_accumulator < 0x10000 {
+            return;
+        }
+        self.mod_accumulator &= 0xFFFF;
+
+        self.mod_counter = match MOD_ADJUSTMENTS[self.mod_table[self.mod_position as usize] as usize] {
+            Some(adjustment) => {
+                // The counter is 7 bits, and wraps around
+                let next = ((self.mod_counter as i16) + (adjustment as i16)) & 0x7F;
+                (if next >= 64 { next - 128 } else { next }) as i8
+            },
+            None => 0
+        };
+        self.mod_position = (self.mod_position + 1) & 0x3F;
+    }
+}
+
+impl ExpansionAudio for FdsAudio {
+    fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4040 ... 0x407F => Some(self.wave[(addr - 0x4040) as usize] | 0x40),
+            0x4090 => Some(self.volume.gain | 0x40),
+            0x4092 => Some(self.modulation.gain | 0x40),
+            _ => None
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x4040 ... 0x407F => {
+                if self.wave_write {
+                    self.wave[(addr - 0x4040) as usize] = val & 0x3F;
+                }
+            },
+            0x4080 => self.volume.write(val),
+            0x4082 => self.wave_freq = (self.wave_freq & 0x0F00) | (val as u16),
+            0x4083 => {
+                self.wave_freq = (self.wave_freq & 0x00FF) | (((val & 0x0F) as u16) << 8);
+                self.wave_halt = val & 0x80 != 0;
+                self.envelopes_halted = val & 0x40 != 0;
+                if self.wave_halt {
+                    self.wave_accumulator = 0;
+                    self.wave_position = 0;
+                }
+            },
+            0x4084 => self.modulation.write(val),
+            0x4085 => self.mod_counter = {
+                let counter = val & 0x7F;
+                (if counter >= 64 { (counter as i16) - 128 } else { counter as i16 }) as i8
+            },
+            0x4086 => self.mod_freq = (self.mod_freq & 0x0F00) | (val as u16),
+            0x4087 => {
+                self.mod_freq = (self.mod_freq & 0x00FF) | (((val & 0x0F) as u16) << 8);
+                self.mod_halt = val & 0x80 != 0;
+                if self.mod_halt {
+                    self.mod_accumulator = 0;
+                }
+            },
+            0x4088 => {
+                // Each write fills two entries of the table, which can only be written while halted
+                if self.mod_halt {
+                    let position = (self.mod_position & 0x3E) as usize;
+                    self.mod_table[position] = val & 0x07;
+                    self.mod_table[position + 1] = val & 0x07;
+                    self.mod_position = (self.mod_position + 2) & 0x3F;
+                }
+            },
+            0x4089 => {
+                self.wave_write = val & 0x80 != 0;
+                self.master_volume = val & 0x03;
+            },
+            0x408A => self.master_speed = val,
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self) {
+        if !self.wave_halt && !self.envelopes_halted && self.master_speed != 0 {
+            self.volume.clock(self.master_speed);
+            self.modulation.clock(self.master_speed);
+        }
+
+        self.clock_modulation();
+
+        if !self.wave_halt {
+            self.wave_accumulator += self.modulated_freq() as u32;
+            while self.wave_accumulator >= 0x10000 {
+                self.wave_accumulator -= 0x10000;
+                self.wave_position = (self.wave_position + 1) & 0x3F;
+            }
+        }
+
+        // The output holds its last value while the wavetable is being written
+        if !self.wave_write {
+            self.output = self.wave[self.wave_position as usize];
+        }
+    }
+
+    fn output(&self) -> f32 {
+        let gain = ::std::cmp::min(self.volume.gain, 32) as f32;
+        (self.output as f32) / 63.0 * gain / 32.0 * MASTER_VOLUME[self.master_volume as usize] * LEVEL
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::expansion_audio::{ExpansionAudio,FdsAudio};
+
+    fn fds_with_wave() -> FdsAudio {
+        let mut fds = FdsAudio::new();
+        fds.write(0x4089, 0x80);
+        for i in 0 .. 64 {
+            fds.write(0x4040 + i, if i < 32 { 0x3F } else { 0x00 });
+        }
+        fds.write(0x4089, 0x00);
+        fds.write(0x4080, 0xA0);
+        fds
+    }
+
+    #[test]
+    pub fn wavetable_is_only_writable_when_enabled() {
+        let mut fds = FdsAudio::new();
+        fds.write(0x4040, 0x3F);
+        assert_eq!(Some(0x40), fds.read(0x4040));
+        fds.write(0x4089, 0x80);
+        fds.write(0x4040, 0x3F);
+        assert_eq!(Some(0x7F), fds.read(0x4040));
+    }
+
+    #[test]
+    pub fn wave_plays_at_frequency() {
+        let mut fds = fds_with_wave();
+
+        // A frequency of $400 moves one step every 64 cycles
+        fds.write(0x4082, 0x00);
+        fds.write(0x4083, 0x04);
+        fds.clock();
+        assert!(fds.output() > 0.0);
+        for _ in 0 .. 64 * 32 {
+            fds.clock();
+        }
+        assert_eq!(0.0, fds.output());
+    }
+
+    #[test]
+    pub fn volume_envelope_can_be_disabled() {
+        let mut fds = fds_with_wave();
+        assert_eq!(Some(0x60), fds.read(0x4090));
+        fds.write(0x4080, 0x90);
+        assert_eq!(Some(0x50), fds.read(0x4090));
+    }
+
+    #[test]
+    pub fn modulation_changes_pitch() {
+        let mut fds = fds_with_wave();
+        fds.write(0x4084, 0x80 | 0x20);
+        fds.write(0x4085, 0x10);
+        fds.write(0x4082, 0x00);
+        fds.write(0x4083, 0x04);
+        assert!(fds.modulated_freq() > 0x400);
+        fds.write(0x4085, 0x70);
+        assert!(fds.modulated_freq() < 0x400);
+    }
+}
@@ -0,0 +1,131 @@
+use hw::expansion_audio::{ExpansionAudio,PULSE_LEVEL};
+use hw::rp2A03::pulse::Pulse;
+
+/// The number of CPU cycles between clocks of the envelopes and length counters (240Hz)
+const FRAME_PERIOD: u32 = 7457;
+
+/// The output of the PCM channel, per step of its 8-bit level
+const PCM_LEVEL: f32 = 0.002;
+
+/// Emulates the audio of the Nintendo MMC5: two pulse channels and an 8-bit PCM channel
+///
+/// The pulse channels are the same as the APU's, without the sweep units. Their envelopes and
+/// length counters are clocked at a fixed 240Hz, rather than by the APU frame counter. Only the
+/// write mode of the PCM channel is supported, read mode (which captures the value of reads from
+/// $8000-$BFFF) requires help from the mapper.
+pub struct Mmc5Audio {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    pcm: u8,
+    frame_counter: u32,
+    cycle: u64
+}
+
+impl Mmc5Audio {
+    pub fn new() -> Mmc5Audio {
+        Mmc5Audio {
+            pulse1: Pulse::new(false),
+            pulse2: Pulse::new(false),
+            pcm: 0,
+            frame_counter: 0,
+            cycle: 0
+        }
+    }
+}
+
+impl ExpansionAudio for Mmc5Audio {
+    fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x5015 => {
+                let mut val = 0;
+                if self.pulse1.length.active() { val |= 0x01; }
+                if self.pulse2.length.active() { val |= 0x02; }
+                Some(val)
+            },
+            _ => None
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // The second register of each channel would be the sweep unit, which the MMC5 lacks
+            0x5000 | 0x5002 | 0x5003 => self.pulse1.write(addr - 0x5000, val),
+            0x5004 | 0x5006 | 0x5007 => self.pulse2.write(addr - 0x5004, val),
+            0x5011 => {
+                // Writes of 0 are ignored, so that 0 can be used to signal the end of a sample
+                if val != 0 {
+                    self.pcm = val;
+                }
+            },
+            0x5015 => {
+                self.pulse1.length.set_enabled(val & 0x01 != 0);
+                self.pulse2.length.set_enabled(val & 0x02 != 0);
+            },
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.cycle % 2 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.cycle += 1;
+
+        self.frame_counter += 1;
+        if self.frame_counter == FRAME_PERIOD {
+            self.frame_counter = 0;
+            self.pulse1.envelope.clock();
+            self.pulse2.envelope.clock();
+            self.pulse1.length.clock();
+            self.pulse2.length.clock();
+        }
+    }
+
+    fn output(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        pulse * PULSE_LEVEL + (self.pcm as f32) * PCM_LEVEL
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::expansion_audio::{ExpansionAudio,Mmc5Audio};
+
+    #[test]
+    pub fn status_reports_active_length_counters() {
+        let mut mmc5 = Mmc5Audio::new();
+        mmc5.write(0x5015, 0x02);
+        mmc5.write(0x5003, 0x08);
+        mmc5.write(0x5007, 0x08);
+        assert_eq!(Some(0x02), mmc5.read(0x5015));
+        assert_eq!(None, mmc5.read(0x5014));
+    }
+
+    #[test]
+    pub fn length_counter_is_clocked_at_240hz() {
+        let mut mmc5 = Mmc5Audio::new();
+        mmc5.write(0x5015, 0x01);
+
+        // Length index 3 is 2 clocks
+        mmc5.write(0x5003, 0x18);
+        for _ in 0 .. 7457 {
+            mmc5.clock();
+        }
+        assert_eq!(Some(0x01), mmc5.read(0x5015));
+        for _ in 0 .. 7457 {
+            mmc5.clock();
+        }
+        assert_eq!(Some(0x00), mmc5.read(0x5015));
+    }
+
+    #[test]
+    pub fn pcm_ignores_zero_writes() {
+        let mut mmc5 = Mmc5Audio::new();
+        mmc5.write(0x5011, 0x80);
+        let level = mmc5.output();
+        assert!(level > 0.0);
+        mmc5.write(0x5011, 0x00);
+        assert_eq!(level, mmc5.output());
+    }
+}
@@ -0,0 +1,94 @@
+pub use self::vrc6::Vrc6Audio;
+pub use self::vrc7::Vrc7Audio;
+pub use self::n163::N163Audio;
+pub use self::s5b::Sunsoft5bAudio;
+pub use self::mmc5::Mmc5Audio;
+pub use self::fds::FdsAudio;
+
+/// Contains code to emulate the audio of the Konami VRC6
+pub mod vrc6;
+
+/// Contains code to emulate the audio of the Konami VRC7 (a subset of the Yamaha YM2413 OPLL)
+pub mod vrc7;
+
+/// Contains code to emulate the audio of the Namco 163
+pub mod n163;
+
+/// Contains code to emulate the audio of the Sunsoft 5B (a variant of the AY-3-8910)
+pub mod s5b;
+
+/// Contains code to emulate the audio of the Nintendo MMC5
+pub mod mmc5;
+
+/// Contains code to emulate the audio of the Famicom Disk System
+pub mod fds;
+
+/// The output of a single APU pulse channel, per step of volume
+///
+/// The APU mixer is non-linear, but this is a close approximation at the levels expansion chips
+/// are mixed at. Chips scale their output relative to it so they are mixed at the right level.
+pub const PULSE_LEVEL: f32 = 95.88 / (8128.0 / 15.0 + 100.0) / 15.0;
+
+/// Represents a sound chip on a cartridge, which the Famicom mixes with the APU's output
+///
+/// The chip registers are addressed as they are in the NSF format (for example, $9000-$9003 for
+/// the first VRC6 pulse channel). Mappers that mirror the registers or swap address lines should
+/// translate the address before passing it on.
+pub trait ExpansionAudio {
+    /// Reads one of the chip's registers, if the chip has a readable register at `addr`
+    fn read(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// Writes one of the chip's registers, writes to addresses the chip doesn't use are ignored
+    fn write(&mut self, addr: u16, val: u8);
+
+    /// Clocks the chip, this occurs every CPU cycle
+    fn clock(&mut self);
+
+    /// Gets the current output of the chip, scaled to be added to the output of the APU mixer
+    fn output(&self) -> f32;
+}
+
+/// Combines several expansion chips, as used by NSF tunes that use more than one
+pub struct Combined {
+    chips: Vec<Box<ExpansionAudio>>
+}
+
+impl Combined {
+    pub fn new() -> Combined {
+        Combined {
+            chips: Vec::new()
+        }
+    }
+
+    pub fn add(&mut self, chip: Box<ExpansionAudio>) {
+        self.chips.push(chip);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chips.is_empty()
+    }
+}
+
+impl ExpansionAudio for Combined {
+    fn read(&self, addr: u16) -> Option<u8> {
+        self.chips.iter().filter_map(|c| c.read(addr)).next()
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        for chip in self.chips.iter_mut() {
+            chip.write(addr, val);
+        }
+    }
+
+    fn clock(&mut self) {
+        for chip in self.chips.iter_mut() {
+            chip.clock();
+        }
+    }
+
+    fn output(&self) -> f32 {
+        self.chips.iter().map(|c| c.output()).sum()
+    }
+}
@@ -0,0 +1,169 @@
+use std::cell::Cell;
+
+use hw::expansion_audio::ExpansionAudio;
+
+/// The number of CPU cycles spent updating each channel
+const CYCLES_PER_CHANNEL: u8 = 15;
+
+/// The output per step of (sample - 8) * volume
+const LEVEL: f32 = 0.0027;
+
+/// Emulates the audio of the Namco 163
+///
+/// The chip has 128 bytes of internal RAM, which holds both the channel registers (at $40-$7F)
+/// and 4-bit wavetable samples. Up to 8 channels are enabled, and the chip updates one of them
+/// every 15 CPU cycles, outputting only that channel. With many channels enabled this produces
+/// an audible whine, so the outputs of the enabled channels are averaged instead.
+///
+/// The RAM is accessed through a data port at $4800, at the address set through $F800. Bit 7 of
+/// the address enables auto-increment, which also happens on reads, so the address is a `Cell`.
+pub struct N163Audio {
+    ram: [u8; 128],
+    address: Cell<u8>,
+    auto_increment: bool,
+    outputs: [i16; 8],
+    current: u8,
+    counter: u8
+}
+
+impl N163Audio {
+    pub fn new() -> N163Audio {
+        N163Audio {
+            ram: [0; 128],
+            address: Cell::new(0),
+            auto_increment: false,
+            outputs: [0; 8],
+            current: 7,
+            counter: 0
+        }
+    }
+
+    fn channel_count(&self) -> u8 {
+        ((self.ram[0x7F] >> 4) & 0x07) + 1
+    }
+
+    fn advance_address(&self) {
+        if self.auto_increment {
+            self.address.set((self.address.get() + 1) & 0x7F);
+        }
+    }
+
+    /// Updates the phase of the provided channel, and returns its output
+    fn update_channel(&mut self, channel: u8) -> i16 {
+        let base = 0x40 + (channel as usize) * 8;
+        let freq = (self.ram[base] as u32) |
+            ((self.ram[base + 2] as u32) << 8) |
+            (((self.ram[base + 4] & 0x03) as u32) << 16);
+        let length = 256 - ((self.ram[base + 4] & 0xFC) as u32);
+        let mut phase = (self.ram[base + 1] as u32) |
+            ((self.ram[base + 3] as u32) << 8) |
+            ((self.ram[base + 5] as u32) << 16);
+
+        phase = (phase + freq) % (length << 16);
+        self.ram[base + 1] = phase as u8;
+        self.ram[base + 3] = (phase >> 8) as u8;
+        self.ram[base + 5] = (phase >> 16) as u8;
+
+        // Samples are 4 bits, with the low nibble of each byte first
+        let sample_addr = ((self.ram[base + 6] as u32) + (phase >> 16)) & 0xFF;
+        let byte = self.ram[(sample_addr >> 1) as usize];
+        let sample = if sample_addr & 0x01 == 0 { byte & 0x0F } else { byte >> 4 };
+        let volume = self.ram[base + 7] & 0x0F;
+
+        ((sample as i16) - 8) * (volume as i16)
+    }
+}
+
+impl ExpansionAudio for N163Audio {
+    fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4800 => {
+                let val = self.ram[self.address.get() as usize];
+                self.advance_address();
+                Some(val)
+            },
+            _ => None
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x4800 => {
+                self.ram[self.address.get() as usize] = val;
+                self.advance_address();
+            },
+            0xF800 => {
+                self.address.set(val & 0x7F);
+                self.auto_increment = val & 0x80 != 0;
+            },
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self) {
+        self.counter += 1;
+        if self.counter < CYCLES_PER_CHANNEL {
+            return;
+        }
+        self.counter = 0;
+
+        // Channels are updated from 7 downwards
+        let first = 8 - self.channel_count();
+        let channel = self.current;
+        self.outputs[channel as usize] = self.update_channel(channel);
+        self.current = if channel <= first { 7 } else { channel - 1 };
+    }
+
+    fn output(&self) -> f32 {
+        let first = (8 - self.channel_count()) as usize;
+        let sum: i16 = self.outputs[first..].iter().sum();
+        (sum as f32) / ((8 - first) as f32) * LEVEL
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::expansion_audio::{ExpansionAudio,N163Audio};
+
+    #[test]
+    pub fn data_port_auto_increments() {
+        let mut n163 = N163Audio::new();
+        n163.write(0xF800, 0x80 | 0x10);
+        n163.write(0x4800, 0x12);
+        n163.write(0x4800, 0x34);
+        n163.write(0xF800, 0x80 | 0x10);
+        assert_eq!(Some(0x12), n163.read(0x4800));
+        assert_eq!(Some(0x34), n163.read(0x4800));
+
+        n163.write(0xF800, 0x11);
+        assert_eq!(Some(0x34), n163.read(0x4800));
+        assert_eq!(Some(0x34), n163.read(0x4800));
+    }
+
+    #[test]
+    pub fn channel_plays_wavetable() {
+        let mut n163 = N163Audio::new();
+
+        // A 2 sample wave of $F and $0 at address 0, played by channel 7 (the only one enabled)
+        n163.write(0xF800, 0x80);
+        n163.write(0x4800, 0x0F);
+        n163.write(0xF800, 0x80 | 0x78);
+        for val in &[0x00, 0x00, 0x00, 0x00, 0xFC, 0x00, 0x00, 0x0F] {
+            n163.write(0x4800, *val);
+        }
+
+        // With a frequency of 0, the channel stays on the first sample
+        for _ in 0 .. 15 {
+            n163.clock();
+        }
+        assert!(n163.output() > 0.0);
+
+        // A frequency of $10000 moves one sample per update
+        n163.write(0xF800, 0x7C);
+        n163.write(0x4800, 0xFD);
+        for _ in 0 .. 15 {
+            n163.clock();
+        }
+        assert!(n163.output() < 0.0);
+    }
+}
@@ -0,0 +1,298 @@
+use hw::expansion_audio::ExpansionAudio;
+
+/// The number of CPU cycles between clocks of the tone and noise generators
+const TONE_DIVIDER: u8 = 16;
+
+/// The output of a channel at full volume
+const LEVEL: f32 = 0.12;
+
+/// Emulates one of the three square wave tone generators
+struct Tone {
+    period: u16,
+    counter: u16,
+    output: bool
+}
+
+impl Tone {
+    fn new() -> Tone {
+        Tone {
+            period: 0,
+            counter: 0,
+            output: false
+        }
+    }
+
+    fn clock(&mut self) {
+        self.counter += 1;
+        if self.counter >= self.period {
+            self.counter = 0;
+            self.output = !self.output;
+        }
+    }
+}
+
+/// Emulates the envelope generator, shared by all channels
+///
+/// The 5B's envelope has 32 steps, twice as many as the AY-3-8910's.
+struct Envelope {
+    period: u16,
+    counter: u16,
+    step: u8,
+    attack: bool,
+    alternate: bool,
+    hold: bool,
+    continuing: bool,
+    holding: bool
+}
+
+impl Envelope {
+    fn new() -> Envelope {
+        Envelope {
+            period: 0,
+            counter: 0,
+            step: 0,
+            attack: false,
+            alternate: false,
+            hold: false,
+            continuing: false,
+            holding: false
+        }
+    }
+
+    fn set_shape(&mut self, val: u8) {
+        self.continuing = val & 0x08 != 0;
+        self.attack = val & 0x04 != 0;
+        self.alternate = val & 0x02 != 0;
+        self.hold = val & 0x01 != 0;
+        self.step = 0;
+        self.counter = 0;
+        self.holding = false;
+    }
+
+    fn clock(&mut self) {
+        if self.holding {
+            return;
+        }
+        self.counter += 1;
+        if self.counter < self.period {
+            return;
+        }
+        self.counter = 0;
+
+        if self.step < 31 {
+            self.step += 1;
+            return;
+        }
+
+        // The end of a cycle, the shape decides what happens next
+        if !self.continuing {
+            self.attack = false;
+            self.step = 31;
+            self.holding = true;
+        } else if self.hold {
+            if self.alternate {
+                self.attack = !self.attack;
+            }
+            self.step = 31;
+            self.holding = true;
+        } else {
+            if self.alternate {
+                self.attack = !self.attack;
+            }
+            self.step = 0;
+        }
+    }
+
+    fn level(&self) -> u8 {
+        if self.holding {
+            if self.attack { 31 } else { 0 }
+        } else if self.attack {
+            self.step
+        } else {
+            31 - self.step
+        }
+    }
+}
+
+/// Emulates the audio of the Sunsoft 5B, a variant of the AY-3-8910
+///
+/// The chip has three channels, each of which mixes a square wave tone with a shared noise
+/// generator, and uses a fixed volume or the shared envelope. Its 16 registers are accessed by
+/// writing the register number to $C000, then the value to $E000.
+pub struct Sunsoft5bAudio {
+    registers: [u8; 16],
+    address: u8,
+    tones: [Tone; 3],
+    noise_period: u8,
+    noise_counter: u8,
+    noise_shift: u32,
+    envelope: Envelope,
+    divider: u8,
+    levels: [f32; 32]
+}
+
+impl Sunsoft5bAudio {
+    pub fn new() -> Sunsoft5bAudio {
+        // The volume is logarithmic, each step is 1.5dB
+        let mut levels = [0.0; 32];
+        for (i, level) in levels.iter_mut().enumerate().skip(1) {
+            *level = LEVEL * 10.0f32.powf(((i as f32) - 31.0) * 1.5 / 20.0);
+        }
+
+        Sunsoft5bAudio {
+            registers: [0; 16],
+            address: 0,
+            tones: [Tone::new(), Tone::new(), Tone::new()],
+            noise_period: 0,
+            noise_counter: 0,
+            noise_shift: 1,
+            envelope: Envelope::new(),
+            divider: 0,
+            levels: levels
+        }
+    }
+
+    fn write_register(&mut self, reg: u8, val: u8) {
+        self.registers[reg as usize] = val;
+        match reg {
+            0 ... 5 => {
+                let channel = (reg / 2) as usize;
+                let lo = self.registers[(channel * 2) as usize] as u16;
+                let hi = (self.registers[(channel * 2 + 1) as usize] & 0x0F) as u16;
+                self.tones[channel].period = (hi << 8) | lo;
+            },
+            6 => self.noise_period = val & 0x1F,
+            11 | 12 => self.envelope.period = ((self.registers[12] as u16) << 8) | (self.registers[11] as u16),
+            13 => self.envelope.set_shape(val),
+            _ => {}
+        }
+    }
+
+    fn clock_noise(&mut self) {
+        self.noise_counter += 1;
+        if self.noise_counter >= self.noise_period {
+            self.noise_counter = 0;
+
+            // A 17-bit LFSR with taps at bits 0 and 3
+            let feedback = (self.noise_shift ^ (self.noise_shift >> 3)) & 0x01;
+            self.noise_shift = (self.noise_shift >> 1) | (feedback << 16);
+        }
+    }
+
+    fn channel_output(&self, channel: usize) -> f32 {
+        let mixer = self.registers[7];
+        let tone = self.tones[channel].output || mixer & (0x01 << channel) != 0;
+        let noise = self.noise_shift & 0x01 != 0 || mixer & (0x08 << channel) != 0;
+        if !(tone && noise) {
+            return 0.0;
+        }
+
+        let volume = self.registers[8 + channel];
+        let level = if volume & 0x10 != 0 {
+            self.envelope.level()
+        } else if volume & 0x0F == 0 {
+            0
+        } else {
+            (volume & 0x0F) * 2 + 1
+        };
+        self.levels[level as usize]
+    }
+}
+
+impl ExpansionAudio for Sunsoft5bAudio {
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0xC000 => self.address = val & 0x0F,
+            0xE000 => {
+                let reg = self.address;
+                self.write_register(reg, val);
+            },
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self) {
+        // The envelope runs at twice the rate of the tone generators, for its 32 steps
+        if self.divider % (TONE_DIVIDER / 2) == 0 {
+            self.envelope.clock();
+        }
+        self.divider += 1;
+        if self.divider < TONE_DIVIDER {
+            return;
+        }
+        self.divider = 0;
+
+        for tone in self.tones.iter_mut() {
+            tone.clock();
+        }
+        self.clock_noise();
+    }
+
+    fn output(&self) -> f32 {
+        (0 .. 3).map(|c| self.channel_output(c)).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::expansion_audio::{ExpansionAudio,Sunsoft5bAudio};
+
+    fn write(chip: &mut Sunsoft5bAudio, reg: u8, val: u8) {
+        chip.write(0xC000, reg);
+        chip.write(0xE000, val);
+    }
+
+    #[test]
+    pub fn tone_toggles_at_period() {
+        let mut chip = Sunsoft5bAudio::new();
+        write(&mut chip, 0, 0x02);
+        write(&mut chip, 7, 0x3E);
+        write(&mut chip, 8, 0x0F);
+
+        let mut outputs = Vec::new();
+        for _ in 0 .. 4 {
+            for _ in 0 .. 32 {
+                chip.clock();
+            }
+            outputs.push(chip.output() > 0.0);
+        }
+        assert_eq!(vec![true, false, true, false], outputs);
+    }
+
+    #[test]
+    pub fn volume_is_logarithmic() {
+        let mut chip = Sunsoft5bAudio::new();
+
+        // Disable the tone and noise, so the channel outputs its volume
+        write(&mut chip, 7, 0x3F);
+        write(&mut chip, 8, 0x0F);
+        let full = chip.output();
+        write(&mut chip, 8, 0x0D);
+        let lower = chip.output();
+        write(&mut chip, 8, 0x00);
+
+        // Each step of the 4-bit volume is 3dB
+        assert!((lower / full - 0.5).abs() < 0.01);
+        assert_eq!(0.0, chip.output());
+    }
+
+    #[test]
+    pub fn envelope_decays_and_holds() {
+        let mut chip = Sunsoft5bAudio::new();
+        write(&mut chip, 7, 0x3F);
+        write(&mut chip, 8, 0x10);
+        write(&mut chip, 11, 0x01);
+        write(&mut chip, 13, 0x00);
+
+        let start = chip.output();
+        for _ in 0 .. 8 * 16 {
+            chip.clock();
+        }
+        let middle = chip.output();
+        for _ in 0 .. 8 * 32 {
+            chip.clock();
+        }
+        assert!(middle < start);
+        assert_eq!(0.0, chip.output());
+    }
+}
@@ -0,0 +1,237 @@
+use hw::expansion_audio::{ExpansionAudio,PULSE_LEVEL};
+
+/// Emulates one of the two VRC6 pulse channels
+///
+/// Unlike the APU pulse channels, the duty cycle can be set in 1/16 steps, and there is no
+/// envelope, sweep or length counter.
+struct Pulse {
+    enabled: bool,
+    ignore_duty: bool,
+    duty: u8,
+    volume: u8,
+    period: u16,
+    timer: u16,
+    step: u8
+}
+
+impl Pulse {
+    fn new() -> Pulse {
+        Pulse {
+            enabled: false,
+            ignore_duty: false,
+            duty: 0,
+            volume: 0,
+            period: 0,
+            timer: 0,
+            step: 0
+        }
+    }
+
+    fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.ignore_duty = val & 0x80 != 0;
+                self.duty = (val >> 4) & 0x07;
+                self.volume = val & 0x0F;
+            },
+            1 => self.period = (self.period & 0x0F00) | (val as u16),
+            _ => {
+                self.period = (self.period & 0x00FF) | (((val & 0x0F) as u16) << 8);
+                self.enabled = val & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = (self.step + 1) & 0x0F;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+/// Emulates the VRC6 sawtooth channel
+///
+/// An accumulator is increased by the rate every other clock, and reset every 14 clocks. The top
+/// 5 bits of the accumulator are the output.
+struct Saw {
+    enabled: bool,
+    rate: u8,
+    period: u16,
+    timer: u16,
+    step: u8,
+    accumulator: u8
+}
+
+impl Saw {
+    fn new() -> Saw {
+        Saw {
+            enabled: false,
+            rate: 0,
+            period: 0,
+            timer: 0,
+            step: 0,
+            accumulator: 0
+        }
+    }
+
+    fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => self.rate = val & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | (val as u16),
+            _ => {
+                self.period = (self.period & 0x00FF) | (((val & 0x0F) as u16) << 8);
+                self.enabled = val & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period >> shift;
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step % 2 == 0 {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+/// Emulates the audio of the Konami VRC6: two pulse channels and a sawtooth channel
+pub struct Vrc6Audio {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    saw: Saw,
+    halt: bool,
+    shift: u8
+}
+
+impl Vrc6Audio {
+    pub fn new() -> Vrc6Audio {
+        Vrc6Audio {
+            pulse1: Pulse::new(),
+            pulse2: Pulse::new(),
+            saw: Saw::new(),
+            halt: false,
+            shift: 0
+        }
+    }
+}
+
+impl ExpansionAudio for Vrc6Audio {
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x9000 ... 0x9002 => self.pulse1.write(addr - 0x9000, val),
+            0x9003 => {
+                // The frequency control register halts the channels, or speeds them up by 16 or
+                // 256 times (which is only used to test the chip)
+                self.halt = val & 0x01 != 0;
+                self.shift = if val & 0x04 != 0 { 8 } else if val & 0x02 != 0 { 4 } else { 0 };
+            },
+            0xA000 ... 0xA002 => self.pulse2.write(addr - 0xA000, val),
+            0xB000 ... 0xB002 => self.saw.write(addr - 0xB000, val),
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.halt {
+            return;
+        }
+        self.pulse1.clock(self.shift);
+        self.pulse2.clock(self.shift);
+        self.saw.clock(self.shift);
+    }
+
+    fn output(&self) -> f32 {
+        let total = self.pulse1.output() + self.pulse2.output() + self.saw.output();
+        (total as f32) * PULSE_LEVEL
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::expansion_audio::{ExpansionAudio,Vrc6Audio};
+
+    #[test]
+    pub fn pulse_follows_duty_cycle() {
+        let mut vrc6 = Vrc6Audio::new();
+        vrc6.write(0x9000, 0x3F);
+        vrc6.write(0x9001, 0x00);
+        vrc6.write(0x9002, 0x80);
+
+        // Duty 3 is high for 4 of the 16 steps
+        let mut high = 0;
+        for _ in 0 .. 16 {
+            vrc6.clock();
+            if vrc6.output() > 0.0 {
+                high += 1;
+            }
+        }
+        assert_eq!(4, high);
+    }
+
+    #[test]
+    pub fn saw_accumulates_rate() {
+        let mut vrc6 = Vrc6Audio::new();
+        vrc6.write(0xB000, 0x20);
+        vrc6.write(0xB001, 0x00);
+        vrc6.write(0xB002, 0x80);
+
+        let mut levels = Vec::new();
+        for _ in 0 .. 14 {
+            vrc6.clock();
+            levels.push((vrc6.output() / ::hw::expansion_audio::PULSE_LEVEL).round() as u8);
+        }
+        assert_eq!(vec![0, 4, 4, 8, 8, 12, 12, 16, 16, 20, 20, 24, 24, 0], levels);
+    }
+
+    #[test]
+    pub fn halt_stops_channels() {
+        let mut vrc6 = Vrc6Audio::new();
+        vrc6.write(0x9000, 0x0F);
+        vrc6.write(0x9002, 0x80);
+        vrc6.write(0x9003, 0x01);
+
+        // Duty 0 is only high on the first step, which the channel stays on while halted
+        for _ in 0 .. 4 {
+            vrc6.clock();
+            assert!(vrc6.output() > 0.0);
+        }
+        vrc6.write(0x9003, 0x00);
+        vrc6.clock();
+        assert_eq!(0.0, vrc6.output());
+    }
+}
@@ -0,0 +1,465 @@
+use std::f32::consts::PI;
+
+use hw::expansion_audio::ExpansionAudio;
+
+/// The number of CPU cycles per sample produced by the chip (about 49.7KHz)
+const CYCLES_PER_SAMPLE: u8 = 36;
+
+/// The sample rate of the chip
+const SAMPLE_RATE: f32 = 1789773.0 / (CYCLES_PER_SAMPLE as f32);
+
+/// The output of a channel at full volume
+const LEVEL: f32 = 0.1;
+
+/// The attenuation, in dB, at which an operator is considered silent
+const MAX_ATTENUATION: f32 = 96.0;
+
+/// The time taken to attack from silence to full volume at rate 4 (AR = 1), in milliseconds
+const ATTACK_TIME: f32 = 2826.0;
+
+/// The time taken to decay from full volume to silence at rate 4 (DR or RR = 1), in milliseconds
+const DECAY_TIME: f32 = 39280.0;
+
+/// The frequency multipliers selected by the MULT bits of an instrument
+const MULTIPLIERS: [f32; 16] = [0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 10.0, 12.0, 12.0, 15.0, 15.0];
+
+/// The key scale level attenuation, in dB at 6dB/octave, by the top 4 bits of the F-Number
+const KEY_SCALE_LEVELS: [f32; 16] = [
+    0.0, 18.0, 24.0, 27.75, 30.0, 32.25, 33.75, 35.25, 36.0, 37.5, 38.25, 39.0, 39.75, 40.5, 41.25, 42.0
+];
+
+/// The fraction of the key scale level applied for each KSL setting (0, 1.5, 3 and 6 dB/octave)
+const KEY_SCALE_FACTORS: [f32; 4] = [0.0, 0.25, 0.5, 1.0];
+
+/// The 15 built-in instruments of the VRC7, instrument 0 is the custom instrument in registers
+/// $00-$07
+const INSTRUMENTS: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06]
+];
+
+/// The frequency of the amplitude modulation (tremolo) LFO, in Hz
+const AM_FREQUENCY: f32 = 3.7;
+
+/// The depth of the amplitude modulation, in dB
+const AM_DEPTH: f32 = 4.8;
+
+/// The frequency of the vibrato LFO, in Hz
+const VIBRATO_FREQUENCY: f32 = 6.4;
+
+/// The depth of the vibrato, as a fraction of the frequency (about 14 cents)
+const VIBRATO_DEPTH: f32 = 0.008;
+
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off
+}
+
+/// The parameters of one operator, decoded from an instrument
+#[derive(Copy,Clone)]
+struct Patch {
+    am: bool,
+    vibrato: bool,
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: f32,
+    key_scale_level: u8,
+    rectify: bool,
+    attack: u8,
+    decay: u8,
+    sustain_level: u8,
+    release: u8
+}
+
+impl Patch {
+    /// Decodes the modulator (`carrier` is false) or carrier operator of an instrument
+    fn decode(instrument: &[u8; 8], carrier: bool) -> Patch {
+        let i = if carrier { 1 } else { 0 };
+        Patch {
+            am: instrument[i] & 0x80 != 0,
+            vibrato: instrument[i] & 0x40 != 0,
+            sustained: instrument[i] & 0x20 != 0,
+            key_scale_rate: instrument[i] & 0x10 != 0,
+            multiplier: MULTIPLIERS[(instrument[i] & 0x0F) as usize],
+            key_scale_level: instrument[2 + i] >> 6,
+            rectify: instrument[3] & (if carrier { 0x10 } else { 0x08 }) != 0,
+            attack: instrument[4 + i] >> 4,
+            decay: instrument[4 + i] & 0x0F,
+            sustain_level: instrument[6 + i] >> 4,
+            release: instrument[6 + i] & 0x0F
+        }
+    }
+}
+
+/// Emulates one of the two FM operators (the modulator or the carrier) of a channel
+///
+/// The envelope is tracked as an attenuation in dB, using the rates of the YM2413.
+struct Operator {
+    phase: f32,
+    state: EnvelopeState,
+    attenuation: f32
+}
+
+impl Operator {
+    fn new() -> Operator {
+        Operator {
+            phase: 0.0,
+            state: EnvelopeState::Off,
+            attenuation: MAX_ATTENUATION
+        }
+    }
+
+    fn key_on(&mut self) {
+        self.phase = 0.0;
+        self.state = EnvelopeState::Attack;
+    }
+
+    fn key_off(&mut self) {
+        if self.state != EnvelopeState::Off {
+            self.state = EnvelopeState::Release;
+        }
+    }
+
+    /// Advances the envelope by one sample, using the key scale rate offset of the channel
+    fn clock_envelope(&mut self, patch: &Patch, rate_offset: u8, channel_sustain: bool) {
+        match self.state {
+            EnvelopeState::Attack => {
+                match attack_multiplier(patch.attack, rate_offset) {
+                    Some(multiplier) => self.attenuation *= multiplier,
+                    None => {}
+                }
+                if self.attenuation < 0.1 {
+                    self.attenuation = 0.0;
+                    self.state = EnvelopeState::Decay;
+                }
+            },
+            EnvelopeState::Decay => {
+                self.attenuation += decay_step(patch.decay, rate_offset);
+                let sustain_level = (patch.sustain_level as f32) * 3.0;
+                if self.attenuation >= sustain_level {
+                    self.attenuation = sustain_level;
+                    self.state = EnvelopeState::Sustain;
+                }
+            },
+            EnvelopeState::Sustain => {
+                // Percussive instruments keep decaying at the release rate while the key is held
+                if !patch.sustained {
+                    self.attenuation += decay_step(patch.release, rate_offset);
+                }
+            },
+            EnvelopeState::Release => {
+                let rate = if channel_sustain { 5 } else if patch.sustained { patch.release } else { 7 };
+                self.attenuation += decay_step(rate, rate_offset);
+            },
+            EnvelopeState::Off => {}
+        }
+
+        if self.attenuation >= MAX_ATTENUATION {
+            self.attenuation = MAX_ATTENUATION;
+            if self.state != EnvelopeState::Attack {
+                self.state = EnvelopeState::Off;
+            }
+        }
+    }
+
+    /// Advances the phase and returns the output of the operator, from -1.0 to 1.0
+    fn clock(&mut self, increment: f32, modulation: f32, attenuation: f32, rectify: bool) -> f32 {
+        self.phase = (self.phase + increment) % 1.0;
+        let mut wave = (2.0 * PI * self.phase + modulation).sin();
+        if rectify && wave < 0.0 {
+            wave = 0.0;
+        }
+
+        let total = self.attenuation + attenuation;
+        if total >= MAX_ATTENUATION {
+            0.0
+        } else {
+            wave * 10.0f32.powf(-total / 20.0)
+        }
+    }
+}
+
+/// Gets the amount the attenuation is multiplied by each sample during the attack
+fn attack_multiplier(rate: u8, offset: u8) -> Option<f32> {
+    if rate == 0 {
+        return None;
+    }
+    let rate = ::std::cmp::min((rate as u32) * 4 + (offset as u32), 63);
+    if rate >= 60 {
+        return Some(0.0);
+    }
+
+    // The attack falls from 96dB to 0.1dB, which is a factor of roughly 0.001
+    let samples = ATTACK_TIME / 1000.0 * SAMPLE_RATE / 2.0f32.powf(((rate as f32) - 4.0) / 4.0);
+    Some(0.001f32.powf(1.0 / samples))
+}
+
+/// Gets the amount the attenuation increases by each sample during the decay or release
+fn decay_step(rate: u8, offset: u8) -> f32 {
+    if rate == 0 {
+        return 0.0;
+    }
+    let rate = ::std::cmp::min((rate as u32) * 4 + (offset as u32), 63);
+    let samples = DECAY_TIME / 1000.0 * SAMPLE_RATE / 2.0f32.powf(((rate as f32) - 4.0) / 4.0);
+    MAX_ATTENUATION / samples
+}
+
+/// Emulates one of the 6 channels, each of which has a modulator and a carrier
+struct Channel {
+    fnum: u16,
+    block: u8,
+    key: bool,
+    sustain: bool,
+    instrument: u8,
+    volume: u8,
+    modulator: Operator,
+    carrier: Operator,
+    feedback: [f32; 2]
+}
+
+impl Channel {
+    fn new() -> Channel {
+        Channel {
+            fnum: 0,
+            block: 0,
+            key: false,
+            sustain: false,
+            instrument: 0,
+            volume: 0,
+            modulator: Operator::new(),
+            carrier: Operator::new(),
+            feedback: [0.0; 2]
+        }
+    }
+
+    fn set_key(&mut self, key: bool) {
+        if key && !self.key {
+            self.modulator.key_on();
+            self.carrier.key_on();
+        } else if !key && self.key {
+            self.modulator.key_off();
+            self.carrier.key_off();
+        }
+        self.key = key;
+    }
+
+    /// Produces the next sample of the channel, `lfo` is the position of the AM and vibrato LFOs
+    fn clock(&mut self, instrument: &[u8; 8], am: f32, vibrato: f32) -> f32 {
+        let modulator = Patch::decode(instrument, false);
+        let carrier = Patch::decode(instrument, true);
+
+        let rate_offset = |patch: &Patch| if patch.key_scale_rate {
+            (self.block << 1) | ((self.fnum >> 8) as u8)
+        } else {
+            self.block >> 1
+        };
+        let modulator_offset = rate_offset(&modulator);
+        let carrier_offset = rate_offset(&carrier);
+        self.modulator.clock_envelope(&modulator, modulator_offset, self.sustain);
+        self.carrier.clock_envelope(&carrier, carrier_offset, self.sustain);
+
+        // The base frequency, in cycles per sample
+        let base = (self.fnum as f32) * 2.0f32.powi(self.block as i32) / 524288.0;
+        let key_scale = (KEY_SCALE_LEVELS[(self.fnum >> 5) as usize] - 6.0 * (7 - self.block) as f32).max(0.0);
+
+        let increment = |patch: &Patch| {
+            let inc = base * patch.multiplier;
+            if patch.vibrato { inc * (1.0 + vibrato * VIBRATO_DEPTH) } else { inc }
+        };
+        let attenuation = |patch: &Patch| {
+            let am = if patch.am { am } else { 0.0 };
+            key_scale * KEY_SCALE_FACTORS[patch.key_scale_level as usize] + am
+        };
+
+        // The modulator feeds back the average of its last two outputs
+        let feedback_level = instrument[3] & 0x07;
+        let feedback = if feedback_level == 0 {
+            0.0
+        } else {
+            (self.feedback[0] + self.feedback[1]) / 2.0 * PI * 2.0f32.powi((feedback_level as i32) - 6)
+        };
+        let total_level = ((instrument[2] & 0x3F) as f32) * 0.75;
+        let modulator_out = self.modulator.clock(
+            increment(&modulator),
+            feedback,
+            attenuation(&modulator) + total_level,
+            modulator.rectify);
+        self.feedback = [self.feedback[1], modulator_out];
+
+        let volume = (self.volume as f32) * 3.0;
+        self.carrier.clock(
+            increment(&carrier),
+            modulator_out * 2.0 * PI,
+            attenuation(&carrier) + volume,
+            carrier.rectify)
+    }
+}
+
+/// Emulates the audio of the Konami VRC7, a cut down Yamaha YM2413 (OPLL)
+///
+/// The chip has 6 two-operator FM channels, 15 built-in instruments and one custom instrument.
+/// Registers are accessed by writing the register number to $9010, then the value to $9030.
+///
+/// This is an approximation of the chip using floating point sine waves and envelopes in dB,
+/// rather than the log-sin and exponent tables of the hardware, so it won't match recordings
+/// exactly but plays the right notes with the right instruments.
+pub struct Vrc7Audio {
+    address: u8,
+    custom: [u8; 8],
+    channels: [Channel; 6],
+    lfo_phase: f32,
+    counter: u8,
+    output: f32
+}
+
+impl Vrc7Audio {
+    pub fn new() -> Vrc7Audio {
+        Vrc7Audio {
+            address: 0,
+            custom: [0; 8],
+            channels: [Channel::new(), Channel::new(), Channel::new(), Channel::new(), Channel::new(), Channel::new()],
+            lfo_phase: 0.0,
+            counter: 0,
+            output: 0.0
+        }
+    }
+
+    fn write_register(&mut self, reg: u8, val: u8) {
+        match reg {
+            0x00 ... 0x07 => self.custom[reg as usize] = val,
+            0x10 ... 0x15 => {
+                let channel = &mut self.channels[(reg - 0x10) as usize];
+                channel.fnum = (channel.fnum & 0x100) | (val as u16);
+            },
+            0x20 ... 0x25 => {
+                let channel = &mut self.channels[(reg - 0x20) as usize];
+                channel.fnum = (channel.fnum & 0xFF) | (((val & 0x01) as u16) << 8);
+                channel.block = (val >> 1) & 0x07;
+                channel.sustain = val & 0x20 != 0;
+                channel.set_key(val & 0x10 != 0);
+            },
+            0x30 ... 0x35 => {
+                let channel = &mut self.channels[(reg - 0x30) as usize];
+                channel.instrument = val >> 4;
+                channel.volume = val & 0x0F;
+            },
+            _ => {}
+        }
+    }
+
+    fn generate_sample(&mut self) {
+        self.lfo_phase += 1.0 / SAMPLE_RATE;
+        let am = AM_DEPTH * (1.0 + (2.0 * PI * AM_FREQUENCY * self.lfo_phase).sin()) / 2.0;
+        let vibrato = (2.0 * PI * VIBRATO_FREQUENCY * self.lfo_phase).sin();
+
+        let custom = self.custom;
+        let mut total = 0.0;
+        for channel in self.channels.iter_mut() {
+            let instrument = if channel.instrument == 0 {
+                custom
+            } else {
+                INSTRUMENTS[(channel.instrument - 1) as usize]
+            };
+            total += channel.clock(&instrument, am, vibrato);
+        }
+        self.output = total * LEVEL;
+    }
+}
+
+impl ExpansionAudio for Vrc7Audio {
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x9010 => self.address = val,
+            0x9030 => {
+                let reg = self.address;
+                self.write_register(reg, val);
+            },
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self) {
+        self.counter += 1;
+        if self.counter == CYCLES_PER_SAMPLE {
+            self.counter = 0;
+            self.generate_sample();
+        }
+    }
+
+    fn output(&self) -> f32 {
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::expansion_audio::{ExpansionAudio,Vrc7Audio};
+
+    fn write(chip: &mut Vrc7Audio, reg: u8, val: u8) {
+        chip.write(0x9010, reg);
+        chip.write(0x9030, val);
+    }
+
+    fn run(chip: &mut Vrc7Audio, samples: usize) -> Vec<f32> {
+        let mut outputs = Vec::new();
+        for _ in 0 .. samples {
+            for _ in 0 .. 36 {
+                chip.clock();
+            }
+            outputs.push(chip.output());
+        }
+        outputs
+    }
+
+    #[test]
+    pub fn silent_until_key_on() {
+        let mut chip = Vrc7Audio::new();
+        write(&mut chip, 0x30, 0x30);
+        write(&mut chip, 0x10, 0xAC);
+        assert!(run(&mut chip, 1000).iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    pub fn key_on_plays_instrument() {
+        let mut chip = Vrc7Audio::new();
+
+        // Flute at full volume, A4 (F-Number 288, block 4)
+        write(&mut chip, 0x30, 0x40);
+        write(&mut chip, 0x10, 0x20);
+        write(&mut chip, 0x20, 0x19);
+        let samples = run(&mut chip, 5000);
+        assert!(samples.iter().any(|s| *s > 0.01));
+        assert!(samples.iter().any(|s| *s < -0.01));
+    }
+
+    #[test]
+    pub fn key_off_releases_note() {
+        let mut chip = Vrc7Audio::new();
+
+        // Synth, which has a fast release
+        write(&mut chip, 0x30, 0x60);
+        write(&mut chip, 0x10, 0x20);
+        write(&mut chip, 0x20, 0x19);
+        run(&mut chip, 5000);
+        write(&mut chip, 0x20, 0x09);
+        let samples = run(&mut chip, 50000);
+        assert!(samples[45000 ..].iter().all(|s| s.abs() < 0.001));
+    }
+}
@@ -11,3 +11,14 @@ pub mod mos6502;
 /// This processor is found in the [Nintendo Entertainment System](http://en.wikipedia.org/wiki/Nintendo_Entertainment_System)
 #[allow(non_snake_case)]
 pub mod rp2C02;
+
+/// Provides emulation for the Audio Processing Unit (APU) of the [Ricoh RP2A03](https://en.wikipedia.org/wiki/Ricoh_2A03),
+/// the CPU of the [Nintendo Entertainment System](http://en.wikipedia.org/wiki/Nintendo_Entertainment_System)
+///
+/// The CPU core of the RP2A03 is emulated by `mos6502`
+#[allow(non_snake_case)]
+pub mod rp2A03;
+
+/// Provides emulation for the sound chips found on Famicom cartridges and in the Famicom Disk
+/// System, which are mixed with the output of the RP2A03's APU
+pub mod expansion_audio;
@@ -1,7 +1,8 @@
 use std::{convert,error,fmt};
+use byteorder::LittleEndian;
 
 use pc;
-use mem;
+use mem::{self,MemoryExt};
 use clock;
 
 use super::{instr,exec};
@@ -125,7 +126,7 @@ pub struct Mos6502 {
     /// The processor status flags
     pub flags: Flags,
     /// The program counter for the cpu
-    pub pc: pc::ProgramCounter,
+    pub pc: pc::ProgramCounter<u16>,
     /// Indicates if BCD arithmetic is enabled on this instance
     pub bcd_enabled: bool,
     /// Tracks CPU cycles spent during execution
@@ -169,10 +170,10 @@ impl Mos6502 {
     ///
     /// # Arguments
     /// * `val` - The value to push on to the stack
-    pub fn push<M>(&mut self, mem: &mut M, val: u8) -> mem::Result<()> where M: mem::Memory {
-        let addr = (self.registers.sp as u64) + super::STACK_START;
+    pub fn push<M>(&mut self, mem: &mut M, val: u8) -> mem::Result<()> where M: mem::Memory<u16> {
+        let addr = (self.registers.sp as u16) + super::STACK_START;
         try!(mem.set_u8(addr, val));
-        self.registers.sp -= 1;
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
         Ok(())
     }
 
@@ -181,9 +182,9 @@ impl Mos6502 {
     /// Note: A `MemoryError::OutOfBounds` result is returned
     /// if there is no memory available in the stack range
     /// ($0100 - $01FF)
-    pub fn pull<M>(&mut self, mem: &M) -> mem::Result<u8> where M: mem::Memory {
-        self.registers.sp += 1;
-        let addr = (self.registers.sp as u64) + super::STACK_START;
+    pub fn pull<M>(&mut self, mem: &M) -> mem::Result<u8> where M: mem::Memory<u16> {
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        let addr = (self.registers.sp as u16) + super::STACK_START;
         mem.get_u8(addr)
     }
 
@@ -192,10 +193,44 @@ impl Mos6502 {
     /// Note: A `MemoryError::OutOfBounds` result is returned
     /// if there is no memory available in the stack range
     /// ($0100 - $01FF)
-    pub fn peek<M>(&mut self, mem: &M) -> mem::Result<u8> where M: mem::Memory {
-        let addr = (self.registers.sp as u64 + 1) + super::STACK_START;
+    pub fn peek<M>(&mut self, mem: &M) -> mem::Result<u8> where M: mem::Memory<u16> {
+        let addr = (self.registers.sp.wrapping_add(1) as u16) + super::STACK_START;
         mem.get_u8(addr)
     }
+
+    /// Services a Non-Maskable Interrupt
+    ///
+    /// Pushes the program counter and flags (with the BREAK flag clear) on to the stack,
+    /// disables interrupts and jumps to the address stored in the NMI vector ($FFFA)
+    pub fn nmi<M>(&mut self, mem: &mut M) -> mem::Result<()> where M: mem::Memory<u16> {
+        self.interrupt(mem, super::NMI_VECTOR)
+    }
+
+    /// Triggers an Interrupt Request (IRQ), unless interrupts are disabled
+    ///
+    /// This behaves like `nmi`, but uses the IRQ vector ($FFFE). Returns `true` if the interrupt
+    /// was taken and `false` if the INTERRUPT flag is set.
+    pub fn irq<M>(&mut self, mem: &mut M) -> mem::Result<bool> where M: mem::Memory<u16> {
+        if self.flags.intersects(Flags::INTERRUPT()) {
+            return Ok(false);
+        }
+        try!(self.interrupt(mem, super::IRQ_VECTOR));
+        Ok(true)
+    }
+
+    fn interrupt<M>(&mut self, mem: &mut M, vector: u16) -> mem::Result<()> where M: mem::Memory<u16> {
+        let pc = self.pc.get();
+        try!(self.push(mem, (pc >> 8) as u8));
+        try!(self.push(mem, (pc & 0x00FF) as u8));
+
+        let flags = self.flags & !Flags::BREAK();
+        try!(self.push(mem, flags.bits));
+
+        self.flags.set(Flags::INTERRUPT());
+        self.pc.set(try!(mem.get_u16::<LittleEndian>(vector)));
+        self.clock.tick(7);
+        Ok(())
+    }
 }
 
 impl<'a> ::slog::ser::Serialize for &'a mut Mos6502 {
@@ -469,7 +504,52 @@ mod test {
             assert_eq!(6, cpu.registers.sp);
         }
 
-        pub fn setup_cpu<'a>() -> (mos6502::Mos6502,mem::Virtual<'a>) {
+        #[test]
+        pub fn nmi_pushes_pc_and_flags_without_break() {
+            let (mut cpu, mut mem) = setup_cpu();
+            mem.attach(0xFFFA, Box::new(mem::Fixed::from_contents(vec![0xEF, 0xBE]))).unwrap();
+            cpu.pc.set(0xABCD);
+            cpu.flags.replace(mos6502::Flags::CARRY() | mos6502::Flags::BREAK());
+            cpu.nmi(&mut mem).unwrap();
+
+            assert_eq!(Ok((mos6502::Flags::CARRY() | mos6502::Flags::RESERVED()).bits), cpu.pull(&mem));
+            assert_eq!(Ok(0xCD), cpu.pull(&mem));
+            assert_eq!(Ok(0xAB), cpu.pull(&mem));
+        }
+
+        #[test]
+        pub fn nmi_disables_interrupts_and_jumps_to_vector() {
+            let (mut cpu, mut mem) = setup_cpu();
+            mem.attach(0xFFFA, Box::new(mem::Fixed::from_contents(vec![0xEF, 0xBE]))).unwrap();
+            cpu.nmi(&mut mem).unwrap();
+
+            assert!(cpu.flags.intersects(mos6502::Flags::INTERRUPT()));
+            assert_eq!(0xBEEF, cpu.pc.get());
+        }
+
+        #[test]
+        pub fn irq_jumps_to_vector_if_interrupts_are_enabled() {
+            let (mut cpu, mut mem) = setup_cpu();
+            mem.attach(0xFFFE, Box::new(mem::Fixed::from_contents(vec![0xEF, 0xBE]))).unwrap();
+            cpu.flags.clear(mos6502::Flags::INTERRUPT());
+
+            assert_eq!(Ok(true), cpu.irq(&mut mem));
+            assert!(cpu.flags.intersects(mos6502::Flags::INTERRUPT()));
+            assert_eq!(0xBEEF, cpu.pc.get());
+        }
+
+        #[test]
+        pub fn irq_is_ignored_if_interrupts_are_disabled() {
+            let (mut cpu, mut mem) = setup_cpu();
+            mem.attach(0xFFFE, Box::new(mem::Fixed::from_contents(vec![0xEF, 0xBE]))).unwrap();
+            cpu.pc.set(0xABCD);
+            cpu.flags.set(mos6502::Flags::INTERRUPT());
+
+            assert_eq!(Ok(false), cpu.irq(&mut mem));
+            assert_eq!(0xABCD, cpu.pc.get());
+        }
+
+        pub fn setup_cpu<'a>() -> (mos6502::Mos6502,mem::Virtual<'a, u16>) {
             let mem = mem::Fixed::new(10);
             let mut vm = mem::Virtual::new();
             vm.attach(mos6502::STACK_START, Box::new(mem)).unwrap();
@@ -3,7 +3,7 @@ use mem::Memory;
 use hw::mos6502::exec;
 use hw::mos6502::{Operand,Mos6502,Flags};
 
-pub fn exec<M>(cpu: &mut Mos6502, mem: &M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory {
+pub fn exec<M>(cpu: &mut Mos6502, mem: &M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     let m = try_log!(op.get_u8(cpu, mem), log);
     let a = cpu.registers.a;
     let c = if cpu.flags.carry() { 1 } else { 0 };
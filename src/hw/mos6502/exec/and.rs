@@ -3,7 +3,7 @@ use mem::Memory;
 use hw::mos6502::exec;
 use hw::mos6502::{Operand,Mos6502,Flags};
 
-pub fn exec<M>(cpu: &mut Mos6502, mem: &M, op: Operand, with_carry: bool, log: &slog::Logger) -> exec::Result where M: Memory {
+pub fn exec<M>(cpu: &mut Mos6502, mem: &M, op: Operand, with_carry: bool, log: &slog::Logger) -> exec::Result where M: Memory<u16> {
     let opv = try_log!(op.get_u8(cpu, mem), log);
     let res = cpu.registers.a & opv;
 
@@ -30,7 +30,7 @@ pub fn exec<M>(cpu: &mut Mos6502, mem: &M, op: Operand, with_carry: bool, log: &
     Ok(())
 }
 
-pub fn xaa<M>(cpu: &mut Mos6502, mem: &M, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory {
+pub fn xaa<M>(cpu: &mut Mos6502, mem: &M, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory<u16> {
     let m = try_log!(op.get_u8(cpu, mem), log);
     let val = cpu.registers.x & m;
     trace!(log, "cpu" => cpu,
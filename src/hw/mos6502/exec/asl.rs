@@ -3,7 +3,7 @@ use mem::Memory;
 use hw::mos6502::exec;
 use hw::mos6502::{Operand,Mos6502,Flags};
 
-pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory {
+pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     let _x = cpu.clock.suspend();
     let b = try_log!(op.get_u8(cpu, mem), log);
     let r = (b << 1) & 0xFE;
@@ -14,7 +14,7 @@ pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger)
         "op" => op;
         "evaluated b << 1 = r");
 
-    try_log!(op.set_u8(cpu, mem, r), log);
+    try_log!(exec::write_modified(cpu, mem, op, b, r), log);
 
     if cpu.flags.set_if(Flags::CARRY(), b & 0x80 != 0) {
         trace!(log, "cpu" => cpu; "setting CARRY");
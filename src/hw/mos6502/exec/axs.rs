@@ -4,7 +4,7 @@ use hw::mos6502::exec;
 use hw::mos6502::{Operand,Mos6502,Flags};
 
 // X := A & X - op ; with sign, zero and carry set as appropriate
-pub fn exec<M>(cpu: &mut Mos6502, mem: &M, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory {
+pub fn exec<M>(cpu: &mut Mos6502, mem: &M, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory<u16> {
     let m = try_log!(op.get_u8(cpu, mem), log);
     let val = (cpu.registers.a & cpu.registers.x).wrapping_sub(m);
     trace!(log, "cpu" => cpu,
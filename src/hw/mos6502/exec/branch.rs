@@ -31,10 +31,10 @@ pub fn if_set(cpu: &mut Mos6502, op: Operand, flags: Flags, log: &slog::Logger)
     }
 }
 
-fn calc_target_and_tick_clock(cpu: &mut Mos6502, offset: i8, log: &slog::Logger) -> u64 {
+fn calc_target_and_tick_clock(cpu: &mut Mos6502, offset: i8, log: &slog::Logger) -> u16 {
     // Check if we're jumping pages
     let current = cpu.pc.get();
-    let target = ((current as i64) + (offset as i64)) as u64;
+    let target = current.wrapping_add(offset as i16 as u16);
     if (current & 0xFF00) == (target & 0xFF00) {
         trace!(log, "cpu" => cpu; "ticking clock for near jump");
         cpu.clock.tick(1);
@@ -5,7 +5,7 @@ use mem::{Memory,MemoryExt};
 use hw::mos6502::exec;
 use hw::mos6502::{Mos6502,Flags};
 
-pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory {
+pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     cpu.pc.advance(1);
     let pc = cpu.pc.get();
     try_log!(cpu.push(mem, ((pc & 0xFF00) >> 8) as u8), log);
@@ -17,7 +17,7 @@ pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, log: &slog::Logger) -> Result<(),
     trace!(log, "cpu" => cpu, "pushed_flags" => new_flags; "pushed flags on stack");
 
     trace!(log, "cpu" => cpu; "jumping to $FFFE");
-    cpu.pc.set(try_log!(mem.get_u16::<LittleEndian>(0xFFFE), log) as u64);
+    cpu.pc.set(try_log!(mem.get_u16::<LittleEndian>(0xFFFE), log));
     Ok(())
 }
 
@@ -68,7 +68,7 @@ mod test {
         assert_eq!(0xBEEF, cpu.pc.get());
     }
 
-    fn init_cpu() -> (Mos6502, mem::Virtual<'static>) {
+    fn init_cpu() -> (Mos6502, mem::Virtual<'static, u16>) {
         let base_memory = mem::Fixed::new(32);
         let stack_memory = mem::Fixed::new(32);
         let vector_memory = mem::Fixed::new(6);
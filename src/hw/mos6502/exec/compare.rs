@@ -3,7 +3,7 @@ use mem::Memory;
 use hw::mos6502::exec;
 use hw::mos6502::{cpu,Mos6502,Flags,Operand};
 
-pub fn exec<M>(cpu: &mut Mos6502, mem: &M, reg: cpu::RegisterName, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory {
+pub fn exec<M>(cpu: &mut Mos6502, mem: &M, reg: cpu::RegisterName, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     let val = try_log!(op.get_u8(cpu, mem), log);
     let r = reg.get(cpu) as i16;
     let t = r - val as i16;
@@ -20,7 +20,7 @@ pub fn reg(cpu: &mut Mos6502, reg: cpu::RegisterName, log: &slog::Logger) -> Res
     Ok(())
 }
 
-pub fn mem<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory {
+pub fn mem<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     let _x = cpu.clock.suspend();
 
     let old_val = try_log!(op.get_u8(cpu, mem), log);
@@ -33,7 +33,7 @@ pub fn mem<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -
         "evaluated mem-- = r");
 
     cpu.flags.set_sign_and_zero(new_val); 
-    try_log!(op.set_u8(cpu, mem, new_val), log);
+    try_log!(exec::write_modified(cpu, mem, op, old_val, new_val), log);
     trace!(log, "cpu" => cpu, "addr" => op.get_addr(cpu, mem).ok(); "stored result");
 
     Ok(())
@@ -89,7 +89,7 @@ mod test {
         assert_eq!(Ok(41), mem.get_u8(0));
     }
 
-    fn init_cpu() -> (Mos6502,mem::Virtual<'static>) {
+    fn init_cpu() -> (Mos6502,mem::Virtual<'static, u16>) {
         let base_memory = mem::Fixed::new(10);
         let mut vm = mem::Virtual::new();
 
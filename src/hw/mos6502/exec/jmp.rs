@@ -3,11 +3,11 @@ use mem::Memory;
 use hw::mos6502::exec;
 use hw::mos6502::{Mos6502,Operand};
 
-pub fn exec<M>(cpu: &mut Mos6502, mem: &M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory {
+pub fn exec<M>(cpu: &mut Mos6502, mem: &M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     let addr = try_log!(op.get_addr(cpu, mem), log);
 
     trace!(log, "cpu" => cpu, "target" => addr; "jumping to ${:04X}", addr);
-    cpu.pc.set(addr as u64);
+    cpu.pc.set(addr);
 
     Ok(())
 }
@@ -35,7 +35,7 @@ mod test {
     pub fn jmp_sets_pc_to_value_at_address_if_indirect_argument() {
         let mut vm = mem::Virtual::new();
         let mut mem = mem::Fixed::new(10);
-        mem.set_u16::<LittleEndian>(5, 0xBEEF).unwrap();
+        mem.set_u16::<LittleEndian>(5u16, 0xBEEF).unwrap();
         vm.attach(0, Box::new(mem)).unwrap();
         let mut cpu = Mos6502::new();
 
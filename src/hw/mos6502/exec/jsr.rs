@@ -2,10 +2,10 @@ use slog;
 use mem::Memory;
 use hw::mos6502::{exec,Mos6502,Operand};
 
-pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory {
+pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     let _x = cpu.clock.suspend();
 
-    let pc = cpu.pc.get() - 1;
+    let pc = cpu.pc.get().wrapping_sub(1);
     let addr = try_log!(op.get_addr(cpu, mem), log);
 
     try_log!(cpu.push(mem, ((pc & 0xFF00) >> 8) as u8), log);
@@ -13,7 +13,7 @@ pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger)
     trace!(log, "cpu" => cpu, "next_pc" => pc; "pushed next PC value on stack");
 
     trace!(log, "cpu" => cpu, "target" => addr; "jumping to ${:04X}", addr);
-    cpu.pc.set(addr as u64);
+    cpu.pc.set(addr);
 
     Ok(())
 }
@@ -44,7 +44,7 @@ mod test {
         assert_eq!(Ok(0xAB), cpu.pull(&mem));
     }
 
-    fn init_cpu() -> (Mos6502,mem::Virtual<'static>) {
+    fn init_cpu() -> (Mos6502,mem::Virtual<'static, u16>) {
         let stack_memory = mem::Fixed::new(32);
         let mut vm = mem::Virtual::new();
 
@@ -3,7 +3,7 @@ use mem::Memory;
 use hw::mos6502::{exec, cpu};
 use hw::mos6502::{Mos6502,Operand};
 
-pub fn exec<M>(cpu: &mut Mos6502, mem: &M, reg: cpu::RegisterName, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory {
+pub fn exec<M>(cpu: &mut Mos6502, mem: &M, reg: cpu::RegisterName, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory<u16> {
     let val = try_log!(op.get_u8(cpu, mem), log);
     reg.set(cpu, val);
     trace!(log, "cpu" => cpu,
@@ -16,7 +16,7 @@ pub fn exec<M>(cpu: &mut Mos6502, mem: &M, reg: cpu::RegisterName, op: Operand,
     Ok(())
 }
 
-pub fn las<M>(cpu: &mut Mos6502, mem: &M, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory {
+pub fn las<M>(cpu: &mut Mos6502, mem: &M, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory<u16> {
     let a = try_log!(op.get_u8(cpu, mem), log);
     let val = a & cpu.registers.sp;
     trace!(log, "cpu" => cpu,
@@ -3,7 +3,7 @@ use mem::Memory;
 use hw::mos6502::exec;
 use hw::mos6502::{Mos6502,Operand,Flags};
 
-pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M : Memory {
+pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     let _x = cpu.clock.suspend();
 
     let n = try_log!(op.get_u8(cpu, mem), log);
@@ -29,7 +29,7 @@ pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger)
         trace!(log, "cpu" => cpu; "clearing ZERO");
     }
 
-    try!(op.set_u8(cpu, mem, m));
+    try_log!(exec::write_modified(cpu, mem, op, n, m), log);
     trace!(log, "cpu" => cpu, "addr" => op.get_addr(cpu, mem).ok(); "storing result");
 
     Ok(())
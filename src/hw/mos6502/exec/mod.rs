@@ -3,7 +3,7 @@ use std::{error,fmt};
 
 use mem;
 
-use hw::mos6502::{cpu,operand,Mos6502,Flags,Instruction};
+use hw::mos6502::{cpu,operand,Mos6502,Flags,Instruction,Operand};
 
 mod adc;
 mod and;
@@ -95,7 +95,7 @@ impl fmt::Display for Error {
 ///
 /// * `inst` - The instruction to execute
 /// * `cpu` - The process on which to execute the instruction
-pub fn dispatch<M>(inst: Instruction, cpu: &mut Mos6502, mem: &mut M, logger: Option<slog::Logger>) -> Result where M: mem::Memory {
+pub fn dispatch<M>(inst: Instruction, cpu: &mut Mos6502, mem: &mut M, logger: Option<slog::Logger>) -> Result where M: mem::Memory<u16> {
     let log = unwrap_logger!(logger).new(o!(
         "inst" => inst
     ));
@@ -223,3 +223,60 @@ pub fn dispatch<M>(inst: Instruction, cpu: &mut Mos6502, mem: &mut M, logger: Op
 
     result
 }
+
+/// Writes the result of a read-modify-write instruction to its operand
+///
+/// While the 6502 modifies the value it has read it writes the unmodified value back, so the
+/// operand is written twice. Memory mapped registers can see both writes.
+fn write_modified<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, old: u8, new: u8) -> operand::Result<()> where M: mem::Memory<u16> {
+    try!(op.set_u8(cpu, mem, old));
+    op.set_u8(cpu, mem, new)
+}
+
+#[cfg(test)]
+mod test {
+    use mem;
+    use mem::Memory;
+    use hw::mos6502::exec;
+    use hw::mos6502::{Mos6502,Instruction,Operand};
+
+    /// A memory that records the address and value of every write made to it
+    struct RecordingMemory {
+        mem: mem::Fixed,
+        writes: Vec<(u16, u8)>
+    }
+
+    impl mem::Memory<u16> for RecordingMemory {
+        fn len(&self) -> u64 { self.mem.len() }
+
+        fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+            self.mem.get_u8(addr)
+        }
+
+        fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+            self.writes.push((addr, val));
+            self.mem.set_u8(addr, val)
+        }
+    }
+
+    #[test]
+    pub fn read_modify_write_instructions_write_the_old_value_then_the_new_one() {
+        let tests = [
+            (Instruction::INC(Operand::Absolute(0x0004)), 0x43),
+            (Instruction::DEC(Operand::Absolute(0x0004)), 0x41),
+            (Instruction::ASL(Operand::Absolute(0x0004)), 0x84),
+            (Instruction::LSR(Operand::Absolute(0x0004)), 0x21),
+            (Instruction::ROL(Operand::Absolute(0x0004)), 0x84),
+            (Instruction::ROR(Operand::Absolute(0x0004)), 0x21)
+        ];
+
+        for &(inst, result) in tests.iter() {
+            let mut cpu = Mos6502::new();
+            let mut mem = RecordingMemory { mem: mem::Fixed::new(0x10), writes: Vec::new() };
+            mem.mem.set_u8(0x0004u64, 0x42).unwrap();
+
+            exec::dispatch(inst, &mut cpu, &mut mem, None).unwrap();
+            assert_eq!(vec![(0x0004, 0x42), (0x0004, result)], mem.writes);
+        }
+    }
+}
@@ -3,7 +3,7 @@ use mem::Memory;
 use hw::mos6502::exec;
 use hw::mos6502::{Mos6502,Operand};
 
-pub fn exec<M>(cpu: &mut Mos6502, mem: &M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M : Memory {
+pub fn exec<M>(cpu: &mut Mos6502, mem: &M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     let m = try_log!(op.get_u8(cpu, mem), log);
     let v = cpu.registers.a | m;
     trace!(log, "cpu" => cpu,
@@ -55,7 +55,7 @@ mod test {
         assert_eq!(0b11111111, cpu.registers.a);
     }
 
-    fn init_cpu() -> (Mos6502,mem::Virtual<'static>) {
+    fn init_cpu() -> (Mos6502,mem::Virtual<'static, u16>) {
         let base_memory = mem::Fixed::new(10);
         let mut vm = mem::Virtual::new();
 
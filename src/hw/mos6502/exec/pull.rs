@@ -3,7 +3,7 @@ use mem::Memory;
 use hw::mos6502::{exec,cpu};
 use hw::mos6502::Mos6502;
 
-pub fn exec<M>(cpu: &mut Mos6502, mem: &M, r: cpu::RegisterName, log: &slog::Logger) -> Result<(), exec::Error> where M : Memory {
+pub fn exec<M>(cpu: &mut Mos6502, mem: &M, r: cpu::RegisterName, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     let val = try_log!(cpu.pull(mem), log);
     trace!(log, "cpu" => cpu,
         "from" => cpu.registers.sp,
@@ -80,7 +80,7 @@ mod test {
         assert_eq!(cpu::Flags::SIGN() | cpu::Flags::RESERVED(), cpu.flags);
     }
 
-    fn init_cpu() -> (Mos6502,mem::Virtual<'static>) {
+    fn init_cpu() -> (Mos6502,mem::Virtual<'static, u16>) {
         let stack_memory = mem::Fixed::new(32);
         let mut vm = mem::Virtual::new();
 
@@ -3,7 +3,7 @@ use mem::Memory;
 use hw::mos6502::{exec, cpu};
 use hw::mos6502::Mos6502;
 
-pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, r: cpu::RegisterName, log: &slog::Logger) -> Result<(), exec::Error> where M : Memory {
+pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, r: cpu::RegisterName, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     let val = if r == cpu::RegisterName::P {
         // http://visual6502.org/wiki/index.php?title=6502_BRK_and_B_bit
         // Set B bit on the value before pushing it
@@ -45,7 +45,7 @@ mod test {
         assert_eq!(Ok(0b10110010), cpu.pull(&mem));
     }
 
-    fn init_cpu() -> (Mos6502,mem::Virtual<'static>) {
+    fn init_cpu() -> (Mos6502,mem::Virtual<'static, u16>) {
         let stack_memory = mem::Fixed::new(32);
         let mut vm = mem::Virtual::new();
 
@@ -3,7 +3,7 @@ use mem::Memory;
 use hw::mos6502::exec;
 use hw::mos6502::{Flags,Mos6502};
 
-pub fn from_interrupt<M>(cpu: &mut Mos6502, mem: &M, log: &slog::Logger) -> Result<(), exec::Error> where M : Memory {
+pub fn from_interrupt<M>(cpu: &mut Mos6502, mem: &M, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     let p = try_log!(cpu.pull(mem), log);
     let flags = Flags::new(p);
     trace!(log, "cpu" => cpu,
@@ -11,8 +11,8 @@ pub fn from_interrupt<M>(cpu: &mut Mos6502, mem: &M, log: &slog::Logger) -> Resu
         "flags" => flags;
         "pulled flags from stack");
 
-    let l = try_log!(cpu.pull(mem), log) as u64;
-    let h = try_log!(cpu.pull(mem), log) as u64;
+    let l = try_log!(cpu.pull(mem), log) as u16;
+    let h = try_log!(cpu.pull(mem), log) as u16;
     let pc = (h << 8) | l;
     trace!(log, "cpu" => cpu,
         "from" => cpu.registers.sp - 1,
@@ -25,10 +25,10 @@ pub fn from_interrupt<M>(cpu: &mut Mos6502, mem: &M, log: &slog::Logger) -> Resu
     Ok(())
 }
 
-pub fn from_sub<M>(cpu: &mut Mos6502, mem: &M, log: &slog::Logger) -> Result<(), exec::Error> where M : Memory {
-    let l = try_log!(cpu.pull(mem), log) as u64;
-    let h = try_log!(cpu.pull(mem), log) as u64;
-    let pc = ((h << 8) | l) + 1;
+pub fn from_sub<M>(cpu: &mut Mos6502, mem: &M, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
+    let l = try_log!(cpu.pull(mem), log) as u16;
+    let h = try_log!(cpu.pull(mem), log) as u16;
+    let pc = ((h << 8) | l).wrapping_add(1);
     trace!(log, "cpu" => cpu,
         "from" => cpu.registers.sp - 1,
         "pc" => pc;
@@ -86,7 +86,7 @@ mod test {
         assert_eq!(cpu.pc.get(), 0xABCE);
     }
 
-    fn init_cpu() -> (Mos6502,mem::Virtual<'static>) {
+    fn init_cpu() -> (Mos6502,mem::Virtual<'static, u16>) {
         let stack_memory = mem::Fixed::new(32);
         let mut vm = mem::Virtual::new();
 
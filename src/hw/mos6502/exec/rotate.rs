@@ -3,15 +3,15 @@ use mem::Memory;
 use hw::mos6502::exec;
 use hw::mos6502::{Mos6502,Operand,Flags};
 
-pub fn left<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M : Memory {
+pub fn left<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     exec(cpu, mem, op, true, log)
 }
 
-pub fn right<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M : Memory {
+pub fn right<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     exec(cpu, mem, op, false, log)
 }
 
-fn exec<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, left: bool, log: &slog::Logger) -> Result<(), exec::Error> where M : Memory {
+fn exec<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, left: bool, log: &slog::Logger) -> Result<(), exec::Error> where M: Memory<u16> {
     let _x = cpu.clock.suspend();
 
     let n = try_log!(op.get_u8(cpu, mem), log);
@@ -39,7 +39,7 @@ fn exec<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, left: bool, log: &slog::
         "carry_in" => carry_byte;
         "rotated mem {}", if left { "left" } else { "right" });
 
-    try_log!(op.set_u8(cpu, mem, b), log);
+    try_log!(exec::write_modified(cpu, mem, op, n, b), log);
     trace!(log, "cpu" => cpu, "addr" => addr_str!(op.get_addr(cpu, mem)); "stored result");
 
     // Set the flags
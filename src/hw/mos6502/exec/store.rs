@@ -3,7 +3,7 @@ use mem::Memory;
 use hw::mos6502::{exec, cpu};
 use hw::mos6502::{Mos6502,Operand};
 
-pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, reg: cpu::RegisterName, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory {
+pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, reg: cpu::RegisterName, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory<u16> {
     let _x = cpu.clock.suspend();
 
     let val = reg.get(cpu);
@@ -17,7 +17,7 @@ pub fn exec<M>(cpu: &mut Mos6502, mem: &mut M, reg: cpu::RegisterName, op: Opera
     Ok(())
 }
 
-pub fn ahx<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory {
+pub fn ahx<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory<u16> {
     let h = ((try_log!(op.get_addr(cpu, mem), log) & 0xFF00) >> 8) as u8;
     let val = cpu.registers.a & cpu.registers.x & h;
     trace!(log, "cpu" => cpu,
@@ -38,7 +38,7 @@ pub fn ahx<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -
     Ok(())
 }
 
-pub fn sax<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory {
+pub fn sax<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory<u16> {
     let val = cpu.registers.a & cpu.registers.x;
     trace!(log, "cpu" => cpu,
         "a" => cpu.registers.a,
@@ -55,7 +55,7 @@ pub fn sax<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -
     Ok(())
 }
 
-pub fn sh<M>(cpu: &mut Mos6502, mem: &mut M, reg: cpu::RegisterName, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory {
+pub fn sh<M>(cpu: &mut Mos6502, mem: &mut M, reg: cpu::RegisterName, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory<u16> {
     let h = ((try_log!(op.get_addr(cpu, mem), log) & 0xFF00) >> 8) as u8;
     let r = reg.get(cpu);
     let val = r & h;
@@ -76,7 +76,7 @@ pub fn sh<M>(cpu: &mut Mos6502, mem: &mut M, reg: cpu::RegisterName, op: Operand
     Ok(())
 }
 
-pub fn tas<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory {
+pub fn tas<M>(cpu: &mut Mos6502, mem: &mut M, op: Operand, log: &slog::Logger) -> exec::Result where M: Memory<u16> {
     let val = cpu.registers.a & cpu.registers.x;
     trace!(log, "cpu" => cpu,
         "a" => cpu.registers.a,
@@ -121,7 +121,7 @@ mod test {
         cpu.registers.a = 42;
         store::exec(&mut cpu, &mut mem, cpu::RegisterName::A, Operand::Absolute(5), &slog::Logger::root(slog::Discard, o!())).unwrap();
 
-        assert_eq!(Ok(42), mem.get_u8(5));
+        assert_eq!(Ok(42), mem.get_u8(5u16));
     }
 
     #[test]
@@ -135,7 +135,7 @@ mod test {
         cpu.registers.x = 0xF0;
         store::sh(&mut cpu, &mut vm, cpu::RegisterName::X, Operand::Absolute(0x3C01), &slog::Logger::root(slog::Discard, o!())).unwrap();
 
-        assert_eq!(Ok(0x30), vm.get_u8(0x3C01));
+        assert_eq!(Ok(0x30), vm.get_u8(0x3C01u16));
     }
 
     #[test]
@@ -151,7 +151,7 @@ mod test {
         store::tas(&mut cpu, &mut vm, Operand::Absolute(0x1C01), &slog::Logger::root(slog::Discard, o!())).unwrap();
 
         assert_eq!(0x30, cpu.registers.sp);
-        assert_eq!(Ok(0x10), vm.get_u8(0x1C01));
+        assert_eq!(Ok(0x10), vm.get_u8(0x1C01u16));
     }
 
     #[test]
@@ -166,7 +166,7 @@ mod test {
         cpu.registers.x = 0xF0;
         store::ahx(&mut cpu, &mut vm, Operand::Absolute(0x3C01), &slog::Logger::root(slog::Discard, o!())).unwrap();
 
-        assert_eq!(Ok(0x30), vm.get_u8(0x3C01));
+        assert_eq!(Ok(0x30), vm.get_u8(0x3C01u16));
     }
 
     #[test]
@@ -178,6 +178,6 @@ mod test {
         cpu.registers.x = 0xF0;
         store::sax(&mut cpu, &mut mem, Operand::Absolute(5), &slog::Logger::root(slog::Discard, o!())).unwrap();
 
-        assert_eq!(Ok(0x30), mem.get_u8(5));
+        assert_eq!(Ok(0x30), mem.get_u8(5u16));
     }
 }
@@ -98,7 +98,7 @@ impl Instruction {
     /// # Arguments
     ///
     /// * `cpu` - The process on which to execute the instruction
-    pub fn exec<M>(self, cpu: &mut Mos6502, mem: &mut M, logger: Option<slog::Logger>) -> Result<(), exec::Error> where M: mem::Memory {
+    pub fn exec<M>(self, cpu: &mut Mos6502, mem: &mut M, logger: Option<slog::Logger>) -> Result<(), exec::Error> where M: mem::Memory<u16> {
         exec::dispatch(self, cpu, mem, logger)
     }
 
@@ -477,7 +477,7 @@ impl Instruction {
     }
 
     /// Get a string in the form of the nestest "golden log" output
-    pub fn get_log_string<M>(&self, cpu: &mut Mos6502, mem: &M) -> operand::Result<String> where M: mem::Memory {
+    pub fn get_log_string<M>(&self, cpu: &mut Mos6502, mem: &M) -> operand::Result<String> where M: mem::Memory<u16> {
         use instr::Instruction as InstrTrait;
 
         Ok(format!(
@@ -492,7 +492,7 @@ impl Instruction {
                         match op {
                             // Technically this isn't the way the indirect address is calculated,
                             // but it is now nestest.log displays it
-                            Operand::Indirect(addr) => format!(" {} = {:04X}", op, try!(mem.get_u16::<LittleEndian>(addr as u64))),
+                            Operand::Indirect(addr) => format!(" {} = {:04X}", op, try!(mem.get_u16::<LittleEndian>(addr))),
                             _                       => format!(" {}", op)
                         },
                 _ => match self.operand() {
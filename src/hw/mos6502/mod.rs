@@ -16,7 +16,13 @@ pub mod cpu;
 pub mod operand;
 
 /// Indicates the start of the MOS 6502 Stack
-const STACK_START   : u64 = 0x0100;
+const STACK_START   : u16 = 0x0100;
+
+/// Indicates the location of the address to jump to when a Non-Maskable Interrupt occurs
+const NMI_VECTOR    : u16 = 0xFFFA;
+
+/// Indicates the location of the address to jump to when an Interrupt Request occurs
+const IRQ_VECTOR    : u16 = 0xFFFE;
 
 #[cfg(test)]
 pub mod tests {
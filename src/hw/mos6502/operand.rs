@@ -108,7 +108,7 @@ impl Operand {
     /// # Arguments
     ///
     /// * `cpu` - The cpu from which to get the operand value
-    pub fn get_u8<M>(&self, cpu: &mut Mos6502, mem: &M) -> Result<u8> where M: mem::Memory {
+    pub fn get_u8<M>(&self, cpu: &mut Mos6502, mem: &M) -> Result<u8> where M: mem::Memory<u16> {
         Ok(match self {
             &Operand::Immediate(n)      => n,
             &Operand::Accumulator       => cpu.registers.a,
@@ -117,7 +117,7 @@ impl Operand {
                 if oops {
                     cpu.clock.tick(1);
                 }
-                try!(mem.get_u8(addr as u64))
+                try!(mem.get_u8(addr))
             }
         })
     }
@@ -138,7 +138,7 @@ impl Operand {
     ///
     /// * `cpu` - The cpu on which to set the operand value
     /// * `val` - The value to set the operand to
-    pub fn set_u8<M>(&self, cpu: &mut Mos6502, mem: &mut M, val: u8) -> Result<()> where M: mem::Memory {
+    pub fn set_u8<M>(&self, cpu: &mut Mos6502, mem: &mut M, val: u8) -> Result<()> where M: mem::Memory<u16> {
         match self {
             &Operand::Accumulator        => { cpu.registers.a = val; Ok(()) },
             _                            => {
@@ -146,7 +146,7 @@ impl Operand {
                 if oops {
                     cpu.clock.tick(1);
                 }
-                Ok(try!(mem.set_u8(addr as u64, val)))
+                Ok(try!(mem.set_u8(addr, val)))
             }
         }
     }
@@ -156,7 +156,7 @@ impl Operand {
     /// # Arguments
     ///
     /// * `cpu` - The cpu on which to get the operand value
-    pub fn get_addr<M>(&self, cpu: &Mos6502, mem: &M) -> Result<u16> where M: mem::Memory {
+    pub fn get_addr<M>(&self, cpu: &Mos6502, mem: &M) -> Result<u16> where M: mem::Memory<u16> {
         match self.get_addr_impl(cpu, mem) {
             Ok((addr, _)) => Ok(addr),
             Err(e) => Err(e)
@@ -164,20 +164,20 @@ impl Operand {
     }
 
     /// Get a string in the form of the nestest "golden log" output
-    pub fn get_log_string<M>(&self, cpu: &mut Mos6502, mem: &M) -> Result<String> where M: mem::Memory {
+    pub fn get_log_string<M>(&self, cpu: &mut Mos6502, mem: &M) -> Result<String> where M: mem::Memory<u16> {
         let _ = cpu.clock.suspend(); // Don't tick the clock while getting the log message
 
         Ok(match self {
-            &Operand::Offset(offset) => format!("${:04X}", ((cpu.pc.get() as i32) + (offset as i32)) as u16),
+            &Operand::Offset(offset) => format!("${:04X}", cpu.pc.get().wrapping_add(offset as i16 as u16)),
             &Operand::PreIndexedIndirect(addr) => {
                 let (preindex_addr, _) = try!(self.get_addr_impl(cpu, mem));
-                let eaddr = (addr as u64 + cpu.registers.x as u64) & 0xFF;
+                let eaddr = addr.wrapping_add(cpu.registers.x);
                 format!("{} @ {:02X} = {:04X} = {:02X}", self, eaddr, preindex_addr, try!(self.get_u8(cpu, mem)))
             },
             &Operand::PostIndexedIndirect(addr) => {
                 let (preindex_addr, _) = try!(self.get_addr_impl(cpu, mem));
-                let low = try!(mem.get_u8(addr as u64)) as u64;
-                let high = try!(mem.get_u8((addr as u64 + 1) & 0xFF)) as u64;
+                let low = try!(mem.get_u8(addr as u16)) as u16;
+                let high = try!(mem.get_u8(addr.wrapping_add(1) as u16)) as u16;
                 let eaddr = low | (high << 8);
                 format!("{} = {:04X} @ {:04X} = {:02X}", self, eaddr, preindex_addr, try!(self.get_u8(cpu, mem)))
             },
@@ -191,48 +191,46 @@ impl Operand {
             },
             &op if op.has_addr() => {
                 let (addr, _) = try!(op.get_addr_impl(cpu, mem));
-                let value = try!(mem.get_u8(addr as u64));
+                let value = try!(mem.get_u8(addr));
                 format!("{} = {:02X}", op, value)
             },
             &op => format!("{}", op),
         })
     }
 
-    fn get_addr_impl<M>(&self, cpu: &Mos6502, mem: &M) -> Result<(u16,bool)> where M: mem::Memory {
+    fn get_addr_impl<M>(&self, cpu: &Mos6502, mem: &M) -> Result<(u16,bool)> where M: mem::Memory<u16> {
         Ok(match self {
             &Operand::Absolute(addr)             => (addr, false),
             &Operand::Indirect(addr)             => {
                 // Indirect accesses can't leave the page, they wrap around
-                let low = try!(mem.get_u8(addr as u64)) as u64;
-                let high = try!(mem.get_u8((addr as u64 & 0xFF00) | ((addr as u64 + 1) & 0x00FF))) as u64;
-                ((low | (high << 8)) as u16, false)
+                let low = try!(mem.get_u8(addr)) as u16;
+                let high = try!(mem.get_u8((addr & 0xFF00) | (addr.wrapping_add(1) & 0x00FF))) as u16;
+                (low | (high << 8), false)
             },
             &Operand::Indexed(addr, r)           => {
-                let mut eaddr = addr as u64 + r.get(cpu) as u64;
-                if addr < 0x0100 {
+                let eaddr = if addr < 0x0100 {
                     // Zero-page accesses can't leave the zero page, they wrap around
-                    eaddr = eaddr & 0xFF;
+                    (addr as u8).wrapping_add(r.get(cpu)) as u16
                 } else {
-                    eaddr = eaddr & 0xFFFF;
-                }
-                (eaddr as u16, oops_cycle(addr as u64, eaddr))
+                    addr.wrapping_add(r.get(cpu) as u16)
+                };
+                (eaddr, oops_cycle(addr, eaddr))
             },
             &Operand::PreIndexedIndirect(addr)   => {
                 // Indirect accesses can't leave the zero page, they wrap around
-                let mut eaddr = (addr as u64 + cpu.registers.x as u64) & 0xFF;
-                let low = try!(mem.get_u8(eaddr)) as u16;
-                eaddr = (eaddr + 1) & 0xFF;
-                let high = try!(mem.get_u8(eaddr)) as u16;
+                let eaddr = addr.wrapping_add(cpu.registers.x);
+                let low = try!(mem.get_u8(eaddr as u16)) as u16;
+                let high = try!(mem.get_u8(eaddr.wrapping_add(1) as u16)) as u16;
                 ((high << 8) | low, false)
             },
             &Operand::PostIndexedIndirect(addr)  => {
                 // Indirect accesses can't leave the page, they wrap around
-                let low = try!(mem.get_u8(addr as u64)) as u64;
-                let high = try!(mem.get_u8((addr as u64 + 1) & 0xFF)) as u64;
+                let low = try!(mem.get_u8(addr as u16)) as u16;
+                let high = try!(mem.get_u8(addr.wrapping_add(1) as u16)) as u16;
 
                 let original_addr = low | (high << 8);
-                let final_addr = original_addr + cpu.registers.y as u64;
-                ((final_addr & 0xFFFF) as u16, oops_cycle(original_addr, final_addr))
+                let final_addr = original_addr.wrapping_add(cpu.registers.y as u16);
+                (final_addr, oops_cycle(original_addr, final_addr))
             },
             _                                   => return Err(Error::NonAddressOperand)
         })
@@ -240,7 +238,7 @@ impl Operand {
 
 }
 
-fn oops_cycle(original_addr: u64, actual_addr: u64) -> bool {
+fn oops_cycle(original_addr: u16, actual_addr: u16) -> bool {
     (original_addr & 0xFF00) != (actual_addr & 0xFF00)
 }
 
@@ -310,7 +308,7 @@ mod test {
             let mut mem = mem::Fixed::new(10);
             let mut cpu = Mos6502::new();
             assert!(Operand::Absolute(2).set_u8(&mut cpu, &mut mem, 24).is_ok());
-            let val = mem.get_u8(2).unwrap();
+            let val = mem.get_u8(2u16).unwrap();
             assert_eq!(val, 24);
         }
 
@@ -320,7 +318,7 @@ mod test {
             let mut cpu = Mos6502::new();
             cpu.registers.x = 1;
             assert!(Operand::Indexed(2, cpu::RegisterName::X).set_u8(&mut cpu, &mut mem, 24).is_ok());
-            let val = mem.get_u8(3).unwrap();
+            let val = mem.get_u8(3u16).unwrap();
             assert_eq!(val, 24);
         }
 
@@ -330,7 +328,7 @@ mod test {
             let mut cpu = Mos6502::new();
             cpu.registers.y = 1;
             assert!(Operand::Indexed(2, cpu::RegisterName::Y).set_u8(&mut cpu, &mut mem, 24).is_ok());
-            let val = mem.get_u8(3).unwrap();
+            let val = mem.get_u8(3u16).unwrap();
             assert_eq!(val, 24);
         }
 
@@ -361,7 +359,7 @@ mod test {
         pub fn get_absolute_returns_value_from_memory_address() {
             let mut mem = mem::Fixed::new(10);
             let mut cpu = Mos6502::new();
-            assert!(mem.set_u8(4, 42).is_ok());
+            assert!(mem.set_u8(4u16, 42).is_ok());
             let val = Operand::Absolute(4).get_u8(&mut cpu, &mem).unwrap();
             assert_eq!(val, 42);
         }
@@ -370,7 +368,7 @@ mod test {
         pub fn get_indexed_x_adds_x_to_address() {
             let mut mem = mem::Fixed::new(10);
             let mut cpu = Mos6502::new();
-            assert!(mem.set_u8(4, 42).is_ok());
+            assert!(mem.set_u8(4u16, 42).is_ok());
             cpu.registers.x = 2;
             let val = Operand::Indexed(2, cpu::RegisterName::X).get_u8(&mut cpu, &mem).unwrap();
             assert_eq!(val, 42);
@@ -380,7 +378,7 @@ mod test {
         pub fn get_indexed_y_adds_y_to_address() {
             let mut mem = mem::Fixed::new(10);
             let mut cpu = Mos6502::new();
-            assert!(mem.set_u8(4, 42).is_ok());
+            assert!(mem.set_u8(4u16, 42).is_ok());
             cpu.registers.y = 2;
             let val = Operand::Indexed(2, cpu::RegisterName::Y).get_u8(&mut cpu, &mem).unwrap();
             assert_eq!(val, 42);
@@ -390,8 +388,8 @@ mod test {
         pub fn get_preindexed_indirect_works() {
             let mut mem = mem::Fixed::new(10);
             let mut cpu = Mos6502::new();
-            assert!(mem.set_u8(8, 42).is_ok()); // Value
-            assert!(mem.set_u16::<LittleEndian>(6, 8).is_ok()); // Indirect Memory Address
+            assert!(mem.set_u8(8u16, 42).is_ok()); // Value
+            assert!(mem.set_u16::<LittleEndian>(6u16, 8).is_ok()); // Indirect Memory Address
             cpu.registers.x = 2;
             let val = Operand::PreIndexedIndirect(4).get_u8(&mut cpu, &mem).unwrap();
             assert_eq!(val, 42);
@@ -401,8 +399,8 @@ mod test {
         pub fn get_postindexed_indirect_works() {
             let mut mem = mem::Fixed::new(10);
             let mut cpu = Mos6502::new();
-            assert!(mem.set_u8(8, 42).is_ok()); // Value
-            assert!(mem.set_u16::<LittleEndian>(2, 6).is_ok()); // Indirect Memory Address
+            assert!(mem.set_u8(8u16, 42).is_ok()); // Value
+            assert!(mem.set_u16::<LittleEndian>(2u16, 6).is_ok()); // Indirect Memory Address
             cpu.registers.y = 2;
             let val = Operand::PostIndexedIndirect(2).get_u8(&mut cpu, &mem).unwrap();
             assert_eq!(val, 42);
@@ -435,7 +433,7 @@ mod test {
             let mut mem = mem::Virtual::new();
             mem.attach(0x0000, Box::new(mem::Fixed::new(0x20))).unwrap();
             mem.attach(0x01F0, Box::new(mem::Fixed::new(0x20))).unwrap();
-            mem.set_u16::<LittleEndian>(0x0000, 0x01F0).unwrap();
+            mem.set_u16::<LittleEndian>(0x0000u16, 0x01F0).unwrap();
             let mut cpu = Mos6502::new();
             cpu.registers.y = 2;
             cpu.clock.set(41);
@@ -448,7 +446,7 @@ mod test {
             let mut mem = mem::Virtual::new();
             mem.attach(0x0000, Box::new(mem::Fixed::new(0x20))).unwrap();
             mem.attach(0x01F0, Box::new(mem::Fixed::new(0x20))).unwrap();
-            mem.set_u16::<LittleEndian>(0x0000, 0x01FF).unwrap();
+            mem.set_u16::<LittleEndian>(0x0000u16, 0x01FF).unwrap();
             let mut cpu = Mos6502::new();
             cpu.registers.y = 2;
             cpu.clock.set(41);
@@ -567,7 +567,7 @@ pub fn ign() {
 
 struct TestContext<'a> {
     cpu: mos6502::Mos6502,
-    mem: mem::Virtual<'a>,
+    mem: mem::Virtual<'a, u16>,
     errors: Vec<String>
 }
 
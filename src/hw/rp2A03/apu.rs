@@ -0,0 +1,507 @@
+use std::cell::Cell;
+
+use slog;
+
+use audio::{BlipBuffer,NesFilters};
+use clock;
+use mem;
+use hw::expansion_audio::ExpansionAudio;
+use hw::rp2A03::pulse::Pulse;
+use hw::rp2A03::triangle::Triangle;
+use hw::rp2A03::noise::Noise;
+use hw::rp2A03::dmc::Dmc;
+
+/// The frequency of the NTSC NES CPU, in Hz
+pub const CPU_FREQUENCY: f64 = 1789773.0;
+
+/// The sample rate used for the sample stream, unless one is specified
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+/// The number of CPU cycles in each audio frame, roughly one video frame
+const AUDIO_FRAME_CYCLES: u64 = 29781;
+
+/// Identifies the sequence used by the frame counter
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum FrameMode {
+    /// Four steps per frame, raising an IRQ at the end of the frame unless inhibited
+    FourStep,
+
+    /// Five steps per frame, with no IRQ
+    FiveStep
+}
+
+/// Represents the Audio Processing Unit (APU) of the Ricoh RP2A03
+///
+/// The APU is clocked by the CPU, and is run in batches in the same way as the PPU: after the CPU
+/// executes an instruction, `step` runs the APU until it has caught up. Each CPU cycle, the output
+/// of the channels is mixed, and changes in the mixed output are fed to a band-limited resampler.
+/// The resampled output is passed through the NES's output filters to produce samples at the
+/// sample rate.
+///
+/// Samples are completed once per audio frame, and held until they are taken. At most a second of
+/// samples is held, older samples are dropped if they aren't taken in time.
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_mode: FrameMode,
+    frame_irq_inhibit: bool,
+    frame_irq: Cell<bool>,
+    frame_cycle: u64,
+    pending_frame_reset: Option<u64>,
+    clock: clock::Clock,
+    blip: BlipBuffer,
+    filters: NesFilters,
+    last_output: f32,
+    audio_frame_start: u64,
+    samples: Vec<f32>,
+    max_samples: usize,
+    log: slog::Logger
+}
+
+impl Apu {
+    pub fn new(logger: Option<slog::Logger>) -> Apu {
+        Apu {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::new(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            frame_mode: FrameMode::FourStep,
+            frame_irq_inhibit: false,
+            frame_irq: Cell::new(false),
+            frame_cycle: 0,
+            pending_frame_reset: None,
+            clock: clock::Clock::new(),
+            blip: BlipBuffer::new(CPU_FREQUENCY, DEFAULT_SAMPLE_RATE as f64),
+            filters: NesFilters::new(DEFAULT_SAMPLE_RATE as f32),
+            last_output: 0.0,
+            audio_frame_start: 0,
+            samples: Vec::new(),
+            max_samples: DEFAULT_SAMPLE_RATE as usize,
+            log: unwrap_logger!(logger).new(o!("component" => "apu"))
+        }
+    }
+
+    /// Sets the rate, in Hz, of the samples produced by the APU
+    ///
+    /// Any samples that haven't been taken yet are discarded
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        self.blip = BlipBuffer::new(CPU_FREQUENCY, rate as f64);
+        self.filters = NesFilters::new(rate as f32);
+        self.last_output = 0.0;
+        self.audio_frame_start = self.clock.get();
+        self.samples.clear();
+        self.max_samples = rate as usize;
+    }
+
+    /// Removes and returns the samples produced since the last call
+    ///
+    /// The high-pass filters remove the DC offset of the mixer, so samples are centered on 0.0 and
+    /// range from roughly -1.0 to 1.0
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.end_audio_frame();
+        ::std::mem::replace(&mut self.samples, Vec::new())
+    }
+
+    /// Returns a value indicating if the APU is asserting the IRQ line
+    pub fn irq(&self) -> bool {
+        self.frame_irq.get() || self.dmc.irq
+    }
+
+    /// Provides the byte read by a DMC DMA, from the address returned by `step`
+    pub fn dmc_fill(&mut self, val: u8) {
+        self.dmc.fill(val);
+    }
+
+    /// Reads the status register ($4015)
+    ///
+    /// Reading the status register clears the frame IRQ flag, so it is stored in a `Cell`
+    pub fn read_status(&self) -> u8 {
+        let mut val = 0;
+        if self.pulse1.length.active() { val |= 0x01; }
+        if self.pulse2.length.active() { val |= 0x02; }
+        if self.triangle.length.active() { val |= 0x04; }
+        if self.noise.length.active() { val |= 0x08; }
+        if self.dmc.active() { val |= 0x10; }
+        if self.frame_irq.get() { val |= 0x40; }
+        if self.dmc.irq { val |= 0x80; }
+        self.frame_irq.set(false);
+        val
+    }
+
+    /// Writes one of the APU registers, `addr` is the CPU address of the register ($4000-$4017)
+    pub fn write_register(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x4000 ... 0x4003 => self.pulse1.write(addr - 0x4000, val),
+            0x4004 ... 0x4007 => self.pulse2.write(addr - 0x4004, val),
+            0x4008 ... 0x400B => self.triangle.write(addr - 0x4008, val),
+            0x400C ... 0x400F => self.noise.write(addr - 0x400C, val),
+            0x4010 ... 0x4013 => self.dmc.write(addr - 0x4010, val),
+            0x4015 => {
+                self.dmc.set_enabled(val & 0x10 != 0);
+                self.pulse1.length.set_enabled(val & 0x01 != 0);
+                self.pulse2.length.set_enabled(val & 0x02 != 0);
+                self.triangle.length.set_enabled(val & 0x04 != 0);
+                self.noise.length.set_enabled(val & 0x08 != 0);
+            },
+            0x4017 => {
+                self.frame_mode = if val & 0x80 == 0 { FrameMode::FourStep } else { FrameMode::FiveStep };
+                self.frame_irq_inhibit = val & 0x40 != 0;
+                if self.frame_irq_inhibit {
+                    self.frame_irq.set(false);
+                }
+
+                // The sequencer is reset 3 or 4 CPU cycles after the write, depending on whether
+                // the write occurs during an APU cycle
+                let delay = if self.clock.get() % 2 == 0 { 3 } else { 4 };
+                self.pending_frame_reset = Some(delay);
+            },
+            _ => {
+                trace!(self.log, "addr" => format!("${:04X}", addr); "write to unsupported APU register");
+            }
+        }
+    }
+
+    /// Gets the number of CPU cycles the APU has been run for
+    pub fn cycles(&self) -> u64 {
+        self.clock.get()
+    }
+
+    /// Runs the APU until it has caught up with the provided CPU cycle, or the DMC needs to read
+    /// a byte from memory
+    ///
+    /// If the DMC needs a byte, the address to read is returned, and the DMA starts on the cycle
+    /// given by `cycles`. The caller must read it, provide
+    /// it through `dmc_fill` and call `step` again. The DMA stalls the CPU, so the caller should
+    /// also advance the CPU (and the cycle provided to `step`) accordingly. `step_with_dma` does
+    /// all of this for a `DmcBus`.
+    ///
+    /// If the cartridge has an expansion sound chip, it is clocked along with the APU and its
+    /// output is mixed with the APU's output.
+    pub fn step(&mut self, cpu_cycle: u64, mut expansion: Option<&mut ExpansionAudio>) -> Option<u16> {
+        while self.clock.get() < cpu_cycle {
+            let expansion_output = match expansion {
+                Some(ref mut chip) => {
+                    chip.clock();
+                    chip.output()
+                },
+                None => 0.0
+            };
+            self.tick(expansion_output);
+            if let Some(addr) = self.dmc.fetch_address() {
+                return Some(addr);
+            }
+        }
+        None
+    }
+
+    /// Gets the current output of the mixer
+    ///
+    /// This uses the non-linear mixing formulas from the NesDev wiki, rather than a lookup table
+    pub fn output(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out = if pulse == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulse + 100.0) };
+
+        let tnd = (self.triangle.output() as f32) / 8227.0 +
+            (self.noise.output() as f32) / 12241.0 +
+            (self.dmc.output() as f32) / 22638.0;
+        let tnd_out = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+
+        pulse_out + tnd_out
+    }
+
+    /// Emulates a single CPU cycle, `expansion_output` is the output of any expansion sound chip
+    fn tick(&mut self, expansion_output: f32) {
+        self.clock_frame_counter();
+
+        // The pulse timers are clocked every other CPU cycle
+        if self.clock.get() % 2 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        self.clock.tick(1);
+        self.generate_sample(expansion_output);
+        if self.clock.get() - self.audio_frame_start >= AUDIO_FRAME_CYCLES {
+            self.end_audio_frame();
+        }
+    }
+
+    fn clock_frame_counter(&mut self) {
+        if let Some(delay) = self.pending_frame_reset {
+            if delay == 0 {
+                self.pending_frame_reset = None;
+                self.frame_cycle = 0;
+                if self.frame_mode == FrameMode::FiveStep {
+                    self.quarter_frame();
+                    self.half_frame();
+                }
+            } else {
+                self.pending_frame_reset = Some(delay - 1);
+            }
+        }
+
+        self.frame_cycle += 1;
+        match (self.frame_mode, self.frame_cycle) {
+            (_, 7457) | (_, 22371) => self.quarter_frame(),
+            (_, 14913) => {
+                self.quarter_frame();
+                self.half_frame();
+            },
+            (FrameMode::FourStep, 29828) => self.raise_frame_irq(),
+            (FrameMode::FourStep, 29829) => {
+                self.quarter_frame();
+                self.half_frame();
+                self.raise_frame_irq();
+            },
+            (FrameMode::FourStep, 29830) => {
+                self.raise_frame_irq();
+                self.frame_cycle = 0;
+            },
+            (FrameMode::FiveStep, 37281) => {
+                self.quarter_frame();
+                self.half_frame();
+            },
+            (FrameMode::FiveStep, 37282) => self.frame_cycle = 0,
+            _ => {}
+        }
+    }
+
+    fn raise_frame_irq(&mut self) {
+        if !self.frame_irq_inhibit {
+            self.frame_irq.set(true);
+        }
+    }
+
+    fn quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.triangle.clock_linear();
+        self.noise.envelope.clock();
+    }
+
+    fn half_frame(&mut self) {
+        self.pulse1.length.clock();
+        self.pulse2.length.clock();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+
+    fn generate_sample(&mut self, expansion_output: f32) {
+        let output = self.output() + expansion_output;
+        if output != self.last_output {
+            let time = self.clock.get() - self.audio_frame_start;
+            self.blip.add_delta(time, output - self.last_output);
+            self.last_output = output;
+        }
+    }
+
+    /// Completes the samples for the cycles run so far, and filters them in to the sample stream
+    fn end_audio_frame(&mut self) {
+        let now = self.clock.get();
+        self.blip.end_frame(now - self.audio_frame_start);
+        self.audio_frame_start = now;
+
+        let start = self.samples.len();
+        self.blip.read_samples(&mut self.samples);
+        for sample in &mut self.samples[start ..] {
+            *sample = self.filters.process(*sample);
+        }
+
+        if self.samples.len() > self.max_samples {
+            let excess = self.samples.len() - self.max_samples;
+            self.samples.drain(0 .. excess);
+        }
+    }
+}
+
+/// Represents the memory the DMC reads its samples from, which owns the APU
+pub trait DmcBus: mem::Memory<u16> {
+    /// Gets the APU, and the expansion sound chip to clock along with it, if there is one
+    fn apu_and_expansion(&mut self) -> (&mut Apu, Option<&mut ExpansionAudio>);
+
+    /// Notifies the bus that a DMC DMA has halted the CPU on the provided cycle, before the DMA
+    /// reads its byte
+    ///
+    /// On the NES, a controller port the CPU was reading on that cycle is read again once the DMA
+    /// completes, so the port sees an extra read. Does nothing by default.
+    fn dmc_dma_halted(&mut self, _cpu_cycle: u64) {
+    }
+}
+
+/// Gets the number of cycles a DMC DMA that halts the CPU on the provided cycle stalls it for
+///
+/// After the halt cycle and a dummy cycle, the read waits for an odd cycle to line up with the
+/// APU, as OAM DMA reads do, so the DMA takes 3 or 4 cycles. The CPU can only be halted on a
+/// read cycle, so the DMA can take longer when it lands on a write, but the CPU runs a whole
+/// instruction at a time and that isn't emulated.
+pub fn dmc_dma_stall(cpu_cycle: u64) -> u64 {
+    if cpu_cycle % 2 == 1 { 3 } else { 4 }
+}
+
+/// Runs the APU on `bus` until it has caught up with the provided CPU cycle, performing any DMC DMA
+/// reads it requires
+///
+/// Returns the number of cycles the CPU was stalled by the DMA reads, as given by `dmc_dma_stall`
+/// for each. The caller is responsible for advancing the CPU clock by that amount.
+pub fn step_with_dma<B>(bus: &mut B, cpu_cycle: u64) -> mem::Result<u64> where B: DmcBus {
+    let mut stall = 0;
+    loop {
+        let (addr, start) = {
+            let (apu, expansion) = bus.apu_and_expansion();
+            match apu.step(cpu_cycle + stall, expansion) {
+                Some(addr) => (addr, apu.cycles()),
+                None => return Ok(stall)
+            }
+        };
+        bus.dmc_dma_halted(start);
+        let val = try!(bus.get_u8(addr));
+        bus.apu_and_expansion().0.dmc_fill(val);
+        stall += dmc_dma_stall(start);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::rp2A03::Apu;
+    use hw::rp2A03::apu::AUDIO_FRAME_CYCLES;
+
+    #[test]
+    pub fn four_step_mode_raises_frame_irq() {
+        let mut apu = Apu::new(None);
+        apu.step(29829, None);
+        assert!(apu.irq());
+        assert_eq!(0x40, apu.read_status() & 0x40);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    pub fn frame_irq_can_be_inhibited() {
+        let mut apu = Apu::new(None);
+        apu.write_register(0x4017, 0x40);
+        apu.step(40000, None);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    pub fn five_step_mode_does_not_raise_frame_irq() {
+        let mut apu = Apu::new(None);
+        apu.write_register(0x4017, 0x80);
+        apu.step(40000, None);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    pub fn status_reports_active_length_counters() {
+        let mut apu = Apu::new(None);
+        apu.write_register(0x4015, 0x05);
+        apu.write_register(0x4003, 0x08);
+        apu.write_register(0x4007, 0x08);
+        apu.write_register(0x400B, 0x08);
+        assert_eq!(0x05, apu.read_status());
+
+        apu.write_register(0x4015, 0x00);
+        assert_eq!(0x00, apu.read_status());
+    }
+
+    #[test]
+    pub fn length_counter_expires_after_half_frames() {
+        let mut apu = Apu::new(None);
+        apu.write_register(0x4015, 0x01);
+
+        // Length index 1 is 254 half frames, index 3 is 2
+        apu.write_register(0x4003, 0x18);
+        apu.step(14913, None);
+        assert_eq!(0x01, apu.read_status() & 0x01);
+        apu.step(29829, None);
+        assert_eq!(0x00, apu.read_status() & 0x01);
+    }
+
+    #[test]
+    pub fn step_stops_when_dmc_needs_a_byte() {
+        let mut apu = Apu::new(None);
+        apu.write_register(0x4010, 0x80);
+        apu.write_register(0x4012, 0x02);
+        apu.write_register(0x4013, 0x00);
+        apu.write_register(0x4015, 0x10);
+
+        assert_eq!(Some(0xC080), apu.step(100, None));
+        assert_eq!(0x10, apu.read_status() & 0x10);
+        apu.dmc_fill(0x00);
+        assert_eq!(None, apu.step(100, None));
+
+        // The one-byte sample has completed, raising an IRQ
+        assert!(apu.irq());
+        assert_eq!(0x80, apu.read_status() & 0x90);
+        apu.write_register(0x4015, 0x00);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    pub fn produces_samples_at_sample_rate() {
+        let mut apu = Apu::new(None);
+        apu.set_sample_rate(48000);
+        apu.step(1789773, None);
+        let samples = apu.take_samples();
+        assert!(samples.len() >= 47999 && samples.len() <= 48001);
+        assert!(apu.take_samples().is_empty());
+    }
+
+    #[test]
+    pub fn samples_are_completed_without_being_taken() {
+        let mut apu = Apu::new(None);
+        apu.step(AUDIO_FRAME_CYCLES, None);
+        assert_eq!(0, apu.blip.samples_available());
+        assert!(apu.samples.len() >= 733 && apu.samples.len() <= 734);
+    }
+
+    #[test]
+    pub fn untaken_samples_are_dropped_after_a_second() {
+        let mut apu = Apu::new(None);
+        apu.set_sample_rate(48000);
+        apu.step(3 * 1789773, None);
+        assert_eq!(48000, apu.take_samples().len());
+    }
+
+    #[test]
+    pub fn expansion_audio_is_mixed_with_output() {
+        use hw::expansion_audio::{ExpansionAudio,Vrc6Audio};
+
+        let mut apu = Apu::new(None);
+        let mut vrc6 = Vrc6Audio::new();
+        vrc6.write(0x9000, 0x8F);
+        vrc6.write(0x9002, 0x80);
+        apu.step(17898, Some(&mut vrc6));
+
+        // The constant output of the VRC6 is removed by the high-pass filters, but starting it
+        // produces a pulse
+        let samples = apu.take_samples();
+        assert!(samples.iter().any(|s| *s > 0.05));
+    }
+
+    #[test]
+    pub fn pulse_output_is_centered_on_zero() {
+        let mut apu = Apu::new(None);
+        apu.write_register(0x4015, 0x01);
+        apu.write_register(0x4000, 0xBF);
+        apu.write_register(0x4002, 0xFD);
+        apu.write_register(0x4003, 0x08);
+        apu.step(178977, None);
+
+        // Skip the first part, while the high-pass filters settle
+        let samples = apu.take_samples();
+        let settled = &samples[2000 ..];
+        let mean = settled.iter().sum::<f32>() / (settled.len() as f32);
+        assert!(mean.abs() < 0.01);
+        assert!(settled.iter().any(|s| *s > 0.05));
+        assert!(settled.iter().any(|s| *s < -0.05));
+    }
+}
@@ -0,0 +1,217 @@
+/// The timer periods for the DMC, in CPU cycles, indexed by the lower 4 bits of $4010
+const RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54
+];
+
+/// Emulates the delta modulation channel (DMC)
+///
+/// The DMC plays 1-bit delta-encoded samples from CPU memory ($C000-$FFFF). The APU can't access
+/// memory itself, so when the sample buffer needs to be refilled, the DMC reports the address it
+/// needs through `fetch_address`, and whoever owns the memory performs the read (stalling the CPU)
+/// and provides the byte through `fill`.
+pub struct Dmc {
+    irq_enabled: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+    level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    buffer: Option<u8>,
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+    pub irq: bool
+}
+
+impl Dmc {
+    pub fn new() -> Dmc {
+        Dmc {
+            irq_enabled: false,
+            looping: false,
+            timer_period: RATE_TABLE[0],
+            timer: 0,
+            level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+            irq: false
+        }
+    }
+
+    /// Writes one of the channel's four registers, `reg` is the offset of the register (0-3)
+    pub fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.irq_enabled = val & 0x80 != 0;
+                self.looping = val & 0x40 != 0;
+                self.timer_period = RATE_TABLE[(val & 0x0F) as usize];
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            },
+            1 => self.level = val & 0x7F,
+            2 => self.sample_address = 0xC000 | ((val as u16) << 6),
+            _ => self.sample_length = ((val as u16) << 4) | 0x0001
+        }
+    }
+
+    /// Enables or disables the channel, through bit 4 of $4015
+    ///
+    /// Disabling the channel stops the sample, but the bits already in the buffer still play.
+    /// Enabling it restarts the sample, if it has finished.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    /// Returns a value indicating if the sample still has bytes left to fetch
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    /// Gets the address of the next sample byte, if the sample buffer needs to be refilled
+    pub fn fetch_address(&self) -> Option<u16> {
+        if self.buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    /// Fills the sample buffer with the byte read from the address given by `fetch_address`
+    pub fn fill(&mut self, val: u8) {
+        self.buffer = Some(val);
+        self.current_address = if self.current_address == 0xFFFF { 0x8000 } else { self.current_address + 1 };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// Clocks the timer, this occurs every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if self.shift & 0x01 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(val) => {
+                    self.silence = false;
+                    self.shift = val;
+                },
+                None => self.silence = true
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.level
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::rp2A03::dmc::Dmc;
+
+    #[test]
+    pub fn enabling_starts_fetching_from_sample_address() {
+        let mut dmc = Dmc::new();
+        dmc.write(2, 0x01);
+        dmc.write(3, 0x00);
+        assert_eq!(None, dmc.fetch_address());
+        dmc.set_enabled(true);
+        assert_eq!(Some(0xC040), dmc.fetch_address());
+        dmc.fill(0xFF);
+        assert_eq!(None, dmc.fetch_address());
+    }
+
+    #[test]
+    pub fn irq_is_raised_at_end_of_sample() {
+        let mut dmc = Dmc::new();
+        dmc.write(0, 0x80);
+        dmc.write(3, 0x00);
+        dmc.set_enabled(true);
+        dmc.fill(0x00);
+        assert!(dmc.irq);
+        assert!(!dmc.active());
+    }
+
+    #[test]
+    pub fn looping_sample_restarts_without_irq() {
+        let mut dmc = Dmc::new();
+        dmc.write(0, 0xC0);
+        dmc.write(3, 0x00);
+        dmc.set_enabled(true);
+        dmc.fill(0x00);
+        assert!(!dmc.irq);
+        assert!(dmc.active());
+    }
+
+    #[test]
+    pub fn direct_load_sets_output_level() {
+        let mut dmc = Dmc::new();
+        dmc.write(1, 0xC2);
+        assert_eq!(0x42, dmc.output());
+    }
+
+    #[test]
+    pub fn sample_bits_move_output_level() {
+        let mut dmc = Dmc::new();
+        dmc.write(0, 0x0F);
+        dmc.write(1, 0x40);
+        dmc.write(3, 0x00);
+        dmc.set_enabled(true);
+        dmc.fill(0x0F);
+
+        // The first byte is loaded in to the shift register after the first 8 bits of silence
+        for _ in 0 .. 8 * 54 {
+            dmc.clock_timer();
+        }
+        assert_eq!(0x40, dmc.output());
+        for _ in 0 .. 4 * 54 {
+            dmc.clock_timer();
+        }
+        assert_eq!(0x48, dmc.output());
+        for _ in 0 .. 4 * 54 {
+            dmc.clock_timer();
+        }
+        assert_eq!(0x40, dmc.output());
+    }
+}
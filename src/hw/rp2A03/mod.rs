@@ -0,0 +1,14 @@
+pub use self::apu::{Apu,DmcBus,step_with_dma};
+
+/// Contains code to emulate the APU
+pub mod apu;
+
+/// Contains the length counter and envelope units shared by several channels
+pub mod units;
+
+/// Contains code to emulate the pulse channels, which are also used by the MMC5
+pub mod pulse;
+
+mod triangle;
+mod noise;
+mod dmc;
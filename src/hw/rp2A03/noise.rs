@@ -0,0 +1,109 @@
+use hw::rp2A03::units::{Envelope,LengthCounter};
+
+/// The timer periods for the noise channel, in CPU cycles, indexed by the lower 4 bits of $400E
+const PERIOD_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068
+];
+
+/// Emulates the noise channel
+///
+/// The noise is generated by a 15-bit linear feedback shift register. In the normal mode the
+/// feedback comes from bits 0 and 1, producing a long pseudo-random sequence. In the short mode it
+/// comes from bits 0 and 6, producing a sequence of only 93 (or 31) steps with a metallic tone.
+pub struct Noise {
+    short_mode: bool,
+    timer_period: u16,
+    timer: u16,
+    shift: u16,
+    pub length: LengthCounter,
+    pub envelope: Envelope
+}
+
+impl Noise {
+    pub fn new() -> Noise {
+        Noise {
+            short_mode: false,
+            timer_period: PERIOD_TABLE[0],
+            timer: 0,
+            shift: 1,
+            length: LengthCounter::new(),
+            envelope: Envelope::new()
+        }
+    }
+
+    /// Writes one of the channel's four registers, `reg` is the offset of the register (0-3)
+    pub fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.length.halt = val & 0x20 != 0;
+                self.envelope.write(val);
+            },
+            1 => {},
+            2 => {
+                self.short_mode = val & 0x80 != 0;
+                self.timer_period = PERIOD_TABLE[(val & 0x0F) as usize];
+            },
+            _ => {
+                self.length.load(val >> 3);
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    /// Clocks the timer, this occurs every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let other = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift & 0x01) ^ ((self.shift >> other) & 0x01);
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.active() || self.shift & 0x01 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::rp2A03::noise::Noise;
+
+    fn sequence_length(short_mode: bool) -> usize {
+        let mut noise = Noise::new();
+        noise.write(2, if short_mode { 0x80 } else { 0x00 });
+
+        // Skip past the start of the sequence, which may not be part of the loop
+        for _ in 0 .. 4 * 100 {
+            noise.clock_timer();
+        }
+        let start = noise.shift;
+        let mut steps = 0;
+        loop {
+            for _ in 0 .. 4 {
+                noise.clock_timer();
+            }
+            steps += 1;
+            if noise.shift == start {
+                return steps;
+            }
+        }
+    }
+
+    #[test]
+    pub fn normal_mode_produces_long_sequence() {
+        assert_eq!(32767, sequence_length(false));
+    }
+
+    #[test]
+    pub fn short_mode_produces_short_sequence() {
+        let length = sequence_length(true);
+        assert!(length == 93 || length == 31);
+    }
+}
@@ -0,0 +1,171 @@
+use hw::rp2A03::units::{Envelope,LengthCounter};
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1]
+];
+
+/// Emulates one of the two pulse (square wave) channels
+///
+/// The two channels only differ in how the sweep unit negates the change in period. The first
+/// channel uses one's complement, so its period moves down one further than the second channel's.
+pub struct Pulse {
+    ones_complement: bool,
+    duty: u8,
+    step: u8,
+    timer_period: u16,
+    timer: u16,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+    pub length: LengthCounter,
+    pub envelope: Envelope
+}
+
+impl Pulse {
+    /// Creates a new pulse channel, `first` indicates if it is the first of the two channels
+    pub fn new(first: bool) -> Pulse {
+        Pulse {
+            ones_complement: first,
+            duty: 0,
+            step: 0,
+            timer_period: 0,
+            timer: 0,
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_divider: 0,
+            sweep_reload: false,
+            length: LengthCounter::new(),
+            envelope: Envelope::new()
+        }
+    }
+
+    /// Writes one of the channel's four registers, `reg` is the offset of the register (0-3)
+    pub fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.duty = val >> 6;
+                self.length.halt = val & 0x20 != 0;
+                self.envelope.write(val);
+            },
+            1 => {
+                self.sweep_enabled = val & 0x80 != 0;
+                self.sweep_period = (val >> 4) & 0x07;
+                self.sweep_negate = val & 0x08 != 0;
+                self.sweep_shift = val & 0x07;
+                self.sweep_reload = true;
+            },
+            2 => self.timer_period = (self.timer_period & 0x0700) | (val as u16),
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | (((val & 0x07) as u16) << 8);
+                self.length.load(val >> 3);
+                self.step = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    /// Clocks the timer, this occurs once every APU cycle (every 2 CPU cycles)
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.step = (self.step + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Clocks the sweep unit, this occurs on half-frame signals from the frame counter
+    pub fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.timer_period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        if self.sweep_negate {
+            let change = if self.ones_complement { change + 1 } else { change };
+            self.timer_period.saturating_sub(change)
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    /// The channel is muted when the period is too small, or the sweep unit would take it out of
+    /// range, even if the sweep unit is disabled
+    fn muted(&self) -> bool {
+        self.timer_period < 8 || self.sweep_target() > 0x07FF
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.active() || self.muted() || DUTY_TABLE[self.duty as usize][self.step as usize] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::rp2A03::pulse::Pulse;
+
+    fn enabled_pulse(first: bool) -> Pulse {
+        let mut pulse = Pulse::new(first);
+        pulse.length.set_enabled(true);
+        pulse
+    }
+
+    #[test]
+    pub fn outputs_volume_during_high_part_of_duty_cycle() {
+        let mut pulse = enabled_pulse(true);
+        pulse.write(0, 0x9F);
+        pulse.write(2, 0x00);
+        pulse.write(3, 0x01);
+
+        // 50% duty starts low for one step, then high for four
+        assert_eq!(0, pulse.output());
+        for _ in 0 .. 0x101 {
+            pulse.clock_timer();
+        }
+        assert_eq!(15, pulse.output());
+    }
+
+    #[test]
+    pub fn is_muted_when_period_is_less_than_8() {
+        let mut pulse = enabled_pulse(true);
+        pulse.write(0, 0xDF);
+        pulse.write(2, 0x07);
+        pulse.write(3, 0x00);
+        assert_eq!(0, pulse.output());
+    }
+
+    #[test]
+    pub fn sweep_negation_differs_between_channels() {
+        let mut first = enabled_pulse(true);
+        let mut second = enabled_pulse(false);
+        for pulse in [&mut first, &mut second].iter_mut() {
+            pulse.write(1, 0x89);
+            pulse.write(2, 0x00);
+            pulse.write(3, 0x01);
+            pulse.clock_sweep();
+        }
+
+        assert_eq!(0x0100 - 0x80 - 1, first.timer_period);
+        assert_eq!(0x0100 - 0x80, second.timer_period);
+    }
+}
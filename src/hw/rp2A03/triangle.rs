@@ -0,0 +1,110 @@
+use hw::rp2A03::units::LengthCounter;
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10,  9,  8,  7,  6,  5,  4,  3,  2,  1,  0,
+     0,  1,  2,  3,  4,  5,  6,  7,  8,  9, 10, 11, 12, 13, 14, 15
+];
+
+/// Emulates the triangle channel
+///
+/// The triangle has no volume control. Instead, it is silenced by either the length counter or a
+/// second, finer-grained, linear counter clocked by quarter-frame signals. When silenced, the
+/// sequencer simply stops, holding the current output level.
+pub struct Triangle {
+    step: u8,
+    timer_period: u16,
+    timer: u16,
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    pub length: LengthCounter
+}
+
+impl Triangle {
+    pub fn new() -> Triangle {
+        Triangle {
+            step: 0,
+            timer_period: 0,
+            timer: 0,
+            control: false,
+            linear_reload_value: 0,
+            linear_counter: 0,
+            linear_reload: false,
+            length: LengthCounter::new()
+        }
+    }
+
+    /// Writes one of the channel's four registers, `reg` is the offset of the register (0-3)
+    pub fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.control = val & 0x80 != 0;
+                self.length.halt = self.control;
+                self.linear_reload_value = val & 0x7F;
+            },
+            1 => {},
+            2 => self.timer_period = (self.timer_period & 0x0700) | (val as u16),
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | (((val & 0x07) as u16) << 8);
+                self.length.load(val >> 3);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    /// Clocks the timer, this occurs every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.linear_counter > 0 && self.length.active() {
+                self.step = (self.step + 1) & 0x1F;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Clocks the linear counter, this occurs on quarter-frame signals from the frame counter
+    pub fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.step as usize]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::rp2A03::triangle::Triangle;
+
+    #[test]
+    pub fn sequencer_only_advances_while_both_counters_are_nonzero() {
+        let mut triangle = Triangle::new();
+        triangle.length.set_enabled(true);
+        triangle.write(0, 0x01);
+        triangle.write(2, 0x00);
+        triangle.write(3, 0x08);
+
+        // The linear counter hasn't been reloaded yet
+        triangle.clock_timer();
+        assert_eq!(15, triangle.output());
+
+        triangle.clock_linear();
+        triangle.clock_timer();
+        assert_eq!(14, triangle.output());
+
+        // The linear counter runs out after one more quarter frame
+        triangle.clock_linear();
+        triangle.clock_timer();
+        assert_eq!(14, triangle.output());
+    }
+}
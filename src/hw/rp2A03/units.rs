@@ -0,0 +1,173 @@
+/// The values loaded in to a length counter, indexed by the upper 5 bits of the length register
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20,  2, 40,  4, 80,  6, 160,  8, 60, 10, 14, 12, 26, 14,
+    12,  16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30
+];
+
+/// Silences a channel after a certain amount of time
+///
+/// The counter is clocked by half-frame signals from the frame counter, and the channel is silenced
+/// when it reaches zero. Disabling the channel through $4015 clears the counter and prevents it
+/// from being loaded.
+pub struct LengthCounter {
+    value: u8,
+    pub halt: bool,
+    enabled: bool
+}
+
+impl LengthCounter {
+    pub fn new() -> LengthCounter {
+        LengthCounter {
+            value: 0,
+            halt: false,
+            enabled: false
+        }
+    }
+
+    /// Loads the counter with the length at `index` in the length table, if the channel is enabled
+    pub fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.value = LENGTH_TABLE[(index & 0x1F) as usize];
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.value = 0;
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if !self.halt && self.value > 0 {
+            self.value -= 1;
+        }
+    }
+
+    /// Returns a value indicating if the counter is non-zero, allowing the channel to play
+    pub fn active(&self) -> bool {
+        self.value > 0
+    }
+}
+
+/// Generates a volume that is either constant or decays in a saw envelope
+///
+/// The envelope is clocked by quarter-frame signals from the frame counter
+pub struct Envelope {
+    pub start: bool,
+    pub looping: bool,
+    pub constant: bool,
+    pub volume: u8,
+    divider: u8,
+    decay: u8
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            start: false,
+            looping: false,
+            constant: false,
+            volume: 0,
+            divider: 0,
+            decay: 0
+        }
+    }
+
+    /// Updates the envelope from the lower 6 bits written to a channel's control register
+    pub fn write(&mut self, val: u8) {
+        self.looping = val & 0x20 != 0;
+        self.constant = val & 0x10 != 0;
+        self.volume = val & 0x0F;
+    }
+
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.constant { self.volume } else { self.decay }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::rp2A03::units::{Envelope,LengthCounter};
+
+    #[test]
+    pub fn length_counter_is_not_loaded_while_disabled() {
+        let mut length = LengthCounter::new();
+        length.load(1);
+        assert!(!length.active());
+    }
+
+    #[test]
+    pub fn length_counter_counts_down_unless_halted() {
+        let mut length = LengthCounter::new();
+        length.set_enabled(true);
+        length.load(3);
+        length.halt = true;
+        length.clock();
+        assert!(length.active());
+        length.halt = false;
+        length.clock();
+        assert!(length.active());
+        length.clock();
+        assert!(!length.active());
+    }
+
+    #[test]
+    pub fn disabling_length_counter_clears_it() {
+        let mut length = LengthCounter::new();
+        length.set_enabled(true);
+        length.load(1);
+        length.set_enabled(false);
+        assert!(!length.active());
+    }
+
+    #[test]
+    pub fn envelope_decays_from_15_once_started() {
+        let mut envelope = Envelope::new();
+        envelope.write(0x00);
+        envelope.start = true;
+        envelope.clock();
+        assert_eq!(15, envelope.output());
+        envelope.clock();
+        assert_eq!(14, envelope.output());
+    }
+
+    #[test]
+    pub fn envelope_loops_when_looping_is_set() {
+        let mut envelope = Envelope::new();
+        envelope.write(0x20);
+        envelope.start = true;
+        for _ in 0 .. 16 {
+            envelope.clock();
+        }
+        assert_eq!(0, envelope.output());
+        envelope.clock();
+        assert_eq!(15, envelope.output());
+    }
+
+    #[test]
+    pub fn constant_volume_ignores_decay() {
+        let mut envelope = Envelope::new();
+        envelope.write(0x17);
+        envelope.start = true;
+        envelope.clock();
+        assert_eq!(7, envelope.output());
+    }
+}
@@ -1,4 +1,4 @@
-//pub use self::ppu::Rp2C02;
+pub use self::ppu::{Rp2C02,Register,Error,Result};
 
-//// Contains code to emulate the PPU
-//pub mod ppu;
+/// Contains code to emulate the PPU
+pub mod ppu;
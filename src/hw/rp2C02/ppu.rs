@@ -1,23 +1,28 @@
-use mem::{self,Memory};
-use clock;
+use std::{error,fmt};
+use std::cell::Cell;
+
+use slog;
 
-pub const NAMETABLE_SIZE: usize = 0x0400;
-pub const NAMETABLE_BASE: usize = 0x2000;
-pub const NAMETABLE_END: usize = 0x2C00;
+use mem;
+use clock;
 
-pub const BACKDROP_COLOR_ADDR: usize = 0x3F00;
-pub const BG_PALETTE_BASE: usize = 0x3F01;
+pub const NAMETABLE_BASE: u16 = 0x2000;
+pub const ATTRIBUTE_TABLE_BASE: u16 = 0x23C0;
+pub const PALETTE_BASE: u16 = 0x3F00;
+pub const SPRITE_PALETTE_BASE: u16 = 0x3F10;
 
 pub const PIXELS_PER_SCANLINE: usize = 256;
 pub const PIXELS_PER_TILE: usize = 8;
+pub const SPRITES_PER_SCANLINE: usize = 8;
+pub const SPRITES_IN_OAM: usize = 64;
 pub const PIXELS_PER_SCREEN: usize = PIXELS_PER_SCANLINE * SCANLINES_PER_FRAME;
 pub const BYTES_PER_PIXEL: usize = 3;
 pub const BYTES_PER_SCREEN: usize = BYTES_PER_PIXEL * PIXELS_PER_SCREEN;
-pub const TILES_PER_SCANLINE: usize = PIXELS_PER_SCANLINE / PIXELS_PER_TILE;
 pub const SCANLINES_PER_FRAME: usize = 240;
-pub const CYCLES_PER_SCANLINE: usize = 114;
+pub const DOTS_PER_SCANLINE: u64 = 341;
+pub const DOTS_PER_CPU_CYCLE: u64 = 3;
 pub const VBLANK_SCANLINE: usize = 241;
-pub const END_SCANLINE: usize = 261;
+pub const PRERENDER_SCANLINE: usize = 261;
 
 // Unapologetically yanked from https://github.com/pcwalton/sprocketnes/blob/master/ppu.rs
 const PALETTE: [u8; 192] = [
@@ -41,116 +46,152 @@ const PALETTE: [u8; 192] = [
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
+/// Represents an error that can occur while the PPU is running
+#[derive(Clone,Debug,Eq,PartialEq)]
 pub enum Error {
+    /// Indicates that an error occurred reading or writing the PPU's memory
+    ErrorAccessingMemory(mem::Error)
 }
 
-pub struct Rp2C02 {
-    clock: clock::Clock,
-    registers: Registers,
-    current_scanline: usize
-}
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match self {
+            &Error::ErrorAccessingMemory(_) => "error accessing memory"
+        }
+    }
 
-pub struct Pixel {
-    red: u8,
-    green: u8,
-    blue: u8
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            &Error::ErrorAccessingMemory(ref err) => Some(err)
+        }
+    }
 }
 
-pub struct Registers {
-    ppuctrl: PpuCtrl,
-    ppumask: PpuMask,
-    ppustatus: PpuStatus,
-    ppuscroll: PpuScroll
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Error::ErrorAccessingMemory(ref err) => write!(fmt, "error accessing memory: {}", err)
+        }
+    }
 }
 
-impl Registers {
-    pub fn new() -> Registers {
-        Registers {
-            ppuctrl: PpuCtrl::new(),
-            ppumask: PpuMask::new(),
-            ppustatus: PpuStatus::new(),
-            ppuscroll: PpuScroll::new()
-        }
+impl From<mem::Error> for Error {
+    fn from(err: mem::Error) -> Error {
+        Error::ErrorAccessingMemory(err)
     }
 }
 
-pub enum NextScrollField { X, Y }
+serialize_via_debug!(Error);
 
-pub struct PpuScroll {
-    x: u8,
-    y: u8,
-    next: NextScrollField
+/// Identifies one of the eight memory-mapped PPU registers (located at $2000-$2007 in CPU space)
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum Register {
+    PpuCtrl,
+    PpuMask,
+    PpuStatus,
+    OamAddr,
+    OamData,
+    PpuScroll,
+    PpuAddr,
+    PpuData
 }
 
-impl PpuScroll {
-    pub fn new() {
-        PpuScroll {
-            x: 0,
-            y: 0,
-            next: NextScrollField::X
+impl Register {
+    /// Gets the register mapped at the provided offset from $2000
+    ///
+    /// The registers repeat every 8 bytes, so only the lowest 3 bits of `offset` are used
+    pub fn from_offset(offset: u16) -> Register {
+        match offset & 0x0007 {
+            0 => Register::PpuCtrl,
+            1 => Register::PpuMask,
+            2 => Register::PpuStatus,
+            3 => Register::OamAddr,
+            4 => Register::OamData,
+            5 => Register::PpuScroll,
+            6 => Register::PpuAddr,
+            _ => Register::PpuData
         }
     }
 }
 
-pub enum VramDirection {
-    GoingAcross,
-    GoingDown
-}
-
+/// Represents the value of the PPUCTRL ($2000) register
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
 pub struct PpuCtrl {
-    nametable_base: u16,
-    vram_direction: VramDirection,
-    sprite_pattern_table: u8,
-    bg_pattern_table: u8,
-    large_sprites: bool,
-    secondary: bool,
-    generate_nmi: bool
+    pub vram_increment: u16,
+    pub sprite_pattern_table: u16,
+    pub bg_pattern_table: u16,
+    pub large_sprites: bool,
+    pub secondary: bool,
+    pub generate_nmi: bool
 }
 
 impl PpuCtrl {
     pub fn new() -> PpuCtrl {
+        PpuCtrl::from_u8(0)
+    }
+
+    /// Decodes the register from the value written by the CPU
+    ///
+    /// The nametable select bits are not stored here, they are copied in to the `t` register
+    pub fn from_u8(val: u8) -> PpuCtrl {
         PpuCtrl {
-            nametable: 0,
-            vram_direction: VramDirection::GoingAcross,
-            sprite_pattern_table: 0,
-            bg_pattern_table: 0,
-            large_sprites: false,
-            secondary: false,
-            generate_nmi: false
+            vram_increment: if val & 0x04 == 0 { 1 } else { 32 },
+            sprite_pattern_table: if val & 0x08 == 0 { 0x0000 } else { 0x1000 },
+            bg_pattern_table: if val & 0x10 == 0 { 0x0000 } else { 0x1000 },
+            large_sprites: val & 0x20 != 0,
+            secondary: val & 0x40 != 0,
+            generate_nmi: val & 0x80 != 0
         }
     }
 }
 
+/// Represents the value of the PPUMASK ($2001) register
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
 pub struct PpuMask {
-    greyscale: bool,
-    leftmost_background: bool,
-    leftmost_sprites: bool,
-    background: bool,
-    sprites: bool,
-    emphasize_red: bool,
-    emphasize_green: bool,
-    emphasize_blue: bool
+    pub greyscale: bool,
+    pub leftmost_background: bool,
+    pub leftmost_sprites: bool,
+    pub background: bool,
+    pub sprites: bool,
+    pub emphasize_red: bool,
+    pub emphasize_green: bool,
+    pub emphasize_blue: bool
 }
 
 impl PpuMask {
     pub fn new() -> PpuMask {
+        PpuMask::from_u8(0)
+    }
+
+    /// Decodes the register from the value written by the CPU
+    pub fn from_u8(val: u8) -> PpuMask {
         PpuMask {
-            greyscale: false,
-            leftmost_background: false,
-            leftmost_sprites: false,
-            background: false,
-            sprites: false,
-            emphasize_red: false,
-            emphasize_green: false,
-            emphasize_blue: false
+            greyscale: val & 0x01 != 0,
+            leftmost_background: val & 0x02 != 0,
+            leftmost_sprites: val & 0x04 != 0,
+            background: val & 0x08 != 0,
+            sprites: val & 0x10 != 0,
+            emphasize_red: val & 0x20 != 0,
+            emphasize_green: val & 0x40 != 0,
+            emphasize_blue: val & 0x80 != 0
         }
     }
+
+    /// Returns a value indicating if either the background or sprites are being rendered
+    pub fn rendering(&self) -> bool {
+        self.background || self.sprites
+    }
 }
 
+/// Represents the value of the PPUSTATUS ($2002) register
+///
+/// Reading PPUSTATUS clears the vertical blank flag, and reads are performed through a shared
+/// reference, so that flag is stored in a `Cell`
+#[derive(Clone,Debug,Eq,PartialEq)]
 pub struct PpuStatus {
-    sprite_overflow: bool,
-    sprite_0_hit: bool,
-    vertical_blank: bool
+    pub sprite_overflow: bool,
+    pub sprite_0_hit: bool,
+    pub vertical_blank: Cell<bool>
 }
 
 impl PpuStatus {
@@ -158,142 +199,1061 @@ impl PpuStatus {
         PpuStatus {
             sprite_overflow: false,
             sprite_0_hit: false,
-            vertical_blank: false
+            vertical_blank: Cell::new(false)
         }
     }
+
+    /// Encodes the flags in to the upper 3 bits of a byte, as they appear to the CPU
+    pub fn to_u8(&self) -> u8 {
+        (if self.sprite_overflow { 0x20 } else { 0 }) |
+        (if self.sprite_0_hit { 0x40 } else { 0 }) |
+        (if self.vertical_blank.get() { 0x80 } else { 0 })
+    }
+}
+
+/// Contains the registers visible to the CPU, as well as the internal scrolling registers
+///
+/// The internal registers use the names given to them on the NesDev wiki: `v` is the current VRAM
+/// address, `t` is the temporary VRAM address (the top-left corner of the screen), `x` is the fine
+/// X scroll and `w` is the write toggle shared by PPUSCROLL and PPUADDR.
+pub struct Registers {
+    pub ppuctrl: PpuCtrl,
+    pub ppumask: PpuMask,
+    pub ppustatus: PpuStatus,
+    pub oamaddr: u8,
+    pub v: Cell<u16>,
+    pub t: u16,
+    pub x: u8,
+    pub w: Cell<bool>
+}
+
+impl Registers {
+    pub fn new() -> Registers {
+        Registers {
+            ppuctrl: PpuCtrl::new(),
+            ppumask: PpuMask::new(),
+            ppustatus: PpuStatus::new(),
+            oamaddr: 0,
+            v: Cell::new(0),
+            t: 0,
+            x: 0,
+            w: Cell::new(false)
+        }
+    }
+}
+
+/// Represents a Ricoh RP2C02 Picture Processing Unit
+///
+/// The PPU is attached to its own 14-bit address bus, which is provided to each method that needs
+/// it as a `mem::Memory<u16>`. That bus covers the pattern tables and nametables ($0000-$3EFF).
+/// Palette memory and OAM are internal to the PPU.
+pub struct Rp2C02 {
+    pub registers: Registers,
+    clock: clock::Clock,
+    scanline: usize,
+    dot: usize,
+    odd_frame: bool,
+    frame: u64,
+    bg: Background,
+    palette: [u8; 0x20],
+    oam: [u8; 0x100],
+    secondary_oam: [u8; 4 * SPRITES_PER_SCANLINE],
+    secondary_count: usize,
+    secondary_has_sprite_0: bool,
+    sprites: [Sprite; SPRITES_PER_SCANLINE],
+    sprite_count: usize,
+    sprite_0_on_line: bool,
+    read_buffer: Cell<u8>,
+    pending_read: Cell<Option<u16>>,
+    io_latch: u8,
+    nmi: bool,
+    screen: Box<[u8; BYTES_PER_SCREEN]>,
+    log: slog::Logger
 }
 
 impl Rp2C02 {
-    pub fn new() -> Rp2C02 {
+    pub fn new(logger: Option<slog::Logger>) -> Rp2C02 {
         Rp2C02 {
-            clock: clock::Clock::new(),
             registers: Registers::new(),
-            current_scanline: 0
+            clock: clock::Clock::new(),
+            scanline: 0,
+            dot: 0,
+            odd_frame: false,
+            frame: 0,
+            bg: Background::new(),
+            palette: [0; 0x20],
+            oam: [0; 0x100],
+            secondary_oam: [0xFF; 4 * SPRITES_PER_SCANLINE],
+            secondary_count: 0,
+            secondary_has_sprite_0: false,
+            sprites: [Sprite::new(); SPRITES_PER_SCANLINE],
+            sprite_count: 0,
+            sprite_0_on_line: false,
+            read_buffer: Cell::new(0),
+            pending_read: Cell::new(None),
+            io_latch: 0,
+            nmi: false,
+            screen: Box::new([0; BYTES_PER_SCREEN]),
+            log: unwrap_logger!(logger).new(o!("component" => "ppu"))
         }
     }
 
-    /// Emulates the execution of PPU cycles until `target_cycle` is reached
+    /// Gets the most recently completed frame, as 8-bit RGB triples in row-major order
+    pub fn screen(&self) -> &[u8] {
+        &self.screen[..]
+    }
+
+    /// Gets the number of frames that have been completed since the PPU was created
     ///
-    /// The PPU emulation only runs entire scanlines at once. So, this method
-    /// only executes a scanline if the current clock, plus the number of cycles
-    /// required to render a scanline (`CYCLES_PER_SCANLINE`) is less than
-    /// the desired `target_cycle`. If it is, a single scan line is rendered,
-    /// the PPU clock is updated by `CYCLES_PER_SCANLINE` and the process is
-    /// repeated. If it is not, this method returns to allow the CPU to continue
-    /// processing.
-    pub fn step(&mut self, target_cycle: usize, mem: &mut mem::Memory, screen: &mut [u8; BYTES_PER_SCREEN]) -> Result<()> {
-        loop {
-            // Check when the next scan line is
-            let next_scan_line = self.clock.get() + CYCLES_PER_SCANLINE;
-            if next_scan_line > target_cycle {
-                // Next scan line is beyond our target, we've done enough.
-                break;
+    /// A frame is considered complete when vertical blank starts
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Gets the number of dots (PPU cycles) that have been executed
+    pub fn cycles(&self) -> u64 {
+        self.clock.get()
+    }
+
+    /// Returns `true`, and clears the request, if the PPU has requested a Non-Maskable Interrupt
+    pub fn take_nmi(&mut self) -> bool {
+        let nmi = self.nmi;
+        self.nmi = false;
+        nmi
+    }
+
+    /// Reads one of the memory-mapped registers
+    ///
+    /// This takes a shared reference because it is called from `mem::Memory::get_u8`. A read from
+    /// PPUDATA returns the contents of the internal read buffer and schedules the buffer to be
+    /// refilled on the next call to `step`, much like the real PPU does a few cycles after the read.
+    pub fn read_register(&self, reg: Register) -> u8 {
+        match reg {
+            Register::PpuStatus => {
+                let val = self.registers.ppustatus.to_u8() | (self.io_latch & 0x1F);
+                self.registers.ppustatus.vertical_blank.set(false);
+                self.registers.w.set(false);
+                val
+            },
+            Register::OamData => {
+                let addr = self.registers.oamaddr as usize;
+                if addr & 0x03 == 2 {
+                    // Bits 2-4 of the sprite attributes don't exist
+                    self.oam[addr] & 0xE3
+                } else {
+                    self.oam[addr]
+                }
+            },
+            Register::PpuData => {
+                let addr = self.registers.v.get() & 0x3FFF;
+                let val = if addr >= PALETTE_BASE {
+                    // Palette reads aren't buffered, but the buffer is filled with the nametable
+                    // byte "underneath" the palette
+                    self.pending_read.set(Some(addr - 0x1000));
+                    (self.io_latch & 0xC0) | self.read_palette(addr)
+                } else {
+                    self.pending_read.set(Some(addr));
+                    self.read_buffer.get()
+                };
+                self.increment_v();
+                val
+            },
+
+            // The remaining registers are write-only, the value left on the PPU's I/O bus is read
+            _ => self.io_latch
+        }
+    }
+
+    /// Writes one of the memory-mapped registers
+    pub fn write_register<M>(&mut self, reg: Register, val: u8, bus: &mut M) -> Result<()> where M: mem::Memory<u16> {
+        self.io_latch = val;
+        match reg {
+            Register::PpuCtrl => {
+                let was_enabled = self.registers.ppuctrl.generate_nmi;
+                self.registers.ppuctrl = PpuCtrl::from_u8(val);
+                self.registers.t = (self.registers.t & 0xF3FF) | (((val & 0x03) as u16) << 10);
+
+                // Enabling NMIs during vertical blank triggers one immediately
+                if !was_enabled && self.registers.ppuctrl.generate_nmi && self.registers.ppustatus.vertical_blank.get() {
+                    self.nmi = true;
+                }
+            },
+            Register::PpuMask => self.registers.ppumask = PpuMask::from_u8(val),
+            Register::PpuStatus => {},
+            Register::OamAddr => self.registers.oamaddr = val,
+            Register::OamData => self.write_oam(val),
+            Register::PpuScroll => {
+                if !self.registers.w.get() {
+                    self.registers.t = (self.registers.t & 0xFFE0) | ((val >> 3) as u16);
+                    self.registers.x = val & 0x07;
+                    self.registers.w.set(true);
+                } else {
+                    self.registers.t = (self.registers.t & 0x8C1F) |
+                        (((val & 0xF8) as u16) << 2) |
+                        (((val & 0x07) as u16) << 12);
+                    self.registers.w.set(false);
+                }
+            },
+            Register::PpuAddr => {
+                if !self.registers.w.get() {
+                    self.registers.t = (self.registers.t & 0x80FF) | (((val & 0x3F) as u16) << 8);
+                    self.registers.w.set(true);
+                } else {
+                    self.registers.t = (self.registers.t & 0xFF00) | (val as u16);
+                    self.registers.v.set(self.registers.t);
+                    self.registers.w.set(false);
+                }
+            },
+            Register::PpuData => {
+                let addr = self.registers.v.get() & 0x3FFF;
+                if addr >= PALETTE_BASE {
+                    self.write_palette(addr, val);
+                } else {
+                    try!(bus.set_u8(addr, val));
+                }
+                self.increment_v();
             }
+        }
+        Ok(())
+    }
+
+    /// Writes a byte to OAM at the current OAMADDR and increments OAMADDR
+    ///
+    /// This is how both OAMDATA ($2004) writes and OAM DMA transfers update OAM
+    pub fn write_oam(&mut self, val: u8) {
+        self.oam[self.registers.oamaddr as usize] = val;
+        self.registers.oamaddr = self.registers.oamaddr.wrapping_add(1);
+    }
+
+    /// Emulates the execution of PPU cycles until the PPU catches up with the CPU
+    ///
+    /// The PPU runs one dot at a time, `DOTS_PER_CPU_CYCLE` dots for every CPU cycle, until the
+    /// PPU clock reaches the dot corresponding to `cpu_cycle`.
+    pub fn step<M>(&mut self, cpu_cycle: u64, bus: &mut M) -> Result<()> where M: mem::Memory<u16> {
+        // Service any PPUDATA read that has occurred since the last step
+        if let Some(addr) = self.pending_read.get() {
+            self.read_buffer.set(try!(bus.get_u8(addr)));
+            self.pending_read.set(None);
+        }
+
+        let target_dot = cpu_cycle * DOTS_PER_CPU_CYCLE;
+        while self.clock.get() < target_dot {
+            try!(self.tick(bus));
+        }
+        Ok(())
+    }
+
+    /// Gets the scanline the PPU is currently processing, from 0 to `PRERENDER_SCANLINE`
+    pub fn scanline(&self) -> usize {
+        self.scanline
+    }
+
+    /// Gets the dot within the current scanline that the PPU will process next
+    pub fn dot(&self) -> usize {
+        self.dot
+    }
 
-            if self.current_scanline < SCANLINES_PER_FRAME {
-                // Render the scanline
-                debug!("rendering scanline {}", self.current_scanline);
+    /// Emulates a single dot (PPU cycle)
+    fn tick<M>(&mut self, bus: &mut M) -> Result<()> where M: mem::Memory<u16> {
+        let visible = self.scanline < SCANLINES_PER_FRAME;
+        let prerender = self.scanline == PRERENDER_SCANLINE;
+        let rendering = self.registers.ppumask.rendering();
+        let dot = self.dot;
 
-                let start = PIXELS_PER_SCANLINE * self.current_scanline;
-                let end = start + PIXELS_PER_SCANLINE;
-                assert!(start < end && start < BYTES_PER_SCREEN && end < BYTES_PER_SCREEN);
+        if prerender && dot == 1 {
+            self.end_frame();
+        } else if self.scanline == VBLANK_SCANLINE && dot == 1 {
+            self.start_vblank();
+        }
 
-                let scanline_screen = screen[start .. end];
+        // Produce the pixel for this dot before the shift registers move on
+        if visible && dot >= 1 && dot <= PIXELS_PER_SCANLINE {
+            self.output_pixel(dot - 1);
+        }
+
+        if (visible || prerender) && rendering {
+            try!(self.fetch(bus, prerender));
+        }
 
-                try!(self.render_scanline(mem, scanline_screen));
-            } else if self.current_scanline == VBLANK_SCANLINE {
-                debug!("vblank starting");
-                self.start_vblank();
-            } else if self.current_scanline == END_SCANLINE {
-                debug!("frame completed");
-                self.end_frame();
+        // Advance to the next dot, skipping the last dot of the pre-render line on odd frames
+        self.clock.tick(1);
+        if prerender && dot == DOTS_PER_SCANLINE as usize - 2 && self.odd_frame && rendering {
+            self.dot = DOTS_PER_SCANLINE as usize;
+        } else {
+            self.dot += 1;
+        }
+        if self.dot >= DOTS_PER_SCANLINE as usize {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline > PRERENDER_SCANLINE {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
             }
+        }
+        Ok(())
+    }
 
-            // Advance to the next scanline
-            self.current_scanline += 1;
+    /// Performs the memory fetches and scroll register updates for the current dot of a
+    /// rendering scanline
+    ///
+    /// Each 8-dot group fetches the nametable byte, attribute byte and the two pattern bytes
+    /// for one tile, with each fetch taking two dots. Dots 1-256 fetch the tiles for the current
+    /// line, dots 321-336 prefetch the first two tiles of the next line. The shift registers move
+    /// one bit at the end of every fetching dot, and the latched tile is loaded in to them at the
+    /// start of each group.
+    ///
+    /// The hardware also makes fetches whose results are never used: two nametable fetches in
+    /// place of the nametable and attribute fetches of each sprite, and two at the end of the
+    /// line. These are made as well, as some mappers watch the bus to count scanlines.
+    fn fetch<M>(&mut self, bus: &mut M, prerender: bool) -> Result<()> where M: mem::Memory<u16> {
+        let dot = self.dot;
+        let fetching = (dot >= 1 && dot <= 256) || (dot >= 321 && dot <= 336);
 
-            self.clock.tick(CYCLES_PER_SCANLINE);
+        if fetching {
+            let v = self.registers.v.get();
+            match dot % 8 {
+                1 => {
+                    self.bg.reload();
+                    self.bg.nametable = try!(bus.get_u8(NAMETABLE_BASE | (v & 0x0FFF)));
+                },
+                3 => {
+                    let attribute_addr = ATTRIBUTE_TABLE_BASE | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+                    let shift = ((v >> 4) & 0x04) | (v & 0x02);
+                    self.bg.attribute = (try!(bus.get_u8(attribute_addr)) >> shift) & 0x03;
+                },
+                5 => {
+                    let addr = self.bg_pattern_addr();
+                    self.bg.pattern_lo = try!(bus.get_u8(addr));
+                },
+                7 => {
+                    let addr = self.bg_pattern_addr() + 8;
+                    self.bg.pattern_hi = try!(bus.get_u8(addr));
+                },
+                0 => self.increment_coarse_x(),
+                _ => {}
+            }
+            self.bg.shift();
+        } else if dot == 337 || dot == 339 {
+            if dot == 337 {
+                // The second prefetched tile is loaded once its fetches are complete
+                self.bg.reload();
+            }
+            let v = self.registers.v.get();
+            try!(bus.get_u8(NAMETABLE_BASE | (v & 0x0FFF)));
+        } else if dot >= 257 && dot <= 320 {
+            // Fetch the patterns for the sprites on the next line, 8 dots per sprite
+            self.registers.oamaddr = 0;
+            let index = (dot - 257) / 8;
+            match (dot - 257) % 8 {
+                0 | 2 => {
+                    let v = self.registers.v.get();
+                    try!(bus.get_u8(NAMETABLE_BASE | (v & 0x0FFF)));
+                },
+                4 => {
+                    let addr = self.sprite_pattern_addr(index);
+                    let lo = try!(bus.get_u8(addr));
+                    self.load_sprite(index, lo, true);
+                },
+                6 => {
+                    let addr = self.sprite_pattern_addr(index) + 8;
+                    let hi = try!(bus.get_u8(addr));
+                    self.load_sprite(index, hi, false);
+                },
+                _ => {}
+            }
+            if dot == 320 {
+                self.sprite_count = self.secondary_count;
+                self.sprite_0_on_line = self.secondary_has_sprite_0;
+            }
         }
+
+        if dot == 256 {
+            self.increment_y();
+            if prerender {
+                // No sprites are evaluated on the pre-render line, so none are drawn on line 0
+                self.secondary_count = 0;
+                self.secondary_has_sprite_0 = false;
+            } else {
+                self.evaluate_sprites();
+            }
+        } else if dot == 257 {
+            // Copy the horizontal scroll position from t
+            let v = (self.registers.v.get() & 0xFBE0) | (self.registers.t & 0x041F);
+            self.registers.v.set(v);
+        } else if prerender && dot >= 280 && dot <= 304 {
+            // Copy the vertical scroll position from t
+            let v = (self.registers.v.get() & 0x841F) | (self.registers.t & 0x7BE0);
+            self.registers.v.set(v);
+        }
+        Ok(())
+    }
+
+    fn bg_pattern_addr(&self) -> u16 {
+        let fine_y = (self.registers.v.get() >> 12) & 0x07;
+        self.registers.ppuctrl.bg_pattern_table + ((self.bg.nametable as u16) * 16) + fine_y
     }
 
-    fn get_pixel(&self, index: usize) -> Pixel {
-        assert!(index * 3 + 2 < PALETTE.len());
-        Pixel {
-            red: PALETTE[index * 3 + 2],
-            green: PALETTE[index * 3 + 1],
-            blue: PALETTE[index * 3]
+    /// Finds the sprites in range of the next scanline and copies them in to secondary OAM
+    ///
+    /// Once 8 sprites have been found, the hardware continues to scan OAM to set the sprite
+    /// overflow flag. Due to a hardware bug, it increments both the sprite index and the byte
+    /// within the sprite when a sprite is out of range, so the overflow flag can be set by
+    /// a sprite that isn't on the line, or not set by one that is.
+    fn evaluate_sprites(&mut self) {
+        let height = self.sprite_height();
+        let line = self.scanline;
+        let in_range = |y: u8| line >= y as usize && line - (y as usize) < height;
+
+        self.secondary_count = 0;
+        self.secondary_has_sprite_0 = false;
+        let mut n = 0;
+        while n < SPRITES_IN_OAM && self.secondary_count < SPRITES_PER_SCANLINE {
+            if in_range(self.oam[n * 4]) {
+                let start = self.secondary_count * 4;
+                self.secondary_oam[start .. start + 4].copy_from_slice(&self.oam[n * 4 .. n * 4 + 4]);
+                self.secondary_count += 1;
+                if n == 0 {
+                    self.secondary_has_sprite_0 = true;
+                }
+            }
+            n += 1;
+        }
+
+        let mut m = 0;
+        while n < SPRITES_IN_OAM {
+            if in_range(self.oam[n * 4 + m]) {
+                self.registers.ppustatus.sprite_overflow = true;
+                break;
+            }
+            n += 1;
+            m = (m + 1) & 0x03;
         }
     }
 
-    fn get_background(&mut self, mem: &mut mem::Memory, x: usize, y: usize) -> Option<Pixel> {
-        // Determine active nametable
-        let nametable = self.registers.ppuctrl.nametable;
-        assert!(nametable == 0x2000 || nametable == 0x2400 || nametable == 0x2800 || nametable == 0x2C00);
+    fn sprite_height(&self) -> usize {
+        if self.registers.ppuctrl.large_sprites { 16 } else { 8 }
+    }
 
-        // Calculate nametable cell and tile offset
-        let (col, tile_x) = (x / 8, x % 8);
-        let (row, tile_y) = (y / 8, y % 8);
+    /// Calculates the address of the current row of the sprite in the provided secondary OAM slot
+    ///
+    /// Empty slots fetch the pattern for tile $FF, just like the hardware does
+    fn sprite_pattern_addr(&self, index: usize) -> u16 {
+        if index >= self.secondary_count {
+            return if self.registers.ppuctrl.large_sprites { 0x1FF0 } else { self.registers.ppuctrl.sprite_pattern_table + 0x0FF0 };
+        }
 
-        // Load tile from nametable
-        let tile_index = try!(mem.get_u8(nametable + (row * TILES_PER_SCANLINE) + col));
+        let y = self.secondary_oam[index * 4] as usize;
+        let tile = self.secondary_oam[index * 4 + 1] as u16;
+        let attributes = self.secondary_oam[index * 4 + 2];
+        let height = self.sprite_height();
 
-        // Load palette index from the pattern table
-        let pattern_table = self.registers.ppuctrl.bg_pattern_table;
-        let pattern_color = self.get_pattern_value(pattern_table, tile_index, tile_x, tile_y);
-        if pattern_color == 0 {
-            // Pattern has no value here, background is transparent.
-            return None;
+        let mut row = self.scanline - y;
+        if attributes & 0x80 != 0 {
+            // Flip vertically
+            row = height - 1 - row;
         }
 
-        // Load attribute table
-        let attribute_group = row / 4 * 8 + col / 4;
-        let attribute = try!(mem.get_u8(nametable + 0x3C0 + attribute_group));
-        let palette_index = match (col % 4 < 2, row % 4 < 2) {
-            (true, true) => attribute & 0x0003,
-            (false, true) => (attribute >> 2) & 0x0003,
-            (true, false) => (attribute >> 4) & 0x0003,
-            (false, false) => (attribute >> 6) & 0x0003
+        let (table, tile) = if height == 16 {
+            // 8x16 sprites take their pattern table from bit 0 of the tile number
+            let top = tile & 0xFE;
+            ((tile & 0x01) * 0x1000, if row >= 8 { top + 1 } else { top })
+        } else {
+            (self.registers.ppuctrl.sprite_pattern_table, tile)
         };
+        table + tile * 16 + (row & 0x07) as u16
+    }
+
+    fn load_sprite(&mut self, index: usize, pattern: u8, low: bool) {
+        if index >= self.secondary_count {
+            self.sprites[index] = Sprite::new();
+            return;
+        }
 
-        // Load the colour out of the palette
-        let color_offset = (palette_index << 2) | pattern_color;
-        let color = try!(mem.get_u8(BG_PALETTE_BASE + color_offset));
-        Some(self.get_color(color))
+        let attributes = self.secondary_oam[index * 4 + 2];
+        let pattern = if attributes & 0x40 != 0 { flip_byte(pattern) } else { pattern };
+        let sprite = &mut self.sprites[index];
+        sprite.attributes = attributes;
+        sprite.x = self.secondary_oam[index * 4 + 3];
+        if low {
+            sprite.pattern_lo = pattern;
+        } else {
+            sprite.pattern_hi = pattern;
+        }
     }
 
-    fn render_scanline(&mut self, mem: &mut mem::Memory, screen: &mut [u8]) -> Result<()> {
-        let mut cursor = 0;
+    /// Finds the first opaque sprite pixel at the provided X coordinate
+    ///
+    /// Returns the color number, palette number, priority bit and whether the pixel came from
+    /// sprite 0
+    fn sprite_pixel(&self, x: usize) -> Option<(u8, u8, bool, bool)> {
+        for (i, sprite) in self.sprites[0 .. self.sprite_count].iter().enumerate() {
+            let start = sprite.x as usize;
+            if x < start || x >= start + PIXELS_PER_TILE {
+                continue;
+            }
+            let bit = 7 - (x - start);
+            let color = ((sprite.pattern_lo >> bit) & 0x01) | (((sprite.pattern_hi >> bit) & 0x01) << 1);
+            if color != 0 {
+                return Some((color, sprite.attributes & 0x03, sprite.attributes & 0x20 != 0, i == 0 && self.sprite_0_on_line));
+            }
+        }
+        None
+    }
+
+    fn output_pixel(&mut self, x: usize) {
+        let mut color = 0;
+        let mut palette = 0;
+        if self.registers.ppumask.background &&
+            (x >= PIXELS_PER_TILE || self.registers.ppumask.leftmost_background) {
+            let (c, p) = self.bg.pixel(self.registers.x);
+            color = c;
+            palette = p;
+        }
+        let mut palette_base = PALETTE_BASE;
 
-        let backdrop_index = try!(mem.get_u8(BACKDROP_COLOR_ADDR));
-        let backdrop = self.get_pixel(backdrop_index);
+        if self.registers.ppumask.sprites &&
+            (x >= PIXELS_PER_TILE || self.registers.ppumask.leftmost_sprites) {
+            if let Some((sprite_color, sprite_palette, behind, sprite_0)) = self.sprite_pixel(x) {
+                // Sprite 0 hit never occurs at the right-most pixel
+                if sprite_0 && color != 0 && x != PIXELS_PER_SCANLINE - 1 {
+                    self.registers.ppustatus.sprite_0_hit = true;
+                }
 
-        for x in 0 .. PIXELS_PER_SCANLINE {
-            let background = if self.ppumask.background {
-                self.get_background(mem, x, self.current_scanline)
-            } else {
-                None
-            };
+                if color == 0 || !behind {
+                    color = sprite_color;
+                    palette = sprite_palette;
+                    palette_base = SPRITE_PALETTE_BASE;
+                }
+            }
+        }
+
+        // Determine the visible pixel. Color 0 of every palette shows the backdrop color.
+        let rgb = if color == 0 {
+            self.get_color(PALETTE_BASE)
+        } else {
+            self.get_color(palette_base | ((palette as u16) << 2) | (color as u16))
+        };
+
+        // Put it to the screen
+        let offset = (self.scanline * PIXELS_PER_SCANLINE + x) * BYTES_PER_PIXEL;
+        self.screen[offset .. offset + BYTES_PER_PIXEL].copy_from_slice(&rgb);
+    }
+
+    fn increment_v(&self) {
+        let v = self.registers.v.get().wrapping_add(self.registers.ppuctrl.vram_increment) & 0x7FFF;
+        self.registers.v.set(v);
+    }
 
-            // TODO: Sprites
+    fn palette_index(addr: u16) -> usize {
+        let index = (addr & 0x001F) as usize;
+        if index & 0x13 == 0x10 {
+            // $3F10, $3F14, $3F18 and $3F1C mirror the background entries
+            index & 0x0F
+        } else {
+            index
+        }
+    }
 
-            // Determine the visible pixel
-            let pixel = match background {
-                Some(pix) => pix,
-                None => backdrop
-            };
+    fn read_palette(&self, addr: u16) -> u8 {
+        self.palette[Rp2C02::palette_index(addr)]
+    }
+
+    fn write_palette(&mut self, addr: u16, val: u8) {
+        self.palette[Rp2C02::palette_index(addr)] = val & 0x3F;
+    }
 
-            // Put it to the screen
-            screen[x * 3] = pixel.red;
-            screen[x * 3 + 1] = pixel.green;
-            screen[x * 3 + 2] = pixel.blue;
+    fn get_color(&self, palette_addr: u16) -> [u8; 3] {
+        let mut index = self.read_palette(palette_addr) as usize;
+        if self.registers.ppumask.greyscale {
+            index &= 0x30;
         }
+        [PALETTE[index * 3], PALETTE[index * 3 + 1], PALETTE[index * 3 + 2]]
+    }
+
+    fn increment_coarse_x(&self) {
+        let v = self.registers.v.get();
+        let v = if v & 0x001F == 31 {
+            // Wrap around and switch horizontal nametable
+            (v & !0x001F) ^ 0x0400
+        } else {
+            v + 1
+        };
+        self.registers.v.set(v);
+    }
+
+    fn increment_y(&self) {
+        let mut v = self.registers.v.get();
+        if v & 0x7000 != 0x7000 {
+            v += 0x1000;
+        } else {
+            v &= !0x7000;
+            let mut coarse_y = (v & 0x03E0) >> 5;
+            if coarse_y == 29 {
+                // Wrap around and switch vertical nametable
+                coarse_y = 0;
+                v ^= 0x0800;
+            } else if coarse_y == 31 {
+                // Coarse Y can be set out of bounds, in which case it wraps without switching
+                coarse_y = 0;
+            } else {
+                coarse_y += 1;
+            }
+            v = (v & !0x03E0) | (coarse_y << 5);
+        }
+        self.registers.v.set(v);
     }
 
     fn start_vblank(&mut self) {
-        unimplemented!()
+        trace!(self.log, "frame" => self.frame; "vblank starting");
+        self.registers.ppustatus.vertical_blank.set(true);
+        if self.registers.ppuctrl.generate_nmi {
+            self.nmi = true;
+        }
+
+        // The visible portion of the frame is complete
+        self.frame += 1;
     }
 
     fn end_frame(&mut self) {
-        unimplemented!()
+        trace!(self.log, "frame" => self.frame; "vblank ending");
+        self.registers.ppustatus.vertical_blank.set(false);
+        self.registers.ppustatus.sprite_0_hit = false;
+        self.registers.ppustatus.sprite_overflow = false;
+    }
+}
+
+/// Holds the data for one of the sprites being drawn on the current scanline
+#[derive(Copy,Clone)]
+struct Sprite {
+    x: u8,
+    attributes: u8,
+    pattern_lo: u8,
+    pattern_hi: u8
+}
+
+impl Sprite {
+    fn new() -> Sprite {
+        Sprite {
+            x: 0xFF,
+            attributes: 0,
+            pattern_lo: 0,
+            pattern_hi: 0
+        }
+    }
+}
+
+/// Reverses the order of the bits in a byte, to flip a row of a pattern horizontally
+fn flip_byte(val: u8) -> u8 {
+    let mut result = 0;
+    for i in 0 .. 8 {
+        if val & (1 << i) != 0 {
+            result |= 0x80 >> i;
+        }
+    }
+    result
+}
+
+/// Holds the latches and shift registers used to render the background
+///
+/// The 16-bit pattern shift registers hold the pattern bits for two tiles, the tile being drawn
+/// in the upper 8 bits and the next tile in the lower 8 bits. The attribute shift registers work
+/// the same way, with the 2-bit palette number for a tile expanded to fill 8 bits.
+struct Background {
+    nametable: u8,
+    attribute: u8,
+    pattern_lo: u8,
+    pattern_hi: u8,
+    pattern_shift_lo: u16,
+    pattern_shift_hi: u16,
+    attribute_shift_lo: u16,
+    attribute_shift_hi: u16
+}
+
+impl Background {
+    fn new() -> Background {
+        Background {
+            nametable: 0,
+            attribute: 0,
+            pattern_lo: 0,
+            pattern_hi: 0,
+            pattern_shift_lo: 0,
+            pattern_shift_hi: 0,
+            attribute_shift_lo: 0,
+            attribute_shift_hi: 0
+        }
+    }
+
+    fn shift(&mut self) {
+        self.pattern_shift_lo <<= 1;
+        self.pattern_shift_hi <<= 1;
+        self.attribute_shift_lo <<= 1;
+        self.attribute_shift_hi <<= 1;
+    }
+
+    /// Loads the latched tile in to the lower 8 bits of the shift registers
+    fn reload(&mut self) {
+        self.pattern_shift_lo = (self.pattern_shift_lo & 0xFF00) | (self.pattern_lo as u16);
+        self.pattern_shift_hi = (self.pattern_shift_hi & 0xFF00) | (self.pattern_hi as u16);
+        self.attribute_shift_lo = (self.attribute_shift_lo & 0xFF00) |
+            (if self.attribute & 0x01 != 0 { 0x00FF } else { 0x0000 });
+        self.attribute_shift_hi = (self.attribute_shift_hi & 0xFF00) |
+            (if self.attribute & 0x02 != 0 { 0x00FF } else { 0x0000 });
+    }
+
+    /// Gets the color number and palette number of the current pixel, using the provided fine X scroll
+    fn pixel(&self, fine_x: u8) -> (u8, u8) {
+        let bit = 15 - fine_x as u16;
+        let color = (((self.pattern_shift_lo >> bit) & 0x01) | (((self.pattern_shift_hi >> bit) & 0x01) << 1)) as u8;
+        let palette = (((self.attribute_shift_lo >> bit) & 0x01) | (((self.attribute_shift_hi >> bit) & 0x01) << 1)) as u8;
+        (color, palette)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use mem::{self,Memory};
+    use hw::rp2C02::{Rp2C02,Register};
+
+    #[test]
+    pub fn reading_ppustatus_clears_vblank_and_write_toggle() {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = mem::Fixed::new(0x4000);
+        ppu.registers.ppustatus.vertical_blank.set(true);
+        ppu.write_register(Register::PpuAddr, 0x21, &mut bus).unwrap();
+
+        assert_eq!(0x80, ppu.read_register(Register::PpuStatus) & 0xE0);
+        assert_eq!(0x00, ppu.read_register(Register::PpuStatus) & 0xE0);
+        assert!(!ppu.registers.w.get());
+    }
+
+    #[test]
+    pub fn ppuaddr_writes_high_byte_then_low_byte() {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = mem::Fixed::new(0x4000);
+        ppu.write_register(Register::PpuAddr, 0x21, &mut bus).unwrap();
+        ppu.write_register(Register::PpuAddr, 0x08, &mut bus).unwrap();
+        assert_eq!(0x2108, ppu.registers.v.get());
+    }
+
+    #[test]
+    pub fn ppuscroll_shares_write_toggle_with_ppuaddr() {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = mem::Fixed::new(0x4000);
+        ppu.write_register(Register::PpuScroll, 0x7D, &mut bus).unwrap();
+        ppu.write_register(Register::PpuAddr, 0x08, &mut bus).unwrap();
+        assert_eq!(0x0008, ppu.registers.v.get());
+        assert_eq!(0x05, ppu.registers.x);
+        assert!(!ppu.registers.w.get());
+    }
+
+    #[test]
+    pub fn ppudata_reads_are_buffered() {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = mem::Fixed::new(0x4000);
+        bus.set_u8(0x2108u16, 42).unwrap();
+        bus.set_u8(0x2109u16, 24).unwrap();
+        ppu.write_register(Register::PpuAddr, 0x21, &mut bus).unwrap();
+        ppu.write_register(Register::PpuAddr, 0x08, &mut bus).unwrap();
+
+        ppu.read_register(Register::PpuData);
+        ppu.step(0, &mut bus).unwrap();
+        assert_eq!(42, ppu.read_register(Register::PpuData));
+        ppu.step(0, &mut bus).unwrap();
+        assert_eq!(24, ppu.read_register(Register::PpuData));
+    }
+
+    #[test]
+    pub fn ppudata_writes_go_to_bus_and_increment_address() {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = mem::Fixed::new(0x4000);
+        ppu.write_register(Register::PpuCtrl, 0x04, &mut bus).unwrap();
+        ppu.write_register(Register::PpuAddr, 0x20, &mut bus).unwrap();
+        ppu.write_register(Register::PpuAddr, 0x00, &mut bus).unwrap();
+        ppu.write_register(Register::PpuData, 42, &mut bus).unwrap();
+        ppu.write_register(Register::PpuData, 24, &mut bus).unwrap();
+
+        assert_eq!(Ok(42), bus.get_u8(0x2000u16));
+        assert_eq!(Ok(24), bus.get_u8(0x2020u16));
+    }
+
+    #[test]
+    pub fn palette_reads_are_not_buffered() {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = mem::Fixed::new(0x4000);
+        ppu.write_register(Register::PpuAddr, 0x3F, &mut bus).unwrap();
+        ppu.write_register(Register::PpuAddr, 0x01, &mut bus).unwrap();
+        ppu.write_register(Register::PpuData, 0x2A, &mut bus).unwrap();
+        ppu.write_register(Register::PpuAddr, 0x3F, &mut bus).unwrap();
+        ppu.write_register(Register::PpuAddr, 0x01, &mut bus).unwrap();
+        assert_eq!(0x2A, ppu.read_register(Register::PpuData) & 0x3F);
+    }
+
+    #[test]
+    pub fn vblank_is_set_and_nmi_requested_at_scanline_241() {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = mem::Fixed::new(0x4000);
+        ppu.write_register(Register::PpuCtrl, 0x80, &mut bus).unwrap();
+
+        // Run until the end of scanline 241
+        ppu.step(242 * 341 / 3 + 1, &mut bus).unwrap();
+        assert!(ppu.registers.ppustatus.vertical_blank.get());
+        assert!(ppu.take_nmi());
+        assert!(!ppu.take_nmi());
+    }
+
+    #[test]
+    pub fn enabling_nmi_during_vblank_requests_nmi() {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = mem::Fixed::new(0x4000);
+        ppu.registers.ppustatus.vertical_blank.set(true);
+        ppu.write_register(Register::PpuCtrl, 0x80, &mut bus).unwrap();
+        assert!(ppu.take_nmi());
+    }
+
+    fn run_to_frame(ppu: &mut Rp2C02, bus: &mut mem::Fixed, frame: u64) {
+        while ppu.frame() < frame {
+            ppu.tick(bus).unwrap();
+        }
+    }
+
+    #[test]
+    pub fn odd_frames_skip_a_dot_when_rendering() {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = mem::Fixed::new(0x4000);
+        ppu.write_register(Register::PpuMask, 0x08, &mut bus).unwrap();
+
+        run_to_frame(&mut ppu, &mut bus, 1);
+        let first = ppu.cycles();
+        run_to_frame(&mut ppu, &mut bus, 2);
+        let second = ppu.cycles();
+        run_to_frame(&mut ppu, &mut bus, 3);
+        let third = ppu.cycles();
+
+        assert_eq!(341 * 262 - 1, third - first - (341 * 262));
+        assert!(second - first == 341 * 262 || third - second == 341 * 262);
+    }
+
+    #[test]
+    pub fn frames_are_full_length_when_not_rendering() {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = mem::Fixed::new(0x4000);
+
+        run_to_frame(&mut ppu, &mut bus, 1);
+        let first = ppu.cycles();
+        run_to_frame(&mut ppu, &mut bus, 3);
+        assert_eq!(2 * 341 * 262, ppu.cycles() - first);
+    }
+
+    /// A bus that records the address of every read made through it
+    struct RecordingBus {
+        mem: mem::Fixed,
+        reads: RefCell<Vec<u16>>
+    }
+
+    impl mem::Memory<u16> for RecordingBus {
+        fn len(&self) -> u64 { self.mem.len() }
+
+        fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+            self.reads.borrow_mut().push(addr);
+            self.mem.get_u8(addr)
+        }
+
+        fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+            self.mem.set_u8(addr, val)
+        }
+    }
+
+    #[test]
+    pub fn rendering_scanline_makes_every_fetch() {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = RecordingBus { mem: mem::Fixed::new(0x4000), reads: RefCell::new(Vec::new()) };
+        ppu.write_register(Register::PpuMask, 0x18, &mut bus).unwrap();
+        bus.reads.borrow_mut().clear();
+
+        // Run through the whole of scanline 0
+        ppu.step(341 / 3 + 1, &mut bus).unwrap();
+        let reads = bus.reads.borrow();
+        let nametable = reads.iter().filter(|&&a| a >= 0x2000 && a & 0x03FF < 0x03C0).count();
+
+        // 34 tiles of 4 fetches, 8 sprites of 4 fetches, and 2 extra nametable fetches
+        assert_eq!(34 * 4 + 8 * 4 + 2, reads.len());
+        assert_eq!(34 + 8 * 2 + 2, nametable);
+    }
+
+    #[test]
+    pub fn horizontal_scroll_is_copied_at_dot_257() {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = mem::Fixed::new(0x4000);
+        ppu.write_register(Register::PpuMask, 0x08, &mut bus).unwrap();
+        ppu.registers.t = 0x0415;
+
+        // Run through dot 257 of scanline 0
+        ppu.step(86, &mut bus).unwrap();
+        assert_eq!(0x0415, ppu.registers.v.get() & 0x041F);
+    }
+
+    #[test]
+    pub fn vertical_scroll_is_copied_during_prerender_scanline() {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = mem::Fixed::new(0x4000);
+        ppu.write_register(Register::PpuMask, 0x08, &mut bus).unwrap();
+        ppu.registers.t = 0x7BE0;
+
+        // Run through dot 304 of the pre-render scanline
+        ppu.step((261 * 341 + 305) / 3 + 1, &mut bus).unwrap();
+        assert_eq!(0x7BE0, ppu.registers.v.get() & 0x7BE0);
+    }
+
+    #[test]
+    pub fn background_pixels_are_rendered_with_fine_x_scroll() {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = mem::Fixed::new(0x4000);
+
+        // Tile 1 has a single opaque pixel in the top-left corner, and is the first tile on screen
+        bus.set_u8(0x0010u16, 0x80).unwrap();
+        bus.set_u8(0x2000u16, 0x01).unwrap();
+        ppu.write_palette(0x3F00, 0x0F);
+        ppu.write_palette(0x3F01, 0x30);
+        ppu.write_register(Register::PpuMask, 0x0A, &mut bus).unwrap();
+
+        run_to_frame(&mut ppu, &mut bus, 2);
+        assert_eq!(&[252, 252, 252], &ppu.screen()[0..3]);
+        assert_eq!(&[0, 0, 0], &ppu.screen()[3..6]);
+
+        // Scrolling right by one pixel moves the opaque pixel off screen
+        ppu.write_register(Register::PpuScroll, 0x01, &mut bus).unwrap();
+        ppu.write_register(Register::PpuScroll, 0x00, &mut bus).unwrap();
+        run_to_frame(&mut ppu, &mut bus, 3);
+        assert_eq!(&[0, 0, 0], &ppu.screen()[0..3]);
+    }
+
+    fn sprite_test_setup() -> (Rp2C02, mem::Fixed) {
+        let mut ppu = Rp2C02::new(None);
+        let mut bus = mem::Fixed::new(0x4000);
+
+        // Tile 1 is a solid block of color 1, and fills the background
+        for row in 0 .. 8 {
+            bus.set_u8(0x0010u16 + row, 0xFF).unwrap();
+        }
+        for addr in 0x2000 .. 0x23C0 {
+            bus.set_u8(addr as u16, 0x01).unwrap();
+        }
+
+        // Tile 2 has a single opaque pixel in the top-left corner
+        bus.set_u8(0x0020u16, 0x80).unwrap();
+
+        // Move every sprite off screen
+        for i in 0 .. 64 {
+            ppu.oam[i * 4] = 0xFF;
+        }
+
+        ppu.write_palette(0x3F00, 0x0F);
+        ppu.write_palette(0x3F01, 0x30);
+        ppu.write_palette(0x3F11, 0x16);
+        (ppu, bus)
+    }
+
+    fn pixel(ppu: &Rp2C02, x: usize, y: usize) -> [u8; 3] {
+        let offset = (y * 256 + x) * 3;
+        [ppu.screen()[offset], ppu.screen()[offset + 1], ppu.screen()[offset + 2]]
+    }
+
+    #[test]
+    pub fn sprite_0_hit_is_set_when_opaque_pixels_overlap() {
+        let (mut ppu, mut bus) = sprite_test_setup();
+        ppu.oam[0 .. 4].copy_from_slice(&[19, 2, 0, 40]);
+        ppu.write_register(Register::PpuMask, 0x1E, &mut bus).unwrap();
+
+        run_to_frame(&mut ppu, &mut bus, 1);
+        while ppu.scanline() != 20 || ppu.dot() != 41 {
+            ppu.tick(&mut bus).unwrap();
+        }
+        assert!(!ppu.registers.ppustatus.sprite_0_hit);
+        ppu.tick(&mut bus).unwrap();
+        assert!(ppu.registers.ppustatus.sprite_0_hit);
+    }
+
+    #[test]
+    pub fn sprite_0_hit_is_not_set_over_transparent_background() {
+        let (mut ppu, mut bus) = sprite_test_setup();
+        ppu.oam[0 .. 4].copy_from_slice(&[19, 2, 0, 40]);
+        ppu.write_register(Register::PpuMask, 0x10, &mut bus).unwrap();
+
+        run_to_frame(&mut ppu, &mut bus, 2);
+        assert!(!ppu.registers.ppustatus.sprite_0_hit);
+    }
+
+    #[test]
+    pub fn sprite_is_drawn_in_front_of_background() {
+        let (mut ppu, mut bus) = sprite_test_setup();
+        ppu.oam[4 .. 8].copy_from_slice(&[19, 2, 0, 40]);
+        ppu.write_register(Register::PpuMask, 0x1E, &mut bus).unwrap();
+
+        run_to_frame(&mut ppu, &mut bus, 2);
+        assert_eq!([248, 56, 0], pixel(&ppu, 40, 20));
+        assert_eq!([252, 252, 252], pixel(&ppu, 41, 20));
+    }
+
+    #[test]
+    pub fn sprite_with_priority_bit_is_drawn_behind_background() {
+        let (mut ppu, mut bus) = sprite_test_setup();
+        ppu.oam[4 .. 8].copy_from_slice(&[19, 2, 0x20, 40]);
+        ppu.write_register(Register::PpuMask, 0x1E, &mut bus).unwrap();
+
+        run_to_frame(&mut ppu, &mut bus, 2);
+        assert_eq!([252, 252, 252], pixel(&ppu, 40, 20));
+    }
+
+    #[test]
+    pub fn sprite_can_be_flipped() {
+        let (mut ppu, mut bus) = sprite_test_setup();
+        ppu.oam[4 .. 8].copy_from_slice(&[19, 2, 0xC0, 40]);
+        ppu.write_register(Register::PpuMask, 0x10, &mut bus).unwrap();
+
+        run_to_frame(&mut ppu, &mut bus, 2);
+        assert_eq!([248, 56, 0], pixel(&ppu, 47, 27));
+        assert_eq!([0, 0, 0], pixel(&ppu, 40, 20));
+    }
+
+    #[test]
+    pub fn sprites_in_left_column_are_clipped() {
+        let (mut ppu, mut bus) = sprite_test_setup();
+        ppu.oam[4 .. 8].copy_from_slice(&[19, 2, 0, 0]);
+        ppu.write_register(Register::PpuMask, 0x10, &mut bus).unwrap();
+
+        run_to_frame(&mut ppu, &mut bus, 2);
+        assert_eq!([0, 0, 0], pixel(&ppu, 0, 20));
+    }
+
+    #[test]
+    pub fn sprite_overflow_is_set_when_more_than_8_sprites_are_on_a_line() {
+        let (mut ppu, mut bus) = sprite_test_setup();
+        for i in 0 .. 9 {
+            ppu.oam[i * 4] = 19;
+        }
+        ppu.write_register(Register::PpuMask, 0x10, &mut bus).unwrap();
+
+        run_to_frame(&mut ppu, &mut bus, 1);
+        assert!(ppu.registers.ppustatus.sprite_overflow);
+    }
+
+    #[test]
+    pub fn sprite_overflow_evaluation_reproduces_hardware_bug() {
+        let (mut ppu, mut bus) = sprite_test_setup();
+        for i in 0 .. 8 {
+            ppu.oam[i * 4] = 19;
+        }
+
+        // The ninth sprite isn't on the line, so the tenth sprite's tile number is used as a Y
+        // coordinate, and it is in range
+        ppu.oam[8 * 4] = 100;
+        ppu.oam[9 * 4] = 100;
+        ppu.oam[9 * 4 + 1] = 19;
+        ppu.write_register(Register::PpuMask, 0x10, &mut bus).unwrap();
+
+        run_to_frame(&mut ppu, &mut bus, 1);
+        assert!(ppu.registers.ppustatus.sprite_overflow);
     }
 }
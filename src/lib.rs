@@ -24,6 +24,9 @@ extern crate byteorder;
 #[macro_use]
 mod macros;
 
+/// Contains code to resample, filter and record audio output
+pub mod audio;
+
 /// Contains code to emulate supported Hardware
 pub mod hw;
 
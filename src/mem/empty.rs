@@ -4,16 +4,16 @@ use mem;
 /// an `OutOfBounds` error.
 pub struct Empty;
 
-impl mem::Memory for Empty {
+impl<A> mem::Memory<A> for Empty where A: mem::Address {
     fn len(&self) -> u64 { 0 }
 
     #[allow(unused_variables)]
-    fn get_u8(&self, addr: u64) -> mem::Result<u8> {
+    fn get_u8(&self, addr: A) -> mem::Result<u8> {
         Err(mem::Error::new(mem::ErrorKind::MemoryNotReadable, "EmptyMemory cannot be read from"))
     }
 
     #[allow(unused_variables)]
-    fn set_u8(&mut self, addr: u64, val: u8) -> mem::Result<()> {
+    fn set_u8(&mut self, addr: A, val: u8) -> mem::Result<()> {
         Err(mem::Error::new(mem::ErrorKind::MemoryNotWritable, "EmptyMemory cannot be written to"))
     }
 }
@@ -34,15 +34,34 @@ impl Fixed {
             data: contents.into()
         }
     }
+
+    /// Retrieves the size of the memory
+    ///
+    /// `Fixed` can be addressed by any `mem::Address` type, so this is provided to avoid having
+    /// to name the address type just to get the size.
+    pub fn len(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    /// Gets the contents of the memory
+    pub fn contents(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Gets the contents of the memory, for modification
+    pub fn contents_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
 }
 
-impl mem::Memory for Fixed {
+impl<A> mem::Memory<A> for Fixed where A: mem::Address {
     /// Retrieves the size of the memory.
     fn len(&self) -> u64 {
         self.data.len() as u64
     }
 
-    fn get_u8(&self, addr: u64) -> mem::Result<u8> {
+    fn get_u8(&self, addr: A) -> mem::Result<u8> {
+        let addr = addr.to_u64();
         if addr >= self.data.len() as u64 {
             Err(mem::Error::with_detail(
                 mem::ErrorKind::OutOfBounds,
@@ -54,7 +73,8 @@ impl mem::Memory for Fixed {
         }
     }
 
-    fn set_u8(&mut self, addr: u64, val: u8) -> mem::Result<()> {
+    fn set_u8(&mut self, addr: A, val: u8) -> mem::Result<()> {
+        let addr = addr.to_u64();
         if addr >= self.data.len() as u64 {
             Err(mem::Error::with_detail(
                 mem::ErrorKind::OutOfBounds,
@@ -75,19 +95,19 @@ mod test {
     #[test]
     pub fn get_and_set_work() {
         let mut mem = mem::Fixed::new(10);
-        mem.set_u8(1, 42).ok().expect("set failed");
-        assert_eq!(Ok(42), mem.get_u8(1));
+        mem.set_u8(1u16, 42).ok().expect("set failed");
+        assert_eq!(Ok(42), mem.get_u8(1u16));
     }
 
     #[test]
     pub fn get_returns_err_if_out_of_bounds() {
         let mem = mem::Fixed::new(10);
-        assert_eq!(mem::ErrorKind::OutOfBounds, mem.get_u8(12).unwrap_err().kind);
+        assert_eq!(mem::ErrorKind::OutOfBounds, mem.get_u8(12u16).unwrap_err().kind);
     }
 
     #[test]
     pub fn set_returns_err_if_out_of_bounds() {
         let mut mem = mem::Fixed::new(10);
-        assert_eq!(mem::ErrorKind::OutOfBounds, mem.set_u8(12, 42).unwrap_err().kind);
+        assert_eq!(mem::ErrorKind::OutOfBounds, mem.set_u8(12u16, 42).unwrap_err().kind);
     }
 }
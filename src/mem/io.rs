@@ -1,17 +1,20 @@
 use mem;
 
 use std::{io,convert};
+use std::marker::PhantomData;
 
 /// Cursor which implements the ability to read and seek over memory
-pub struct ReadCursor<'a, M> where M: mem::Memory + 'a {
+pub struct ReadCursor<'a, M, A> where M: mem::Memory<A> + 'a, A: mem::Address {
     inner: &'a M,
-    pos: u64
+    pos: u64,
+    _address: PhantomData<A>
 }
 
 /// Cursor which implements the ability to read, write and seek over memory
-pub struct Cursor<'a, M> where M: mem::Memory + 'a {
+pub struct Cursor<'a, M, A> where M: mem::Memory<A> + 'a, A: mem::Address {
     inner: &'a mut M,
-    pos: u64
+    pos: u64,
+    _address: PhantomData<A>
 }
 
 macro_rules! cursor_impl {
@@ -28,21 +31,21 @@ macro_rules! cursor_impl {
     }
 }
 
-impl<'a, M> ReadCursor<'a, M> where M: mem::Memory + 'a { cursor_impl!{} }
-impl<'a, M> Cursor<'a, M> where M: mem::Memory + 'a { cursor_impl!{} }
+impl<'a, M, A> ReadCursor<'a, M, A> where M: mem::Memory<A> + 'a, A: mem::Address { cursor_impl!{} }
+impl<'a, M, A> Cursor<'a, M, A> where M: mem::Memory<A> + 'a, A: mem::Address { cursor_impl!{} }
 
 macro_rules! read_impl {
     () => {
         fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-            try!(self.inner.get(self.pos, buf));
+            try!(self.inner.get(A::from_u64(self.pos), buf));
             self.pos += buf.len() as u64;
             Ok(buf.len())
         }
     }
 }
 
-impl<'a, M> io::Read for ReadCursor<'a, M> where M: mem::Memory + 'a { read_impl!{} }
-impl<'a, M> io::Read for Cursor<'a, M> where M: mem::Memory + 'a { read_impl!{} }
+impl<'a, M, A> io::Read for ReadCursor<'a, M, A> where M: mem::Memory<A> + 'a, A: mem::Address { read_impl!{} }
+impl<'a, M, A> io::Read for Cursor<'a, M, A> where M: mem::Memory<A> + 'a, A: mem::Address { read_impl!{} }
 
 macro_rules! seek_impl {
     () => {
@@ -64,12 +67,12 @@ macro_rules! seek_impl {
     }
 }
 
-impl<'a, M> io::Seek for ReadCursor<'a, M> where M: mem::Memory + 'a { seek_impl!{} }
-impl<'a, M> io::Seek for Cursor<'a, M> where M: mem::Memory + 'a { seek_impl!{} }
+impl<'a, M, A> io::Seek for ReadCursor<'a, M, A> where M: mem::Memory<A> + 'a, A: mem::Address { seek_impl!{} }
+impl<'a, M, A> io::Seek for Cursor<'a, M, A> where M: mem::Memory<A> + 'a, A: mem::Address { seek_impl!{} }
 
-impl<'a, M> io::Write for Cursor<'a, M> where M: mem::Memory + 'a {
+impl<'a, M, A> io::Write for Cursor<'a, M, A> where M: mem::Memory<A> + 'a, A: mem::Address {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        try!(self.inner.set(self.pos, buf));
+        try!(self.inner.set(A::from_u64(self.pos), buf));
         self.pos += buf.len() as u64;
         Ok(buf.len())
     }
@@ -84,18 +87,20 @@ impl convert::From<mem::Error> for io::Error {
 
 /// Creates a read-only cursor pointing in to memory which allows the consumer to view the memory as
 /// an I/O stream.
-pub fn read_cursor<'a, M>(memory: &'a M, start: u64) -> ReadCursor<'a, M> where M: mem::Memory {
+pub fn read_cursor<'a, M, A>(memory: &'a M, start: A) -> ReadCursor<'a, M, A> where M: mem::Memory<A>, A: mem::Address {
     ReadCursor {
         inner: memory,
-        pos: start
+        pos: start.to_u64(),
+        _address: PhantomData
     }
 }
 
 /// Creates a read/write cursor pointing in to memory which allows the consumer to view the memory as
 /// an I/O stream.
-pub fn cursor<'a, M>(memory: &'a mut M, start: u64) -> Cursor<'a, M> where M: mem::Memory {
+pub fn cursor<'a, M, A>(memory: &'a mut M, start: A) -> Cursor<'a, M, A> where M: mem::Memory<A>, A: mem::Address {
     Cursor {
         inner: memory,
-        pos: start
+        pos: start.to_u64(),
+        _address: PhantomData
     }
 }
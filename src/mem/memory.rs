@@ -1,6 +1,36 @@
 use std::{error,fmt};
 use byteorder::ByteOrder;
 
+/// Represents an integer type that can be used to address a `Memory`
+///
+/// Arithmetic on addresses wraps around at the width of the address type, so on a 16-bit bus the
+/// byte after `$FFFF` is `$0000`.
+pub trait Address: Copy + Eq + Ord + fmt::Debug + fmt::Display + fmt::UpperHex + fmt::LowerHex {
+    /// Converts the address to a `u64`
+    fn to_u64(self) -> u64;
+
+    /// Converts a `u64` to an address, truncating any bits that don't fit in the address type
+    fn from_u64(val: u64) -> Self;
+
+    /// Adds `offset` to the address, wrapping around at the end of the address space
+    fn offset(self, offset: u64) -> Self;
+}
+
+macro_rules! address_impl {
+    ($t: ty) => {
+        impl Address for $t {
+            #[inline] fn to_u64(self) -> u64 { self as u64 }
+            #[inline] fn from_u64(val: u64) -> $t { val as $t }
+            #[inline] fn offset(self, offset: u64) -> $t { self.wrapping_add(offset as $t) }
+        }
+    }
+}
+
+address_impl!(u8);
+address_impl!(u16);
+address_impl!(u32);
+address_impl!(u64);
+
 pub type Result<T> = ::std::result::Result<T, Error>;
 
 /// Represents an error that occurs when accessing a `Memory`
@@ -82,82 +112,89 @@ pub enum ErrorKind {
 ///
 /// Implementations of this may use various sparse storage techniques to avoid
 /// allocating the entire memory buffer, or may use ROM content from files to
-/// back the memory. In the current implementation, the memory may not have an
-/// address size larger than 64-bits. Internally, all addresses are expected to
-/// be provided as 64-bit integers.
-pub trait Memory {
+/// back the memory. The memory is addressed using the address type `A`, which
+/// should match the width of the address bus it is attached to (for example,
+/// `u16` for the MOS 6502).
+pub trait Memory<A: Address = u64> {
     /// Gets the size of the memory
+    ///
+    /// This is always a `u64`, since a memory can fill its entire address space (a 16-bit
+    /// memory map has a length of `0x10000`, which does not fit in a `u16`)
     fn len(&self) -> u64;
 
     /// Reads a single byte from the memory at `addr`
     ///
     /// # Arguments
     /// * `addr` - The address at which to begin reading the data
-    fn get_u8(&self, addr: u64) -> Result<u8>;
+    fn get_u8(&self, addr: A) -> Result<u8>;
 
     /// Writes a single byte `val` to the memory at `addr`
     ///
     /// # Arguments
     /// * `addr` - The address at which to begin writing the data
     /// * `val` - The byte to set
-    fn set_u8(&mut self, addr: u64, val: u8) -> Result<()>;
+    fn set_u8(&mut self, addr: A, val: u8) -> Result<()>;
 
     /// Fills the provided buffer with data from the memory starting at `addr`
-    fn get(&self, addr: u64, buf: &mut [u8]) -> Result<()> {
+    ///
+    /// If the end of the address space is reached, reading continues from address zero
+    fn get(&self, addr: A, buf: &mut [u8]) -> Result<()> {
         for i in 0..buf.len() {
-            buf[i] = try!(self.get_u8(addr + i as u64));
+            buf[i] = try!(self.get_u8(addr.offset(i as u64)));
         }
         Ok(())
     }
 
     /// Writes the provided buffer to the memory starting at `addr`
-    fn set(&mut self, addr: u64, buf: &[u8]) -> Result<()> {
+    ///
+    /// If the end of the address space is reached, writing continues from address zero
+    fn set(&mut self, addr: A, buf: &[u8]) -> Result<()> {
         for i in 0..buf.len() {
-            try!(self.set_u8(addr + i as u64, buf[i]))
+            try!(self.set_u8(addr.offset(i as u64), buf[i]))
         }
         Ok(())
     }
 }
 
 /// Extension trait that provides the ability to read specific values out of memory
-pub trait MemoryExt: Memory {
+pub trait MemoryExt<A: Address = u64>: Memory<A> {
     /// Gets a u16 value, in the specified byte order `B`, from the address specified by `addr`
-    fn get_u16<B>(&self, addr: u64) -> Result<u16> where B: ByteOrder {
+    fn get_u16<B>(&self, addr: A) -> Result<u16> where B: ByteOrder {
         let mut raw = [0u8; 2];
         try!(self.get(addr, &mut raw));
         Ok(<B as ByteOrder>::read_u16(&raw))
     }
 
     /// Gets a i16 value, in the specified byte order `B`, from the address specified by `addr`
-    fn get_i16<B>(&self, addr: u64) -> Result<i16> where B: ByteOrder {
+    fn get_i16<B>(&self, addr: A) -> Result<i16> where B: ByteOrder {
         let mut raw = [0u8; 2];
         try!(self.get(addr, &mut raw));
         Ok(<B as ByteOrder>::read_i16(&raw))
     }
 
     /// Gets a u32 value, in the specified byte order `B`, from the address specified by `addr`
-    fn get_u32<B>(&self, addr: u64) -> Result<u32> where B: ByteOrder {
+    fn get_u32<B>(&self, addr: A) -> Result<u32> where B: ByteOrder {
         let mut raw = [0u8; 4];
         try!(self.get(addr, &mut raw));
         Ok(<B as ByteOrder>::read_u32(&raw))
     }
 
     /// Gets a i32 value, in the specified byte order `B`, from the address specified by `addr`
-    fn get_i32<B>(&self, addr: u64) -> Result<i32> where B: ByteOrder {
+    fn get_i32<B>(&self, addr: A) -> Result<i32> where B: ByteOrder {
         let mut raw = [0u8; 4];
         try!(self.get(addr, &mut raw));
         Ok(<B as ByteOrder>::read_i32(&raw))
     }
 
     /// Gets a u64 value, in the specified byte order `B`, from the address specified by `addr`
-    fn get_u64<B>(&self, addr: u64) -> Result<u64> where B: ByteOrder {
+    fn get_u64<B>(&self, addr: A) -> Result<u64> where B: ByteOrder {
         let mut raw = [0u8; 8];
         try!(self.get(addr, &mut raw));
         Ok(<B as ByteOrder>::read_u64(&raw))
     }
 
     /// Gets a i64 value, in the specified byte order `B`, from the address specified by `addr`
-    fn get_i64<B>(&self, addr: u64) -> Result<i64> where B: ByteOrder {
+    fn get_i64<B>(&self, addr: A) -> Result<i64> where B: ByteOrder {
         let mut raw = [0u8; 8];
         try!(self.get(addr, &mut raw));
         Ok(<B as ByteOrder>::read_i64(&raw))
@@ -165,7 +202,7 @@ pub trait MemoryExt: Memory {
 
     /// Writes the u16 value specified in `val` to the address specified by `addr`, in the
     /// specified byte order `B`
-    fn set_u16<B>(&mut self, addr: u64, val: u16) -> Result<()> where B: ByteOrder {
+    fn set_u16<B>(&mut self, addr: A, val: u16) -> Result<()> where B: ByteOrder {
         let mut buf = [0u8; 2];
         <B as ByteOrder>::write_u16(&mut buf, val);
         try!(self.set(addr, &buf));
@@ -174,7 +211,7 @@ pub trait MemoryExt: Memory {
 
     /// Writes the i16 value specified in `val` to the address specified by `addr`, in the
     /// specified byte order `B`
-    fn set_i16<B>(&mut self, addr: u64, val: i16) -> Result<()> where B: ByteOrder {
+    fn set_i16<B>(&mut self, addr: A, val: i16) -> Result<()> where B: ByteOrder {
         let mut buf = [0u8; 2];
         <B as ByteOrder>::write_i16(&mut buf, val);
         try!(self.set(addr, &buf));
@@ -183,7 +220,7 @@ pub trait MemoryExt: Memory {
 
     /// Writes the u32 value specified in `val` to the address specified by `addr`, in the
     /// specified byte order `B`
-    fn set_u32<B>(&mut self, addr: u64, val: u32) -> Result<()> where B: ByteOrder {
+    fn set_u32<B>(&mut self, addr: A, val: u32) -> Result<()> where B: ByteOrder {
         let mut buf = [0u8; 4];
         <B as ByteOrder>::write_u32(&mut buf, val);
         try!(self.set(addr, &buf));
@@ -192,7 +229,7 @@ pub trait MemoryExt: Memory {
 
     /// Writes the i32 value specified in `val` to the address specified by `addr`, in the
     /// specified byte order `B`
-    fn set_i32<B>(&mut self, addr: u64, val: i32) -> Result<()> where B: ByteOrder {
+    fn set_i32<B>(&mut self, addr: A, val: i32) -> Result<()> where B: ByteOrder {
         let mut buf = [0u8; 4];
         <B as ByteOrder>::write_i32(&mut buf, val);
         try!(self.set(addr, &buf));
@@ -201,7 +238,7 @@ pub trait MemoryExt: Memory {
 
     /// Writes the u64 value specified in `val` to the address specified by `addr`, in the
     /// specified byte order `B`
-    fn set_u64<B>(&mut self, addr: u64, val: u64) -> Result<()> where B: ByteOrder {
+    fn set_u64<B>(&mut self, addr: A, val: u64) -> Result<()> where B: ByteOrder {
         let mut buf = [0u8; 8];
         <B as ByteOrder>::write_u64(&mut buf, val);
         try!(self.set(addr, &buf));
@@ -210,7 +247,7 @@ pub trait MemoryExt: Memory {
 
     /// Writes the i64 value specified in `val` to the address specified by `addr`, in the
     /// specified byte order `B`
-    fn set_i64<B>(&mut self, addr: u64, val: i64) -> Result<()> where B: ByteOrder {
+    fn set_i64<B>(&mut self, addr: A, val: i64) -> Result<()> where B: ByteOrder {
         let mut buf = [0u8; 8];
         <B as ByteOrder>::write_i64(&mut buf, val);
         try!(self.set(addr, &buf));
@@ -218,7 +255,7 @@ pub trait MemoryExt: Memory {
     }
 }
 
-impl<M: Memory + ?Sized> MemoryExt for M {}
+impl<A: Address, M: Memory<A> + ?Sized> MemoryExt<A> for M {}
 
 #[cfg(test)]
 mod test {
@@ -240,94 +277,112 @@ mod test {
     #[test]
     pub fn get_u8_returns_single_byte_at_location() {
         let mut mem = mem::Fixed::new(1);
-        mem.set(0, &[42]).unwrap();
-        assert_eq!(mem.get_u8(0).unwrap(), 42);
+        mem.set(0u16, &[42]).unwrap();
+        assert_eq!(mem.get_u8(0u16).unwrap(), 42);
     }
 
     #[test]
     pub fn set_u8_writes_single_byte_at_location() {
         let mut mem = mem::Fixed::new(1);
-        mem.set_u8(0, 42).unwrap();
+        mem.set_u8(0u16, 42).unwrap();
         let mut buf = [0];
-        mem.get(0, &mut buf).unwrap();
+        mem.get(0u16, &mut buf).unwrap();
         assert_eq!([42], buf);
     }
 
     #[test]
-    pub fn get_be_u16_works() { assert_eq_hex!(0x2345, init_mem_get().get_u16::<BigEndian>(1).unwrap()); }
+    pub fn get_wraps_around_at_end_of_address_space() {
+        let mut mem = mem::Fixed::new(0x100);
+        mem.set_u8(0x00u8, 42).unwrap();
+        mem.set_u8(0xFFu8, 24).unwrap();
+        let mut buf = [0, 0];
+        mem.get(0xFFu8, &mut buf).unwrap();
+        assert_eq!([24, 42], buf);
+    }
+
+    #[test]
+    pub fn set_wraps_around_at_end_of_address_space() {
+        let mut mem = mem::Fixed::new(0x100);
+        mem.set(0xFFu8, &[24, 42]).unwrap();
+        assert_eq!(Ok(24), mem.get_u8(0xFFu8));
+        assert_eq!(Ok(42), mem.get_u8(0x00u8));
+    }
+
+    #[test]
+    pub fn get_be_u16_works() { assert_eq_hex!(0x2345, init_mem_get().get_u16::<BigEndian>(1u16).unwrap()); }
 
     #[test]
-    pub fn get_le_u16_works() { assert_eq_hex!(0x4523, init_mem_get().get_u16::<LittleEndian>(1).unwrap()); }
+    pub fn get_le_u16_works() { assert_eq_hex!(0x4523, init_mem_get().get_u16::<LittleEndian>(1u16).unwrap()); }
 
     #[test]
-    pub fn get_be_u32_works() { assert_eq_hex!(0x23456789, init_mem_get().get_u32::<BigEndian>(1).unwrap()); }
+    pub fn get_be_u32_works() { assert_eq_hex!(0x23456789, init_mem_get().get_u32::<BigEndian>(1u16).unwrap()); }
 
     #[test]
-    pub fn get_le_u32_works() { assert_eq_hex!(0x89674523, init_mem_get().get_u32::<LittleEndian>(1).unwrap()); }
+    pub fn get_le_u32_works() { assert_eq_hex!(0x89674523, init_mem_get().get_u32::<LittleEndian>(1u16).unwrap()); }
 
     #[test]
-    pub fn get_be_u64_works() { assert_eq_hex!(0x23456789ABCDEF00, init_mem_get().get_u64::<BigEndian>(1).unwrap()); }
+    pub fn get_be_u64_works() { assert_eq_hex!(0x23456789ABCDEF00, init_mem_get().get_u64::<BigEndian>(1u16).unwrap()); }
 
     #[test]
-    pub fn get_le_u64_works() { assert_eq_hex!(0x00EFCDAB89674523, init_mem_get().get_u64::<LittleEndian>(1).unwrap()); }
+    pub fn get_le_u64_works() { assert_eq_hex!(0x00EFCDAB89674523, init_mem_get().get_u64::<LittleEndian>(1u16).unwrap()); }
 
     #[test]
     pub fn set_be_u16_works() {
         let mut mem = mem::Fixed::new(10);
-        mem.set_u16::<BigEndian>(1, 0x2345).unwrap();
+        mem.set_u16::<BigEndian>(1u16, 0x2345).unwrap();
         let mut buf = [0, 0];
-        mem.get(1, &mut buf).unwrap();
+        mem.get(1u16, &mut buf).unwrap();
         assert_eq!([0x23, 0x45], buf);
     }
 
     #[test]
     pub fn set_be_u32_works() {
         let mut mem = mem::Fixed::new(10);
-        mem.set_u32::<BigEndian>(1, 0x23456789).unwrap();
+        mem.set_u32::<BigEndian>(1u16, 0x23456789).unwrap();
         let mut buf = [0, 0, 0, 0];
-        mem.get(1, &mut buf).unwrap();
+        mem.get(1u16, &mut buf).unwrap();
         assert_eq!([0x23, 0x45, 0x67, 0x89], buf);
     }
 
     #[test]
     pub fn set_be_u64_works() {
         let mut mem = mem::Fixed::new(10);
-        mem.set_u64::<BigEndian>(1, 0x23456789ABCDEF00).unwrap();
+        mem.set_u64::<BigEndian>(1u16, 0x23456789ABCDEF00).unwrap();
         let mut buf = [0, 0, 0, 0, 0, 0, 0, 0];
-        mem.get(1, &mut buf).unwrap();
+        mem.get(1u16, &mut buf).unwrap();
         assert_eq!([0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF, 0x00], buf);
     }
 
     #[test]
     pub fn set_le_u16_works() {
         let mut mem = mem::Fixed::new(10);
-        mem.set_u16::<LittleEndian>(1, 0x2345).unwrap();
+        mem.set_u16::<LittleEndian>(1u16, 0x2345).unwrap();
         let mut buf = [0, 0];
-        mem.get(1, &mut buf).unwrap();
+        mem.get(1u16, &mut buf).unwrap();
         assert_eq!([0x45, 0x23], buf);
     }
 
     #[test]
     pub fn set_le_u32_works() {
         let mut mem = mem::Fixed::new(10);
-        mem.set_u32::<LittleEndian>(1, 0x23456789).unwrap();
+        mem.set_u32::<LittleEndian>(1u16, 0x23456789).unwrap();
         let mut buf = [0, 0, 0, 0];
-        mem.get(1, &mut buf).unwrap();
+        mem.get(1u16, &mut buf).unwrap();
         assert_eq!([0x89, 0x67, 0x45, 0x23], buf);
     }
 
     #[test]
     pub fn set_le_u64_works() {
         let mut mem = mem::Fixed::new(10);
-        mem.set_u64::<LittleEndian>(1, 0x23456789ABCDEF00).unwrap();
+        mem.set_u64::<LittleEndian>(1u16, 0x23456789ABCDEF00).unwrap();
         let mut buf = [0, 0, 0, 0, 0, 0, 0, 0];
-        mem.get(1, &mut buf).unwrap();
+        mem.get(1u16, &mut buf).unwrap();
         assert_eq!([0x00, 0xEF, 0xCD, 0xAB, 0x89, 0x67, 0x45, 0x23], buf);
     }
 
     fn init_mem_get() -> mem::Fixed {
         let mut mem = mem::Fixed::new(10);
-        mem.set(0, &[0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF, 0x00, 0x00]).unwrap();
+        mem.set(0u16, &[0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF, 0x00, 0x00]).unwrap();
         mem
     }
 }
@@ -5,12 +5,12 @@ use mem;
 /// This is useful in systems like the NES where the 2KB of on-board RAM occupy addresses
 /// 0x0000 through 0x2000 and simply repeat every 0x800 bytes. So a read or write to
 /// 0x0042 is exactly the same as a read or write to 0x0842 or 0x1042 or 0x1842
-pub struct Mirrored<M> where M: mem::Memory {
+pub struct Mirrored<M> {
     mem: M,
     size: u64
 }
 
-impl<M> Mirrored<M> where M: mem::Memory {
+impl<M> Mirrored<M> {
     /// Creates a new `Mirrored` memory wrapping the provided memory and mirroring it through
     /// the `size` bytes.
     ///
@@ -24,32 +24,32 @@ impl<M> Mirrored<M> where M: mem::Memory {
     }
 }
 
-impl<M> mem::Memory for Mirrored<M> where M: mem::Memory {
+impl<A, M> mem::Memory<A> for Mirrored<M> where A: mem::Address, M: mem::Memory<A> {
     fn len(&self) -> u64 { self.size }
 
-    fn get_u8(&self, addr: u64) -> mem::Result<u8> {
-        if addr >= self.size {
+    fn get_u8(&self, addr: A) -> mem::Result<u8> {
+        if addr.to_u64() >= self.size {
             Err(mem::Error::with_detail(
                 mem::ErrorKind::OutOfBounds,
                 "Read would reach end of memory",
                 format!("attempted to read from 0x{:X}, but size is 0x{:x}", addr, self.size)))
         }
         else {
-            let eaddr = addr % self.mem.len();
-            self.mem.get_u8(eaddr)
+            let eaddr = addr.to_u64() % self.mem.len();
+            self.mem.get_u8(A::from_u64(eaddr))
         }
     }
 
-    fn set_u8(&mut self, addr: u64, val: u8) -> mem::Result<()> {
-        if addr >= self.size {
+    fn set_u8(&mut self, addr: A, val: u8) -> mem::Result<()> {
+        if addr.to_u64() >= self.size {
             Err(mem::Error::with_detail(
                 mem::ErrorKind::OutOfBounds,
                 "Write would reach end of memory",
                 format!("attempted to write to 0x{:X}, but size is 0x{:x}", addr, self.size)))
         }
         else {
-            let eaddr = addr % self.mem.len();
-            self.mem.set_u8(eaddr, val)
+            let eaddr = addr.to_u64() % self.mem.len();
+            self.mem.set_u8(A::from_u64(eaddr), val)
         }
     }
 }
@@ -65,8 +65,8 @@ mod test {
         let exp: [u8; 2] = [42, 24];
         let mut buf = [0; 2];
 
-        mem.set(1, &exp).unwrap();
-        mem.get(1, &mut buf).unwrap();
+        mem.set(1u16, &exp).unwrap();
+        mem.get(1u16, &mut buf).unwrap();
         assert_eq!(exp, buf);
     }
 
@@ -75,8 +75,8 @@ mod test {
         let mut mem = mem::Mirrored::new(mem::Fixed::new(6), 18);
         let mut buf = [0; 6];
 
-        mem.set(0, &[1, 2, 3, 4, 5, 6]).unwrap();
-        mem.get(3, &mut buf).unwrap();
+        mem.set(0u16, &[1, 2, 3, 4, 5, 6]).unwrap();
+        mem.get(3u16, &mut buf).unwrap();
         assert_eq!([4, 5, 6, 1, 2, 3], buf);
     }
 
@@ -85,8 +85,8 @@ mod test {
         let mut mem = mem::Mirrored::new(mem::Fixed::new(6), 18);
         let mut buf = [0; 6];
 
-        mem.set(3, &[1, 2, 3, 4, 5, 6]).unwrap();
-        mem.get(0, &mut buf).unwrap();
+        mem.set(3u16, &[1, 2, 3, 4, 5, 6]).unwrap();
+        mem.get(0u16, &mut buf).unwrap();
         assert_eq!([4, 5, 6, 1, 2, 3], buf);
     }
 
@@ -95,8 +95,8 @@ mod test {
         let mut mem = mem::Mirrored::new(mem::Fixed::new(2), 6);
         let mut buf = [0; 6];
 
-        mem.set(0, &[1, 2]).unwrap();
-        mem.get(0, &mut buf).unwrap();
+        mem.set(0u16, &[1, 2]).unwrap();
+        mem.get(0u16, &mut buf).unwrap();
         assert_eq!([1, 2, 1, 2, 1, 2], buf);
     }
 
@@ -105,8 +105,8 @@ mod test {
         let mut mem = mem::Mirrored::new(mem::Fixed::new(2), 6);
         let mut buf = [0; 6];
 
-        mem.set(0, &[1, 2, 3, 4, 5, 6]).unwrap();
-        mem.get(0, &mut buf).unwrap();
+        mem.set(0u16, &[1, 2, 3, 4, 5, 6]).unwrap();
+        mem.get(0u16, &mut buf).unwrap();
         assert_eq!([5, 6, 5, 6, 5, 6], buf);
     }
 
@@ -115,8 +115,8 @@ mod test {
         let mut mem = mem::Mirrored::new(mem::Fixed::new(10), 10);
         let mut buf = [0; 2];
 
-        assert_eq!(mem::ErrorKind::OutOfBounds, mem.set(10, &buf).unwrap_err().kind);
-        assert_eq!(mem::ErrorKind::OutOfBounds, mem.get(10, &mut buf).unwrap_err().kind);
+        assert_eq!(mem::ErrorKind::OutOfBounds, mem.set(10u16, &buf).unwrap_err().kind);
+        assert_eq!(mem::ErrorKind::OutOfBounds, mem.get(10u16, &mut buf).unwrap_err().kind);
     }
 
     #[test]
@@ -124,7 +124,7 @@ mod test {
         let mut mem = mem::Mirrored::new(mem::Fixed::new(10), 10);
         let mut buf = [0; 2];
 
-        assert_eq!(mem::ErrorKind::OutOfBounds, mem.set(9, &buf).unwrap_err().kind);
-        assert_eq!(mem::ErrorKind::OutOfBounds, mem.get(9, &mut buf).unwrap_err().kind);
+        assert_eq!(mem::ErrorKind::OutOfBounds, mem.set(9u16, &buf).unwrap_err().kind);
+        assert_eq!(mem::ErrorKind::OutOfBounds, mem.get(9u16, &mut buf).unwrap_err().kind);
     }
 }
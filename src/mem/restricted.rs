@@ -1,47 +1,47 @@
 use mem;
 
-pub struct ReadOnlyMemory<M>(M) where M: mem::Memory;
+pub struct ReadOnlyMemory<M>(M);
 
-impl<M> mem::Memory for ReadOnlyMemory<M> where M: mem::Memory {
+impl<A, M> mem::Memory<A> for ReadOnlyMemory<M> where A: mem::Address, M: mem::Memory<A> {
     fn len(&self) -> u64 {
         let &ReadOnlyMemory(ref m) = self;
         m.len()
     }
 
-    fn get_u8(&self, addr: u64) -> mem::Result<u8> {
+    fn get_u8(&self, addr: A) -> mem::Result<u8> {
         let &ReadOnlyMemory(ref m) = self;
         m.get_u8(addr)
     }
 
     #[allow(unused_variables)]
-    fn set_u8(&mut self, addr: u64, val: u8) -> mem::Result<()> {
+    fn set_u8(&mut self, addr: A, val: u8) -> mem::Result<()> {
         Err(mem::Error::new(mem::ErrorKind::MemoryNotWritable, "attempted to write to read-only memory"))
     }
 }
 
-pub fn read_only<M>(inner: M) -> ReadOnlyMemory<M> where M: mem::Memory {
+pub fn read_only<M>(inner: M) -> ReadOnlyMemory<M> {
     ReadOnlyMemory(inner)
 }
 
-pub struct WriteOnlyMemory<M>(M) where M: mem::Memory;
+pub struct WriteOnlyMemory<M>(M);
 
-impl<M> mem::Memory for WriteOnlyMemory<M> where M: mem::Memory {
+impl<A, M> mem::Memory<A> for WriteOnlyMemory<M> where A: mem::Address, M: mem::Memory<A> {
     fn len(&self) -> u64 {
         let &WriteOnlyMemory(ref m) = self;
         m.len()
     }
 
     #[allow(unused_variables)]
-    fn get_u8(&self, addr: u64) -> mem::Result<u8> {
+    fn get_u8(&self, addr: A) -> mem::Result<u8> {
         Err(mem::Error::new(mem::ErrorKind::MemoryNotReadable, "attempted to read from write-only memory"))
     }
 
-    fn set_u8(&mut self, addr: u64, val: u8) -> mem::Result<()> {
+    fn set_u8(&mut self, addr: A, val: u8) -> mem::Result<()> {
         let &mut WriteOnlyMemory(ref mut m) = self;
         m.set_u8(addr, val)
     }
 }
 
-pub fn write_only<M>(inner: M) -> WriteOnlyMemory<M> where M: mem::Memory {
+pub fn write_only<M>(inner: M) -> WriteOnlyMemory<M> {
     WriteOnlyMemory(inner)
 }
@@ -2,27 +2,31 @@ use mem;
 
 use std::{error,fmt};
 
-struct Segment<'a> {
-    base : u64,
-    memory : Box<mem::Memory+'a>
+struct Segment<'a, A> where A: mem::Address {
+    base : A,
+    memory : Box<mem::Memory<A>+'a>
 }
 
-impl<'a> Segment<'a> {
-    fn new(base: u64, memory: Box<mem::Memory+'a>) -> Segment<'a> {
+impl<'a, A> Segment<'a, A> where A: mem::Address {
+    fn new(base: A, memory: Box<mem::Memory<A>+'a>) -> Segment<'a, A> {
         Segment {
             base: base,
             memory: memory
         }
     }
 
-    fn has_addr(&self, addr: u64) -> bool {
-        addr >= self.base && addr < (self.base + self.memory.len())
+    fn has_addr(&self, addr: A) -> bool {
+        addr >= self.base && (addr.to_u64() - self.base.to_u64()) < self.memory.len()
+    }
+
+    fn last_addr(&self) -> u64 {
+        self.base.to_u64() + self.memory.len() - 1
     }
 }
 
-impl<'a> fmt::Debug for Segment<'a> {
+impl<'a, A> fmt::Debug for Segment<'a, A> where A: mem::Address {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt.write_fmt(format_args!("${:04X} - ${:04X}", self.base, self.base + self.memory.len() - 1))
+        fmt.write_fmt(format_args!("${:04X} - ${:04X}", self.base, self.last_addr()))
     }
 }
 
@@ -52,13 +56,13 @@ impl fmt::Display for Error {
 /// the memory operation on the memory that is mapped at the specified base address
 ///
 /// Warning: Memories may NOT overlap
-pub struct Virtual<'a> {
-    segments : Vec<Segment<'a>>
+pub struct Virtual<'a, A = u64> where A: mem::Address {
+    segments : Vec<Segment<'a, A>>
 }
 
-impl<'a> Virtual<'a> {
+impl<'a, A> Virtual<'a, A> where A: mem::Address {
     /// Constructs a new Virtual Memory with no member segments
-    pub fn new() -> Virtual<'a> {
+    pub fn new() -> Virtual<'a, A> {
         Virtual {
             segments: Vec::new()
         }
@@ -69,7 +73,7 @@ impl<'a> Virtual<'a> {
     /// # Arguments
     /// * `base` - The address to use as the base for the specified memory
     /// * `mem` - The memory to attach.
-    pub fn attach(&mut self, base: u64, mem: Box<mem::Memory+'a>) -> Result<(), Error> {
+    pub fn attach(&mut self, base: A, mem: Box<mem::Memory<A>+'a>) -> Result<(), Error> {
         // Find the appropriate place to attach the memory
         let new_segment = Segment::new(base, mem);
         let pos = self.segments.iter()
@@ -83,7 +87,7 @@ impl<'a> Virtual<'a> {
         if insert_point > 0 {
             // Check the memory on the left
             let left = &self.segments[insert_point - 1];
-            if left.base + left.memory.len() - 1 >= base {
+            if left.last_addr() >= base.to_u64() {
                 return Err(Error::MemoryOverlap)
             }
         }
@@ -91,7 +95,7 @@ impl<'a> Virtual<'a> {
         if insert_point < self.segments.len() {
             // Check the memory on the right
             let right = &self.segments[insert_point];
-            if base + new_segment.memory.len() - 1 >= right.base {
+            if new_segment.last_addr() >= right.base.to_u64() {
                 return Err(Error::MemoryOverlap)
             }
         }
@@ -100,31 +104,31 @@ impl<'a> Virtual<'a> {
         Ok(())
     }
 
-    fn find(&self, addr: u64) -> Option<&Segment<'a>> {
+    fn find(&self, addr: A) -> Option<&Segment<'a, A>> {
         self.segments.iter().find(|l| l.has_addr(addr))
     }
 
-    fn find_mut(&mut self, addr: u64) -> Option<&mut Segment<'a>> {
+    fn find_mut(&mut self, addr: A) -> Option<&mut Segment<'a, A>> {
         self.segments.iter_mut().find(|l| l.has_addr(addr))
     }
 }
 
-impl<'a> fmt::Debug for Virtual<'a> {
+impl<'a, A> fmt::Debug for Virtual<'a, A> where A: mem::Address {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         self.segments.iter().fold(&mut fmt.debug_list(), |b, e| b.entry(e)).finish()
     }
 }
 
-impl<'a> mem::Memory for Virtual<'a> {
+impl<'a, A> mem::Memory<A> for Virtual<'a, A> where A: mem::Address {
     fn len(&self) -> u64 {
         unimplemented!()
     }
 
-    fn get_u8(&self, addr: u64) -> mem::Result<u8> {
+    fn get_u8(&self, addr: A) -> mem::Result<u8> {
         // Find the memory at the current address
         match self.find(addr) {
             Some(segment) => {
-                let eaddr = addr - segment.base;
+                let eaddr = A::from_u64(addr.to_u64() - segment.base.to_u64());
                 segment.memory.get_u8(eaddr)
             },
             None => Err(mem::Error::with_detail(
@@ -134,11 +138,11 @@ impl<'a> mem::Memory for Virtual<'a> {
         }
     }
 
-    fn set_u8(&mut self, addr: u64, val: u8) -> mem::Result<()> {
+    fn set_u8(&mut self, addr: A, val: u8) -> mem::Result<()> {
         // Find the memory at the current address
         match self.find_mut(addr) {
             Some(segment) => {
-                let eaddr = addr - segment.base;
+                let eaddr = A::from_u64(addr.to_u64() - segment.base.to_u64());
                 segment.memory.set_u8(eaddr, val)
             },
             None => Err(mem::Error::with_detail(
@@ -157,7 +161,7 @@ mod test {
     #[test]
     pub fn attach_with_no_items() {
         let mem = mem::Fixed::new(10);
-        let mut vm = mem::Virtual::new();
+        let mut vm = mem::Virtual::<u16>::new();
         vm.attach(1000, Box::new(mem)).unwrap();
         assert_eq!(vm.segments.len(), 1);
         assert_eq!(vm.segments[0].base, 1000);
@@ -167,7 +171,7 @@ mod test {
     pub fn attach_at_end() {
         let mem1 = mem::Fixed::new(10);
         let mem2 = mem::Fixed::new(10);
-        let mut vm = mem::Virtual::new();
+        let mut vm = mem::Virtual::<u16>::new();
         vm.attach(1000, Box::new(mem1)).unwrap();
         vm.attach(1010, Box::new(mem2)).unwrap();
         assert_eq!(vm.segments.len(), 2);
@@ -179,7 +183,7 @@ mod test {
     pub fn attach_at_end_with_overlap() {
         let mem1 = mem::Fixed::new(10);
         let mem2 = mem::Fixed::new(10);
-        let mut vm = mem::Virtual::new();
+        let mut vm = mem::Virtual::<u16>::new();
         vm.attach(1000, Box::new(mem1)).unwrap();
         assert_eq!(
             vm.attach(1005, Box::new(mem2)),
@@ -190,7 +194,7 @@ mod test {
     pub fn attach_at_beginning() {
         let mem1 = mem::Fixed::new(10);
         let mem2 = mem::Fixed::new(10);
-        let mut vm = mem::Virtual::new();
+        let mut vm = mem::Virtual::<u16>::new();
         vm.attach(1010, Box::new(mem1)).unwrap();
         vm.attach(1000, Box::new(mem2)).unwrap();
         assert_eq!(vm.segments.len(), 2);
@@ -202,7 +206,7 @@ mod test {
     pub fn attach_at_beginning_with_overlap() {
         let mem1 = mem::Fixed::new(10);
         let mem2 = mem::Fixed::new(10);
-        let mut vm = mem::Virtual::new();
+        let mut vm = mem::Virtual::<u16>::new();
         vm.attach(0x1005, Box::new(mem1)).unwrap();
         assert_eq!(
             vm.attach(0x1000, Box::new(mem2)),
@@ -214,7 +218,7 @@ mod test {
         let mem1 = mem::Fixed::new(10);
         let mem2 = mem::Fixed::new(10);
         let mem3 = mem::Fixed::new(10);
-        let mut vm = mem::Virtual::new();
+        let mut vm = mem::Virtual::<u16>::new();
         vm.attach(1000, Box::new(mem1)).unwrap();
         vm.attach(1020, Box::new(mem2)).unwrap();
         vm.attach(1010, Box::new(mem3)).unwrap();
@@ -229,7 +233,7 @@ mod test {
         let mem1 = mem::Fixed::new(10);
         let mem2 = mem::Fixed::new(10);
         let mem3 = mem::Fixed::new(10);
-        let mut vm = mem::Virtual::new();
+        let mut vm = mem::Virtual::<u16>::new();
         vm.attach(1000, Box::new(mem1)).unwrap();
         vm.attach(1010, Box::new(mem2)).unwrap();
         assert_eq!(
@@ -241,16 +245,16 @@ mod test {
     pub fn get_from_single_memory() {
         let mut mem1 = mem::Fixed::new(10);
         let mut mem2 = mem::Fixed::new(10);
-        let mut vm = mem::Virtual::new();
+        let mut vm = mem::Virtual::<u16>::new();
 
-        mem1.set(0, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]).unwrap();
-        mem2.set(0, &[11, 12, 13, 14, 15, 16, 17, 18, 19, 20]).unwrap();
+        mem1.set(0u16, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]).unwrap();
+        mem2.set(0u16, &[11, 12, 13, 14, 15, 16, 17, 18, 19, 20]).unwrap();
 
         vm.attach(1000, Box::new(mem1)).unwrap();
         vm.attach(1010, Box::new(mem2)).unwrap();
 
         let mut buf = [0, 0, 0, 0];
-        vm.get(1006, &mut buf).unwrap();
+        vm.get(1006u16, &mut buf).unwrap();
         assert_eq!([7, 8, 9, 10], buf);
     }
 
@@ -258,16 +262,16 @@ mod test {
     pub fn get_spanning_memories() {
         let mut mem1 = mem::Fixed::new(10);
         let mut mem2 = mem::Fixed::new(10);
-        let mut vm = mem::Virtual::new();
+        let mut vm = mem::Virtual::<u16>::new();
 
-        mem1.set(0, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]).unwrap();
-        mem2.set(0, &[11, 12, 13, 14, 15, 16, 17, 18, 19, 20]).unwrap();
+        mem1.set(0u16, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]).unwrap();
+        mem2.set(0u16, &[11, 12, 13, 14, 15, 16, 17, 18, 19, 20]).unwrap();
 
         vm.attach(1000, Box::new(mem1)).unwrap();
         vm.attach(1010, Box::new(mem2)).unwrap();
 
         let mut buf = [0, 0, 0, 0];
-        vm.get(1008, &mut buf).unwrap();
+        vm.get(1008u16, &mut buf).unwrap();
         assert_eq!([9, 10, 11, 12], buf);
     }
 
@@ -275,15 +279,15 @@ mod test {
     pub fn set_to_single_memory() {
         let mut mem1 = mem::Fixed::new(10);
         let mut mem2 = mem::Fixed::new(10);
-        let mut vm = mem::Virtual::new();
+        let mut vm = mem::Virtual::<u16>::new();
 
-        mem1.set(0, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]).unwrap();
-        mem2.set(0, &[11, 12, 13, 14, 15, 16, 17, 18, 19, 20]).unwrap();
+        mem1.set(0u16, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]).unwrap();
+        mem2.set(0u16, &[11, 12, 13, 14, 15, 16, 17, 18, 19, 20]).unwrap();
 
         vm.attach(1000, Box::new(mem1)).unwrap();
         vm.attach(1010, Box::new(mem2)).unwrap();
 
-        vm.set(1006, &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap();
+        vm.set(1006u16, &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap();
 
         let mut buf = [0, 0, 0, 0];
         vm.segments[0].memory.get(6, &mut buf).unwrap();
@@ -294,15 +298,15 @@ mod test {
     pub fn set_spanning_memories() {
         let mut mem1 = mem::Fixed::new(10);
         let mut mem2 = mem::Fixed::new(10);
-        let mut vm = mem::Virtual::new();
+        let mut vm = mem::Virtual::<u16>::new();
 
-        mem1.set(0, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]).unwrap();
-        mem2.set(0, &[11, 12, 13, 14, 15, 16, 17, 18, 19, 20]).unwrap();
+        mem1.set(0u16, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]).unwrap();
+        mem2.set(0u16, &[11, 12, 13, 14, 15, 16, 17, 18, 19, 20]).unwrap();
 
         vm.attach(1000, Box::new(mem1)).unwrap();
         vm.attach(1010, Box::new(mem2)).unwrap();
 
-        vm.set(1008, &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap();
+        vm.set(1008u16, &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap();
 
         let mut buf = [0, 0, 0, 0];
         vm.segments[0].memory.get(8, &mut buf[0..2]).unwrap();
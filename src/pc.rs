@@ -2,19 +2,22 @@ use instr;
 use mem;
 
 /// Represents a program counter value
+///
+/// The program counter is the same width as the address bus, `A`, and wraps around when it is
+/// advanced past either end of the address space
 #[derive(Copy,Clone,Debug,Eq,PartialEq)]
-pub struct ProgramCounter {
-    pc: u64
+pub struct ProgramCounter<A = u64> where A: mem::Address {
+    pc: A
 }
 
-impl ProgramCounter {
+impl<A> ProgramCounter<A> where A: mem::Address {
     /// Allocates a new program counter value (initialized to 0)
-    pub fn new() -> ProgramCounter {
-        ProgramCounter { pc: 0 }
+    pub fn new() -> ProgramCounter<A> {
+        ProgramCounter { pc: A::from_u64(0) }
     }
 
     /// Retrieves the current value of the program counter
-    pub fn get(&self) -> u64 {
+    pub fn get(&self) -> A {
         self.pc
     }
 
@@ -23,7 +26,7 @@ impl ProgramCounter {
     /// # Arguments
     ///
     /// * `val` - The value to set the program counter to
-    pub fn set(&mut self, val: u64) {
+    pub fn set(&mut self, val: A) {
         self.pc = val;
     }
 
@@ -33,16 +36,12 @@ impl ProgramCounter {
     ///
     /// * `amount` - The amount to advance (or retreat) the program counter by
     pub fn advance(&mut self, amount: i64) {
-        self.pc = if amount < 0 {
-            self.pc.wrapping_sub(amount.abs() as u64)
-        } else {
-            self.pc.wrapping_add(amount.abs() as u64)
-        }
+        self.pc = self.pc.offset(amount as u64)
     }
 
     /// Decodes an instruction from the provided memory and updates the program counter as
     /// necessary
-    pub fn decode<M, I>(&mut self, mem: &M) -> Result<I, I::DecodeError> where I: instr::Instruction, M: mem::Memory {
+    pub fn decode<M, I>(&mut self, mem: &M) -> Result<I, I::DecodeError> where I: instr::Instruction, M: mem::Memory<A> {
         // Construct the reader
         let mut r = mem::read_cursor(mem, self.pc);
 
@@ -50,7 +49,7 @@ impl ProgramCounter {
         let inst : Result<I, I::DecodeError> = instr::Instruction::decode(&mut r);
 
         // Adjust the PC
-        self.pc = r.position();
+        self.pc = A::from_u64(r.position());
 
         // Return the value
         inst
@@ -65,22 +64,32 @@ mod test {
 
     #[test]
     pub fn advance_by_positive_value_increases_pc() {
-        let mut pc = ProgramCounter::new();
+        let mut pc = ProgramCounter::<u16>::new();
         pc.advance(42);
         assert_eq!(pc.get(), 42);
     }
 
     #[test]
     pub fn advance_by_negative_value_decreases_pc() {
-        let mut pc = ProgramCounter::new();
+        let mut pc = ProgramCounter::<u16>::new();
         pc.advance(42);
         pc.advance(-24);
         assert_eq!(pc.get(), 18);
     }
 
+    #[test]
+    pub fn advance_wraps_around_at_end_of_address_space() {
+        let mut pc = ProgramCounter::<u16>::new();
+        pc.set(0xFFFE);
+        pc.advance(3);
+        assert_eq!(pc.get(), 0x0001);
+        pc.advance(-2);
+        assert_eq!(pc.get(), 0xFFFF);
+    }
+
     #[test]
     pub fn decode_returns_decoded_instruction_and_advances_pc_on_successful_decode() {
-        let mut pc = ProgramCounter::new();
+        let mut pc = ProgramCounter::<u16>::new();
         let mem = mem::Fixed::from_contents(vec![0x00, 0x00, 0x0C, 0xCD, 0xAB, 0x00, 0x00]);
         pc.advance(2);
 
@@ -92,7 +101,7 @@ mod test {
 
     #[test]
     pub fn decode_provides_an_instruction_stream() {
-        let mut pc = ProgramCounter::new();
+        let mut pc = ProgramCounter::<u16>::new();
         let mem = mem::Fixed::from_contents(vec![
             0x0C, 0xCD, 0xAB,
             0x80, 0x42,
@@ -111,7 +120,7 @@ mod test {
 
     #[test]
     pub fn decode_returns_error_and_advances_pc_on_failed_decode() {
-        let mut pc = ProgramCounter::new();
+        let mut pc = ProgramCounter::<u16>::new();
         let mem = mem::Fixed::from_contents(vec![0x00, 0x00, 0x0C]);
         pc.advance(2);
 
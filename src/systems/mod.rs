@@ -3,3 +3,9 @@
 /// 
 /// For additional documentation, check the [NesDev Wiki](http://wiki.nesdev.com/)
 pub mod nes;
+
+/// Contains code to play music in the NES Sound Format (NSF), the format used to distribute music
+/// ripped from NES games
+///
+/// For additional documentation, check the [NesDev Wiki](http://wiki.nesdev.com/w/index.php/NSF)
+pub mod nsf;
@@ -0,0 +1,410 @@
+use slog;
+
+use mem;
+use systems::nes;
+use systems::nes::cart::chr::Chr;
+use systems::nes::cart::open_bus;
+use systems::nes::cart::eeprom::{Eeprom,EepromChip};
+
+/// The size of a switchable PRG ROM bank
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/// The size of a switchable CHR bank
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// Identifies the Bandai board a cartridge uses
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum BandaiBoard {
+    /// FCG-1 and FCG-2 (mapper 16, submapper 4), with registers at $6000-$7FFF and no EEPROM
+    Fcg,
+
+    /// LZ93D50 with a 24C02 EEPROM (mapper 16, submapper 5), with registers at $8000-$FFFF
+    Lz93d50,
+
+    /// LZ93D50 with a 24C01 EEPROM (mapper 159)
+    Lz93d50X24C01,
+
+    /// LZ93D50 with 8KB of battery-backed PRG RAM and 512KB of PRG ROM (mapper 153)
+    ///
+    /// Bit 0 of the CHR bank registers selects the 256KB half of PRG ROM, and CHR is unbanked RAM
+    Sram,
+
+    /// Datach Joint ROM System (mapper 157), with a 24C02 EEPROM and unbanked CHR RAM
+    ///
+    /// The barcode reader and the EEPROMs in the game cartridges are not emulated
+    Datach,
+
+    /// Mapper 16 without a submapper, with registers at both $6000-$7FFF and $8000-$FFFF
+    ///
+    /// Writes to $6000-$7FFF behave as on the FCG, and writes to $8000-$FFFF as on the LZ93D50,
+    /// which covers games for either board
+    Combined
+}
+
+impl BandaiBoard {
+    fn eeprom(&self) -> Option<EepromChip> {
+        match *self {
+            BandaiBoard::Lz93d50 | BandaiBoard::Datach | BandaiBoard::Combined => Some(EepromChip::X24C02),
+            BandaiBoard::Lz93d50X24C01 => Some(EepromChip::X24C01),
+            BandaiBoard::Fcg | BandaiBoard::Sram => None
+        }
+    }
+
+    /// Checks if the board decodes registers at the provided address
+    fn decodes(&self, addr: u16) -> bool {
+        match *self {
+            BandaiBoard::Fcg => addr < 0x8000,
+            BandaiBoard::Combined => true,
+            _ => addr >= 0x8000
+        }
+    }
+}
+
+/// Emulates the Bandai FCG and LZ93D50 mappers
+///
+/// The registers are selected by the low four bits of the address, within the range the board
+/// decodes.
+///
+/// * $x0-$x7: 1KB CHR banks (or the outer PRG bank, on mapper 153)
+/// * $x8: 16KB PRG bank at $8000
+/// * $x9: Mirroring
+/// * $xA: IRQ enable and acknowledge
+/// * $xB/$xC: IRQ counter low and high bytes
+/// * $xD: EEPROM clock and data lines (or PRG RAM enable, on mapper 153)
+///
+/// The last 16KB PRG bank is fixed at $C000. The IRQ counter decrements every CPU cycle while
+/// enabled, and fires when it passes zero. On the FCG, $xB/$xC write the counter itself, while
+/// on the LZ93D50 they write a latch that is copied to the counter when $xA is written.
+///
+/// On boards with an EEPROM, its data line is read back through bit 4 of $6000-$7FFF.
+pub struct Bandai {
+    board: BandaiBoard,
+    rom: mem::Fixed,
+    ram: mem::Fixed,
+    chr: Chr,
+    chr_banked: bool,
+    prg_bank: usize,
+    outer_bank: usize,
+    mirroring: nes::Mirroring,
+    ram_enabled: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    irq_counter: u16,
+    irq_latch: u16,
+    eeprom: Option<Eeprom>,
+    log: slog::Logger
+}
+
+impl Bandai {
+    /// Creates a new Bandai cartridge using the provided board
+    ///
+    /// If `chr` is empty, the cartridge uses 8KB of unbanked CHR RAM instead. Only mapper 153
+    /// uses `ram_size`, as the other boards have no PRG RAM.
+    pub fn new(board: BandaiBoard, prg: Vec<u8>, chr: Vec<u8>, ram_size: usize, logger: Option<slog::Logger>) -> Bandai {
+        let log = unwrap_logger!(logger).new(o!("mapper" => "Bandai", "cartridge" => true));
+        let chr_banked = !chr.is_empty();
+
+        Bandai {
+            board: board,
+            rom: mem::Fixed::from_contents(prg),
+            ram: mem::Fixed::new(if board == BandaiBoard::Sram { ram_size } else { 0 }),
+            chr: Chr::new(chr, 0x2000, CHR_BANK_SIZE, log.clone()),
+            chr_banked: chr_banked,
+            prg_bank: 0,
+            outer_bank: 0,
+            mirroring: nes::Mirroring::Vertical,
+            ram_enabled: false,
+            irq_enabled: false,
+            irq_pending: false,
+            irq_counter: 0,
+            irq_latch: 0,
+            eeprom: board.eeprom().map(Eeprom::new),
+            log: log
+        }
+    }
+
+    /// Gets the offset in to PRG ROM of the provided 16KB bank, within the selected outer bank
+    fn prg_offset(&self, bank: usize) -> usize {
+        ((self.outer_bank << 4 | bank) * PRG_BANK_SIZE) % (self.rom.len() as usize)
+    }
+
+    fn write_register(&mut self, addr: u16, val: u8) {
+        trace!(self.log,
+            "vaddr" => format!("${:04X}", addr),
+            "reg" => format!("${:X}", addr & 0x0F),
+            "val" => val;
+            "wrote register");
+        // On the combined board, writes in the FCG's range load the counter directly
+        let latched = addr >= 0x8000 && self.board != BandaiBoard::Fcg;
+        match addr & 0x0F {
+            reg @ 0x0 ... 0x7 => {
+                if self.board == BandaiBoard::Sram {
+                    self.outer_bank = (val & 0x01) as usize;
+                } else if self.chr_banked {
+                    self.chr.set_bank(reg as usize, val as usize);
+                }
+            },
+            0x8 => self.prg_bank = (val & 0x0F) as usize,
+            0x9 => {
+                self.mirroring = match val & 0x03 {
+                    0 => nes::Mirroring::Vertical,
+                    1 => nes::Mirroring::Horizontal,
+                    2 => nes::Mirroring::SingleScreenLower,
+                    _ => nes::Mirroring::SingleScreenUpper
+                };
+            },
+            0xA => {
+                self.irq_enabled = val & 0x01 != 0;
+                self.irq_pending = false;
+                if latched {
+                    self.irq_counter = self.irq_latch;
+                }
+            },
+            0xB => {
+                if latched {
+                    self.irq_latch = (self.irq_latch & 0xFF00) | val as u16;
+                } else {
+                    self.irq_counter = (self.irq_counter & 0xFF00) | val as u16;
+                }
+            },
+            0xC => {
+                if latched {
+                    self.irq_latch = (self.irq_latch & 0x00FF) | ((val as u16) << 8);
+                } else {
+                    self.irq_counter = (self.irq_counter & 0x00FF) | ((val as u16) << 8);
+                }
+            },
+            0xD => {
+                if self.board == BandaiBoard::Sram {
+                    self.ram_enabled = val & 0x20 != 0;
+                } else if let Some(ref mut eeprom) = self.eeprom {
+                    eeprom.set_lines(val & 0x20 != 0, val & 0x40 != 0);
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+impl nes::Mapper for Bandai {
+    fn name(&self) -> &'static str { "Bandai" }
+
+    fn prg(&self) -> &mem::Memory<u16> {
+        self
+    }
+
+    fn prg_mut(&mut self) -> &mut mem::Memory<u16> {
+        self
+    }
+
+    fn chr(&self) -> &mem::Memory<u16> {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut mem::Memory<u16> {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Option<nes::Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn clock_cpu(&mut self) {
+        if self.irq_enabled {
+            if self.irq_counter == 0 {
+                self.irq_pending = true;
+            }
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+        }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        match self.eeprom {
+            Some(ref eeprom) => Some(eeprom.contents()),
+            None => Some(self.ram.contents())
+        }
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut [u8]> {
+        match self.eeprom {
+            Some(ref mut eeprom) => Some(eeprom.contents_mut()),
+            None => Some(self.ram.contents_mut())
+        }
+    }
+}
+
+impl mem::Memory<u16> for Bandai {
+    fn len(&self) -> u64 { 0xA000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "read");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on Bandai cartridge",
+                format!("${:4X} is below the addressable range of 0x6000-0xFFFF", addr)))
+        } else if addr < 0x8000 {
+            if let Some(ref eeprom) = self.eeprom {
+                // Only the data line is driven, the other bits are open bus
+                return Ok((open_bus(addr) & 0xEF) | ((eeprom.output() as u8) << 4));
+            }
+            if !self.ram_enabled || self.ram.len() == 0 {
+                return Ok(open_bus(addr));
+            }
+            self.ram.get_u8(((addr - 0x6000) as u64) % self.ram.len())
+        } else {
+            let bank = if addr < 0xC000 { self.prg_bank } else { 0x0F };
+            let eaddr = (self.prg_offset(bank) + (addr as usize & (PRG_BANK_SIZE - 1))) % (self.rom.len() as usize);
+            trace!(self.log,
+                "read";
+                "vaddr" => format!("${:04X}", addr),
+                "paddr" => format!("${:05X}", eaddr),
+                "target" => "ROM",
+                "action" => "read");
+            self.rom.get_u8(eaddr as u64)
+        }
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "write");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on Bandai cartridge",
+                format!("${:4X} is below the addressable range on Bandai cartridge", addr)))
+        } else if self.board.decodes(addr) {
+            self.write_register(addr, val);
+            Ok(())
+        } else if addr < 0x8000 {
+            if !self.ram_enabled || self.ram.len() == 0 {
+                trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to disabled RAM");
+                return Ok(());
+            }
+            let eaddr = ((addr - 0x6000) as u64) % self.ram.len();
+            self.ram.set_u8(eaddr, val)
+        } else {
+            trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to ROM");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mem::Memory;
+    use systems::nes::{Mapper,Mirroring};
+    use systems::nes::cart::{Bandai,BandaiBoard};
+    use systems::nes::cart::testing::numbered_banks;
+
+    /// Sets the EEPROM clock and data lines through register $800D
+    fn lines(cart: &mut Bandai, scl: bool, sda: bool) {
+        cart.set_u8(0x800D, ((scl as u8) << 5) | ((sda as u8) << 6)).unwrap();
+    }
+
+    /// Sends a byte to a 24C02, most significant bit first, and returns if it was acknowledged
+    fn send(cart: &mut Bandai, byte: u8) -> bool {
+        for i in (0 .. 8).rev() {
+            let bit = (byte >> i) & 0x01 != 0;
+            lines(cart, false, bit);
+            lines(cart, true, bit);
+            lines(cart, false, bit);
+        }
+        lines(cart, false, true);
+        lines(cart, true, true);
+        let ack = cart.get_u8(0x6000).unwrap() & 0x10 == 0;
+        lines(cart, false, true);
+        ack
+    }
+
+    #[test]
+    pub fn banks_prg_and_chr() {
+        let mut cart = Bandai::new(BandaiBoard::Lz93d50, numbered_banks(16, 0x4000), numbered_banks(256, 0x0400), 0, None);
+        cart.set_u8(0x8008, 3).unwrap();
+        cart.set_u8(0x8005, 42).unwrap();
+        cart.set_u8(0x8009, 1).unwrap();
+        assert_eq!(Ok(3), cart.get_u8(0x8000));
+        assert_eq!(Ok(15), cart.get_u8(0xC000));
+        assert_eq!(Ok(42), cart.chr().get_u8(0x1400));
+        assert_eq!(Some(Mirroring::Horizontal), cart.mirroring());
+    }
+
+    #[test]
+    pub fn fcg_registers_are_at_6000() {
+        let mut cart = Bandai::new(BandaiBoard::Fcg, numbered_banks(16, 0x4000), numbered_banks(256, 0x0400), 0, None);
+        cart.set_u8(0x8008, 3).unwrap();
+        assert_eq!(Ok(0), cart.get_u8(0x8000));
+        cart.set_u8(0x6008, 3).unwrap();
+        assert_eq!(Ok(3), cart.get_u8(0x8000));
+    }
+
+    #[test]
+    pub fn fcg_irq_counts_cpu_cycles() {
+        let mut cart = Bandai::new(BandaiBoard::Fcg, numbered_banks(16, 0x4000), numbered_banks(256, 0x0400), 0, None);
+        cart.set_u8(0x600B, 2).unwrap();
+        cart.set_u8(0x600C, 0).unwrap();
+        cart.set_u8(0x600A, 1).unwrap();
+        cart.clock_cpu();
+        cart.clock_cpu();
+        assert!(!cart.irq());
+        cart.clock_cpu();
+        assert!(cart.irq());
+        cart.set_u8(0x600A, 0).unwrap();
+        assert!(!cart.irq());
+    }
+
+    #[test]
+    pub fn lz93d50_irq_reloads_from_latch() {
+        let mut cart = Bandai::new(BandaiBoard::Lz93d50, numbered_banks(16, 0x4000), numbered_banks(256, 0x0400), 0, None);
+        cart.set_u8(0x800B, 1).unwrap();
+        cart.set_u8(0x800C, 0).unwrap();
+        cart.clock_cpu();
+        cart.set_u8(0x800A, 1).unwrap();
+        cart.clock_cpu();
+        assert!(!cart.irq());
+        cart.clock_cpu();
+        assert!(cart.irq());
+    }
+
+    #[test]
+    pub fn sram_board_selects_outer_prg_bank_and_enables_ram() {
+        let mut cart = Bandai::new(BandaiBoard::Sram, numbered_banks(32, 0x4000), vec![], 0x2000, None);
+        cart.set_u8(0x8000, 1).unwrap();
+        cart.set_u8(0x8008, 2).unwrap();
+        assert_eq!(Ok(18), cart.get_u8(0x8000));
+        assert_eq!(Ok(31), cart.get_u8(0xC000));
+
+        cart.set_u8(0x6000, 42).unwrap();
+        assert_eq!(Ok(0x60), cart.get_u8(0x6000));
+        cart.set_u8(0x800D, 0x20).unwrap();
+        cart.set_u8(0x6000, 42).unwrap();
+        assert_eq!(Ok(42), cart.get_u8(0x6000));
+        assert_eq!(Some(42), cart.save_data().map(|data| data[0]));
+    }
+
+    #[test]
+    pub fn eeprom_is_written_through_register() {
+        let mut cart = Bandai::new(BandaiBoard::Lz93d50, numbered_banks(16, 0x4000), numbered_banks(256, 0x0400), 0, None);
+        lines(&mut cart, true, true);
+        lines(&mut cart, true, false);
+        lines(&mut cart, false, false);
+        assert!(send(&mut cart, 0xA0));
+        assert!(send(&mut cart, 0x10));
+        assert!(send(&mut cart, 0x99));
+        lines(&mut cart, false, false);
+        lines(&mut cart, true, false);
+        lines(&mut cart, true, true);
+        assert_eq!(Some(0x99), cart.save_data().map(|data| data[0x10]));
+    }
+}
@@ -0,0 +1,151 @@
+use slog;
+
+use mem;
+
+/// The CHR memory of a cartridge, divided in to equally sized slots that banks are mapped in to
+///
+/// Cartridges without CHR ROM have CHR RAM instead, which is writable. Writes to CHR ROM are
+/// dropped. A few boards (such as TQROM) have both, and map banks of either in to each slot.
+pub struct Chr {
+    rom: mem::Fixed,
+    ram: mem::Fixed,
+    bank_size: usize,
+    banks: Vec<Bank>,
+    log: slog::Logger
+}
+
+/// The bank mapped in to a slot, as an offset in to CHR ROM or RAM
+#[derive(Copy,Clone)]
+struct Bank {
+    ram: bool,
+    offset: usize
+}
+
+impl Chr {
+    /// Creates a new CHR memory with slots of `bank_size` bytes covering $0000-$1FFF
+    ///
+    /// If `rom` is empty, `ram_size` bytes of CHR RAM are used instead. Each slot starts with the
+    /// bank of the same number mapped in to it.
+    pub fn new(rom: Vec<u8>, ram_size: usize, bank_size: usize, log: slog::Logger) -> Chr {
+        let ram_size = if rom.is_empty() { ram_size } else { 0 };
+        Chr::with_ram(rom, ram_size, bank_size, log)
+    }
+
+    /// Creates a new CHR memory with both CHR ROM and `ram_size` bytes of CHR RAM
+    ///
+    /// Slots start with banks of CHR ROM mapped in to them, if there is any
+    pub fn with_ram(rom: Vec<u8>, ram_size: usize, bank_size: usize, log: slog::Logger) -> Chr {
+        let mut chr = Chr {
+            rom: mem::Fixed::from_contents(rom),
+            ram: mem::Fixed::new(ram_size),
+            bank_size: bank_size,
+            banks: vec![Bank { ram: false, offset: 0 }; 0x2000 / bank_size],
+            log: log
+        };
+        for slot in 0 .. chr.banks.len() {
+            chr.set_bank(slot, slot);
+        }
+        chr
+    }
+
+    /// Maps the provided bank of CHR ROM in to the provided slot, or of CHR RAM if there is no
+    /// CHR ROM
+    ///
+    /// Bank numbers past the end of the memory wrap around, as the upper banking lines aren't
+    /// connected on smaller boards
+    pub fn set_bank(&mut self, slot: usize, bank: usize) {
+        let ram = self.rom.len() == 0;
+        self.map(slot, bank, ram);
+    }
+
+    /// Maps the provided bank of CHR RAM in to the provided slot
+    pub fn set_ram_bank(&mut self, slot: usize, bank: usize) {
+        self.map(slot, bank, true);
+    }
+
+    fn map(&mut self, slot: usize, bank: usize, ram: bool) {
+        let len = if ram { self.ram.len() } else { self.rom.len() } as usize;
+        self.banks[slot] = Bank {
+            ram: ram,
+            offset: if len == 0 { 0 } else { (bank * self.bank_size) % len }
+        };
+    }
+
+    /// Finds the bank and offset within it that the provided address is mapped to
+    fn locate(&self, addr: u16) -> (Bank, u64) {
+        let addr = (addr & 0x1FFF) as usize;
+        let bank = self.banks[addr / self.bank_size];
+        (bank, (bank.offset + addr % self.bank_size) as u64)
+    }
+}
+
+impl mem::Memory<u16> for Chr {
+    fn len(&self) -> u64 {
+        if self.rom.len() == 0 && self.ram.len() == 0 { 0 } else { 0x2000 }
+    }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        match self.locate(addr) {
+            (Bank { ram: true, .. }, eaddr) => self.ram.get_u8(eaddr),
+            (_, eaddr) => self.rom.get_u8(eaddr)
+        }
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        match self.locate(addr) {
+            (Bank { ram: true, .. }, eaddr) => self.ram.set_u8(eaddr, val),
+            _ => {
+                trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to CHR ROM");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use slog;
+    use mem::Memory;
+    use systems::nes::cart::chr::Chr;
+
+    fn log() -> slog::Logger {
+        slog::Logger::root(slog::Discard, o!())
+    }
+
+    #[test]
+    pub fn slots_start_with_matching_banks() {
+        let rom: Vec<u8> = (0 .. 8).flat_map(|b| vec![b as u8; 0x0400]).collect();
+        let chr = Chr::new(rom, 0, 0x0400, log());
+        assert_eq!(Ok(0), chr.get_u8(0x0000));
+        assert_eq!(Ok(7), chr.get_u8(0x1C00));
+    }
+
+    #[test]
+    pub fn banks_wrap_around_memory() {
+        let rom: Vec<u8> = (0 .. 4).flat_map(|b| vec![b as u8; 0x1000]).collect();
+        let mut chr = Chr::new(rom, 0, 0x1000, log());
+        chr.set_bank(1, 6);
+        assert_eq!(Ok(2), chr.get_u8(0x1000));
+    }
+
+    #[test]
+    pub fn only_ram_is_writable() {
+        let mut ram = Chr::new(vec![], 0x2000, 0x2000, log());
+        ram.set_u8(0x0123, 42).unwrap();
+        assert_eq!(Ok(42), ram.get_u8(0x0123));
+
+        let mut rom = Chr::new(vec![0; 0x2000], 0, 0x2000, log());
+        rom.set_u8(0x0123, 42).unwrap();
+        assert_eq!(Ok(0), rom.get_u8(0x0123));
+    }
+
+    #[test]
+    pub fn rom_and_ram_can_be_mixed() {
+        let mut chr = Chr::with_ram(vec![7; 0x2000], 0x2000, 0x0400, log());
+        chr.set_ram_bank(1, 0);
+        chr.set_u8(0x0400, 42).unwrap();
+        chr.set_u8(0x0000, 42).unwrap();
+        assert_eq!(Ok(7), chr.get_u8(0x0000));
+        assert_eq!(Ok(42), chr.get_u8(0x0400));
+    }
+}
@@ -0,0 +1,326 @@
+use slog;
+
+use mem;
+use systems::nes;
+use systems::nes::cart::chr::Chr;
+use systems::nes::cart::{bank_from_end,open_bus};
+
+/// The size of a switchable PRG ROM bank
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/// Identifies the board emulated by a `Discrete` mapper
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum Board {
+    /// UNROM/UOROM (mapper 2): a switchable 16KB PRG bank at $8000, and the last fixed at $C000
+    UxRom,
+
+    /// CNROM (mapper 3): a switchable 8KB CHR bank
+    CnRom,
+
+    /// AxROM (mapper 7): a switchable 32KB PRG bank, and single-screen mirroring selected by bit 4
+    AxRom,
+
+    /// Color Dreams (mapper 11): a 32KB PRG bank in bits 0-1, and an 8KB CHR bank in bits 4-7
+    ColorDreams,
+
+    /// GxROM/MxROM (mapper 66): a 32KB PRG bank in bits 4-5, and an 8KB CHR bank in bits 0-1
+    GxRom,
+
+    /// BNROM (mapper 34): a switchable 32KB PRG bank
+    BnRom,
+
+    /// NINA-001 (mapper 34): a 32KB PRG bank and two 4KB CHR banks, selected by registers at
+    /// $7FFD-$7FFF that overlap the 8KB of PRG RAM
+    Nina001
+}
+
+impl Board {
+    fn name(&self) -> &'static str {
+        match self {
+            &Board::UxRom => "UxRom",
+            &Board::CnRom => "CnRom",
+            &Board::AxRom => "AxRom",
+            &Board::ColorDreams => "ColorDreams",
+            &Board::GxRom => "GxRom",
+            &Board::BnRom => "BnRom",
+            &Board::Nina001 => "Nina001"
+        }
+    }
+
+    /// Determines if the board usually has bus conflicts, for ROMs that don't say if it does
+    ///
+    /// Of the boards with a latch at $8000-$FFFF, only ANROM (the most common AxROM board) avoids
+    /// bus conflicts. NINA-001 latches writes to $7FFD-$7FFF, where there is no ROM.
+    pub fn default_bus_conflicts(&self) -> bool {
+        match self {
+            &Board::AxRom | &Board::Nina001 => false,
+            _ => true
+        }
+    }
+}
+
+/// Emulates the boards built from discrete logic chips, rather than a custom mapper chip
+///
+/// These boards latch the value written to ROM and use its bits as bank numbers directly. As the
+/// ROM is still outputting the byte at the written address, boards with bus conflicts see the
+/// value ANDed with that byte.
+pub struct Discrete {
+    board: Board,
+    rom: mem::Fixed,
+    ram: mem::Fixed,
+    chr: Chr,
+    prg_banks: [usize; 2],
+    mirroring: Option<nes::Mirroring>,
+    bus_conflicts: bool,
+    log: slog::Logger
+}
+
+impl Discrete {
+    /// Creates a new cartridge with the provided board
+    ///
+    /// If `chr` is empty, the cartridge uses 8KB of CHR RAM instead
+    pub fn new(board: Board, prg: Vec<u8>, chr: Vec<u8>, bus_conflicts: bool, logger: Option<slog::Logger>) -> Discrete {
+        let log = unwrap_logger!(logger).new(o!("mapper" => board.name(), "cartridge" => true));
+        let (ram_size, chr_bank_size) = match board {
+            Board::Nina001 => (0x2000, 0x1000),
+            _ => (0, 0x2000)
+        };
+        let rom_len = prg.len();
+
+        Discrete {
+            board: board,
+            rom: mem::Fixed::from_contents(prg),
+            ram: mem::Fixed::new(ram_size),
+            chr: Chr::new(chr, 0x2000, chr_bank_size, log.clone()),
+            prg_banks: match board {
+                Board::UxRom => [0, bank_from_end(rom_len, PRG_BANK_SIZE, 0) * PRG_BANK_SIZE],
+                _ => [0, PRG_BANK_SIZE % rom_len]
+            },
+            mirroring: match board {
+                Board::AxRom => Some(nes::Mirroring::SingleScreenLower),
+                _ => None
+            },
+            bus_conflicts: bus_conflicts,
+            log: log
+        }
+    }
+
+    /// Switches in the 32KB PRG bank at $8000
+    fn set_prg_32k(&mut self, bank: usize) {
+        let rom_len = self.rom.len() as usize;
+        let offset = (bank * PRG_BANK_SIZE * 2) % rom_len;
+        self.prg_banks = [offset, (offset + PRG_BANK_SIZE) % rom_len];
+    }
+
+    /// Updates the banks from a value latched by a write to $8000-$FFFF
+    fn latch(&mut self, val: u8) {
+        let val = val as usize;
+        match self.board {
+            Board::UxRom => self.prg_banks[0] = (val * PRG_BANK_SIZE) % (self.rom.len() as usize),
+            Board::CnRom => self.chr.set_bank(0, val),
+            Board::AxRom => {
+                self.set_prg_32k(val & 0x07);
+                self.mirroring = Some(if val & 0x10 == 0 {
+                    nes::Mirroring::SingleScreenLower
+                } else {
+                    nes::Mirroring::SingleScreenUpper
+                });
+            },
+            Board::ColorDreams => {
+                self.set_prg_32k(val & 0x03);
+                self.chr.set_bank(0, val >> 4);
+            },
+            Board::GxRom => {
+                self.set_prg_32k((val >> 4) & 0x03);
+                self.chr.set_bank(0, val & 0x03);
+            },
+            Board::BnRom => self.set_prg_32k(val),
+            Board::Nina001 => {}
+        }
+    }
+}
+
+impl nes::Mapper for Discrete {
+    fn name(&self) -> &'static str { self.board.name() }
+
+    fn prg(&self) -> &mem::Memory<u16> {
+        self
+    }
+
+    fn prg_mut(&mut self) -> &mut mem::Memory<u16> {
+        self
+    }
+
+    fn chr(&self) -> &mem::Memory<u16> {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut mem::Memory<u16> {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Option<nes::Mirroring> {
+        self.mirroring
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        Some(self.ram.contents())
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.ram.contents_mut())
+    }
+}
+
+impl mem::Memory<u16> for Discrete {
+    fn len(&self) -> u64 { 0xA000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "read");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on discrete logic cartridge",
+                format!("${:4X} is below the addressable range of 0x6000-0xFFFF", addr)))
+        } else if addr < 0x8000 {
+            if self.ram.len() == 0 {
+                return Ok(open_bus(addr));
+            }
+            self.ram.get_u8(addr - 0x6000)
+        } else {
+            let bank = self.prg_banks[((addr >> 14) & 0x01) as usize];
+            let eaddr = (bank + (addr as usize & (PRG_BANK_SIZE - 1))) % (self.rom.len() as usize);
+            trace!(self.log,
+                "read";
+                "vaddr" => format!("${:04X}", addr),
+                "paddr" => format!("${:05X}", eaddr),
+                "target" => "ROM",
+                "action" => "read");
+            self.rom.get_u8(eaddr as u64)
+        }
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "write");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on discrete logic cartridge",
+                format!("${:4X} is below the addressable range on discrete logic cartridge", addr)))
+        } else if addr < 0x8000 {
+            if self.ram.len() == 0 {
+                trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to missing RAM");
+                return Ok(());
+            }
+            if self.board == Board::Nina001 {
+                match addr {
+                    0x7FFD => self.set_prg_32k((val & 0x01) as usize),
+                    0x7FFE => self.chr.set_bank(0, (val & 0x0F) as usize),
+                    0x7FFF => self.chr.set_bank(1, (val & 0x0F) as usize),
+                    _ => {}
+                }
+            }
+            self.ram.set_u8(addr - 0x6000, val)
+        } else {
+            let latched = if self.bus_conflicts {
+                val & try!(self.get_u8(addr))
+            } else {
+                val
+            };
+            trace!(self.log,
+                "vaddr" => format!("${:04X}", addr),
+                "val" => val,
+                "latched" => latched;
+                "latched bank register");
+            self.latch(latched);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mem::Memory;
+    use systems::nes::{Mapper,Mirroring};
+    use systems::nes::cart::{Board,Discrete};
+    use systems::nes::cart::testing::numbered_banks;
+
+    #[test]
+    pub fn uxrom_switches_first_bank() {
+        let mut cart = Discrete::new(Board::UxRom, numbered_banks(8, 0x4000), vec![], false, None);
+        assert_eq!(Ok(7), cart.get_u8(0xC000));
+        cart.set_u8(0x8000, 3).unwrap();
+        assert_eq!(Ok(3), cart.get_u8(0x8000));
+        assert_eq!(Ok(7), cart.get_u8(0xFFFF));
+    }
+
+    #[test]
+    pub fn cnrom_switches_chr_bank() {
+        let mut cart = Discrete::new(Board::CnRom, numbered_banks(2, 0x4000), numbered_banks(8, 0x1000), false, None);
+        cart.set_u8(0x8000, 2).unwrap();
+        assert_eq!(Ok(4), cart.chr().get_u8(0x0000));
+        assert_eq!(Ok(5), cart.chr().get_u8(0x1000));
+    }
+
+    #[test]
+    pub fn axrom_selects_single_screen_mirroring() {
+        let mut cart = Discrete::new(Board::AxRom, numbered_banks(8, 0x4000), vec![], false, None);
+        assert_eq!(Some(Mirroring::SingleScreenLower), cart.mirroring());
+        cart.set_u8(0x8000, 0x12).unwrap();
+        assert_eq!(Ok(4), cart.get_u8(0x8000));
+        assert_eq!(Ok(5), cart.get_u8(0xC000));
+        assert_eq!(Some(Mirroring::SingleScreenUpper), cart.mirroring());
+    }
+
+    #[test]
+    pub fn gxrom_and_color_dreams_switch_both() {
+        let mut gxrom = Discrete::new(Board::GxRom, numbered_banks(8, 0x4000), numbered_banks(8, 0x1000), false, None);
+        gxrom.set_u8(0x8000, 0x31).unwrap();
+        assert_eq!(Ok(6), gxrom.get_u8(0x8000));
+        assert_eq!(Ok(2), gxrom.chr().get_u8(0x0000));
+
+        let mut dreams = Discrete::new(Board::ColorDreams, numbered_banks(8, 0x4000), numbered_banks(8, 0x1000), false, None);
+        dreams.set_u8(0x8000, 0x31).unwrap();
+        assert_eq!(Ok(2), dreams.get_u8(0x8000));
+        assert_eq!(Ok(6), dreams.chr().get_u8(0x0000));
+    }
+
+    #[test]
+    pub fn nina001_registers_overlap_ram() {
+        let mut cart = Discrete::new(Board::Nina001, numbered_banks(4, 0x4000), numbered_banks(8, 0x1000), false, None);
+        cart.set_u8(0x7FFD, 1).unwrap();
+        cart.set_u8(0x7FFF, 5).unwrap();
+        assert_eq!(Ok(2), cart.get_u8(0x8000));
+        assert_eq!(Ok(5), cart.chr().get_u8(0x1000));
+        assert_eq!(Ok(5), cart.get_u8(0x7FFF));
+    }
+
+    #[test]
+    pub fn bus_conflicts_and_value_with_rom() {
+        let mut prg = numbered_banks(8, 0x4000);
+        prg[0x3FFF] = 0x05;
+        let mut cart = Discrete::new(Board::UxRom, prg.clone(), vec![], true, None);
+        cart.set_u8(0xBFFF, 0x06).unwrap();
+        assert_eq!(Ok(4), cart.get_u8(0x8000));
+
+        let mut cart = Discrete::new(Board::UxRom, prg, vec![], false, None);
+        cart.set_u8(0xBFFF, 0x06).unwrap();
+        assert_eq!(Ok(6), cart.get_u8(0x8000));
+    }
+
+    #[test]
+    pub fn prg_smaller_than_a_bank_is_mirrored() {
+        let cart = Discrete::new(Board::UxRom, vec![42; 0x2000], vec![], false, None);
+        for addr in [0x8000, 0xA000, 0xC000, 0xE000].iter() {
+            assert_eq!(Ok(42), cart.get_u8(*addr));
+        }
+    }
+}
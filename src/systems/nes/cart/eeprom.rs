@@ -0,0 +1,331 @@
+use mem;
+
+/// Identifies the chip emulated by an `Eeprom`
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum EepromChip {
+    /// The Xicor X24C01: 128 bytes, addressed directly by the first byte after a start condition,
+    /// with bits sent least significant first
+    X24C01,
+
+    /// The 24C02: 256 bytes, with a device address byte before the word address, and bits sent
+    /// most significant first
+    X24C02
+}
+
+/// The state of the I2C protocol, which advances on each clock of the bus
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+enum State {
+    /// Waiting for a start condition
+    Idle,
+
+    /// Receiving the device address and direction (24C02 only)
+    Device,
+
+    /// Receiving the word address (and on the 24C01, the direction)
+    Address,
+
+    /// Receiving bytes to write
+    Write,
+
+    /// Sending bytes that have been read
+    Read,
+
+    /// Acknowledging a received byte, then moving to the provided state
+    Ack(Next),
+
+    /// Waiting for the bus master to acknowledge a sent byte, to continue reading
+    MasterAck
+}
+
+/// The state to move to after acknowledging a byte
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+enum Next {
+    Address,
+    Write,
+    Read
+}
+
+/// Emulates a 24C01 or 24C02 serial EEPROM, driven bit by bit over I2C
+///
+/// The bus master (the mapper, on behalf of the CPU) sets the clock (SCL) and data (SDA) lines
+/// through `set_lines`. The data line is open drain, so the EEPROM drives it by pulling it low,
+/// which is read through `output`.
+///
+/// * A start condition is SDA falling while SCL is high, and a stop condition SDA rising
+/// * Data bits are sampled when SCL rises, and the EEPROM changes its output when SCL falls
+/// * Each byte is followed by an acknowledgement bit, sent low by the receiver
+pub struct Eeprom {
+    chip: EepromChip,
+    data: mem::Fixed,
+    state: State,
+    shift: u8,
+    bits: u8,
+    address: u8,
+    acked: bool,
+    scl: bool,
+    sda: bool,
+    output: bool
+}
+
+impl Eeprom {
+    pub fn new(chip: EepromChip) -> Eeprom {
+        Eeprom {
+            chip: chip,
+            data: mem::Fixed::new(match chip {
+                EepromChip::X24C01 => 0x80,
+                EepromChip::X24C02 => 0x100
+            }),
+            state: State::Idle,
+            shift: 0,
+            bits: 0,
+            address: 0,
+            acked: false,
+            scl: false,
+            sda: true,
+            output: true
+        }
+    }
+
+    /// Gets the contents of the EEPROM
+    pub fn contents(&self) -> &[u8] {
+        self.data.contents()
+    }
+
+    /// Gets the contents of the EEPROM, for modification
+    pub fn contents_mut(&mut self) -> &mut [u8] {
+        self.data.contents_mut()
+    }
+
+    /// Gets the level the EEPROM is driving the data line to, `false` if it is pulling it low
+    pub fn output(&self) -> bool {
+        self.output
+    }
+
+    /// Sets the levels of the clock and data lines
+    pub fn set_lines(&mut self, scl: bool, sda: bool) {
+        if self.scl && scl && self.sda && !sda {
+            self.start();
+        } else if self.scl && scl && !self.sda && sda {
+            self.state = State::Idle;
+            self.output = true;
+        } else if !self.scl && scl {
+            self.clock_rise(sda);
+        } else if self.scl && !scl {
+            self.clock_fall();
+        }
+        self.scl = scl;
+        self.sda = sda;
+    }
+
+    fn start(&mut self) {
+        self.state = match self.chip {
+            EepromChip::X24C01 => State::Address,
+            EepromChip::X24C02 => State::Device
+        };
+        self.shift = 0;
+        self.bits = 0;
+        self.output = true;
+    }
+
+    fn clock_rise(&mut self, sda: bool) {
+        match self.state {
+            State::Device | State::Address | State::Write if self.bits < 8 => {
+                let bit = sda as u8;
+                self.shift = match self.chip {
+                    EepromChip::X24C01 => self.shift | (bit << self.bits),
+                    EepromChip::X24C02 => (self.shift << 1) | bit
+                };
+                self.bits += 1;
+            },
+            State::MasterAck => self.acked = !sda,
+            _ => {}
+        }
+    }
+
+    fn clock_fall(&mut self) {
+        match self.state {
+            State::Device | State::Address | State::Write if self.bits == 8 => self.receive(),
+            State::Ack(next) => {
+                self.output = true;
+                self.shift = 0;
+                self.bits = 0;
+                self.state = match next {
+                    Next::Address => State::Address,
+                    Next::Write => State::Write,
+                    Next::Read => {
+                        self.send_bit();
+                        State::Read
+                    }
+                };
+            },
+            State::Read => {
+                self.bits += 1;
+                if self.bits == 8 {
+                    self.output = true;
+                    self.state = State::MasterAck;
+                } else {
+                    self.send_bit();
+                }
+            },
+            State::MasterAck => {
+                if self.acked {
+                    self.address = ((self.address as u64 + 1) % self.data.len()) as u8;
+                    self.bits = 0;
+                    self.send_bit();
+                    self.state = State::Read;
+                } else {
+                    self.state = State::Idle;
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Handles a complete byte received from the bus master, and acknowledges it
+    fn receive(&mut self) {
+        let byte = self.shift;
+        let next = match (self.state, self.chip) {
+            (State::Device, _) => {
+                if byte & 0xF0 != 0xA0 {
+                    // Addressed to another device on the bus
+                    self.state = State::Idle;
+                    return;
+                }
+                if byte & 0x01 != 0 { Next::Read } else { Next::Address }
+            },
+            (State::Address, EepromChip::X24C01) => {
+                self.address = byte & 0x7F;
+                if byte & 0x80 != 0 { Next::Read } else { Next::Write }
+            },
+            (State::Address, EepromChip::X24C02) => {
+                self.address = byte;
+                Next::Write
+            },
+            _ => {
+                self.data.contents_mut()[self.address as usize] = byte;
+                // Writes wrap around within a page, 4 bytes on the 24C01 and 8 on the 24C02
+                let page = match self.chip {
+                    EepromChip::X24C01 => 0x03,
+                    EepromChip::X24C02 => 0x07
+                };
+                self.address = (self.address & !page) | (self.address.wrapping_add(1) & page);
+                Next::Write
+            }
+        };
+        self.output = false;
+        self.state = State::Ack(next);
+    }
+
+    /// Drives the data line with the next bit of the byte being read
+    fn send_bit(&mut self) {
+        let byte = self.data.contents()[self.address as usize];
+        let bit = match self.chip {
+            EepromChip::X24C01 => self.bits,
+            EepromChip::X24C02 => 7 - self.bits
+        };
+        self.output = (byte >> bit) & 0x01 != 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use systems::nes::cart::eeprom::{Eeprom,EepromChip};
+
+    fn start(eeprom: &mut Eeprom) {
+        eeprom.set_lines(false, true);
+        eeprom.set_lines(true, true);
+        eeprom.set_lines(true, false);
+        eeprom.set_lines(false, false);
+    }
+
+    fn stop(eeprom: &mut Eeprom) {
+        eeprom.set_lines(false, false);
+        eeprom.set_lines(true, false);
+        eeprom.set_lines(true, true);
+    }
+
+    /// Clocks a bit in to the EEPROM, returning the level of the data line while the clock is
+    /// high
+    fn clock(eeprom: &mut Eeprom, bit: bool) -> bool {
+        eeprom.set_lines(false, bit);
+        eeprom.set_lines(true, bit);
+        let output = eeprom.output();
+        eeprom.set_lines(false, bit);
+        output
+    }
+
+    /// Sends a byte and returns whether the EEPROM acknowledged it
+    fn send(eeprom: &mut Eeprom, byte: u8, lsb_first: bool) -> bool {
+        for i in 0 .. 8 {
+            let bit = if lsb_first { i } else { 7 - i };
+            clock(eeprom, (byte >> bit) & 0x01 != 0);
+        }
+        !clock(eeprom, true)
+    }
+
+    /// Receives a byte, then acknowledges it if `ack` is set
+    fn receive(eeprom: &mut Eeprom, lsb_first: bool, ack: bool) -> u8 {
+        let mut byte = 0;
+        for i in 0 .. 8 {
+            let bit = if lsb_first { i } else { 7 - i };
+            if clock(eeprom, true) {
+                byte |= 1 << bit;
+            }
+        }
+        clock(eeprom, !ack);
+        byte
+    }
+
+    #[test]
+    pub fn x24c01_writes_and_reads_bytes() {
+        let mut eeprom = Eeprom::new(EepromChip::X24C01);
+        start(&mut eeprom);
+        assert!(send(&mut eeprom, 0x05, true));
+        assert!(send(&mut eeprom, 0x42, true));
+        assert!(send(&mut eeprom, 0x17, true));
+        stop(&mut eeprom);
+        assert_eq!(&[0x42, 0x17], &eeprom.contents()[5 .. 7]);
+
+        start(&mut eeprom);
+        assert!(send(&mut eeprom, 0x85, true));
+        assert_eq!(0x42, receive(&mut eeprom, true, true));
+        assert_eq!(0x17, receive(&mut eeprom, true, false));
+        stop(&mut eeprom);
+    }
+
+    #[test]
+    pub fn x24c02_reads_after_repeated_start() {
+        let mut eeprom = Eeprom::new(EepromChip::X24C02);
+        eeprom.contents_mut()[0x80] = 0x5A;
+        eeprom.contents_mut()[0x81] = 0xC3;
+
+        start(&mut eeprom);
+        assert!(send(&mut eeprom, 0xA0, false));
+        assert!(send(&mut eeprom, 0x80, false));
+        start(&mut eeprom);
+        assert!(send(&mut eeprom, 0xA1, false));
+        assert_eq!(0x5A, receive(&mut eeprom, false, true));
+        assert_eq!(0xC3, receive(&mut eeprom, false, false));
+        stop(&mut eeprom);
+    }
+
+    #[test]
+    pub fn x24c02_ignores_other_devices() {
+        let mut eeprom = Eeprom::new(EepromChip::X24C02);
+        start(&mut eeprom);
+        assert!(!send(&mut eeprom, 0x50, false));
+    }
+
+    #[test]
+    pub fn writes_wrap_within_page() {
+        let mut eeprom = Eeprom::new(EepromChip::X24C02);
+        start(&mut eeprom);
+        send(&mut eeprom, 0xA0, false);
+        send(&mut eeprom, 0x07, false);
+        send(&mut eeprom, 0x11, false);
+        send(&mut eeprom, 0x22, false);
+        stop(&mut eeprom);
+        assert_eq!(0x11, eeprom.contents()[0x07]);
+        assert_eq!(0x22, eeprom.contents()[0x00]);
+    }
+}
@@ -0,0 +1,205 @@
+use mem::{self,Memory};
+
+/// The manufacturer ID returned in software ID mode
+const MANUFACTURER_ID: u8 = 0xBF;
+
+/// The size of the sectors erased by the sector erase command
+const SECTOR_SIZE: usize = 0x1000;
+
+/// The state of the command sequence being written to the chip
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+enum State {
+    /// Waiting for the first unlock write, $AA to $5555
+    Ready,
+
+    /// Waiting for the second unlock write, $55 to $2AAA
+    Unlocked,
+
+    /// Waiting for a command to be written to $5555
+    Command,
+
+    /// Waiting for the byte to program
+    Program,
+
+    /// Waiting for the first unlock write of an erase
+    EraseReady,
+
+    /// Waiting for the second unlock write of an erase
+    EraseUnlocked,
+
+    /// Waiting for the sector or chip erase command
+    EraseCommand
+}
+
+/// Emulates an SST39SF010A/020A/040 flash memory, which is programmed through command sequences
+/// written to it
+///
+/// Each command starts by writing $AA to $5555 and $55 to $2AAA:
+///
+/// * $A0 to $5555: Program the next byte written, which can only clear bits
+/// * $80 to $5555, then the unlock sequence again, then $30 to an address: Erase the 4KB sector
+///   containing the address to $FF
+/// * $80 to $5555, then the unlock sequence again, then $10 to $5555: Erase the whole chip
+/// * $90 to $5555: Enter software ID mode, where reads return the manufacturer and device IDs
+/// * $F0 to any address (without the unlock sequence): Exit software ID mode
+///
+/// Programming and erasing complete immediately, so polling for completion always succeeds.
+pub struct Flash {
+    data: mem::Fixed,
+    state: State,
+    software_id: bool
+}
+
+impl Flash {
+    pub fn new(contents: Vec<u8>) -> Flash {
+        Flash {
+            data: mem::Fixed::from_contents(contents),
+            state: State::Ready,
+            software_id: false
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len() as usize
+    }
+
+    /// Gets the contents of the flash memory
+    pub fn contents(&self) -> &[u8] {
+        self.data.contents()
+    }
+
+    /// Gets the contents of the flash memory, for modification
+    pub fn contents_mut(&mut self) -> &mut [u8] {
+        self.data.contents_mut()
+    }
+
+    /// Gets the device ID of the smallest chip that holds the contents
+    fn device_id(&self) -> u8 {
+        match self.len() {
+            0 ... 0x20000 => 0xB5,
+            0x20001 ... 0x40000 => 0xB6,
+            _ => 0xB7
+        }
+    }
+
+    /// Reads the byte at the provided offset
+    pub fn read(&self, addr: usize) -> mem::Result<u8> {
+        if self.software_id {
+            return Ok(if addr & 0x01 == 0 { MANUFACTURER_ID } else { self.device_id() });
+        }
+        self.data.get_u8(addr as u64)
+    }
+
+    /// Writes a byte in a command sequence to the provided offset
+    pub fn write(&mut self, addr: usize, val: u8) {
+        let command = addr & 0x7FFF;
+        self.state = match (self.state, command, val) {
+            (State::Ready, 0x5555, 0xAA) => State::Unlocked,
+            (State::Ready, _, 0xF0) => {
+                self.software_id = false;
+                State::Ready
+            },
+            (State::Unlocked, 0x2AAA, 0x55) => State::Command,
+            (State::Command, 0x5555, 0xA0) => State::Program,
+            (State::Command, 0x5555, 0x80) => State::EraseReady,
+            (State::Command, 0x5555, 0x90) => {
+                self.software_id = true;
+                State::Ready
+            },
+            (State::Command, 0x5555, 0xF0) => {
+                self.software_id = false;
+                State::Ready
+            },
+            (State::Program, _, _) => {
+                let len = self.len();
+                let byte = &mut self.data.contents_mut()[addr % len];
+                *byte &= val;
+                State::Ready
+            },
+            (State::EraseReady, 0x5555, 0xAA) => State::EraseUnlocked,
+            (State::EraseUnlocked, 0x2AAA, 0x55) => State::EraseCommand,
+            (State::EraseCommand, _, 0x30) => {
+                let len = self.len();
+                let start = (addr % len) & !(SECTOR_SIZE - 1);
+                let end = (start + SECTOR_SIZE).min(len);
+                for byte in &mut self.data.contents_mut()[start .. end] {
+                    *byte = 0xFF;
+                }
+                State::Ready
+            },
+            (State::EraseCommand, 0x5555, 0x10) => {
+                for byte in self.data.contents_mut() {
+                    *byte = 0xFF;
+                }
+                State::Ready
+            },
+            // Anything else out of sequence abandons the command
+            _ => State::Ready
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use systems::nes::cart::flash::Flash;
+
+    fn command(flash: &mut Flash, cmd: u8) {
+        flash.write(0x5555, 0xAA);
+        flash.write(0x2AAA, 0x55);
+        flash.write(0x5555, cmd);
+    }
+
+    #[test]
+    pub fn programs_bytes_by_clearing_bits() {
+        let mut flash = Flash::new(vec![0xFF; 0x80000]);
+        command(&mut flash, 0xA0);
+        flash.write(0x12345, 0xF3);
+        assert_eq!(Ok(0xF3), flash.read(0x12345));
+
+        command(&mut flash, 0xA0);
+        flash.write(0x12345, 0x3F);
+        assert_eq!(Ok(0x33), flash.read(0x12345));
+    }
+
+    #[test]
+    pub fn ignores_writes_without_command() {
+        let mut flash = Flash::new(vec![0xFF; 0x80000]);
+        flash.write(0x1000, 0x00);
+        flash.write(0x5555, 0xAA);
+        flash.write(0x1234, 0x55);
+        flash.write(0x5555, 0xA0);
+        flash.write(0x1000, 0x00);
+        assert_eq!(Ok(0xFF), flash.read(0x1000));
+    }
+
+    #[test]
+    pub fn erases_sector() {
+        let mut flash = Flash::new(vec![0x00; 0x80000]);
+        command(&mut flash, 0x80);
+        flash.write(0x5555, 0xAA);
+        flash.write(0x2AAA, 0x55);
+        flash.write(0x3456, 0x30);
+        assert_eq!(Ok(0x00), flash.read(0x2FFF));
+        assert_eq!(Ok(0xFF), flash.read(0x3000));
+        assert_eq!(Ok(0xFF), flash.read(0x3FFF));
+        assert_eq!(Ok(0x00), flash.read(0x4000));
+    }
+
+    #[test]
+    pub fn erases_chip() {
+        let mut flash = Flash::new(vec![0x00; 0x80000]);
+        command(&mut flash, 0x80);
+        command(&mut flash, 0x10);
+        assert!(flash.contents().iter().all(|&b| b == 0xFF));
+    }
+
+    #[test]
+    pub fn software_id_mode_returns_ids() {
+        let mut flash = Flash::new(vec![0x00; 0x80000]);
+        command(&mut flash, 0x90);
+        assert_eq!(Ok(0xBF), flash.read(0x0000));
+        assert_eq!(Ok(0xB7), flash.read(0x0001));
+        flash.write(0x0000, 0xF0);
+        assert_eq!(Ok(0x00), flash.read(0x0001));
+    }
+}
@@ -0,0 +1,311 @@
+use slog;
+
+use mem;
+use hw::expansion_audio::{ExpansionAudio,Sunsoft5bAudio};
+use systems::nes;
+use systems::nes::cart::chr::Chr;
+use systems::nes::cart::{bank_from_end,open_bus};
+
+/// The size of a switchable PRG bank
+const PRG_BANK_SIZE: usize = 0x2000;
+
+/// The size of a switchable CHR bank
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// Emulates the Sunsoft FME-7 and 5B
+///
+/// Registers are written through a command register at $8000-$9FFF, which selects the register
+/// that the next write to the parameter register at $A000-$BFFF goes to:
+///
+/// * $0-$7: 1KB CHR banks
+/// * $8: 8KB bank at $6000, which is RAM if bit 6 is set (and enabled by bit 7) or ROM otherwise
+/// * $9-$B: 8KB PRG banks at $8000, $A000 and $C000
+/// * $C: Mirroring
+/// * $D: IRQ control, bit 0 enables the IRQ and bit 7 enables counting
+/// * $E/$F: IRQ counter low and high bytes
+///
+/// The last PRG bank is fixed at $E000. The IRQ counter decrements every CPU cycle while counting
+/// is enabled, and fires when it wraps from $0000 to $FFFF. The 5B adds its audio at
+/// $C000-$FFFF.
+pub struct Fme7 {
+    rom: mem::Fixed,
+    ram: mem::Fixed,
+    chr: Chr,
+    command: u8,
+    ram_bank: u8,
+    prg_banks: [usize; 3],
+    mirroring: nes::Mirroring,
+    irq_enabled: bool,
+    counter_enabled: bool,
+    irq_pending: bool,
+    counter: u16,
+    audio: Option<Sunsoft5bAudio>,
+    log: slog::Logger
+}
+
+impl Fme7 {
+    /// Creates a new FME-7 cartridge, or a 5B cartridge if `audio` is set
+    ///
+    /// If `chr` is empty, the cartridge uses 8KB of CHR RAM instead
+    pub fn new(audio: bool, prg: Vec<u8>, chr: Vec<u8>, ram_size: usize, logger: Option<slog::Logger>) -> Fme7 {
+        let log = unwrap_logger!(logger).new(o!("mapper" => "Fme7", "cartridge" => true));
+
+        Fme7 {
+            rom: mem::Fixed::from_contents(prg),
+            ram: mem::Fixed::new(ram_size),
+            chr: Chr::new(chr, 0x2000, CHR_BANK_SIZE, log.clone()),
+            command: 0,
+            ram_bank: 0,
+            prg_banks: [0; 3],
+            mirroring: nes::Mirroring::Vertical,
+            irq_enabled: false,
+            counter_enabled: false,
+            irq_pending: false,
+            counter: 0,
+            audio: if audio { Some(Sunsoft5bAudio::new()) } else { None },
+            log: log
+        }
+    }
+
+    /// Gets the offset in to PRG ROM of the provided 8KB bank
+    fn prg_offset(&self, bank: usize) -> usize {
+        (bank * PRG_BANK_SIZE) % (self.rom.len() as usize)
+    }
+
+    fn write_parameter(&mut self, val: u8) {
+        trace!(self.log,
+            "reg" => format!("${:X}", self.command),
+            "val" => val;
+            "wrote register");
+        match self.command {
+            reg @ 0x0 ... 0x7 => self.chr.set_bank(reg as usize, val as usize),
+            0x8 => self.ram_bank = val,
+            reg @ 0x9 ... 0xB => self.prg_banks[(reg - 0x9) as usize] = (val & 0x3F) as usize,
+            0xC => {
+                self.mirroring = match val & 0x03 {
+                    0 => nes::Mirroring::Vertical,
+                    1 => nes::Mirroring::Horizontal,
+                    2 => nes::Mirroring::SingleScreenLower,
+                    _ => nes::Mirroring::SingleScreenUpper
+                };
+            },
+            0xD => {
+                self.irq_enabled = val & 0x01 != 0;
+                self.counter_enabled = val & 0x80 != 0;
+                self.irq_pending = false;
+            },
+            0xE => self.counter = (self.counter & 0xFF00) | val as u16,
+            _ => self.counter = (self.counter & 0x00FF) | ((val as u16) << 8)
+        }
+    }
+}
+
+impl nes::Mapper for Fme7 {
+    fn name(&self) -> &'static str {
+        if self.audio.is_some() { "Sunsoft5b" } else { "Fme7" }
+    }
+
+    fn prg(&self) -> &mem::Memory<u16> {
+        self
+    }
+
+    fn prg_mut(&mut self) -> &mut mem::Memory<u16> {
+        self
+    }
+
+    fn chr(&self) -> &mem::Memory<u16> {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut mem::Memory<u16> {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Option<nes::Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn expansion_audio(&mut self) -> Option<&mut ExpansionAudio> {
+        match self.audio {
+            Some(ref mut audio) => Some(audio),
+            None => None
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn clock_cpu(&mut self) {
+        if self.counter_enabled {
+            self.counter = self.counter.wrapping_sub(1);
+            if self.counter == 0xFFFF && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        Some(self.ram.contents())
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.ram.contents_mut())
+    }
+}
+
+impl mem::Memory<u16> for Fme7 {
+    fn len(&self) -> u64 { 0xA000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "read");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on FME-7 cartridge",
+                format!("${:4X} is below the addressable range of 0x6000-0xFFFF", addr)))
+        } else if addr < 0x8000 && self.ram_bank & 0x40 != 0 {
+            if self.ram_bank & 0x80 == 0 || self.ram.len() == 0 {
+                return Ok(open_bus(addr));
+            }
+            self.ram.get_u8(((addr - 0x6000) as u64) % self.ram.len())
+        } else {
+            let bank = match addr {
+                0x6000 ... 0x7FFF => self.prg_offset((self.ram_bank & 0x3F) as usize),
+                0x8000 ... 0xDFFF => self.prg_offset(self.prg_banks[((addr - 0x8000) >> 13) as usize]),
+                _ => bank_from_end(self.rom.len() as usize, PRG_BANK_SIZE, 0) * PRG_BANK_SIZE
+            };
+            let eaddr = (bank + (addr as usize & (PRG_BANK_SIZE - 1))) % (self.rom.len() as usize);
+            trace!(self.log,
+                "read";
+                "vaddr" => format!("${:04X}", addr),
+                "paddr" => format!("${:05X}", eaddr),
+                "target" => "ROM",
+                "action" => "read");
+            self.rom.get_u8(eaddr as u64)
+        }
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "write");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on FME-7 cartridge",
+                format!("${:4X} is below the addressable range on FME-7 cartridge", addr)))
+        } else if addr < 0x8000 {
+            if self.ram_bank & 0xC0 != 0xC0 || self.ram.len() == 0 {
+                trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to disabled RAM");
+                return Ok(());
+            }
+            let eaddr = ((addr - 0x6000) as u64) % self.ram.len();
+            self.ram.set_u8(eaddr, val)
+        } else {
+            match addr & 0xE000 {
+                0x8000 => self.command = val & 0x0F,
+                0xA000 => self.write_parameter(val),
+                reg => {
+                    if let Some(ref mut audio) = self.audio {
+                        audio.write(reg, val);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mem::Memory;
+    use systems::nes::{Mapper,Mirroring};
+    use systems::nes::cart::Fme7;
+    use systems::nes::cart::testing::numbered_banks;
+
+    fn write(cart: &mut Fme7, reg: u8, val: u8) {
+        cart.set_u8(0x8000, reg).unwrap();
+        cart.set_u8(0xA000, val).unwrap();
+    }
+
+    #[test]
+    pub fn banks_prg_and_chr() {
+        let mut cart = Fme7::new(false, numbered_banks(32, 0x2000), numbered_banks(256, 0x0400), 0x2000, None);
+        write(&mut cart, 0x9, 4);
+        write(&mut cart, 0xB, 9);
+        write(&mut cart, 0x3, 200);
+        write(&mut cart, 0xC, 1);
+        assert_eq!(Ok(4), cart.get_u8(0x8000));
+        assert_eq!(Ok(9), cart.get_u8(0xC000));
+        assert_eq!(Ok(31), cart.get_u8(0xE000));
+        assert_eq!(Ok(200), cart.chr().get_u8(0x0C00));
+        assert_eq!(Some(Mirroring::Horizontal), cart.mirroring());
+    }
+
+    #[test]
+    pub fn window_at_6000_selects_rom_or_ram() {
+        let mut cart = Fme7::new(false, numbered_banks(32, 0x2000), numbered_banks(256, 0x0400), 0x2000, None);
+        write(&mut cart, 0x8, 7);
+        assert_eq!(Ok(7), cart.get_u8(0x6000));
+
+        write(&mut cart, 0x8, 0x40);
+        cart.set_u8(0x6000, 42).unwrap();
+        assert_eq!(Ok(0x60), cart.get_u8(0x6000));
+
+        write(&mut cart, 0x8, 0xC0);
+        cart.set_u8(0x6000, 42).unwrap();
+        assert_eq!(Ok(42), cart.get_u8(0x6000));
+    }
+
+    #[test]
+    pub fn irq_fires_when_counter_wraps() {
+        let mut cart = Fme7::new(false, numbered_banks(32, 0x2000), numbered_banks(256, 0x0400), 0x2000, None);
+        write(&mut cart, 0xE, 2);
+        write(&mut cart, 0xF, 0);
+        write(&mut cart, 0xD, 0x81);
+        cart.clock_cpu();
+        cart.clock_cpu();
+        assert!(!cart.irq());
+        cart.clock_cpu();
+        assert!(cart.irq());
+        write(&mut cart, 0xD, 0x81);
+        assert!(!cart.irq());
+    }
+
+    #[test]
+    pub fn counter_runs_without_irq_enabled() {
+        let mut cart = Fme7::new(false, numbered_banks(32, 0x2000), numbered_banks(256, 0x0400), 0x2000, None);
+        write(&mut cart, 0xD, 0x80);
+        cart.clock_cpu();
+        assert!(!cart.irq());
+        write(&mut cart, 0xD, 0x01);
+        for _ in 0 .. 0x10000 {
+            cart.clock_cpu();
+        }
+        assert!(!cart.irq());
+    }
+
+    #[test]
+    pub fn audio_is_only_on_5b() {
+        let mut cart = Fme7::new(false, numbered_banks(32, 0x2000), numbered_banks(256, 0x0400), 0x2000, None);
+        assert!(cart.expansion_audio().is_none());
+        let mut cart = Fme7::new(true, numbered_banks(32, 0x2000), numbered_banks(256, 0x0400), 0x2000, None);
+        assert!(cart.expansion_audio().is_some());
+    }
+
+    #[test]
+    pub fn single_prg_bank_is_mirrored() {
+        let mut cart = Fme7::new(false, vec![42; 0x2000], vec![], 0, None);
+        write(&mut cart, 0x09, 3);
+        for addr in [0x8000, 0xA000, 0xC000, 0xE000].iter() {
+            assert_eq!(Ok(42), cart.get_u8(*addr));
+        }
+    }
+}
@@ -0,0 +1,422 @@
+use slog;
+
+use mem;
+use systems::nes;
+use systems::nes::cart::chr::Chr;
+use systems::nes::cart::open_bus;
+
+/// The size of a switchable PRG ROM bank
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/// The size of a switchable CHR bank
+const CHR_BANK_SIZE: usize = 0x1000;
+
+/// The size of the PRG ROM reachable without the SUROM outer bank line
+const PRG_OUTER_BANK_SIZE: usize = 0x40000;
+
+/// The size of a PRG RAM bank
+const PRG_RAM_BANK_SIZE: usize = 0x2000;
+
+/// Emulates the Nintendo MMC1, used by the SxROM boards
+///
+/// The MMC1 is configured through a serial port: each write to $8000-$FFFF shifts bit 0 of the
+/// value in to a 5-bit shift register, and the fifth write copies the shift register to the
+/// internal register selected by bits 13 and 14 of its address. Writing a value with bit 7 set
+/// resets the shift register instead.
+///
+/// * $8000-$9FFF: Control (mirroring, PRG bank mode and CHR bank mode)
+/// * $A000-$BFFF: CHR bank 0
+/// * $C000-$DFFF: CHR bank 1
+/// * $E000-$FFFF: PRG bank and PRG RAM enable
+///
+/// The MMC1 ignores a write that happens on the cycle after another write. This only happens with
+/// the dummy write of read-modify-write instructions, which some games use to reset the shift
+/// register (with a value that has bit 7 set) and rely on the second write being dropped.
+///
+/// Boards with more than 256KB of PRG ROM (SUROM and SXROM) or more than 8KB of PRG RAM (SOROM
+/// and SXROM) use the upper bits of CHR bank 0 as extra banking lines, as they have CHR RAM and
+/// don't need them for CHR banking.
+pub struct Mmc1 {
+    rom: mem::Fixed,
+    ram: mem::Fixed,
+    chr: Chr,
+    shift: u8,
+    shift_count: u8,
+    control: u8,
+    chr_bank0: u8,
+    chr_bank1: u8,
+    prg_bank: u8,
+    prg_banks: [usize; 2],
+    cycle: u64,
+    last_write: Option<u64>,
+    log: slog::Logger
+}
+
+impl Mmc1 {
+    /// Creates a new MMC1 cartridge
+    ///
+    /// If `chr` is empty, the cartridge uses 8KB of CHR RAM instead
+    pub fn new(prg: Vec<u8>, chr: Vec<u8>, ram_size: usize, logger: Option<slog::Logger>) -> Mmc1 {
+        let log = unwrap_logger!(logger).new(o!("mapper" => "Mmc1", "cartridge" => true));
+        let mut mmc1 = Mmc1 {
+            rom: mem::Fixed::from_contents(prg),
+            ram: mem::Fixed::new(ram_size),
+            chr: Chr::new(chr, 0x2000, CHR_BANK_SIZE, log.clone()),
+            shift: 0,
+            shift_count: 0,
+            // The MMC1 powers up with the last PRG bank fixed at $C000
+            control: 0x0C,
+            chr_bank0: 0,
+            chr_bank1: 0,
+            prg_bank: 0,
+            prg_banks: [0, 0],
+            cycle: 0,
+            last_write: None,
+            log: log
+        };
+        mmc1.update_banks();
+        mmc1
+    }
+
+    /// Recalculates the offsets of the PRG and CHR banks from the internal registers
+    fn update_banks(&mut self) {
+        // SUROM and SXROM select the 256KB half of PRG ROM with bit 4 of CHR bank 0
+        let outer = if self.rom.len() as usize > PRG_OUTER_BANK_SIZE {
+            ((self.chr_bank0 & 0x10) >> 4) as usize * PRG_OUTER_BANK_SIZE
+        } else {
+            0
+        };
+        let bank = (self.prg_bank & 0x0F) as usize;
+        let (first, second) = match (self.control >> 2) & 0x03 {
+            // 32KB mode ignores the low bit of the bank number
+            0 | 1 => (bank & 0x0E, bank | 0x01),
+            2 => (0, bank),
+            _ => (bank, 0x0F)
+        };
+        let rom_len = self.rom.len() as usize;
+        self.prg_banks = [
+            (outer + first * PRG_BANK_SIZE) % rom_len,
+            (outer + second * PRG_BANK_SIZE) % rom_len
+        ];
+
+        if self.control & 0x10 == 0 {
+            // 8KB mode ignores the low bit of the bank number
+            let bank = (self.chr_bank0 & 0x1E) as usize;
+            self.chr.set_bank(0, bank);
+            self.chr.set_bank(1, bank | 0x01);
+        } else {
+            self.chr.set_bank(0, self.chr_bank0 as usize);
+            self.chr.set_bank(1, self.chr_bank1 as usize);
+        }
+    }
+
+    /// Gets the offset in to PRG RAM of the provided address in $6000-$7FFF
+    ///
+    /// SXROM selects one of four 8KB banks with bits 2 and 3 of CHR bank 0, and SOROM selects one
+    /// of two with bit 3
+    fn ram_offset(&self, addr: u16) -> u64 {
+        let bank = match self.ram.len() as usize / PRG_RAM_BANK_SIZE {
+            0 | 1 => 0,
+            2 => ((self.chr_bank0 >> 3) & 0x01) as usize,
+            _ => ((self.chr_bank0 >> 2) & 0x03) as usize
+        };
+        ((bank * PRG_RAM_BANK_SIZE + (addr - 0x6000) as usize) as u64) % self.ram.len()
+    }
+
+    fn ram_enabled(&self) -> bool {
+        self.ram.len() > 0 && self.prg_bank & 0x10 == 0
+    }
+
+    /// Handles a write to the serial port at $8000-$FFFF
+    fn write_register(&mut self, addr: u16, val: u8) {
+        // Writes on consecutive cycles are ignored. Cycles are counted after each instruction,
+        // so both writes of a read-modify-write instruction are seen on the same cycle.
+        let consecutive = match self.last_write {
+            Some(cycle) => self.cycle - cycle <= 1,
+            None => false
+        };
+        self.last_write = Some(self.cycle);
+        if consecutive {
+            trace!(self.log, "vaddr" => format!("${:04X}", addr), "val" => val; "ignoring consecutive write");
+            return;
+        }
+
+        if val & 0x80 != 0 {
+            trace!(self.log, "vaddr" => format!("${:04X}", addr), "val" => val; "resetting shift register");
+            self.shift = 0;
+            self.shift_count = 0;
+            self.control |= 0x0C;
+            self.update_banks();
+            return;
+        }
+
+        self.shift |= (val & 0x01) << self.shift_count;
+        self.shift_count += 1;
+        if self.shift_count < 5 {
+            return;
+        }
+
+        let reg = self.shift;
+        self.shift = 0;
+        self.shift_count = 0;
+        match (addr >> 13) & 0x03 {
+            0 => self.control = reg,
+            1 => self.chr_bank0 = reg,
+            2 => self.chr_bank1 = reg,
+            _ => self.prg_bank = reg
+        }
+        trace!(self.log,
+            "vaddr" => format!("${:04X}", addr),
+            "val" => reg;
+            "wrote register {}", (addr >> 13) & 0x03);
+        self.update_banks();
+    }
+}
+
+impl nes::Mapper for Mmc1 {
+    fn name(&self) -> &'static str { "Mmc1" }
+
+    fn prg(&self) -> &mem::Memory<u16> {
+        self
+    }
+
+    fn prg_mut(&mut self) -> &mut mem::Memory<u16> {
+        self
+    }
+
+    fn chr(&self) -> &mem::Memory<u16> {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut mem::Memory<u16> {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Option<nes::Mirroring> {
+        Some(match self.control & 0x03 {
+            0 => nes::Mirroring::SingleScreenLower,
+            1 => nes::Mirroring::SingleScreenUpper,
+            2 => nes::Mirroring::Vertical,
+            _ => nes::Mirroring::Horizontal
+        })
+    }
+
+    fn clock_cpu(&mut self) {
+        self.cycle += 1;
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        Some(self.ram.contents())
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.ram.contents_mut())
+    }
+}
+
+impl mem::Memory<u16> for Mmc1 {
+    fn len(&self) -> u64 { 0xA000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "read");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on MMC1 cartridge",
+                format!("${:4X} is below the addressable range of 0x6000-0xFFFF", addr)))
+        } else if addr < 0x8000 {
+            if !self.ram_enabled() {
+                trace!(self.log, "vaddr" => format!("${:04X}", addr); "read from disabled RAM");
+                return Ok(open_bus(addr));
+            }
+            self.ram.get_u8(self.ram_offset(addr))
+        } else {
+            let bank = self.prg_banks[((addr >> 14) & 0x01) as usize];
+            let eaddr = (bank + (addr as usize & (PRG_BANK_SIZE - 1))) % (self.rom.len() as usize);
+            trace!(self.log,
+                "read";
+                "vaddr" => format!("${:04X}", addr),
+                "paddr" => format!("${:05X}", eaddr),
+                "target" => "ROM",
+                "action" => "read");
+            self.rom.get_u8(eaddr as u64)
+        }
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "write");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on MMC1 cartridge",
+                format!("${:4X} is below the addressable range on MMC1 cartridge", addr)))
+        } else if addr < 0x8000 {
+            if !self.ram_enabled() {
+                trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to disabled RAM");
+                return Ok(());
+            }
+            let eaddr = self.ram_offset(addr);
+            self.ram.set_u8(eaddr, val)
+        } else {
+            self.write_register(addr, val);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mem::Memory;
+    use systems::nes::{Mapper,Mirroring};
+    use systems::nes::cart::Mmc1;
+    use systems::nes::cart::testing::numbered_banks;
+
+    /// Writes a register through the serial port, as a game would with five separate instructions
+    fn write(mmc1: &mut Mmc1, addr: u16, val: u8) {
+        for i in 0 .. 5 {
+            mmc1.set_u8(addr, (val >> i) & 0x01).unwrap();
+            mmc1.clock_cpu();
+            mmc1.clock_cpu();
+        }
+    }
+
+    #[test]
+    pub fn powers_up_with_last_bank_fixed() {
+        let mmc1 = Mmc1::new(numbered_banks(8, 0x4000), vec![], 0x2000, None);
+        assert_eq!(Ok(0), mmc1.get_u8(0x8000));
+        assert_eq!(Ok(7), mmc1.get_u8(0xC000));
+    }
+
+    #[test]
+    pub fn shift_register_loads_after_five_writes() {
+        let mut mmc1 = Mmc1::new(numbered_banks(8, 0x4000), vec![], 0x2000, None);
+        for i in 0 .. 4 {
+            mmc1.set_u8(0xE000, (3 >> i) & 0x01).unwrap();
+            mmc1.clock_cpu();
+            mmc1.clock_cpu();
+        }
+        assert_eq!(Ok(0), mmc1.get_u8(0x8000));
+        mmc1.set_u8(0xE000, 0).unwrap();
+        assert_eq!(Ok(3), mmc1.get_u8(0x8000));
+    }
+
+    #[test]
+    pub fn bit_7_resets_shift_register() {
+        let mut mmc1 = Mmc1::new(numbered_banks(8, 0x4000), vec![], 0x2000, None);
+        mmc1.set_u8(0xE000, 1).unwrap();
+        mmc1.clock_cpu();
+        mmc1.clock_cpu();
+        mmc1.set_u8(0xE000, 0x80).unwrap();
+        mmc1.clock_cpu();
+        mmc1.clock_cpu();
+        write(&mut mmc1, 0xE000, 2);
+        assert_eq!(Ok(2), mmc1.get_u8(0x8000));
+    }
+
+    #[test]
+    pub fn consecutive_writes_are_ignored() {
+        let mut mmc1 = Mmc1::new(numbered_banks(8, 0x4000), vec![], 0x2000, None);
+        write(&mut mmc1, 0x8000, 0x00);
+
+        // The dummy write of a read-modify-write instruction resets the shift register, and the
+        // write of the result is ignored
+        mmc1.set_u8(0x8000, 0xFF).unwrap();
+        mmc1.set_u8(0x8000, 0x00).unwrap();
+        mmc1.clock_cpu();
+        mmc1.clock_cpu();
+        assert_eq!(Ok(7), mmc1.get_u8(0xC000));
+
+        write(&mut mmc1, 0xE000, 1);
+        assert_eq!(Ok(1), mmc1.get_u8(0x8000));
+    }
+
+    #[test]
+    pub fn prg_modes_switch_banks() {
+        let mut mmc1 = Mmc1::new(numbered_banks(8, 0x4000), vec![], 0x2000, None);
+        write(&mut mmc1, 0xE000, 5);
+        assert_eq!(Ok(5), mmc1.get_u8(0x8000));
+        assert_eq!(Ok(7), mmc1.get_u8(0xFFFF));
+
+        write(&mut mmc1, 0x8000, 0x08);
+        assert_eq!(Ok(0), mmc1.get_u8(0x8000));
+        assert_eq!(Ok(5), mmc1.get_u8(0xC000));
+
+        write(&mut mmc1, 0x8000, 0x00);
+        assert_eq!(Ok(4), mmc1.get_u8(0x8000));
+        assert_eq!(Ok(5), mmc1.get_u8(0xC000));
+    }
+
+    #[test]
+    pub fn chr_modes_switch_banks() {
+        let mut mmc1 = Mmc1::new(numbered_banks(2, 0x4000), numbered_banks(8, 0x1000), 0x2000, None);
+        write(&mut mmc1, 0xA000, 3);
+        write(&mut mmc1, 0xC000, 6);
+        assert_eq!(Ok(2), mmc1.chr().get_u8(0x0000));
+        assert_eq!(Ok(3), mmc1.chr().get_u8(0x1000));
+
+        write(&mut mmc1, 0x8000, 0x10);
+        assert_eq!(Ok(3), mmc1.chr().get_u8(0x0000));
+        assert_eq!(Ok(6), mmc1.chr().get_u8(0x1000));
+    }
+
+    #[test]
+    pub fn chr_ram_is_writable() {
+        let mut mmc1 = Mmc1::new(numbered_banks(2, 0x4000), vec![], 0x2000, None);
+        mmc1.chr_mut().set_u8(0x1234, 42).unwrap();
+        assert_eq!(Ok(42), mmc1.chr().get_u8(0x1234));
+    }
+
+    #[test]
+    pub fn control_selects_mirroring() {
+        let mut mmc1 = Mmc1::new(numbered_banks(2, 0x4000), vec![], 0x2000, None);
+        let mirroring: Vec<_> = (0 .. 4).map(|m| {
+            write(&mut mmc1, 0x8000, 0x0C | m);
+            mmc1.mirroring().unwrap()
+        }).collect();
+        assert_eq!(vec![
+            Mirroring::SingleScreenLower,
+            Mirroring::SingleScreenUpper,
+            Mirroring::Vertical,
+            Mirroring::Horizontal], mirroring);
+    }
+
+    #[test]
+    pub fn prg_ram_can_be_disabled() {
+        let mut mmc1 = Mmc1::new(numbered_banks(2, 0x4000), vec![], 0x2000, None);
+        mmc1.set_u8(0x6000, 42).unwrap();
+        write(&mut mmc1, 0xE000, 0x10);
+        mmc1.set_u8(0x6000, 24).unwrap();
+        assert_eq!(Ok(0x60), mmc1.get_u8(0x6000));
+        write(&mut mmc1, 0xE000, 0x00);
+        assert_eq!(Ok(42), mmc1.get_u8(0x6000));
+    }
+
+    #[test]
+    pub fn surom_selects_outer_bank_with_chr_register() {
+        let mut mmc1 = Mmc1::new(numbered_banks(32, 0x4000), vec![], 0x2000, None);
+        assert_eq!(Ok(15), mmc1.get_u8(0xC000));
+        write(&mut mmc1, 0xA000, 0x10);
+        write(&mut mmc1, 0xE000, 2);
+        assert_eq!(Ok(18), mmc1.get_u8(0x8000));
+        assert_eq!(Ok(31), mmc1.get_u8(0xC000));
+    }
+
+    #[test]
+    pub fn sxrom_selects_ram_bank_with_chr_register() {
+        let mut mmc1 = Mmc1::new(numbered_banks(2, 0x4000), vec![], 0x8000, None);
+        mmc1.set_u8(0x6000, 1).unwrap();
+        write(&mut mmc1, 0xA000, 0x0C);
+        mmc1.set_u8(0x6000, 4).unwrap();
+        assert_eq!(Ok(4), mmc1.get_u8(0x6000));
+        write(&mut mmc1, 0xA000, 0x00);
+        assert_eq!(Ok(1), mmc1.get_u8(0x6000));
+    }
+}
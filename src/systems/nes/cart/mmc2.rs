@@ -0,0 +1,339 @@
+use std::cell::Cell;
+
+use slog;
+
+use mem;
+use systems::nes;
+use systems::nes::cart::{bank_from_end,open_bus};
+
+/// The size of a switchable PRG ROM bank on the MMC2, and the size of the slots PRG ROM is
+/// mapped in to
+const PRG_BANK_SIZE: usize = 0x2000;
+
+/// The size of a switchable CHR bank
+const CHR_BANK_SIZE: usize = 0x1000;
+
+/// Identifies the chip emulated by an `Mmc2` mapper
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum Mmc2Variant {
+    /// The MMC2 (mapper 9), used by PxROM: an 8KB switchable PRG bank, and the last three fixed
+    Mmc2,
+
+    /// The MMC4 (mapper 10), used by FxROM: a 16KB switchable PRG bank and the last fixed, with
+    /// 8KB of PRG RAM
+    Mmc4
+}
+
+/// Emulates the Nintendo MMC2 and MMC4
+///
+/// * $A000-$AFFF: PRG bank at $8000
+/// * $B000-$BFFF: CHR bank at $0000, while latch 0 holds $FD
+/// * $C000-$CFFF: CHR bank at $0000, while latch 0 holds $FE
+/// * $D000-$DFFF: CHR bank at $1000, while latch 1 holds $FD
+/// * $E000-$EFFF: CHR bank at $1000, while latch 1 holds $FE
+/// * $F000-$FFFF: Mirroring
+///
+/// Each pattern table has a latch that is set when the PPU fetches the high plane of tile $FD or
+/// $FE from it, which switches between its two CHR banks. On the MMC2 the latch for $0000 only
+/// responds to $0FD8 and $0FE8, the first row of the high plane, while the other latches respond
+/// to any row of it ($xFD8-$xFDF and $xFE8-$xFEF). Games place these tiles where the background
+/// should change, so the bank switches part way through drawing a scanline.
+pub struct Mmc2 {
+    variant: Mmc2Variant,
+    rom: mem::Fixed,
+    ram: mem::Fixed,
+    chr: LatchedChr,
+    prg_banks: [usize; 4],
+    mirroring: nes::Mirroring,
+    log: slog::Logger
+}
+
+/// The CHR ROM of an MMC2 or MMC4, with the banks selected by the latches
+struct LatchedChr {
+    variant: Mmc2Variant,
+    rom: mem::Fixed,
+    banks: [u8; 4],
+    latches: [Cell<bool>; 2],
+    pending: Cell<Option<(usize, bool)>>,
+    log: slog::Logger
+}
+
+impl Mmc2 {
+    /// Creates a new MMC2 or MMC4 cartridge
+    pub fn new(variant: Mmc2Variant, prg: Vec<u8>, chr: Vec<u8>, ram_size: usize, logger: Option<slog::Logger>) -> Mmc2 {
+        let name = if variant == Mmc2Variant::Mmc2 { "Mmc2" } else { "Mmc4" };
+        let log = unwrap_logger!(logger).new(o!("mapper" => name, "cartridge" => true));
+
+        let mut mmc2 = Mmc2 {
+            variant: variant,
+            rom: mem::Fixed::from_contents(prg),
+            ram: mem::Fixed::new(ram_size),
+            chr: LatchedChr {
+                variant: variant,
+                rom: mem::Fixed::from_contents(chr),
+                banks: [0; 4],
+                latches: [Cell::new(false), Cell::new(false)],
+                pending: Cell::new(None),
+                log: log.clone()
+            },
+            prg_banks: [0; 4],
+            mirroring: nes::Mirroring::Vertical,
+            log: log
+        };
+        mmc2.set_prg_bank(0);
+        mmc2
+    }
+
+    /// Switches the PRG bank at $8000, the rest of PRG ROM is fixed to the last banks
+    fn set_prg_bank(&mut self, bank: usize) {
+        let rom_len = self.rom.len() as usize;
+        let from_end = |n| bank_from_end(rom_len, PRG_BANK_SIZE, n);
+        let banks = from_end(0) + 1;
+        self.prg_banks = match self.variant {
+            Mmc2Variant::Mmc2 => [bank % banks, from_end(2), from_end(1), from_end(0)],
+            Mmc2Variant::Mmc4 => [(bank * 2) % banks, (bank * 2 + 1) % banks, from_end(1), from_end(0)]
+        };
+        for bank in self.prg_banks.iter_mut() {
+            *bank *= PRG_BANK_SIZE;
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, val: u8) {
+        trace!(self.log,
+            "vaddr" => format!("${:04X}", addr),
+            "val" => val;
+            "wrote register");
+        match addr & 0xF000 {
+            0xA000 => self.set_prg_bank((val & 0x0F) as usize),
+            0xB000 ... 0xE000 => self.chr.banks[((addr >> 12) - 0xB) as usize] = val & 0x1F,
+            0xF000 => {
+                self.mirroring = if val & 0x01 == 0 {
+                    nes::Mirroring::Vertical
+                } else {
+                    nes::Mirroring::Horizontal
+                };
+            },
+            _ => {}
+        }
+    }
+}
+
+impl LatchedChr {
+    /// Watches an address the PPU has put on its bus, setting the latches
+    ///
+    /// A latch only changes after the fetch that sets it, so the change is held until the next
+    /// address is seen
+    fn observe(&self, addr: u16) {
+        if let Some((table, fe)) = self.pending.get() {
+            self.latches[table].set(fe);
+            self.pending.set(None);
+        }
+        if addr >= 0x2000 {
+            return;
+        }
+
+        // The MMC2 only sets the first latch on exactly $0FD8 or $0FE8, the first row of the high
+        // plane, the others are set by a fetch of any row of the high plane
+        let tile = if self.variant == Mmc2Variant::Mmc2 && addr < 0x1000 { addr } else { addr & 0xFFF8 };
+        let table = (addr >> 12) as usize;
+        match tile & 0x0FFF {
+            0x0FD8 => self.pending.set(Some((table, false))),
+            0x0FE8 => self.pending.set(Some((table, true))),
+            _ => {}
+        }
+    }
+
+    /// Finds where in CHR ROM the provided address is mapped to
+    fn locate(&self, addr: u16) -> u64 {
+        let table = ((addr >> 12) & 0x01) as usize;
+        let latch = if self.latches[table].get() { 1 } else { 0 };
+        let bank = self.banks[table * 2 + latch] as usize;
+        ((bank * CHR_BANK_SIZE + (addr as usize & (CHR_BANK_SIZE - 1))) as u64) % self.rom.len()
+    }
+}
+
+impl nes::Mapper for Mmc2 {
+    fn name(&self) -> &'static str {
+        if self.variant == Mmc2Variant::Mmc2 { "Mmc2" } else { "Mmc4" }
+    }
+
+    fn prg(&self) -> &mem::Memory<u16> {
+        self
+    }
+
+    fn prg_mut(&mut self) -> &mut mem::Memory<u16> {
+        self
+    }
+
+    fn chr(&self) -> &mem::Memory<u16> {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut mem::Memory<u16> {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Option<nes::Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn ppu_address(&self, addr: u16) {
+        self.chr.observe(addr);
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        Some(self.ram.contents())
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.ram.contents_mut())
+    }
+}
+
+impl mem::Memory<u16> for Mmc2 {
+    fn len(&self) -> u64 { 0xA000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "read");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on MMC2 cartridge",
+                format!("${:4X} is below the addressable range of 0x6000-0xFFFF", addr)))
+        } else if addr < 0x8000 {
+            if self.ram.len() == 0 {
+                return Ok(open_bus(addr));
+            }
+            self.ram.get_u8(((addr - 0x6000) as u64) % self.ram.len())
+        } else {
+            let bank = self.prg_banks[((addr >> 13) & 0x03) as usize];
+            let eaddr = (bank + (addr as usize & (PRG_BANK_SIZE - 1))) % (self.rom.len() as usize);
+            trace!(self.log,
+                "read";
+                "vaddr" => format!("${:04X}", addr),
+                "paddr" => format!("${:05X}", eaddr),
+                "target" => "ROM",
+                "action" => "read");
+            self.rom.get_u8(eaddr as u64)
+        }
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "write");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on MMC2 cartridge",
+                format!("${:4X} is below the addressable range on MMC2 cartridge", addr)))
+        } else if addr < 0x8000 {
+            if self.ram.len() == 0 {
+                trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to missing RAM");
+                return Ok(());
+            }
+            let eaddr = ((addr - 0x6000) as u64) % self.ram.len();
+            self.ram.set_u8(eaddr, val)
+        } else {
+            self.write_register(addr, val);
+            Ok(())
+        }
+    }
+}
+
+impl mem::Memory<u16> for LatchedChr {
+    fn len(&self) -> u64 { self.rom.len().min(0x2000) }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        self.rom.get_u8(self.locate(addr))
+    }
+
+    fn set_u8(&mut self, addr: u16, _val: u8) -> mem::Result<()> {
+        trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to CHR ROM");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mem::Memory;
+    use systems::nes::{Mapper,Mirroring};
+    use systems::nes::cart::{Mmc2,Mmc2Variant};
+    use systems::nes::cart::testing::numbered_banks;
+
+    /// Reads through the cartridge as the PPU would
+    fn fetch(cart: &Mmc2, addr: u16) -> u8 {
+        cart.ppu_address(addr);
+        cart.chr().get_u8(addr).unwrap()
+    }
+
+    fn cart(variant: Mmc2Variant) -> Mmc2 {
+        let mut cart = Mmc2::new(variant, numbered_banks(16, 0x2000), numbered_banks(32, 0x1000), 0x2000, None);
+        for (i, addr) in [0xB000, 0xC000, 0xD000, 0xE000].iter().enumerate() {
+            cart.set_u8(*addr, 4 + i as u8).unwrap();
+        }
+        cart
+    }
+
+    #[test]
+    pub fn prg_layouts_differ() {
+        let mut mmc2 = cart(Mmc2Variant::Mmc2);
+        mmc2.set_u8(0xA000, 5).unwrap();
+        assert_eq!(Ok(5), mmc2.get_u8(0x8000));
+        assert_eq!(Ok(13), mmc2.get_u8(0xA000));
+
+        let mut mmc4 = cart(Mmc2Variant::Mmc4);
+        mmc4.set_u8(0xA000, 5).unwrap();
+        assert_eq!(Ok(10), mmc4.get_u8(0x8000));
+        assert_eq!(Ok(11), mmc4.get_u8(0xA000));
+        assert_eq!(Ok(14), mmc4.get_u8(0xC000));
+    }
+
+    #[test]
+    pub fn latches_switch_after_fetch() {
+        let cart = cart(Mmc2Variant::Mmc2);
+        assert_eq!(4, fetch(&cart, 0x0000));
+        assert_eq!(4, fetch(&cart, 0x0FE8));
+        assert_eq!(5, fetch(&cart, 0x0000));
+        assert_eq!(6, fetch(&cart, 0x1000));
+
+        fetch(&cart, 0x1FEB);
+        assert_eq!(7, fetch(&cart, 0x1000));
+        fetch(&cart, 0x1FDF);
+        assert_eq!(6, fetch(&cart, 0x1000));
+    }
+
+    #[test]
+    pub fn mmc2_only_latches_first_table_on_exact_address() {
+        let mmc2 = cart(Mmc2Variant::Mmc2);
+        fetch(&mmc2, 0x0FEA);
+        assert_eq!(4, fetch(&mmc2, 0x0000));
+
+        let mmc4 = cart(Mmc2Variant::Mmc4);
+        fetch(&mmc4, 0x0FEA);
+        assert_eq!(5, fetch(&mmc4, 0x0000));
+    }
+
+    #[test]
+    pub fn mirroring_is_switchable() {
+        let mut cart = cart(Mmc2Variant::Mmc4);
+        cart.set_u8(0xF000, 1).unwrap();
+        assert_eq!(Some(Mirroring::Horizontal), cart.mirroring());
+    }
+
+    #[test]
+    pub fn single_prg_bank_is_mirrored() {
+        for variant in [Mmc2Variant::Mmc2, Mmc2Variant::Mmc4].iter() {
+            let cart = Mmc2::new(*variant, vec![42; 0x2000], numbered_banks(32, 0x1000), 0, None);
+            for addr in [0x8000, 0xA000, 0xC000, 0xE000].iter() {
+                assert_eq!(Ok(42), cart.get_u8(*addr));
+            }
+        }
+    }
+}
@@ -0,0 +1,529 @@
+use std::cell::Cell;
+
+use slog;
+
+use mem;
+use systems::nes;
+use systems::nes::cart::chr::Chr;
+use systems::nes::cart::{bank_from_end,open_bus};
+
+/// The size of a switchable PRG ROM bank
+const PRG_BANK_SIZE: usize = 0x2000;
+
+/// The size of a switchable CHR bank
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// The number of fetches A12 must stay low for before a rise clocks the scanline counter
+///
+/// The MMC3 filters out rises of A12 that happen within a few CPU cycles of it falling, so that
+/// only the first of the pattern fetches from $1000-$1FFF in each scanline is counted. Between
+/// consecutive tiles A12 is only low for the nametable and attribute fetches.
+const A12_FILTER_FETCHES: u8 = 3;
+
+/// Identifies the chip and board emulated by an `Mmc3` mapper
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum Mmc3Variant {
+    /// The MMC3B/MMC3C made by Sharp, which signals an IRQ whenever the counter is 0 after being
+    /// clocked
+    Sharp,
+
+    /// The MMC3A made by NEC, which only signals an IRQ when the counter is decremented or
+    /// explicitly reloaded to 0
+    Nec,
+
+    /// The MMC6 (HKROM), which has 1KB of PRG RAM inside the chip, with separate protection for
+    /// each half
+    Mmc6,
+
+    /// TxSROM (mapper 118), where bit 7 of the CHR banks selects the nametable RAM page instead
+    /// of the mirroring register
+    TxSRom,
+
+    /// TQROM (mapper 119), which has 8KB of CHR RAM as well as CHR ROM, selected by bit 6 of the
+    /// CHR banks
+    TqRom
+}
+
+/// Emulates the Nintendo MMC3, used by the TxROM boards, and its variants
+///
+/// * $8000/$8001 (even/odd): Bank select and bank data, for 8 bank registers
+/// * $A000/$A001: Mirroring and PRG RAM protect
+/// * $C000/$C001: IRQ latch and IRQ reload
+/// * $E000/$E001: IRQ disable and IRQ enable
+///
+/// The scanline counter is clocked by rises of PPU address line A12, which normally happen once
+/// per scanline when the background and sprites use different pattern tables.
+pub struct Mmc3 {
+    variant: Mmc3Variant,
+    rom: mem::Fixed,
+    ram: mem::Fixed,
+    chr: Chr,
+    bank_select: u8,
+    registers: [u8; 8],
+    prg_banks: [usize; 4],
+    mirroring: nes::Mirroring,
+    ram_enabled: bool,
+    ram_protect: u8,
+    irq_latch: u8,
+    irq_enabled: bool,
+    irq_counter: Cell<u8>,
+    irq_reload: Cell<bool>,
+    irq_pending: Cell<bool>,
+    a12_low: Cell<u8>,
+    log: slog::Logger
+}
+
+impl Mmc3 {
+    /// Creates a new MMC3 cartridge
+    ///
+    /// If `chr` is empty, the cartridge uses 8KB of CHR RAM instead. The MMC6 always has 1KB of
+    /// PRG RAM, so `ram_size` is ignored for it.
+    pub fn new(variant: Mmc3Variant, prg: Vec<u8>, chr: Vec<u8>, ram_size: usize, logger: Option<slog::Logger>) -> Mmc3 {
+        let log = unwrap_logger!(logger).new(o!("mapper" => "Mmc3", "cartridge" => true));
+        let chr = match variant {
+            Mmc3Variant::TqRom => Chr::with_ram(chr, 0x2000, CHR_BANK_SIZE, log.clone()),
+            _ => Chr::new(chr, 0x2000, CHR_BANK_SIZE, log.clone())
+        };
+        let ram_size = if variant == Mmc3Variant::Mmc6 { 0x0400 } else { ram_size };
+
+        let mut mmc3 = Mmc3 {
+            variant: variant,
+            rom: mem::Fixed::from_contents(prg),
+            ram: mem::Fixed::new(ram_size),
+            chr: chr,
+            bank_select: 0,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            prg_banks: [0; 4],
+            mirroring: nes::Mirroring::Vertical,
+            ram_enabled: variant != Mmc3Variant::Mmc6,
+            ram_protect: 0,
+            irq_latch: 0,
+            irq_enabled: false,
+            irq_counter: Cell::new(0),
+            irq_reload: Cell::new(false),
+            irq_pending: Cell::new(false),
+            a12_low: Cell::new(0),
+            log: log
+        };
+        mmc3.update_banks();
+        mmc3
+    }
+
+    /// Recalculates the PRG and CHR banks from the bank registers
+    fn update_banks(&mut self) {
+        let rom_len = self.rom.len() as usize;
+        let last = bank_from_end(rom_len, PRG_BANK_SIZE, 0);
+        let second_last = bank_from_end(rom_len, PRG_BANK_SIZE, 1);
+        let r6 = (self.registers[6] & 0x3F) as usize;
+        let r7 = (self.registers[7] & 0x3F) as usize;
+        let banks = if self.bank_select & 0x40 == 0 {
+            [r6, r7, second_last, last]
+        } else {
+            [second_last, r7, r6, last]
+        };
+        for (slot, bank) in banks.iter().enumerate() {
+            self.prg_banks[slot] = (bank * PRG_BANK_SIZE) % rom_len;
+        }
+
+        // R0 and R1 select 2KB banks, R2-R5 1KB banks. With CHR inversion, the 2KB banks are
+        // at $1000-$1FFF instead of $0000-$0FFF.
+        let r = &self.registers;
+        let banks = [r[0] & 0xFE, r[0] | 0x01, r[1] & 0xFE, r[1] | 0x01, r[2], r[3], r[4], r[5]];
+        let inversion = if self.bank_select & 0x80 == 0 { 0 } else { 4 };
+        let mut pages = [0; 4];
+        for (i, bank) in banks.iter().enumerate() {
+            let slot = i ^ inversion;
+            match self.variant {
+                Mmc3Variant::TqRom if bank & 0x40 != 0 => self.chr.set_ram_bank(slot, (bank & 0x07) as usize),
+                Mmc3Variant::TxSRom => self.chr.set_bank(slot, (bank & 0x7F) as usize),
+                _ => self.chr.set_bank(slot, *bank as usize)
+            }
+            if slot < 4 {
+                pages[slot] = bank >> 7;
+            }
+        }
+
+        if self.variant == Mmc3Variant::TxSRom {
+            self.mirroring = nes::Mirroring::Mapped(pages);
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, val: u8) {
+        trace!(self.log,
+            "vaddr" => format!("${:04X}", addr),
+            "val" => val;
+            "wrote register");
+        match addr & 0xE001 {
+            0x8000 => {
+                self.bank_select = val;
+                if self.variant == Mmc3Variant::Mmc6 {
+                    self.ram_enabled = val & 0x20 != 0;
+                }
+                self.update_banks();
+            },
+            0x8001 => {
+                self.registers[(self.bank_select & 0x07) as usize] = val;
+                self.update_banks();
+            },
+            0xA000 => {
+                if self.variant != Mmc3Variant::TxSRom {
+                    self.mirroring = if val & 0x01 == 0 {
+                        nes::Mirroring::Vertical
+                    } else {
+                        nes::Mirroring::Horizontal
+                    };
+                }
+            },
+            0xA001 => {
+                if self.variant == Mmc3Variant::Mmc6 {
+                    // The protection bits can only be changed while the RAM is enabled
+                    if self.ram_enabled {
+                        self.ram_protect = val & 0xF0;
+                    }
+                } else {
+                    self.ram_enabled = val & 0x80 != 0;
+                    self.ram_protect = val & 0x40;
+                }
+            },
+            0xC000 => self.irq_latch = val,
+            0xC001 => {
+                self.irq_counter.set(0);
+                self.irq_reload.set(true);
+            },
+            0xE000 => {
+                self.irq_enabled = false;
+                self.irq_pending.set(false);
+            },
+            _ => self.irq_enabled = true
+        }
+    }
+
+    /// Clocks the scanline counter, on a filtered rise of A12
+    fn clock_counter(&self) {
+        let counter = self.irq_counter.get();
+        let reload = self.irq_reload.get();
+        let next = if counter == 0 || reload { self.irq_latch } else { counter - 1 };
+        self.irq_counter.set(next);
+        self.irq_reload.set(false);
+
+        let signal = match self.variant {
+            Mmc3Variant::Nec => next == 0 && (counter != 0 || reload),
+            _ => next == 0
+        };
+        if signal && self.irq_enabled {
+            trace!(self.log, "latch" => self.irq_latch; "signalling irq");
+            self.irq_pending.set(true);
+        }
+    }
+
+    /// Checks if the provided address in $6000-$7FFF can be read from or written to
+    ///
+    /// Returns `None` if the RAM is disabled, so the address sees an open bus
+    fn ram_access(&self, addr: u16, write: bool) -> Option<bool> {
+        if !self.ram_enabled || self.ram.len() == 0 {
+            return None;
+        }
+        if self.variant != Mmc3Variant::Mmc6 {
+            return Some(!write || self.ram_protect == 0);
+        }
+
+        // The MMC6's RAM is at $7000-$7FFF, with read and write enables for each 512 byte half.
+        // If neither half is readable, the RAM is disabled.
+        if addr < 0x7000 || self.ram_protect & 0xA0 == 0 {
+            return None;
+        }
+        let shift = if addr & 0x0200 == 0 { 4 } else { 6 };
+        let bit = if write { 0x01 } else { 0x02 };
+        Some(self.ram_protect & (bit << shift) != 0)
+    }
+}
+
+impl nes::Mapper for Mmc3 {
+    fn name(&self) -> &'static str {
+        match self.variant {
+            Mmc3Variant::Mmc6 => "Mmc6",
+            Mmc3Variant::TxSRom => "TxSRom",
+            Mmc3Variant::TqRom => "TqRom",
+            _ => "Mmc3"
+        }
+    }
+
+    fn prg(&self) -> &mem::Memory<u16> {
+        self
+    }
+
+    fn prg_mut(&mut self) -> &mut mem::Memory<u16> {
+        self
+    }
+
+    fn chr(&self) -> &mem::Memory<u16> {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut mem::Memory<u16> {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Option<nes::Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn ppu_address(&self, addr: u16) {
+        if addr & 0x1000 == 0 {
+            self.a12_low.set(self.a12_low.get().saturating_add(1));
+            return;
+        }
+        if self.a12_low.get() >= A12_FILTER_FETCHES {
+            self.clock_counter();
+        }
+        self.a12_low.set(0);
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending.get()
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        Some(self.ram.contents())
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.ram.contents_mut())
+    }
+}
+
+impl mem::Memory<u16> for Mmc3 {
+    fn len(&self) -> u64 { 0xA000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "read");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on MMC3 cartridge",
+                format!("${:4X} is below the addressable range of 0x6000-0xFFFF", addr)))
+        } else if addr < 0x8000 {
+            match self.ram_access(addr, false) {
+                Some(true) => self.ram.get_u8(((addr - 0x6000) as u64) % self.ram.len()),
+                Some(false) => Ok(0),
+                None => Ok(open_bus(addr))
+            }
+        } else {
+            let bank = self.prg_banks[((addr >> 13) & 0x03) as usize];
+            let eaddr = (bank + (addr as usize & (PRG_BANK_SIZE - 1))) % (self.rom.len() as usize);
+            trace!(self.log,
+                "read";
+                "vaddr" => format!("${:04X}", addr),
+                "paddr" => format!("${:05X}", eaddr),
+                "target" => "ROM",
+                "action" => "read");
+            self.rom.get_u8(eaddr as u64)
+        }
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "write");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on MMC3 cartridge",
+                format!("${:4X} is below the addressable range on MMC3 cartridge", addr)))
+        } else if addr < 0x8000 {
+            if self.ram_access(addr, true) != Some(true) {
+                trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to protected RAM");
+                return Ok(());
+            }
+            let eaddr = ((addr - 0x6000) as u64) % self.ram.len();
+            self.ram.set_u8(eaddr, val)
+        } else {
+            self.write_register(addr, val);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mem::Memory;
+    use systems::nes::{Mapper,Mirroring};
+    use systems::nes::cart::{Mmc3,Mmc3Variant};
+    use systems::nes::cart::testing::numbered_banks;
+
+    fn mmc3(variant: Mmc3Variant) -> Mmc3 {
+        Mmc3::new(variant, numbered_banks(16, 0x2000), numbered_banks(64, 0x0400), 0x2000, None)
+    }
+
+    fn set_bank(mmc3: &mut Mmc3, select: u8, val: u8) {
+        mmc3.set_u8(0x8000, select).unwrap();
+        mmc3.set_u8(0x8001, val).unwrap();
+    }
+
+    /// Emulates the PPU fetches of a scanline, with the background at $0000 and sprites at $1000
+    fn scanline(mmc3: &Mmc3) {
+        for _ in 0 .. 128 {
+            mmc3.ppu_address(0x0000);
+        }
+        for _ in 0 .. 16 {
+            mmc3.ppu_address(0x1FF0);
+        }
+    }
+
+    #[test]
+    pub fn single_prg_bank_is_mirrored() {
+        let mut mmc3 = Mmc3::new(Mmc3Variant::Sharp, vec![42; 0x2000], numbered_banks(64, 0x0400), 0, None);
+        set_bank(&mut mmc3, 6, 3);
+        mmc3.set_u8(0x8000, 0x40).unwrap();
+        for addr in [0x8000, 0xA000, 0xC000, 0xE000].iter() {
+            assert_eq!(Ok(42), mmc3.get_u8(*addr));
+        }
+    }
+
+    #[test]
+    pub fn prg_mode_swaps_fixed_bank() {
+        let mut mmc3 = mmc3(Mmc3Variant::Sharp);
+        set_bank(&mut mmc3, 6, 3);
+        set_bank(&mut mmc3, 7, 5);
+        assert_eq!(Ok(3), mmc3.get_u8(0x8000));
+        assert_eq!(Ok(5), mmc3.get_u8(0xA000));
+        assert_eq!(Ok(14), mmc3.get_u8(0xC000));
+        assert_eq!(Ok(15), mmc3.get_u8(0xE000));
+
+        mmc3.set_u8(0x8000, 0x40).unwrap();
+        assert_eq!(Ok(14), mmc3.get_u8(0x8000));
+        assert_eq!(Ok(3), mmc3.get_u8(0xC000));
+    }
+
+    #[test]
+    pub fn chr_inversion_swaps_halves() {
+        let mut mmc3 = mmc3(Mmc3Variant::Sharp);
+        set_bank(&mut mmc3, 0, 9);
+        set_bank(&mut mmc3, 5, 20);
+        assert_eq!(Ok(8), mmc3.chr().get_u8(0x0000));
+        assert_eq!(Ok(9), mmc3.chr().get_u8(0x0400));
+        assert_eq!(Ok(20), mmc3.chr().get_u8(0x1C00));
+
+        mmc3.set_u8(0x8000, 0x80).unwrap();
+        assert_eq!(Ok(8), mmc3.chr().get_u8(0x1000));
+        assert_eq!(Ok(20), mmc3.chr().get_u8(0x0C00));
+    }
+
+    #[test]
+    pub fn mirroring_register_selects_mirroring() {
+        let mut mmc3 = mmc3(Mmc3Variant::Sharp);
+        mmc3.set_u8(0xA000, 1).unwrap();
+        assert_eq!(Some(Mirroring::Horizontal), mmc3.mirroring());
+        mmc3.set_u8(0xA000, 0).unwrap();
+        assert_eq!(Some(Mirroring::Vertical), mmc3.mirroring());
+    }
+
+    #[test]
+    pub fn prg_ram_can_be_protected() {
+        let mut mmc3 = mmc3(Mmc3Variant::Sharp);
+        mmc3.set_u8(0xA001, 0x80).unwrap();
+        mmc3.set_u8(0x6000, 42).unwrap();
+        mmc3.set_u8(0xA001, 0xC0).unwrap();
+        mmc3.set_u8(0x6000, 24).unwrap();
+        assert_eq!(Ok(42), mmc3.get_u8(0x6000));
+        mmc3.set_u8(0xA001, 0x00).unwrap();
+        assert_eq!(Ok(0x60), mmc3.get_u8(0x6000));
+    }
+
+    #[test]
+    pub fn irq_is_signalled_after_latched_scanlines() {
+        let mut mmc3 = mmc3(Mmc3Variant::Sharp);
+        mmc3.set_u8(0xC000, 2).unwrap();
+        mmc3.set_u8(0xC001, 0).unwrap();
+        mmc3.set_u8(0xE001, 0).unwrap();
+
+        scanline(&mmc3);
+        scanline(&mmc3);
+        assert!(!mmc3.irq());
+        scanline(&mmc3);
+        assert!(mmc3.irq());
+
+        mmc3.set_u8(0xE000, 0).unwrap();
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    pub fn a12_rises_close_together_are_filtered() {
+        let mut mmc3 = mmc3(Mmc3Variant::Sharp);
+        mmc3.set_u8(0xC000, 0).unwrap();
+        mmc3.set_u8(0xC001, 0).unwrap();
+        mmc3.set_u8(0xE001, 0).unwrap();
+
+        // Background fetches from $1000 only leave A12 low for two fetches between tiles
+        for _ in 0 .. 32 {
+            mmc3.ppu_address(0x2000);
+            mmc3.ppu_address(0x23C0);
+            mmc3.ppu_address(0x1000);
+            mmc3.ppu_address(0x1008);
+        }
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    pub fn nec_revision_only_signals_irq_when_counter_reaches_zero() {
+        let mut sharp = mmc3(Mmc3Variant::Sharp);
+        let mut nec = mmc3(Mmc3Variant::Nec);
+        for mmc3 in [&mut sharp, &mut nec].iter_mut() {
+            mmc3.set_u8(0xC000, 0).unwrap();
+            mmc3.set_u8(0xE001, 0).unwrap();
+            scanline(mmc3);
+            mmc3.set_u8(0xE000, 0).unwrap();
+            mmc3.set_u8(0xE001, 0).unwrap();
+
+            // The counter is reloaded with 0 on each scanline, without an explicit reload
+            scanline(mmc3);
+        }
+        assert!(sharp.irq());
+        assert!(!nec.irq());
+    }
+
+    #[test]
+    pub fn txsrom_selects_nametables_with_chr_banks() {
+        let mut mmc3 = mmc3(Mmc3Variant::TxSRom);
+        set_bank(&mut mmc3, 0, 0x80);
+        set_bank(&mut mmc3, 1, 0x02);
+        mmc3.set_u8(0xA000, 1).unwrap();
+        assert_eq!(Some(Mirroring::Mapped([1, 1, 0, 0])), mmc3.mirroring());
+        assert_eq!(Ok(0), mmc3.chr().get_u8(0x0000));
+    }
+
+    #[test]
+    pub fn tqrom_selects_chr_ram_with_bit_6() {
+        let mut mmc3 = mmc3(Mmc3Variant::TqRom);
+        set_bank(&mut mmc3, 2, 0x41);
+        mmc3.chr_mut().set_u8(0x1000, 42).unwrap();
+        assert_eq!(Ok(42), mmc3.chr().get_u8(0x1000));
+        set_bank(&mut mmc3, 2, 0x01);
+        assert_eq!(Ok(1), mmc3.chr().get_u8(0x1000));
+    }
+
+    #[test]
+    pub fn mmc6_protects_each_half_of_ram() {
+        let mut mmc6 = mmc3(Mmc3Variant::Mmc6);
+        mmc6.set_u8(0x7000, 42).unwrap();
+        assert_eq!(Ok(0x70), mmc6.get_u8(0x7000));
+
+        // Enable the RAM, with the lower half read-write and the upper half read-only
+        mmc6.set_u8(0x8000, 0x20).unwrap();
+        mmc6.set_u8(0xA001, 0xB0).unwrap();
+        mmc6.set_u8(0x7000, 42).unwrap();
+        mmc6.set_u8(0x7200, 24).unwrap();
+        assert_eq!(Ok(42), mmc6.get_u8(0x7400));
+        assert_eq!(Ok(0), mmc6.get_u8(0x7200));
+
+        // With only the upper half readable, the lower half reads as 0
+        mmc6.set_u8(0xA001, 0x80).unwrap();
+        assert_eq!(Ok(0), mmc6.get_u8(0x7000));
+    }
+}
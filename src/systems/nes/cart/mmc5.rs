@@ -0,0 +1,862 @@
+use std::cell::Cell;
+
+use slog;
+
+use mem::{self,Memory};
+use hw::expansion_audio::{ExpansionAudio,Mmc5Audio};
+use hw::rp2C02;
+use systems::nes;
+use systems::nes::cart::open_bus;
+
+/// The size of a switchable PRG bank
+const PRG_BANK_SIZE: usize = 0x2000;
+
+/// The size of the smallest switchable CHR bank
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// The size of the CHR banks used by extended attributes and the split screen
+const CHR_TILE_BANK_SIZE: usize = 0x1000;
+
+/// The size of ExRAM
+const EXRAM_SIZE: usize = 0x0400;
+
+/// The index of the first sprite pattern fetch, counting every PPU read from the start of a
+/// scanline
+///
+/// Each scanline starts with 32 tiles of background fetches (nametable, attribute and two pattern
+/// bytes), then 8 sprites of two garbage nametable fetches and two pattern bytes, then 2 tiles of
+/// background for the next scanline, and finally 2 more garbage nametable fetches.
+const SPRITE_FETCHES: u16 = 128;
+
+/// The index of the first background fetch for the next scanline
+const PREFETCHES: u16 = 160;
+
+/// The index of the first of the garbage nametable fetches at the end of a scanline
+const GARBAGE_FETCHES: u16 = 168;
+
+/// The index of the last fetch of a scanline
+const LAST_FETCH: u16 = 169;
+
+/// The last visible scanline, after which the MMC5 leaves the frame
+const LAST_SCANLINE: u16 = 239;
+
+/// The number of CPU cycles the PPU must stop reading for before the MMC5 considers rendering to
+/// have stopped
+const IDLE_CYCLES: u16 = 600;
+
+/// The bank mapped in to a PRG slot, as an offset in to PRG ROM or RAM
+#[derive(Copy,Clone,Debug)]
+struct Bank {
+    ram: bool,
+    offset: usize
+}
+
+/// Emulates the Nintendo MMC5, used by the ExROM boards
+///
+/// * $5000-$5015: Audio, two pulse channels and a PCM channel
+/// * $5100/$5101: PRG and CHR banking modes
+/// * $5102/$5103: PRG RAM protect, writes are only allowed while they hold 2 and 1
+/// * $5104: ExRAM mode
+/// * $5105: Nametable mapping, 2 bits for each nametable
+/// * $5106/$5107: Fill mode tile and attribute
+/// * $5113-$5117: PRG banks for $6000, $8000, $A000, $C000 and $E000
+/// * $5120-$512B: CHR banks, $5120-$5127 for sprites and $5128-$512B for the background
+/// * $5130: Upper CHR bank bits
+/// * $5200-$5202: Vertical split control, scroll and CHR bank
+/// * $5203/$5204: Scanline IRQ compare value, and IRQ status and enable
+/// * $5205/$5206: Multiplicands, reading them returns the product
+/// * $5C00-$5FFF: ExRAM
+///
+/// The MMC5 has no connection to the PPU's registers or scanline timing, so it tracks the PPU by
+/// watching the addresses it reads from. Three consecutive reads of the same nametable address
+/// only happen at the end of a scanline, so each time they are seen a new scanline is counted and
+/// subsequent reads are identified by counting them.
+pub struct Mmc5 {
+    rom: mem::Fixed,
+    ram: mem::Fixed,
+    video: Video,
+    audio: Mmc5Audio,
+    prg_mode: u8,
+    prg_registers: [u8; 5],
+    prg_banks: [Bank; 5],
+    ram_protect: [u8; 2],
+    multiplicands: [u8; 2],
+    log: slog::Logger
+}
+
+/// The PPU side of the MMC5, which provides the CHR banks and nametables
+struct Video {
+    chr: mem::Fixed,
+    chr_ram: bool,
+    chr_mode: u8,
+    chr_registers: [u16; 12],
+    chr_upper: u8,
+    sprite_banks: [usize; 8],
+    background_banks: [usize; 8],
+    background_last: bool,
+    large_sprites: bool,
+    exram: mem::Fixed,
+    exram_mode: u8,
+    nametables: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+    irq_compare: u8,
+    irq_enabled: bool,
+    irq_pending: Cell<bool>,
+    in_frame: Cell<bool>,
+    scanline: Cell<u16>,
+    fetch: Cell<u16>,
+    last_addr: Cell<u16>,
+    repeats: Cell<u8>,
+    idle_cycles: Cell<u16>,
+    attribute: Cell<u8>,
+    in_split: Cell<bool>,
+    log: slog::Logger
+}
+
+impl Mmc5 {
+    /// Creates a new MMC5 cartridge
+    ///
+    /// If `chr` is empty, the cartridge uses 8KB of CHR RAM instead
+    pub fn new(prg: Vec<u8>, chr: Vec<u8>, ram_size: usize, logger: Option<slog::Logger>) -> Mmc5 {
+        let log = unwrap_logger!(logger).new(o!("mapper" => "Mmc5", "cartridge" => true));
+
+        let mut mmc5 = Mmc5 {
+            rom: mem::Fixed::from_contents(prg),
+            ram: mem::Fixed::new(ram_size),
+            video: Video::new(chr, log.clone()),
+            audio: Mmc5Audio::new(),
+            prg_mode: 3,
+            prg_registers: [0, 0, 0, 0, 0xFF],
+            prg_banks: [Bank { ram: false, offset: 0 }; 5],
+            ram_protect: [0, 0],
+            multiplicands: [0xFF, 0xFF],
+            log: log
+        };
+        mmc5.update_prg();
+        mmc5
+    }
+
+    /// Finds part `index` of the bank of `size` 8KB banks selected by a PRG bank register
+    ///
+    /// Bit 7 of the register selects ROM, otherwise the bank is in RAM
+    fn prg_bank(&self, register: u8, size: u8, index: u8) -> Bank {
+        let bank = ((register & 0x7F & !(size - 1)) + index) as usize;
+        if register & 0x80 == 0 {
+            let ram_len = self.ram.len() as usize;
+            Bank {
+                ram: true,
+                offset: if ram_len == 0 { 0 } else { ((bank & 0x07) * PRG_BANK_SIZE) % ram_len }
+            }
+        } else {
+            Bank {
+                ram: false,
+                offset: (bank * PRG_BANK_SIZE) % (self.rom.len() as usize)
+            }
+        }
+    }
+
+    /// Recalculates the PRG banks from the PRG mode and bank registers
+    fn update_prg(&mut self) {
+        let r = self.prg_registers;
+        // $6000 is always RAM, and $E000 always ROM
+        let last = r[4] | 0x80;
+        self.prg_banks[0] = self.prg_bank(r[0] & 0x7F, 1, 0);
+        let banks = match self.prg_mode & 0x03 {
+            0 => [(last, 4, 0), (last, 4, 1), (last, 4, 2), (last, 4, 3)],
+            1 => [(r[2], 2, 0), (r[2], 2, 1), (last, 2, 0), (last, 2, 1)],
+            2 => [(r[2], 2, 0), (r[2], 2, 1), (r[3], 1, 0), (last, 1, 0)],
+            _ => [(r[1], 1, 0), (r[2], 1, 0), (r[3], 1, 0), (last, 1, 0)]
+        };
+        for (slot, &(register, size, index)) in banks.iter().enumerate() {
+            self.prg_banks[slot + 1] = self.prg_bank(register, size, index);
+        }
+    }
+
+    /// Checks if both PRG RAM protect registers hold the values that allow writes
+    fn ram_writable(&self) -> bool {
+        self.ram_protect == [0x02, 0x01]
+    }
+
+    fn read_register(&self, addr: u16) -> u8 {
+        match addr {
+            0x5204 => {
+                let mut val = 0;
+                if self.video.irq_pending.get() { val |= 0x80; }
+                if self.video.in_frame.get() { val |= 0x40; }
+                // Reading the status acknowledges the IRQ
+                self.video.irq_pending.set(false);
+                val
+            },
+            0x5205 => (self.product() & 0xFF) as u8,
+            0x5206 => (self.product() >> 8) as u8,
+            0x5C00 ... 0x5FFF if self.video.exram_mode >= 2 => {
+                self.video.exram.get_u8((addr - 0x5C00) as u64).unwrap_or(0)
+            },
+            _ => match self.audio.read(addr) {
+                Some(val) => val,
+                None => open_bus(addr)
+            }
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, val: u8) {
+        trace!(self.log,
+            "vaddr" => format!("${:04X}", addr),
+            "val" => val;
+            "wrote register");
+        match addr {
+            0x5000 ... 0x5015 => self.audio.write(addr, val),
+            0x5100 => {
+                self.prg_mode = val & 0x03;
+                self.update_prg();
+            },
+            0x5101 => {
+                self.video.chr_mode = val & 0x03;
+                self.video.update_chr();
+            },
+            0x5102 | 0x5103 => self.ram_protect[(addr - 0x5102) as usize] = val & 0x03,
+            0x5104 => self.video.exram_mode = val & 0x03,
+            0x5105 => self.video.nametables = val,
+            0x5106 => self.video.fill_tile = val,
+            0x5107 => self.video.fill_attribute = val & 0x03,
+            0x5113 ... 0x5117 => {
+                self.prg_registers[(addr - 0x5113) as usize] = val;
+                self.update_prg();
+            },
+            0x5120 ... 0x512B => self.video.set_chr_register((addr - 0x5120) as usize, val),
+            0x5130 => self.video.chr_upper = val & 0x03,
+            0x5200 => self.video.split_control = val,
+            0x5201 => self.video.split_scroll = val,
+            0x5202 => self.video.split_bank = val,
+            0x5203 => self.video.irq_compare = val,
+            0x5204 => self.video.irq_enabled = val & 0x80 != 0,
+            0x5205 | 0x5206 => self.multiplicands[(addr - 0x5205) as usize] = val,
+            0x5C00 ... 0x5FFF => {
+                // ExRAM is read only in mode 3
+                if self.video.exram_mode != 3 {
+                    self.video.exram.set_u8((addr - 0x5C00) as u64, val).unwrap();
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Gets the result of the 8x8 to 16 bit multiplier
+    fn product(&self) -> u16 {
+        self.multiplicands[0] as u16 * self.multiplicands[1] as u16
+    }
+
+    /// Finds the bank and offset within it that the provided address in $6000-$FFFF is mapped to
+    fn locate(&self, addr: u16) -> (Bank, u64) {
+        let bank = self.prg_banks[((addr - 0x6000) as usize) / PRG_BANK_SIZE];
+        (bank, (bank.offset + (addr as usize & (PRG_BANK_SIZE - 1))) as u64)
+    }
+}
+
+impl Video {
+    fn new(chr: Vec<u8>, log: slog::Logger) -> Video {
+        let chr_ram = chr.is_empty();
+        let mut video = Video {
+            chr: if chr_ram { mem::Fixed::new(0x2000) } else { mem::Fixed::from_contents(chr) },
+            chr_ram: chr_ram,
+            chr_mode: 3,
+            chr_registers: [0, 1, 2, 3, 4, 5, 6, 7, 4, 5, 6, 7],
+            chr_upper: 0,
+            sprite_banks: [0; 8],
+            background_banks: [0; 8],
+            background_last: false,
+            large_sprites: false,
+            exram: mem::Fixed::new(EXRAM_SIZE),
+            exram_mode: 0,
+            nametables: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: Cell::new(false),
+            in_frame: Cell::new(false),
+            scanline: Cell::new(0),
+            fetch: Cell::new(0),
+            last_addr: Cell::new(0),
+            repeats: Cell::new(0),
+            idle_cycles: Cell::new(0),
+            attribute: Cell::new(0),
+            in_split: Cell::new(false),
+            log: log
+        };
+        video.update_chr();
+        video
+    }
+
+    /// Writes one of the CHR bank registers, which latches the upper bits from $5130
+    fn set_chr_register(&mut self, index: usize, val: u8) {
+        self.chr_registers[index] = val as u16 | (self.chr_upper as u16) << 8;
+        self.background_last = index >= 8;
+        self.update_chr();
+    }
+
+    /// Recalculates both sets of CHR banks from the CHR mode and bank registers
+    ///
+    /// The sprite set has 8 registers, the background set only 4, which are repeated for
+    /// $1000-$1FFF.
+    fn update_chr(&mut self) {
+        let chr_len = self.chr.len() as usize;
+        let a = &self.chr_registers[0 .. 8];
+        let b = &self.chr_registers[8 .. 12];
+        for slot in 0 .. 8 {
+            let (sprite, background) = match self.chr_mode {
+                0 => (a[7] as usize * 8 + slot, b[3] as usize * 8 + slot),
+                1 => (a[3 | (slot & 4)] as usize * 4 + (slot & 3), b[3] as usize * 4 + (slot & 3)),
+                2 => (a[slot | 1] as usize * 2 + (slot & 1), b[(slot & 2) | 1] as usize * 2 + (slot & 1)),
+                _ => (a[slot] as usize, b[slot & 3] as usize)
+            };
+            self.sprite_banks[slot] = (sprite * CHR_BANK_SIZE) % chr_len;
+            self.background_banks[slot] = (background * CHR_BANK_SIZE) % chr_len;
+        }
+    }
+
+    /// Checks if a fetch fetches the background, rather than sprites or garbage
+    fn is_background_fetch(&self, fetch: u16) -> bool {
+        self.in_frame.get() && (fetch < SPRITE_FETCHES || (fetch >= PREFETCHES && fetch < GARBAGE_FETCHES))
+    }
+
+    /// Checks if a fetch uses the background set of CHR banks
+    ///
+    /// With 8x16 sprites the sprite set is used for sprite fetches, and the background set for
+    /// everything else while rendering. Otherwise whichever set was written last is used.
+    fn uses_background_banks(&self, fetch: u16) -> bool {
+        if self.large_sprites && self.in_frame.get() {
+            fetch < SPRITE_FETCHES || fetch >= PREFETCHES
+        } else {
+            self.background_last
+        }
+    }
+
+    /// Gets the column and scanline of the tile a background fetch is for
+    ///
+    /// The first two tiles of each scanline are fetched at the end of the previous one
+    fn tile_position(&self, fetch: u16) -> (u8, u16) {
+        if fetch < SPRITE_FETCHES {
+            ((fetch / 4) as u8 + 2, self.scanline.get())
+        } else {
+            (((fetch - PREFETCHES) / 4) as u8, self.scanline.get() + 1)
+        }
+    }
+
+    /// Gets the line of the split screen area shown on a scanline
+    fn split_line(&self, scanline: u16) -> u16 {
+        (self.split_scroll as u16 + scanline) % 240
+    }
+
+    /// Checks if a tile column is within the split screen area
+    fn is_split(&self, column: u8) -> bool {
+        if self.split_control & 0x80 == 0 || self.exram_mode >= 2 {
+            return false;
+        }
+        let count = self.split_control & 0x1F;
+        if self.split_control & 0x40 == 0 { column < count } else { column >= count }
+    }
+
+    /// Watches an address the PPU has put on its bus, counting fetches and scanlines
+    fn observe(&self, addr: u16) {
+        self.idle_cycles.set(0);
+        self.fetch.set(self.fetch.get().saturating_add(1));
+        if self.in_frame.get() && self.scanline.get() == LAST_SCANLINE && self.fetch.get() == LAST_FETCH {
+            self.end_frame();
+        }
+
+        let repeats = if addr == self.last_addr.get() { self.repeats.get().saturating_add(1) } else { 1 };
+        self.last_addr.set(addr);
+        self.repeats.set(repeats);
+        if repeats == 3 && addr >= 0x2000 && addr < 0x3000 {
+            self.start_scanline();
+        }
+    }
+
+    fn start_scanline(&self) {
+        self.fetch.set(0);
+        if self.in_frame.get() {
+            let scanline = self.scanline.get() + 1;
+            self.scanline.set(scanline);
+            if scanline == self.irq_compare as u16 {
+                trace!(self.log, "scanline" => scanline; "scanline IRQ");
+                self.irq_pending.set(true);
+            }
+        } else {
+            self.in_frame.set(true);
+            self.scanline.set(0);
+            self.irq_pending.set(false);
+        }
+    }
+
+    fn end_frame(&self) {
+        self.in_frame.set(false);
+        // The PPU can read the same nametable address at the start of the pre-render line as it
+        // did at the end of the last scanline, which shouldn't start a frame
+        self.repeats.set(0);
+    }
+
+    fn clock_cpu(&self) {
+        let idle = self.idle_cycles.get().saturating_add(1);
+        self.idle_cycles.set(idle);
+        if idle == IDLE_CYCLES {
+            self.end_frame();
+        }
+    }
+
+    /// Gets which of the four nametables a nametable address is in, and the offset within it
+    fn nametable(&self, addr: u16) -> (u8, usize) {
+        let table = ((addr >> 10) & 0x03) as u8;
+        ((self.nametables >> (table * 2)) & 0x03, (addr & 0x03FF) as usize)
+    }
+
+    fn read_nametable(&self, addr: u16) -> Option<u8> {
+        let fetch = self.fetch.get();
+        if self.is_background_fetch(fetch) {
+            let (column, scanline) = self.tile_position(fetch);
+            match fetch % 4 {
+                0 => {
+                    self.in_split.set(self.is_split(column));
+                    if self.in_split.get() {
+                        let row = (self.split_line(scanline) / 8) as usize;
+                        return self.exram.get_u8((row * 32 + (column & 0x1F) as usize) as u64).ok();
+                    }
+                    if self.exram_mode == 1 {
+                        let (_, offset) = self.nametable(addr);
+                        self.attribute.set(self.exram.get_u8(offset as u64).unwrap());
+                    }
+                },
+                1 => {
+                    // The palette is repeated for every quadrant, as the PPU picks the quadrant
+                    // from the scroll position rather than the tile being drawn
+                    if self.in_split.get() {
+                        let row = (self.split_line(scanline) / 8) as usize;
+                        let column = (column & 0x1F) as usize;
+                        let attribute = self.exram.get_u8((0x03C0 + (row / 4) * 8 + column / 4) as u64).unwrap();
+                        let shift = ((row & 0x02) << 1) | (column & 0x02);
+                        return Some(((attribute >> shift) & 0x03) * 0x55);
+                    }
+                    if self.exram_mode == 1 {
+                        return Some((self.attribute.get() >> 6) * 0x55);
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        match self.nametable(addr) {
+            (0, _) | (1, _) => None,
+            (2, offset) => Some(if self.exram_mode < 2 { self.exram.get_u8(offset as u64).unwrap() } else { 0 }),
+            (_, offset) => Some(if offset >= 0x03C0 { self.fill_attribute * 0x55 } else { self.fill_tile })
+        }
+    }
+
+    fn write_nametable(&mut self, addr: u16, val: u8) -> bool {
+        match self.nametable(addr) {
+            (0, _) | (1, _) => false,
+            (2, offset) => {
+                if self.exram_mode < 2 {
+                    self.exram.set_u8(offset as u64, val).unwrap();
+                }
+                true
+            },
+            _ => {
+                trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to fill mode nametable");
+                true
+            }
+        }
+    }
+
+    /// Finds where in CHR memory the provided address is mapped to, for the current fetch
+    fn locate(&self, addr: u16) -> u64 {
+        let addr = (addr & 0x1FFF) as usize;
+        let fetch = self.fetch.get();
+        let eaddr = if self.is_background_fetch(fetch) && self.in_split.get() {
+            // The split screen has its own scroll, so the fine Y scroll is replaced too
+            let (_, scanline) = self.tile_position(fetch);
+            let fine = (self.split_line(scanline) & 0x07) as usize;
+            self.split_bank as usize * CHR_TILE_BANK_SIZE + ((addr & 0x0FF8) | fine)
+        } else if self.is_background_fetch(fetch) && self.exram_mode == 1 {
+            let bank = (self.attribute.get() & 0x3F) as usize | (self.chr_upper as usize) << 6;
+            bank * CHR_TILE_BANK_SIZE + (addr & (CHR_TILE_BANK_SIZE - 1))
+        } else {
+            let banks = if self.uses_background_banks(fetch) { &self.background_banks } else { &self.sprite_banks };
+            banks[addr / CHR_BANK_SIZE] + (addr % CHR_BANK_SIZE)
+        };
+        (eaddr as u64) % self.chr.len()
+    }
+}
+
+impl nes::Mapper for Mmc5 {
+    fn name(&self) -> &'static str { "Mmc5" }
+
+    fn prg(&self) -> &mem::Memory<u16> {
+        self
+    }
+
+    fn prg_mut(&mut self) -> &mut mem::Memory<u16> {
+        self
+    }
+
+    fn chr(&self) -> &mem::Memory<u16> {
+        &self.video
+    }
+
+    fn chr_mut(&mut self) -> &mut mem::Memory<u16> {
+        &mut self.video
+    }
+
+    /// Maps nametables 0 and 1 to nametable RAM, the others are provided by `read_nametable`
+    fn mirroring(&self) -> Option<nes::Mirroring> {
+        let mut pages = [0; 4];
+        for (table, page) in pages.iter_mut().enumerate() {
+            *page = (self.video.nametables >> (table * 2)) & 0x01;
+        }
+        Some(nes::Mirroring::Mapped(pages))
+    }
+
+    fn expansion_audio(&mut self) -> Option<&mut ExpansionAudio> {
+        Some(&mut self.audio)
+    }
+
+    fn ppu_address(&self, addr: u16) {
+        self.video.observe(addr);
+    }
+
+    fn read_nametable(&self, addr: u16) -> Option<u8> {
+        self.video.read_nametable(addr)
+    }
+
+    fn write_nametable(&mut self, addr: u16, val: u8) -> bool {
+        self.video.write_nametable(addr, val)
+    }
+
+    fn ppu_register_written(&mut self, reg: rp2C02::Register, val: u8) {
+        match reg {
+            rp2C02::Register::PpuCtrl => self.video.large_sprites = val & 0x20 != 0,
+            // Disabling rendering stops the PPU's fetches, ending the frame
+            rp2C02::Register::PpuMask if val & 0x18 == 0 => self.video.end_frame(),
+            _ => {}
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.video.irq_enabled && self.video.irq_pending.get()
+    }
+
+    fn clock_cpu(&mut self) {
+        self.video.clock_cpu();
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        Some(self.ram.contents())
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.ram.contents_mut())
+    }
+}
+
+impl mem::Memory<u16> for Mmc5 {
+    fn len(&self) -> u64 { 0xB000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        if addr < 0x5000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "read");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on MMC5 cartridge",
+                format!("${:4X} is below the addressable range of 0x5000-0xFFFF", addr)))
+        } else if addr < 0x6000 {
+            Ok(self.read_register(addr))
+        } else {
+            match self.locate(addr) {
+                (Bank { ram: true, .. }, _) if self.ram.len() == 0 => Ok(open_bus(addr)),
+                (Bank { ram: true, .. }, eaddr) => self.ram.get_u8(eaddr),
+                (_, eaddr) => {
+                    trace!(self.log,
+                        "read";
+                        "vaddr" => format!("${:04X}", addr),
+                        "paddr" => format!("${:05X}", eaddr),
+                        "target" => "ROM",
+                        "action" => "read");
+                    self.rom.get_u8(eaddr)
+                }
+            }
+        }
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        if addr < 0x5000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "write");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on MMC5 cartridge",
+                format!("${:4X} is below the addressable range on MMC5 cartridge", addr)))
+        } else if addr < 0x6000 {
+            self.write_register(addr, val);
+            Ok(())
+        } else {
+            match self.locate(addr) {
+                (Bank { ram: true, .. }, eaddr) if self.ram.len() > 0 && self.ram_writable() => {
+                    self.ram.set_u8(eaddr, val)
+                },
+                _ => {
+                    trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to ROM or protected RAM");
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+impl mem::Memory<u16> for Video {
+    fn len(&self) -> u64 { 0x2000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        self.chr.get_u8(self.locate(addr))
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        if !self.chr_ram {
+            trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to CHR ROM");
+            return Ok(());
+        }
+        let eaddr = self.locate(addr);
+        self.chr.set_u8(eaddr, val)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mem::Memory;
+    use hw::rp2C02::Register;
+    use systems::nes::{Mapper,Mirroring};
+    use systems::nes::cart::Mmc5;
+    use systems::nes::cart::testing::numbered_banks;
+
+    fn cart() -> Mmc5 {
+        Mmc5::new(numbered_banks(16, 0x2000), numbered_banks(64, 0x0400), 0x10000, None)
+    }
+
+    /// Reads through the cartridge as the PPU would
+    fn fetch(cart: &Mmc5, addr: u16) -> u8 {
+        cart.ppu_address(addr);
+        if addr >= 0x2000 {
+            cart.read_nametable(addr).unwrap_or(0xEE)
+        } else {
+            cart.chr().get_u8(addr).unwrap()
+        }
+    }
+
+    /// Makes the last two fetches of the pre-render line and the first of scanline 0, returning
+    /// the nametable byte fetched for the first tile
+    fn start_frame(cart: &Mmc5) -> u8 {
+        fetch(cart, 0x2002);
+        fetch(cart, 0x2002);
+        fetch(cart, 0x2002)
+    }
+
+    /// Makes the rest of the fetches of a scanline, and the first of the next
+    fn next_scanline(cart: &Mmc5) {
+        fetch(cart, 0x23C0);
+        fetch(cart, 0x0000);
+        fetch(cart, 0x0008);
+        for tile in 3 .. 34 {
+            for &addr in [0x2000 + tile, 0x23C0, 0x0000, 0x0008].iter() {
+                fetch(cart, addr);
+            }
+        }
+        for _ in 0 .. 8 {
+            for &addr in [0x2000, 0x2000, 0x1FF0, 0x1FF8].iter() {
+                fetch(cart, addr);
+            }
+        }
+        for tile in 0 .. 2 {
+            for &addr in [0x2000 + tile, 0x23C0, 0x0000, 0x0008].iter() {
+                fetch(cart, addr);
+            }
+        }
+        start_frame(cart);
+    }
+
+    #[test]
+    pub fn last_bank_starts_at_e000() {
+        let cart = cart();
+        assert_eq!(Ok(15), cart.get_u8(0xE000));
+    }
+
+    #[test]
+    pub fn prg_modes_select_bank_sizes() {
+        let mut cart = cart();
+        cart.set_u8(0x5100, 0).unwrap();
+        cart.set_u8(0x5117, 0x85).unwrap();
+        assert_eq!(Ok(4), cart.get_u8(0x8000));
+        assert_eq!(Ok(7), cart.get_u8(0xE000));
+
+        cart.set_u8(0x5100, 2).unwrap();
+        cart.set_u8(0x5115, 0x83).unwrap();
+        cart.set_u8(0x5116, 0x89).unwrap();
+        assert_eq!(Ok(2), cart.get_u8(0x8000));
+        assert_eq!(Ok(3), cart.get_u8(0xA000));
+        assert_eq!(Ok(9), cart.get_u8(0xC000));
+        assert_eq!(Ok(5), cart.get_u8(0xE000));
+    }
+
+    #[test]
+    pub fn ram_is_protected_unless_unlocked() {
+        let mut cart = cart();
+        cart.set_u8(0x5113, 3).unwrap();
+        cart.set_u8(0x6000, 42).unwrap();
+        assert_eq!(Ok(0), cart.get_u8(0x6000));
+
+        cart.set_u8(0x5102, 2).unwrap();
+        cart.set_u8(0x5103, 1).unwrap();
+        cart.set_u8(0x6000, 42).unwrap();
+        assert_eq!(Ok(42), cart.get_u8(0x6000));
+
+        // RAM banks can be mapped in to the ROM area too
+        cart.set_u8(0x5114, 3).unwrap();
+        assert_eq!(Ok(42), cart.get_u8(0x8000));
+    }
+
+    #[test]
+    pub fn chr_modes_select_bank_sizes() {
+        let mut cart = cart();
+        cart.set_u8(0x5101, 1).unwrap();
+        cart.set_u8(0x5123, 2).unwrap();
+        cart.set_u8(0x5127, 5).unwrap();
+        assert_eq!(Ok(8), cart.chr().get_u8(0x0000));
+        assert_eq!(Ok(23), cart.chr().get_u8(0x1C00));
+
+        // With 8x8 sprites, the set written last is used for everything
+        cart.set_u8(0x5101, 3).unwrap();
+        cart.set_u8(0x5128, 3).unwrap();
+        assert_eq!(Ok(3), cart.chr().get_u8(0x0000));
+        assert_eq!(Ok(3), cart.chr().get_u8(0x1000));
+    }
+
+    #[test]
+    pub fn large_sprites_use_separate_chr_sets() {
+        let mut cart = cart();
+        cart.ppu_register_written(Register::PpuCtrl, 0x20);
+        for i in 0 .. 8 {
+            cart.set_u8(0x5120 + i, i as u8).unwrap();
+        }
+        for i in 0 .. 4 {
+            cart.set_u8(0x5128 + i, 8 + i as u8).unwrap();
+        }
+
+        start_frame(&cart);
+        assert_eq!(8, fetch(&cart, 0x0000));
+        for _ in 2 .. 128 {
+            fetch(&cart, 0x0000);
+        }
+        assert_eq!(0, fetch(&cart, 0x0000));
+        assert_eq!(7, fetch(&cart, 0x1C00));
+    }
+
+    #[test]
+    pub fn exram_and_fill_mode_nametables() {
+        let mut cart = cart();
+        cart.set_u8(0x5105, 0xE4).unwrap();
+        assert_eq!(Some(Mirroring::Mapped([0, 1, 0, 1])), cart.mirroring());
+
+        assert!(cart.write_nametable(0x2805, 9));
+        assert_eq!(Some(9), cart.read_nametable(0x2805));
+        assert_eq!(Ok(0x5C), cart.get_u8(0x5C05));
+        cart.set_u8(0x5104, 2).unwrap();
+        assert_eq!(Ok(9), cart.get_u8(0x5C05));
+
+        cart.set_u8(0x5106, 0x33).unwrap();
+        cart.set_u8(0x5107, 0x02).unwrap();
+        assert_eq!(Some(0x33), cart.read_nametable(0x2C00));
+        assert_eq!(Some(0xAA), cart.read_nametable(0x2FC0));
+        assert_eq!(None, cart.read_nametable(0x2000));
+    }
+
+    #[test]
+    pub fn extended_attributes_select_palette_and_bank() {
+        let mut cart = cart();
+        cart.set_u8(0x5104, 1).unwrap();
+        cart.set_u8(0x5C02, 0x85).unwrap();
+        start_frame(&cart);
+        assert_eq!(0xAA, fetch(&cart, 0x23C0));
+        assert_eq!(20, fetch(&cart, 0x0010));
+    }
+
+    #[test]
+    pub fn split_screen_uses_exram() {
+        let mut cart = cart();
+        cart.set_u8(0x5200, 0x84).unwrap();
+        cart.set_u8(0x5202, 2).unwrap();
+        cart.set_u8(0x5C02, 0x77).unwrap();
+        assert_eq!(0x77, start_frame(&cart));
+        fetch(&cart, 0x23C0);
+        assert_eq!(9, fetch(&cart, 0x0770));
+    }
+
+    #[test]
+    pub fn irq_fires_on_compare_scanline() {
+        let mut cart = cart();
+        cart.set_u8(0x5203, 2).unwrap();
+        cart.set_u8(0x5204, 0x80).unwrap();
+        start_frame(&cart);
+        next_scanline(&cart);
+        assert!(!cart.irq());
+        next_scanline(&cart);
+        assert!(cart.irq());
+        assert_eq!(Ok(0xC0), cart.get_u8(0x5204));
+        assert!(!cart.irq());
+    }
+
+    #[test]
+    pub fn frame_ends_after_last_scanline_or_idle_ppu() {
+        let mut cart = cart();
+        start_frame(&cart);
+        for _ in 0 .. 240 {
+            next_scanline(&cart);
+        }
+        assert_eq!(Ok(0x00), cart.get_u8(0x5204));
+
+        start_frame(&cart);
+        assert_eq!(Ok(0x40), cart.get_u8(0x5204));
+        for _ in 0 .. 600 {
+            cart.clock_cpu();
+        }
+        assert_eq!(Ok(0x00), cart.get_u8(0x5204));
+    }
+
+    #[test]
+    pub fn multiplier_multiplies() {
+        let mut cart = cart();
+        cart.set_u8(0x5205, 200).unwrap();
+        cart.set_u8(0x5206, 100).unwrap();
+        assert_eq!(Ok(0x20), cart.get_u8(0x5205));
+        assert_eq!(Ok(0x4E), cart.get_u8(0x5206));
+    }
+
+    #[test]
+    pub fn audio_registers_are_passed_on() {
+        let mut cart = cart();
+        cart.set_u8(0x5015, 0x01).unwrap();
+        cart.set_u8(0x5003, 0x08).unwrap();
+        assert_eq!(Ok(0x01), cart.get_u8(0x5015));
+        assert!(cart.expansion_audio().is_some());
+    }
+}
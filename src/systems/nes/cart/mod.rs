@@ -1,17 +1,54 @@
+use std::cmp;
+use std::io;
+
 use slog;
 
 use mem;
+use hw::expansion_audio::ExpansionAudio;
+use hw::rp2C02;
 use systems::nes;
 
 pub use self::nrom::NRom;
+pub use self::mmc1::Mmc1;
+pub use self::mmc2::{Mmc2,Mmc2Variant};
+pub use self::discrete::{Board,Discrete};
+pub use self::mmc3::{Mmc3,Mmc3Variant};
+pub use self::mmc5::Mmc5;
+pub use self::vrc4::{Vrc4,VrcChip};
+pub use self::vrc6::Vrc6;
+pub use self::vrc7::Vrc7;
+pub use self::bandai::{Bandai,BandaiBoard};
+pub use self::unrom512::Unrom512;
+pub use self::fme7::Fme7;
+pub use self::registry::{MapperFactory,MapperRegistry};
 
+mod chr;
 mod nrom;
+mod mmc1;
+mod mmc2;
+mod discrete;
+mod mmc3;
+mod mmc5;
+mod vrc_irq;
+mod vrc4;
+mod vrc6;
+mod vrc7;
+mod eeprom;
+mod bandai;
+mod flash;
+mod unrom512;
+mod fme7;
+mod registry;
+
+#[cfg(test)]
+mod testing;
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
 #[derive(Copy,Clone,Debug,Eq,PartialEq)]
 pub enum Error {
-    UnknownMapper(u16, u8)
+    UnknownMapper(u16, u8),
+    InvalidPrgSize(usize)
 }
 
 /// Represents a cartridge that has been loaded into the system
@@ -24,22 +61,221 @@ impl Cartridge {
     pub fn header(&self) -> &nes::RomHeader {
         &self.header
     }
+
+    /// Gets the nametable mirroring currently in effect
+    ///
+    /// This is the mirroring selected by the mapper, if it controls mirroring, or the mirroring
+    /// specified in the ROM header if it does not. Boards with four-screen VRAM ignore the mapper,
+    /// as the extra RAM takes the place of the nametable mirroring.
+    pub fn mirroring(&self) -> Mirroring {
+        if four_screen_vram(&self.header) {
+            return Mirroring::FourScreen;
+        }
+        self.mapper.mirroring().unwrap_or_else(|| Mirroring::from_header(&self.header))
+    }
+
+    /// Checks if the cartridge is asserting the CPU's IRQ line
+    pub fn irq(&self) -> bool {
+        self.mapper.irq()
+    }
+
+    /// Gets the expansion sound chip on the cartridge, if it has one
+    pub fn expansion_audio(&mut self) -> Option<&mut ExpansionAudio> {
+        self.mapper.expansion_audio()
+    }
+
+    /// Gets the memory on the cartridge that keeps its contents while the power is off
+    ///
+    /// This is only present if the battery flag in the ROM header is set, which is also used to
+    /// indicate boards that save to a chip (such as an EEPROM) rather than battery-backed RAM
+    pub fn save_data(&self) -> Option<&[u8]> {
+        if !self.header.sram_battery_backed {
+            return None;
+        }
+        self.mapper.save_data().and_then(|data| if data.is_empty() { None } else { Some(data) })
+    }
+
+    /// Writes the save data of the cartridge, if it has any
+    pub fn save(&self, w: &mut io::Write) -> io::Result<()> {
+        match self.save_data() {
+            Some(data) => w.write_all(data),
+            None => Ok(())
+        }
+    }
+
+    /// Restores save data previously written by `save`
+    ///
+    /// Save data that is shorter than the cartridge's memory only fills the start of it, and any
+    /// extra is ignored
+    pub fn restore(&mut self, r: &mut io::Read) -> io::Result<()> {
+        if self.save_data().is_none() {
+            return Ok(());
+        }
+        let mut saved = Vec::new();
+        try!(r.read_to_end(&mut saved));
+        if let Some(data) = self.mapper.save_data_mut() {
+            let len = saved.len().min(data.len());
+            data[.. len].copy_from_slice(&saved[.. len]);
+        }
+        Ok(())
+    }
+}
+
+/// Represents the arrangement of the four logical nametables in the PPU's memory
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum Mirroring {
+    /// $2000 and $2400 share the first nametable, $2800 and $2C00 share the second
+    Horizontal,
+
+    /// $2000 and $2800 share the first nametable, $2400 and $2C00 share the second
+    Vertical,
+
+    /// All four nametables share the first nametable
+    SingleScreenLower,
+
+    /// All four nametables share the second nametable
+    SingleScreenUpper,
+
+    /// Each nametable is distinct, using extra RAM on the cartridge
+    FourScreen,
+
+    /// Each nametable uses the page of nametable RAM given for it, allowing arrangements other
+    /// than the standard ones
+    Mapped([u8; 4])
+}
+
+impl Mirroring {
+    /// Determines the mirroring specified by a ROM header
+    pub fn from_header(header: &nes::RomHeader) -> Mirroring {
+        if header.four_screen_vram {
+            Mirroring::FourScreen
+        } else if header.vertical_arrangement {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        }
+    }
+
+    /// Translates an address in the nametable region ($2000-$2FFF) in to an offset in to
+    /// nametable RAM
+    pub fn nametable_addr(&self, addr: u16) -> u16 {
+        let table = (addr >> 10) & 0x03;
+        let physical = match self {
+            &Mirroring::Horizontal => table >> 1,
+            &Mirroring::Vertical => table & 0x01,
+            &Mirroring::SingleScreenLower => 0,
+            &Mirroring::SingleScreenUpper => 1,
+            &Mirroring::FourScreen => table,
+            &Mirroring::Mapped(pages) => (pages[table as usize] & 0x03) as u16
+        };
+        (physical << 10) | (addr & 0x03FF)
+    }
 }
 
+/// Emulates the hardware on a cartridge that maps its memory in to the CPU and PPU address spaces
+///
+/// Beyond the PRG and CHR memories, the hardware can watch and react to the rest of the system
+/// through the provided methods, all of which do nothing by default:
+///
+/// * `mirroring` controls the arrangement of the nametables
+/// * `expansion_audio` adds a sound chip to the APU's output
+/// * `ppu_address` sees every address the PPU puts on its bus
+/// * `read_nametable` and `write_nametable` provide nametables from the cartridge
+/// * `ppu_register_written` sees every write to the PPU's registers
+/// * `clock_cpu` is called for every CPU cycle
+/// * `save_data` and `save_data_mut` expose battery-backed RAM and save chips
+/// * `irq` asserts the CPU's IRQ line
 pub trait Mapper {
     fn name(&self) -> &'static str;
 
     /// Gets a `Memory` representing the active PRG banks
-    fn prg(&self) -> &mem::Memory;
+    fn prg(&self) -> &mem::Memory<u16>;
 
     /// Gets a mutable `Memory` representing the active PRG banks
-    fn prg_mut(&mut self) -> &mut mem::Memory;
+    fn prg_mut(&mut self) -> &mut mem::Memory<u16>;
 
     /// Gets a `Memory` representing the active CHR banks
-    fn chr(&self) -> &mem::Memory;
+    fn chr(&self) -> &mem::Memory<u16>;
 
     /// Gets a mutable `Memory` representing the active CHR banks
-    fn chr_mut(&mut self) -> &mut mem::Memory;
+    fn chr_mut(&mut self) -> &mut mem::Memory<u16>;
+
+    /// Gets the nametable mirroring selected by the mapper
+    ///
+    /// Mappers that can change the mirroring at runtime should override this. Returning `None`
+    /// uses the mirroring specified in the ROM header.
+    fn mirroring(&self) -> Option<Mirroring> {
+        None
+    }
+
+    /// Gets the expansion sound chip on the cartridge
+    ///
+    /// Mappers with a sound chip (such as the VRC6 or MMC5) should override this. The APU clocks
+    /// the chip every CPU cycle and mixes its output with its own channels. The mapper is
+    /// responsible for passing writes to the chip's registers on to it.
+    fn expansion_audio(&mut self) -> Option<&mut ExpansionAudio> {
+        None
+    }
+
+    /// Notifies the mapper of an address the PPU has put on its bus
+    ///
+    /// This is called for every read and write the PPU makes, including nametable fetches.
+    /// Mappers that watch the PPU bus (such as the MMC3, which counts scanlines with A12) should
+    /// override this. The PPU reads through a shared reference, so any state this changes must be
+    /// kept in `Cell`s.
+    fn ppu_address(&self, _addr: u16) {
+    }
+
+    /// Reads from a nametable provided by the cartridge
+    ///
+    /// This is called for every read from $2000-$3EFF, after `ppu_address`. Returning `None`
+    /// reads from nametable RAM, arranged according to `mirroring`. Mappers with their own
+    /// nametable memory (such as the MMC5) should override this.
+    fn read_nametable(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// Writes to a nametable provided by the cartridge
+    ///
+    /// Returns `false` if the write should go to nametable RAM instead
+    fn write_nametable(&mut self, _addr: u16, _val: u8) -> bool {
+        false
+    }
+
+    /// Notifies the mapper of a write to one of the PPU's registers
+    ///
+    /// Some mappers (such as the MMC5) watch these writes to learn how the PPU is configured
+    fn ppu_register_written(&mut self, _reg: rp2C02::Register, _val: u8) {
+    }
+
+    /// Checks if the mapper is asserting the CPU's IRQ line
+    ///
+    /// The line is level triggered, so the mapper should keep asserting it until the interrupt is
+    /// acknowledged through one of its registers.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Gets the memory on the cartridge that can keep its contents while the power is off
+    ///
+    /// Mappers with PRG RAM should return it, as well as any save chip (such as an EEPROM). The
+    /// cartridge only saves it if the ROM header indicates it has a battery.
+    fn save_data(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Gets the memory returned by `save_data`, so that it can be restored
+    fn save_data_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+
+    /// Notifies the mapper that the CPU has run for a cycle
+    ///
+    /// The CPU runs a whole instruction at a time, so this is called for each of the cycles of an
+    /// instruction once it has finished, before any interrupt is delivered. Mappers that count
+    /// cycles (such as the VRC and FME-7 IRQ counters) or time their writes should override this.
+    fn clock_cpu(&mut self) {
+    }
 }
 
 impl Cartridge {
@@ -52,12 +288,32 @@ impl Cartridge {
 
     /// Consumes the provided `Rom` and uses it to build a `Cartridge` to execute
     pub fn load(rom: nes::Rom, logger: Option<slog::Logger>) -> Result<Cartridge> {
+        Cartridge::load_with(rom, &MapperRegistry::new(), logger)
+    }
+
+    /// Consumes the provided `Rom` and uses it to build a `Cartridge` to execute, creating the
+    /// mapper with a factory from `registry` if one is registered for it
+    pub fn load_with(rom: nes::Rom, registry: &MapperRegistry, logger: Option<slog::Logger>) -> Result<Cartridge> {
         let log = unwrap_logger!(logger);
 
         // Pull apart the rom
         let nes::Rom { header, prg, chr } = rom;
 
-        let mapper = create_mapper(&header, prg, chr, log.clone());
+        let mapper = match registry.find(&header) {
+            Some(factory) => Some(factory(&header, prg, chr, log.clone())),
+            None => {
+                // The header gives the size of PRG ROM in 16KB units, which the built-in mappers
+                // rely on when they switch banks
+                if prg.len() == 0 || prg.len() % 0x4000 != 0 {
+                    error!(log,
+                        "prg_size" => prg.len(),
+                        "error" => stringify!(Error::InvalidPrgSize);
+                        "PRG ROM of {} bytes is not a whole number of 16KB banks", prg.len());
+                    return Err(Error::InvalidPrgSize(prg.len()));
+                }
+                create_mapper(&header, prg, chr, log.clone())
+            }
+        };
 
         match mapper {
             Some(m) => {
@@ -81,9 +337,203 @@ impl Cartridge {
     }
 }
 
-fn create_mapper(header: &nes::RomHeader, prg: Vec<u8>, _chr: Vec<u8>, log: slog::Logger) -> Option<Box<Mapper>> {
+fn create_mapper(header: &nes::RomHeader, prg: Vec<u8>, chr: Vec<u8>, log: slog::Logger) -> Option<Box<Mapper>> {
     match (header.cartridge.mapper, header.cartridge.submapper) {
-        (0, _) => Some(Box::new(NRom::new(0x2000, prg, Some(log)))),
+        (0, _) => Some(Box::new(NRom::new(prg_ram_size(header), prg, chr, Some(log)))),
+        (1, _) => Some(Box::new(Mmc1::new(prg, chr, prg_ram_size(header), Some(log)))),
+        (2, _) => Some(Box::new(Discrete::new(Board::UxRom, prg, chr, bus_conflicts(header, Board::UxRom), Some(log)))),
+        (3, _) => Some(Box::new(Discrete::new(Board::CnRom, prg, chr, bus_conflicts(header, Board::CnRom), Some(log)))),
+        (4, 1) => Some(Box::new(Mmc3::new(Mmc3Variant::Mmc6, prg, chr, 0, Some(log)))),
+        (4, 4) => Some(Box::new(Mmc3::new(Mmc3Variant::Nec, prg, chr, prg_ram_size(header), Some(log)))),
+        (4, _) => Some(Box::new(Mmc3::new(Mmc3Variant::Sharp, prg, chr, prg_ram_size(header), Some(log)))),
+        (5, _) => {
+            // Without a NES 2.0 header, assume the most RAM any ExROM board has
+            let ram_size = match header.version {
+                nes::rom::Version::NES2 => prg_ram_size(header),
+                _ => 0x10000
+            };
+            Some(Box::new(Mmc5::new(prg, chr, ram_size, Some(log))))
+        },
+        (7, _) => Some(Box::new(Discrete::new(Board::AxRom, prg, chr, bus_conflicts(header, Board::AxRom), Some(log)))),
+        (9, _) => Some(Box::new(Mmc2::new(Mmc2Variant::Mmc2, prg, chr, 0, Some(log)))),
+        (10, _) => Some(Box::new(Mmc2::new(Mmc2Variant::Mmc4, prg, chr, prg_ram_size(header), Some(log)))),
+        (11, _) => Some(Box::new(Discrete::new(Board::ColorDreams, prg, chr, bus_conflicts(header, Board::ColorDreams), Some(log)))),
+        (16, 4) => Some(Box::new(Bandai::new(BandaiBoard::Fcg, prg, chr, 0, Some(log)))),
+        (16, 5) => Some(Box::new(Bandai::new(BandaiBoard::Lz93d50, prg, chr, 0, Some(log)))),
+        (16, _) => Some(Box::new(Bandai::new(BandaiBoard::Combined, prg, chr, 0, Some(log)))),
+        // VRC2 and VRC4 boards connect different address lines to the register select pins,
+        // which NES 2.0 submappers identify. Without one, the lines of every board that shares
+        // the mapper number are combined, as games only write to addresses that work on theirs.
+        (21, 1) => Some(Box::new(Vrc4::new(VrcChip::Vrc4, 0x02, 0x04, prg, chr, prg_ram_size(header), Some(log)))),
+        (21, 2) => Some(Box::new(Vrc4::new(VrcChip::Vrc4, 0x40, 0x80, prg, chr, prg_ram_size(header), Some(log)))),
+        (21, _) => Some(Box::new(Vrc4::new(VrcChip::Vrc4, 0x42, 0x84, prg, chr, prg_ram_size(header), Some(log)))),
+        (22, _) => Some(Box::new(Vrc4::new(VrcChip::Vrc2a, 0x02, 0x01, prg, chr, 0, Some(log)))),
+        (23, 1) => Some(Box::new(Vrc4::new(VrcChip::Vrc4, 0x01, 0x02, prg, chr, prg_ram_size(header), Some(log)))),
+        (23, 2) => Some(Box::new(Vrc4::new(VrcChip::Vrc4, 0x04, 0x08, prg, chr, prg_ram_size(header), Some(log)))),
+        (23, 3) => Some(Box::new(Vrc4::new(VrcChip::Vrc2, 0x01, 0x02, prg, chr, prg_ram_size(header), Some(log)))),
+        (23, _) => Some(Box::new(Vrc4::new(VrcChip::Vrc4, 0x05, 0x0A, prg, chr, prg_ram_size(header), Some(log)))),
+        (24, _) => Some(Box::new(Vrc6::new(false, prg, chr, prg_ram_size(header), Some(log)))),
+        (25, 1) => Some(Box::new(Vrc4::new(VrcChip::Vrc4, 0x02, 0x01, prg, chr, prg_ram_size(header), Some(log)))),
+        (25, 2) => Some(Box::new(Vrc4::new(VrcChip::Vrc4, 0x08, 0x04, prg, chr, prg_ram_size(header), Some(log)))),
+        (25, 3) => Some(Box::new(Vrc4::new(VrcChip::Vrc2, 0x02, 0x01, prg, chr, prg_ram_size(header), Some(log)))),
+        (25, _) => Some(Box::new(Vrc4::new(VrcChip::Vrc4, 0x0A, 0x05, prg, chr, prg_ram_size(header), Some(log)))),
+        (26, _) => Some(Box::new(Vrc6::new(true, prg, chr, prg_ram_size(header), Some(log)))),
+        (30, _) => {
+            // Boards with a battery are the self-flashing ones, which save to PRG ROM
+            let one_screen = header.four_screen_vram && header.vertical_arrangement;
+            Some(Box::new(Unrom512::new(header.sram_battery_backed, one_screen, prg, chr, Some(log))))
+        },
+        (34, 1) => Some(Box::new(Discrete::new(Board::Nina001, prg, chr, false, Some(log)))),
+        (34, 2) => Some(Box::new(Discrete::new(Board::BnRom, prg, chr, bus_conflicts(header, Board::BnRom), Some(log)))),
+        (34, _) => {
+            // Without a submapper, the boards are told apart by NINA-001 having more than 8KB of
+            // CHR ROM
+            let board = if chr.len() > 0x2000 { Board::Nina001 } else { Board::BnRom };
+            let conflicts = board == Board::BnRom && bus_conflicts(header, board);
+            Some(Box::new(Discrete::new(board, prg, chr, conflicts, Some(log))))
+        },
+        (66, _) => Some(Box::new(Discrete::new(Board::GxRom, prg, chr, bus_conflicts(header, Board::GxRom), Some(log)))),
+        // The header can't tell the 5B apart from the FME-7, so the audio is always included, as
+        // the chip is silent until games write to it
+        (69, _) => Some(Box::new(Fme7::new(true, prg, chr, prg_ram_size(header), Some(log)))),
+        (85, 1) => Some(Box::new(Vrc7::new(0x08, prg, chr, prg_ram_size(header), Some(log)))),
+        (85, 2) => Some(Box::new(Vrc7::new(0x10, prg, chr, prg_ram_size(header), Some(log)))),
+        (85, _) => Some(Box::new(Vrc7::new(0x18, prg, chr, prg_ram_size(header), Some(log)))),
+        (118, _) => Some(Box::new(Mmc3::new(Mmc3Variant::TxSRom, prg, chr, prg_ram_size(header), Some(log)))),
+        (119, _) => Some(Box::new(Mmc3::new(Mmc3Variant::TqRom, prg, chr, prg_ram_size(header), Some(log)))),
+        (153, _) => Some(Box::new(Bandai::new(BandaiBoard::Sram, prg, chr, prg_ram_size(header), Some(log)))),
+        (157, _) => Some(Box::new(Bandai::new(BandaiBoard::Datach, prg, chr, 0, Some(log)))),
+        (159, _) => Some(Box::new(Bandai::new(BandaiBoard::Lz93d50X24C01, prg, chr, 0, Some(log)))),
         _ => None
     }
-}
\ No newline at end of file
+}
+
+/// Determines if a cartridge with the provided board has bus conflicts
+///
+/// For UxROM, CNROM and AxROM, NES 2.0 submapper 1 indicates a board without bus conflicts and 2
+/// a board with them. Other NES 2.0 ROMs use the board's default, as NES 2.0 has no flag for
+/// them, and iNES ROMs use the flag in the header.
+fn bus_conflicts(header: &nes::RomHeader, board: Board) -> bool {
+    match (header.cartridge.mapper, header.cartridge.submapper) {
+        (2, 1) | (3, 1) | (7, 1) => false,
+        (2, 2) | (3, 2) | (7, 2) => true,
+        _ if header.version == nes::rom::Version::NES2 => board.default_bus_conflicts(),
+        _ => header.cartridge.bus_conflicts
+    }
+}
+
+/// Determines if the cartridge has extra nametable RAM for four-screen mirroring
+///
+/// UNROM 512 (mapper 30) reuses the four-screen flag: with horizontal mirroring, it indicates
+/// one-screen mirroring selected by the mapper instead
+fn four_screen_vram(header: &nes::RomHeader) -> bool {
+    match header.cartridge.mapper {
+        30 => header.four_screen_vram && !header.vertical_arrangement,
+        _ => header.four_screen_vram
+    }
+}
+
+/// Gets the value read from an address that nothing on the cartridge drives
+///
+/// The data bus is left holding the last value read, which is approximated by the high byte of the
+/// address, as that is the last value read by an instruction with an absolute address
+fn open_bus(addr: u16) -> u8 {
+    (addr >> 8) as u8
+}
+
+/// Gets the number of the bank `n` banks before the last bank of a ROM
+///
+/// A ROM smaller than a bank is mirrored to fill one bank, and the count wraps around ROMs with
+/// fewer banks than that, so this is always a bank in the ROM
+fn bank_from_end(rom_len: usize, bank_size: usize, n: usize) -> usize {
+    let bank_count = cmp::max(rom_len / bank_size, 1);
+    bank_count - 1 - n % bank_count
+}
+
+/// Gets the size of the PRG RAM on the cartridge
+fn prg_ram_size(header: &nes::RomHeader) -> usize {
+    header.prg_ram_size.total as usize
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use systems::nes::{self,Cartridge};
+    use systems::nes::cart::Error;
+    use systems::nes::cart::testing::numbered_banks;
+
+    /// Builds an iNES ROM for `mapper` whose header declares `banks` 16KB PRG banks and
+    /// `chr_banks` 8KB CHR banks, followed by `prg_size` bytes of PRG ROM and the CHR ROM
+    fn rom(mapper: u8, banks: u8, chr_banks: u8, prg_size: usize) -> nes::Rom {
+        let mut data = vec![b'N', b'E', b'S', 0x1A, banks, chr_banks, mapper << 4, mapper & 0xF0, 0, 0, 0, 0, 0, 0, 0, 0];
+        data.extend(vec![0; prg_size + chr_banks as usize * 0x2000]);
+        nes::load_rom(&mut io::Cursor::new(data)).unwrap()
+    }
+
+    #[test]
+    pub fn rejects_missing_prg_rom() {
+        for &mapper in [0, 2, 3, 7, 11, 34, 66].iter() {
+            let rom = rom(mapper, 0, 0, 0);
+            assert_eq!(mapper as u16, rom.header.cartridge.mapper);
+            assert_eq!(Some(Error::InvalidPrgSize(0)), Cartridge::load(rom, None).err());
+        }
+    }
+
+    #[test]
+    pub fn rejects_truncated_prg_rom() {
+        for &mapper in [2, 4].iter() {
+            assert_eq!(Some(Error::InvalidPrgSize(0x2000)), Cartridge::load(rom(mapper, 2, 0, 0x2000), None).err());
+        }
+    }
+
+    #[test]
+    pub fn mapper_34_is_nina_001_with_more_than_8k_of_chr_rom() {
+        let cart = Cartridge::load(rom(34, 2, 2, 0x8000), None).unwrap();
+        assert_eq!("Nina001", cart.mapper.name());
+    }
+
+    #[test]
+    pub fn mapper_34_is_bnrom_with_8k_of_chr() {
+        for &chr_banks in [0, 1].iter() {
+            let cart = Cartridge::load(rom(34, 2, chr_banks, 0x8000), None).unwrap();
+            assert_eq!("BnRom", cart.mapper.name());
+        }
+    }
+
+    #[test]
+    pub fn sunsoft_carts_include_5b_audio() {
+        // iNES, then NES 2.0 with submappers 0 and 1
+        let headers = [[0x50, 0x40, 0], [0x50, 0x48, 0], [0x50, 0x48, 0x10]];
+        for bytes in headers.iter() {
+            let mut data = vec![b'N', b'E', b'S', 0x1A, 2, 1, bytes[0], bytes[1], bytes[2], 0, 0, 0, 0, 0, 0, 0];
+            data.extend(vec![0; 0xA000]);
+            let rom = nes::load_rom(&mut io::Cursor::new(data)).unwrap();
+            assert_eq!(69, rom.header.cartridge.mapper);
+
+            let mut cart = Cartridge::load(rom, None).unwrap();
+            assert_eq!("Sunsoft5b", cart.mapper.name());
+            assert!(cart.expansion_audio().is_some());
+        }
+    }
+
+    #[test]
+    pub fn nes2_prg_ram_size_is_not_read_as_bus_conflicts() {
+        // AxROM with 8KB of battery-backed PRG RAM, which sets bit 5 of byte 10
+        let mut data = vec![b'N', b'E', b'S', 0x1A, 4, 0, 0x72, 0x08, 0, 0, 0x70, 0, 0, 0, 0, 0];
+        data.extend(numbered_banks(4, 0x4000));
+        let rom = nes::load_rom(&mut io::Cursor::new(data)).unwrap();
+        assert!(!rom.header.cartridge.bus_conflicts);
+
+        // The ROM holds 0 where the bank is written, which would clear the bank with conflicts
+        let mut cart = Cartridge::load(rom, None).unwrap();
+        cart.mapper.prg_mut().set_u8(0x8000, 0x01).unwrap();
+        assert_eq!(Ok(2), cart.mapper.prg().get_u8(0x8000));
+    }
+
+    #[test]
+    pub fn loads_a_single_prg_bank() {
+        let cart = Cartridge::load(rom(2, 1, 0, 0x4000), None).unwrap();
+        assert_eq!("UxRom", cart.mapper.name());
+    }
+}
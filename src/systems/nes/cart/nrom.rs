@@ -2,6 +2,8 @@ use slog;
 
 use mem;
 use systems::nes;
+use systems::nes::cart::chr::Chr;
+use systems::nes::cart::open_bus;
 
 struct Prg {
     ram: mem::Fixed,
@@ -11,18 +13,22 @@ struct Prg {
 
 pub struct NRom {
     prg: Prg,
-    chr: mem::Empty
+    chr: Chr
 }
 
 impl NRom {
-    pub fn new(ram_size: usize, rom: Vec<u8>, logger: Option<slog::Logger>) -> NRom {
+    /// Creates a new NROM cartridge
+    ///
+    /// If `chr` is empty, the cartridge uses 8KB of CHR RAM instead
+    pub fn new(ram_size: usize, rom: Vec<u8>, chr: Vec<u8>, logger: Option<slog::Logger>) -> NRom {
+        let log = unwrap_logger!(logger).new(o!("mapper" => "NRom", "cartridge" => true));
         NRom {
             prg: Prg {
                 ram: mem::Fixed::new(ram_size),
                 rom: mem::Fixed::from_contents(rom),
-                log: unwrap_logger!(logger).new(o!("mapper" => "NRom", "cartridge" => true))
+                log: log.clone()
             },
-            chr: mem::Empty
+            chr: Chr::new(chr, 0x2000, 0x2000, log)
         }
     }
 }
@@ -30,31 +36,41 @@ impl NRom {
 impl nes::Mapper for NRom {
     fn name(&self) -> &'static str { "NRom" }
 
-    fn prg(&self) -> &mem::Memory
+    fn prg(&self) -> &mem::Memory<u16>
     {
         return &self.prg;
     }
 
-    fn prg_mut(&mut self) -> &mut mem::Memory
+    fn prg_mut(&mut self) -> &mut mem::Memory<u16>
     {
         return &mut self.prg;
     }
 
-    fn chr(&self) -> &mem::Memory
+    fn chr(&self) -> &mem::Memory<u16>
     {
         return &self.chr;
     }
 
-    fn chr_mut(&mut self) -> &mut mem::Memory
+    fn chr_mut(&mut self) -> &mut mem::Memory<u16>
     {
         return &mut self.chr;
     }
+
+    fn save_data(&self) -> Option<&[u8]>
+    {
+        return Some(self.prg.ram.contents());
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut [u8]>
+    {
+        return Some(self.prg.ram.contents_mut());
+    }
 }
 
-impl mem::Memory for Prg {
+impl mem::Memory<u16> for Prg {
     fn len(&self) -> u64 { 0xA000 }
 
-    fn get_u8(&self, addr: u64) -> mem::Result<u8> {
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
         if addr < 0x6000 {
             // Out of range!
             error!(self.log,
@@ -66,9 +82,11 @@ impl mem::Memory for Prg {
                     mem::ErrorKind::OutOfBounds,
                     "memory access out of range addressable on NROM cartridge",
                     format!("${:4X} is below the addressable range of 0x6000-0xFFFF", addr)))
+        } else if addr < 0x8000 && self.ram.len() == 0 {
+            Ok(open_bus(addr))
         } else if addr < 0x8000 {
             // RAM! Mirrored as needed
-            let eaddr = (addr - 0x6000) % self.ram.len();
+            let eaddr = ((addr - 0x6000) as u64 % self.ram.len()) as u16;
             trace!(self.log,
                 "read";
                 "vaddr" => format!("${:04X}", addr),
@@ -78,7 +96,7 @@ impl mem::Memory for Prg {
             self.ram.get_u8(eaddr)
         } else {
             // ROM! Mirrored again as needed
-            let eaddr = (addr - 0x8000) % self.rom.len();
+            let eaddr = ((addr - 0x8000) as u64 % self.rom.len()) as u16;
             trace!(self.log,
                 "read";
                 "vaddr" => format!("${:04X}", addr),
@@ -89,7 +107,7 @@ impl mem::Memory for Prg {
         }
     }
 
-    fn set_u8(&mut self, addr: u64, val: u8) -> mem::Result<()> {
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
         if addr < 0x6000 {
             // Out of range!
             error!(self.log,
@@ -101,9 +119,13 @@ impl mem::Memory for Prg {
                 mem::ErrorKind::OutOfBounds,
                 "memory access out of range addressable on NROM cartridge",
                 format!("${:4X} is below the addressable range on NROM cartridge", addr)))
+        } else if addr < 0x8000 && self.ram.len() == 0 {
+            // No RAM! The write goes nowhere
+            trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to missing RAM");
+            Ok(())
         } else if addr < 0x8000 {
             // RAM! Mirrored as needed
-            let eaddr = (addr - 0x6000) % self.ram.len();
+            let eaddr = ((addr - 0x6000) as u64 % self.ram.len()) as u16;
             trace!(self.log,
                 "write";
                 "vaddr" => format!("${:04X}", addr),
@@ -125,3 +147,38 @@ impl mem::Memory for Prg {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use systems::nes::Mapper;
+    use systems::nes::cart::NRom;
+
+    #[test]
+    pub fn chr_rom_is_exposed() {
+        let nrom = NRom::new(0x2000, vec![0; 0x4000], vec![42; 0x2000], None);
+        assert_eq!(0x2000, nrom.chr().len());
+        assert_eq!(Ok(42), nrom.chr().get_u8(0x1FFF));
+    }
+
+    #[test]
+    pub fn chr_ram_is_used_without_chr_rom() {
+        let mut nrom = NRom::new(0x2000, vec![0; 0x4000], vec![], None);
+        nrom.chr_mut().set_u8(0x1234, 42).unwrap();
+        assert_eq!(Ok(42), nrom.chr().get_u8(0x1234));
+    }
+
+    #[test]
+    pub fn prg_ram_can_be_missing() {
+        let mut nrom = NRom::new(0, vec![0; 0x4000], vec![], None);
+        nrom.prg_mut().set_u8(0x6000, 42).unwrap();
+        assert_eq!(Ok(0x60), nrom.prg().get_u8(0x6000));
+    }
+
+    #[test]
+    pub fn prg_rom_is_mirrored() {
+        let mut rom = vec![0; 0x4000];
+        rom[0] = 42;
+        let nrom = NRom::new(0x2000, rom, vec![], None);
+        assert_eq!(Ok(42), nrom.prg().get_u8(0xC000));
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use slog;
+
+use systems::nes;
+use systems::nes::cart::Mapper;
+
+/// Creates the mapper for a cartridge from its ROM header, PRG ROM and CHR ROM
+pub type MapperFactory = Box<Fn(&nes::RomHeader, Vec<u8>, Vec<u8>, slog::Logger) -> Box<Mapper>>;
+
+/// A set of mapper factories, used to load cartridges with mappers that aren't built in
+///
+/// Factories are found by the mapper and submapper numbers in the ROM header. A factory registered
+/// for a specific submapper is used over one registered for every submapper of a mapper, and both
+/// are used over the built-in mappers, so built-in mappers can also be replaced.
+pub struct MapperRegistry {
+    factories: HashMap<(u16, Option<u8>), MapperFactory>
+}
+
+impl MapperRegistry {
+    /// Creates an empty registry, which loads cartridges with the built-in mappers only
+    pub fn new() -> MapperRegistry {
+        MapperRegistry {
+            factories: HashMap::new()
+        }
+    }
+
+    /// Registers a factory for the provided mapper and submapper, or for every submapper of the
+    /// mapper if `submapper` is `None`
+    ///
+    /// This replaces any factory previously registered for the same numbers
+    pub fn register<F>(&mut self, mapper: u16, submapper: Option<u8>, factory: F)
+        where F: Fn(&nes::RomHeader, Vec<u8>, Vec<u8>, slog::Logger) -> Box<Mapper> + 'static {
+        self.factories.insert((mapper, submapper), Box::new(factory));
+    }
+
+    /// Finds the factory registered for the cartridge described by the provided header
+    pub fn find(&self, header: &nes::RomHeader) -> Option<&MapperFactory> {
+        let mapper = header.cartridge.mapper;
+        self.factories.get(&(mapper, Some(header.cartridge.submapper)))
+            .or_else(|| self.factories.get(&(mapper, None)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use systems::nes::{self,Cartridge};
+    use systems::nes::cart::{Board,Discrete,MapperRegistry,NRom};
+
+    /// Builds a NES 2.0 ROM with the provided mapper and submapper numbers
+    fn rom(mapper: u16, submapper: u8) -> nes::Rom {
+        let mut data = vec![
+            b'N', b'E', b'S', 0x1A, 1, 1, ((mapper & 0x0F) << 4) as u8, (mapper & 0xF0) as u8 | 0x08,
+            ((submapper << 4) as u16 | (mapper >> 8)) as u8, 0, 0, 0, 0, 0, 0, 0];
+        data.extend(vec![0; 0x6000]);
+        nes::load_rom(&mut io::Cursor::new(data)).unwrap()
+    }
+
+    fn registry() -> MapperRegistry {
+        let mut registry = MapperRegistry::new();
+        registry.register(200, None, |_, prg, chr, log| Box::new(NRom::new(0, prg, chr, Some(log))));
+        registry.register(200, Some(2), |_, prg, chr, log| Box::new(Discrete::new(Board::CnRom, prg, chr, false, Some(log))));
+        registry
+    }
+
+    #[test]
+    pub fn loads_registered_mappers() {
+        assert!(Cartridge::load(rom(200, 0), None).is_err());
+        let cart = Cartridge::load_with(rom(200, 0), &registry(), None).unwrap();
+        assert_eq!("NRom", cart.mapper.name());
+    }
+
+    #[test]
+    pub fn prefers_exact_submapper() {
+        let cart = Cartridge::load_with(rom(200, 2), &registry(), None).unwrap();
+        assert_eq!("CnRom", cart.mapper.name());
+    }
+
+    #[test]
+    pub fn overrides_built_in_mappers() {
+        let mut registry = MapperRegistry::new();
+        registry.register(0, None, |_, prg, chr, log| Box::new(Discrete::new(Board::CnRom, prg, chr, false, Some(log))));
+        let cart = Cartridge::load_with(rom(0, 0), &registry, None).unwrap();
+        assert_eq!("CnRom", cart.mapper.name());
+        let cart = Cartridge::load(rom(0, 0), None).unwrap();
+        assert_eq!("NRom", cart.mapper.name());
+    }
+}
@@ -0,0 +1,5 @@
+/// Creates ROM where each bank of `bank_size` bytes is filled with its bank number, so tests can
+/// tell which bank is mapped in by reading any byte of it
+pub fn numbered_banks(banks: usize, bank_size: usize) -> Vec<u8> {
+    (0 .. banks).flat_map(|b| vec![b as u8; bank_size]).collect()
+}
@@ -0,0 +1,261 @@
+use slog;
+
+use mem;
+use systems::nes;
+use systems::nes::cart::chr::Chr;
+use systems::nes::cart::flash::Flash;
+use systems::nes::cart::{bank_from_end,open_bus};
+
+/// The size of a switchable PRG ROM bank
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/// The size of a switchable CHR RAM bank
+const CHR_BANK_SIZE: usize = 0x2000;
+
+/// The amount of CHR RAM on the board
+const CHR_RAM_SIZE: usize = 0x8000;
+
+/// Emulates the UNROM 512 board (mapper 30), used by homebrew games
+///
+/// Like UNROM, a 16KB PRG bank is switchable at $8000 and the last is fixed at $C000. The bank
+/// register also selects one of four 8KB banks of CHR RAM, and on boards wired for it, the page
+/// of nametable RAM used for one-screen mirroring.
+///
+/// * Bits 0-4: PRG bank at $8000
+/// * Bits 5-6: CHR RAM bank
+/// * Bit 7: Nametable page, for one-screen mirroring
+///
+/// Self-flashing boards have an SST39SF040 flash chip in place of PRG ROM, which games save to by
+/// writing its command sequences. On these boards, the bank register is written at $C000-$FFFF
+/// and writes to $8000-$BFFF go to the flash chip at the offset of the switchable bank. Boards
+/// without the flash chip decode the register across $8000-$FFFF and have bus conflicts.
+pub struct Unrom512 {
+    flashable: bool,
+    one_screen: bool,
+    rom: Flash,
+    chr: Chr,
+    prg_bank: usize,
+    nametable_page: bool,
+    log: slog::Logger
+}
+
+impl Unrom512 {
+    /// Creates a new UNROM 512 cartridge, with a flash chip if `flashable` is set and with
+    /// mapper-controlled one-screen mirroring if `one_screen` is set
+    ///
+    /// If `chr` is empty, the cartridge uses 32KB of CHR RAM
+    pub fn new(flashable: bool, one_screen: bool, prg: Vec<u8>, chr: Vec<u8>, logger: Option<slog::Logger>) -> Unrom512 {
+        let log = unwrap_logger!(logger).new(o!("mapper" => "Unrom512", "cartridge" => true));
+
+        Unrom512 {
+            flashable: flashable,
+            one_screen: one_screen,
+            rom: Flash::new(prg),
+            chr: Chr::new(chr, CHR_RAM_SIZE, CHR_BANK_SIZE, log.clone()),
+            prg_bank: 0,
+            nametable_page: false,
+            log: log
+        }
+    }
+
+    /// Translates a CPU address in $8000-$FFFF in to an offset in to PRG ROM
+    fn prg_offset(&self, addr: u16) -> usize {
+        let rom_len = self.rom.len();
+        let bank = if addr < 0xC000 {
+            (self.prg_bank * PRG_BANK_SIZE) % rom_len
+        } else {
+            bank_from_end(rom_len, PRG_BANK_SIZE, 0) * PRG_BANK_SIZE
+        };
+        (bank + (addr as usize & (PRG_BANK_SIZE - 1))) % rom_len
+    }
+
+    fn latch(&mut self, val: u8) {
+        self.prg_bank = (val & 0x1F) as usize;
+        self.chr.set_bank(0, ((val >> 5) & 0x03) as usize);
+        self.nametable_page = val & 0x80 != 0;
+    }
+}
+
+impl nes::Mapper for Unrom512 {
+    fn name(&self) -> &'static str { "Unrom512" }
+
+    fn prg(&self) -> &mem::Memory<u16> {
+        self
+    }
+
+    fn prg_mut(&mut self) -> &mut mem::Memory<u16> {
+        self
+    }
+
+    fn chr(&self) -> &mem::Memory<u16> {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut mem::Memory<u16> {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Option<nes::Mirroring> {
+        if !self.one_screen {
+            return None;
+        }
+        Some(if self.nametable_page {
+            nes::Mirroring::SingleScreenUpper
+        } else {
+            nes::Mirroring::SingleScreenLower
+        })
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        if !self.flashable {
+            return None;
+        }
+        Some(self.rom.contents())
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut [u8]> {
+        if !self.flashable {
+            return None;
+        }
+        Some(self.rom.contents_mut())
+    }
+}
+
+impl mem::Memory<u16> for Unrom512 {
+    fn len(&self) -> u64 { 0xA000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "read");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on UNROM 512 cartridge",
+                format!("${:4X} is below the addressable range of 0x6000-0xFFFF", addr)))
+        } else if addr < 0x8000 {
+            Ok(open_bus(addr))
+        } else {
+            let eaddr = self.prg_offset(addr);
+            trace!(self.log,
+                "read";
+                "vaddr" => format!("${:04X}", addr),
+                "paddr" => format!("${:05X}", eaddr),
+                "target" => "ROM",
+                "action" => "read");
+            self.rom.read(eaddr)
+        }
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "write");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on UNROM 512 cartridge",
+                format!("${:4X} is below the addressable range on UNROM 512 cartridge", addr)))
+        } else if addr < 0x8000 {
+            trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to missing RAM");
+            Ok(())
+        } else if self.flashable && addr < 0xC000 {
+            let eaddr = self.prg_offset(addr);
+            trace!(self.log,
+                "vaddr" => format!("${:04X}", addr),
+                "paddr" => format!("${:05X}", eaddr),
+                "val" => val;
+                "wrote flash");
+            self.rom.write(eaddr, val);
+            Ok(())
+        } else {
+            let latched = if self.flashable {
+                val
+            } else {
+                val & try!(self.get_u8(addr))
+            };
+            trace!(self.log,
+                "vaddr" => format!("${:04X}", addr),
+                "val" => val,
+                "latched" => latched;
+                "latched bank register");
+            self.latch(latched);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mem::Memory;
+    use systems::nes::{Mapper,Mirroring};
+    use systems::nes::cart::Unrom512;
+    use systems::nes::cart::testing::numbered_banks;
+
+    /// Writes a flash command sequence through the bank register, as games do
+    fn command(cart: &mut Unrom512, cmd: u8) {
+        cart.set_u8(0xC000, 1).unwrap();
+        cart.set_u8(0x9555, 0xAA).unwrap();
+        cart.set_u8(0xC000, 0).unwrap();
+        cart.set_u8(0xAAAA, 0x55).unwrap();
+        cart.set_u8(0xC000, 1).unwrap();
+        cart.set_u8(0x9555, cmd).unwrap();
+    }
+
+    #[test]
+    pub fn switches_prg_and_chr_banks() {
+        let mut cart = Unrom512::new(true, false, numbered_banks(32, 0x4000), vec![], None);
+        assert_eq!(Ok(31), cart.get_u8(0xC000));
+        cart.set_u8(0xC000, 0x45).unwrap();
+        assert_eq!(Ok(5), cart.get_u8(0x8000));
+
+        cart.chr_mut().set_u8(0x0000, 42).unwrap();
+        cart.set_u8(0xC000, 0x25).unwrap();
+        assert_eq!(Ok(0), cart.chr().get_u8(0x0000));
+        cart.set_u8(0xC000, 0x45).unwrap();
+        assert_eq!(Ok(42), cart.chr().get_u8(0x0000));
+    }
+
+    #[test]
+    pub fn plain_board_has_bus_conflicts() {
+        let mut cart = Unrom512::new(false, false, numbered_banks(32, 0x4000), vec![], None);
+        cart.set_u8(0xC000, 0x45).unwrap();
+        assert_eq!(Ok(5), cart.get_u8(0x8000));
+        cart.set_u8(0x8000, 0x1A).unwrap();
+        assert_eq!(Ok(0), cart.get_u8(0x8000));
+    }
+
+    #[test]
+    pub fn one_screen_page_is_selected_by_bit_7() {
+        let mut cart = Unrom512::new(true, true, numbered_banks(32, 0x4000), vec![], None);
+        assert_eq!(Some(Mirroring::SingleScreenLower), cart.mirroring());
+        cart.set_u8(0xC000, 0x80).unwrap();
+        assert_eq!(Some(Mirroring::SingleScreenUpper), cart.mirroring());
+
+        let cart = Unrom512::new(true, false, numbered_banks(32, 0x4000), vec![], None);
+        assert_eq!(None, cart.mirroring());
+    }
+
+    #[test]
+    pub fn flashable_board_programs_switchable_bank() {
+        let mut cart = Unrom512::new(true, false, vec![0xFF; 0x80000], vec![], None);
+        command(&mut cart, 0xA0);
+        cart.set_u8(0xC000, 3).unwrap();
+        cart.set_u8(0x8010, 0x42).unwrap();
+        assert_eq!(Ok(0x42), cart.get_u8(0x8010));
+        assert_eq!(Some(0x42), cart.save_data().map(|data| data[0xC010]));
+    }
+
+    #[test]
+    pub fn plain_board_ignores_flash_commands() {
+        let mut cart = Unrom512::new(false, false, vec![0xFF; 0x80000], vec![], None);
+        command(&mut cart, 0xA0);
+        cart.set_u8(0x8010, 0x00).unwrap();
+        assert_eq!(Ok(0xFF), cart.get_u8(0x8010));
+        assert_eq!(None, cart.save_data());
+    }
+}
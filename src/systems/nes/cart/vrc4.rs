@@ -0,0 +1,349 @@
+use slog;
+
+use mem;
+use systems::nes;
+use systems::nes::cart::chr::Chr;
+use systems::nes::cart::vrc_irq::VrcIrq;
+use systems::nes::cart::{bank_from_end,open_bus};
+
+/// The size of a switchable PRG ROM bank
+const PRG_BANK_SIZE: usize = 0x2000;
+
+/// The size of a switchable CHR bank
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// Identifies the chip emulated by a `Vrc4` mapper
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum VrcChip {
+    /// The VRC2, which has no IRQ counter or PRG swap mode, and only 4 bits in the upper half of
+    /// each CHR bank
+    Vrc2,
+
+    /// The VRC2 on the VRC2a board (mapper 22), which doesn't connect CHR A10 so ignores the
+    /// lowest bit of each CHR bank
+    Vrc2a,
+
+    /// The VRC4
+    Vrc4
+}
+
+/// Emulates the Konami VRC2 and VRC4
+///
+/// Boards connect different CPU address lines to the two register select pins of the chip, which
+/// is the main difference between mappers 21, 22, 23 and 25. The lines are given as masks when
+/// creating the mapper, and several lines can be given for boards which can't be told apart.
+///
+/// * $8000-$8003: PRG bank at $8000 (or $C000 in swap mode)
+/// * $9000: Mirroring
+/// * $9002: PRG swap mode (VRC4 only)
+/// * $A000-$A003: PRG bank at $A000
+/// * $B000-$E003: CHR banks, each in two registers holding its low and high 4 bits
+/// * $F000-$F003: IRQ latch low and high, control and acknowledge (VRC4 only)
+///
+/// The second last and last PRG banks are fixed at $C000 (or $8000) and $E000.
+pub struct Vrc4 {
+    chip: VrcChip,
+    a0: u16,
+    a1: u16,
+    rom: mem::Fixed,
+    ram: mem::Fixed,
+    chr: Chr,
+    prg_registers: [u8; 2],
+    prg_swap: bool,
+    prg_banks: [usize; 4],
+    chr_registers: [u16; 8],
+    mirroring: nes::Mirroring,
+    latch: u8,
+    irq: VrcIrq,
+    log: slog::Logger
+}
+
+impl Vrc4 {
+    /// Creates a new VRC2 or VRC4 cartridge, with the register select pins connected to the
+    /// address lines in `a0` and `a1`
+    ///
+    /// If `chr` is empty, the cartridge uses 8KB of CHR RAM instead
+    pub fn new(chip: VrcChip, a0: u16, a1: u16, prg: Vec<u8>, chr: Vec<u8>, ram_size: usize, logger: Option<slog::Logger>) -> Vrc4 {
+        let name = if chip == VrcChip::Vrc4 { "Vrc4" } else { "Vrc2" };
+        let log = unwrap_logger!(logger).new(o!("mapper" => name, "cartridge" => true));
+
+        let mut vrc = Vrc4 {
+            chip: chip,
+            a0: a0,
+            a1: a1,
+            rom: mem::Fixed::from_contents(prg),
+            ram: mem::Fixed::new(ram_size),
+            chr: Chr::new(chr, 0x2000, CHR_BANK_SIZE, log.clone()),
+            prg_registers: [0, 1],
+            prg_swap: false,
+            prg_banks: [0; 4],
+            chr_registers: [0; 8],
+            mirroring: nes::Mirroring::Vertical,
+            latch: 0,
+            irq: VrcIrq::new(),
+            log: log
+        };
+        vrc.update_banks();
+        vrc
+    }
+
+    /// Recalculates the PRG and CHR banks from the bank registers
+    fn update_banks(&mut self) {
+        let rom_len = self.rom.len() as usize;
+        let last = bank_from_end(rom_len, PRG_BANK_SIZE, 0);
+        let second_last = bank_from_end(rom_len, PRG_BANK_SIZE, 1);
+        let r0 = (self.prg_registers[0] & 0x1F) as usize;
+        let r1 = (self.prg_registers[1] & 0x1F) as usize;
+        let banks = if self.prg_swap {
+            [second_last, r1, r0, last]
+        } else {
+            [r0, r1, second_last, last]
+        };
+        for (slot, bank) in banks.iter().enumerate() {
+            self.prg_banks[slot] = (bank * PRG_BANK_SIZE) % rom_len;
+        }
+
+        for slot in 0 .. 8 {
+            let bank = self.chr_registers[slot] as usize;
+            let bank = if self.chip == VrcChip::Vrc2a { bank >> 1 } else { bank };
+            self.chr.set_bank(slot, bank);
+        }
+    }
+
+    /// Translates an address to the register it selects, as $x000-$x003
+    fn register(&self, addr: u16) -> u16 {
+        let mut reg = addr & 0xF000;
+        if addr & self.a0 != 0 { reg |= 0x01; }
+        if addr & self.a1 != 0 { reg |= 0x02; }
+        reg
+    }
+
+    fn write_register(&mut self, addr: u16, val: u8) {
+        let reg = self.register(addr);
+        trace!(self.log,
+            "vaddr" => format!("${:04X}", addr),
+            "reg" => format!("${:04X}", reg),
+            "val" => val;
+            "wrote register");
+        let vrc4 = self.chip == VrcChip::Vrc4;
+        match reg {
+            0x8000 ... 0x8003 => {
+                self.prg_registers[0] = val;
+                self.update_banks();
+            },
+            0x9000 | 0x9001 => {
+                let mode = if vrc4 { val & 0x03 } else { val & 0x01 };
+                self.mirroring = match mode {
+                    0 => nes::Mirroring::Vertical,
+                    1 => nes::Mirroring::Horizontal,
+                    2 => nes::Mirroring::SingleScreenLower,
+                    _ => nes::Mirroring::SingleScreenUpper
+                };
+            },
+            0x9002 | 0x9003 if vrc4 => {
+                self.prg_swap = val & 0x02 != 0;
+                self.update_banks();
+            },
+            0xA000 ... 0xA003 => {
+                self.prg_registers[1] = val;
+                self.update_banks();
+            },
+            0xB000 ... 0xEFFF => {
+                let slot = (((reg >> 12) - 0xB) * 2 + ((reg >> 1) & 0x01)) as usize;
+                let bank = self.chr_registers[slot];
+                self.chr_registers[slot] = if reg & 0x01 == 0 {
+                    (bank & 0x1F0) | (val & 0x0F) as u16
+                } else {
+                    let mask = if vrc4 { 0x1F } else { 0x0F };
+                    (bank & 0x0F) | ((val & mask) as u16) << 4
+                };
+                self.update_banks();
+            },
+            0xF000 if vrc4 => self.irq.write_latch_low(val),
+            0xF001 if vrc4 => self.irq.write_latch_high(val),
+            0xF002 if vrc4 => self.irq.write_control(val),
+            0xF003 if vrc4 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+}
+
+impl nes::Mapper for Vrc4 {
+    fn name(&self) -> &'static str {
+        if self.chip == VrcChip::Vrc4 { "Vrc4" } else { "Vrc2" }
+    }
+
+    fn prg(&self) -> &mem::Memory<u16> {
+        self
+    }
+
+    fn prg_mut(&mut self) -> &mut mem::Memory<u16> {
+        self
+    }
+
+    fn chr(&self) -> &mem::Memory<u16> {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut mem::Memory<u16> {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Option<nes::Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn clock_cpu(&mut self) {
+        self.irq.clock();
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        Some(self.ram.contents())
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.ram.contents_mut())
+    }
+}
+
+impl mem::Memory<u16> for Vrc4 {
+    fn len(&self) -> u64 { 0xA000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "read");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on VRC cartridge",
+                format!("${:4X} is below the addressable range of 0x6000-0xFFFF", addr)))
+        } else if addr < 0x8000 {
+            if self.ram.len() > 0 {
+                self.ram.get_u8(((addr - 0x6000) as u64) % self.ram.len())
+            } else if self.chip != VrcChip::Vrc4 && addr < 0x7000 {
+                // Boards without RAM have a 1-bit latch in the VRC2, which some games use as a
+                // copy protection check. The rest of the bits are open bus.
+                Ok((open_bus(addr) & 0xFE) | self.latch)
+            } else {
+                Ok(open_bus(addr))
+            }
+        } else {
+            let bank = self.prg_banks[((addr >> 13) & 0x03) as usize];
+            let eaddr = (bank + (addr as usize & (PRG_BANK_SIZE - 1))) % (self.rom.len() as usize);
+            trace!(self.log,
+                "read";
+                "vaddr" => format!("${:04X}", addr),
+                "paddr" => format!("${:05X}", eaddr),
+                "target" => "ROM",
+                "action" => "read");
+            self.rom.get_u8(eaddr as u64)
+        }
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "write");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on VRC cartridge",
+                format!("${:4X} is below the addressable range on VRC cartridge", addr)))
+        } else if addr < 0x8000 {
+            if self.ram.len() > 0 {
+                let eaddr = ((addr - 0x6000) as u64) % self.ram.len();
+                self.ram.set_u8(eaddr, val)
+            } else if self.chip != VrcChip::Vrc4 && addr < 0x7000 {
+                self.latch = val & 0x01;
+                Ok(())
+            } else {
+                trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to missing RAM");
+                Ok(())
+            }
+        } else {
+            self.write_register(addr, val);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mem::Memory;
+    use systems::nes::{Mapper,Mirroring};
+    use systems::nes::cart::{Vrc4,VrcChip};
+    use systems::nes::cart::testing::numbered_banks;
+
+    #[test]
+    pub fn wiring_selects_registers() {
+        // VRC4c, with the register select pins on A6 and A7
+        let mut cart = Vrc4::new(VrcChip::Vrc4, 0x40, 0x80, numbered_banks(16, 0x2000), numbered_banks(64, 0x0400), 0x2000, None);
+        cart.set_u8(0xB000, 0x05).unwrap();
+        cart.set_u8(0xB040, 0x01).unwrap();
+        cart.set_u8(0xB080, 0x03).unwrap();
+        assert_eq!(Ok(0x15), cart.chr().get_u8(0x0000));
+        assert_eq!(Ok(0x03), cart.chr().get_u8(0x0400));
+    }
+
+    #[test]
+    pub fn prg_swap_mode_moves_first_bank() {
+        let mut cart = Vrc4::new(VrcChip::Vrc4, 0x01, 0x02, numbered_banks(16, 0x2000), vec![], 0x2000, None);
+        cart.set_u8(0x8000, 3).unwrap();
+        cart.set_u8(0xA000, 4).unwrap();
+        assert_eq!(Ok(3), cart.get_u8(0x8000));
+        assert_eq!(Ok(4), cart.get_u8(0xA000));
+        assert_eq!(Ok(14), cart.get_u8(0xC000));
+        cart.set_u8(0x9002, 0x02).unwrap();
+        assert_eq!(Ok(14), cart.get_u8(0x8000));
+        assert_eq!(Ok(3), cart.get_u8(0xC000));
+        assert_eq!(Ok(15), cart.get_u8(0xE000));
+    }
+
+    #[test]
+    pub fn vrc2a_ignores_lowest_chr_bit() {
+        let mut cart = Vrc4::new(VrcChip::Vrc2a, 0x02, 0x01, numbered_banks(16, 0x2000), numbered_banks(64, 0x0400), 0, None);
+        cart.set_u8(0xB000, 0x05).unwrap();
+        assert_eq!(Ok(2), cart.chr().get_u8(0x0000));
+    }
+
+    #[test]
+    pub fn vrc2_has_latch_instead_of_ram() {
+        let mut cart = Vrc4::new(VrcChip::Vrc2, 0x01, 0x02, numbered_banks(16, 0x2000), vec![], 0, None);
+        cart.set_u8(0x6000, 0xFF).unwrap();
+        assert_eq!(Ok(0x61), cart.get_u8(0x6000));
+        cart.set_u8(0x9000, 0x03).unwrap();
+        assert_eq!(Some(Mirroring::Horizontal), cart.mirroring());
+    }
+
+    #[test]
+    pub fn irq_counts_cpu_cycles() {
+        let mut cart = Vrc4::new(VrcChip::Vrc4, 0x01, 0x02, numbered_banks(16, 0x2000), vec![], 0x2000, None);
+        cart.set_u8(0xF000, 0x0E).unwrap();
+        cart.set_u8(0xF001, 0x0F).unwrap();
+        cart.set_u8(0xF002, 0x06).unwrap();
+        cart.clock_cpu();
+        assert!(!cart.irq());
+        cart.clock_cpu();
+        assert!(cart.irq());
+        cart.set_u8(0xF003, 0).unwrap();
+        assert!(!cart.irq());
+    }
+
+    #[test]
+    pub fn single_prg_bank_is_mirrored() {
+        let mut cart = Vrc4::new(VrcChip::Vrc4, 0x01, 0x02, vec![42; 0x2000], vec![], 0, None);
+        cart.set_u8(0x9002, 0x02).unwrap();
+        for addr in [0x8000, 0xA000, 0xC000, 0xE000].iter() {
+            assert_eq!(Ok(42), cart.get_u8(*addr));
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use slog;
+
+use mem;
+use hw::expansion_audio::{ExpansionAudio,Vrc6Audio};
+use systems::nes;
+use systems::nes::cart::chr::Chr;
+use systems::nes::cart::vrc_irq::VrcIrq;
+use systems::nes::cart::{bank_from_end,open_bus};
+
+/// The size of the switchable PRG ROM bank at $8000
+const PRG_16K_BANK_SIZE: usize = 0x4000;
+
+/// The size of the switchable PRG ROM bank at $C000, and the fixed bank at $E000
+const PRG_8K_BANK_SIZE: usize = 0x2000;
+
+/// The size of a switchable CHR bank
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// Emulates the Konami VRC6
+///
+/// VRC6b boards (mapper 26) swap the A0 and A1 lines to the chip compared to VRC6a boards
+/// (mapper 24), so addresses are translated to the VRC6a layout before being decoded.
+///
+/// * $8000-$8003: 16KB PRG bank at $8000
+/// * $9000-$B002: Audio
+/// * $B003: PPU banking style, mirroring and PRG RAM enable
+/// * $C000-$C003: 8KB PRG bank at $C000
+/// * $D000-$E003: 1KB CHR banks
+/// * $F000-$F002: IRQ latch, control and acknowledge
+///
+/// The last 8KB PRG bank is fixed at $E000. Only the PPU banking style that games use (1KB CHR
+/// banks, with nametable RAM) is supported.
+pub struct Vrc6 {
+    swapped: bool,
+    rom: mem::Fixed,
+    ram: mem::Fixed,
+    chr: Chr,
+    prg_banks: [usize; 2],
+    mirroring: nes::Mirroring,
+    ram_enabled: bool,
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+    log: slog::Logger
+}
+
+impl Vrc6 {
+    /// Creates a new VRC6 cartridge, with A0 and A1 swapped if `swapped` is set
+    ///
+    /// If `chr` is empty, the cartridge uses 8KB of CHR RAM instead
+    pub fn new(swapped: bool, prg: Vec<u8>, chr: Vec<u8>, ram_size: usize, logger: Option<slog::Logger>) -> Vrc6 {
+        let log = unwrap_logger!(logger).new(o!("mapper" => "Vrc6", "cartridge" => true));
+        let rom_len = prg.len();
+
+        Vrc6 {
+            swapped: swapped,
+            rom: mem::Fixed::from_contents(prg),
+            ram: mem::Fixed::new(ram_size),
+            chr: Chr::new(chr, 0x2000, CHR_BANK_SIZE, log.clone()),
+            prg_banks: [0, PRG_16K_BANK_SIZE % rom_len],
+            mirroring: nes::Mirroring::Vertical,
+            ram_enabled: false,
+            irq: VrcIrq::new(),
+            audio: Vrc6Audio::new(),
+            log: log
+        }
+    }
+
+    /// Translates an address to the register it selects, as $x000-$x003
+    fn register(&self, addr: u16) -> u16 {
+        let lines = if self.swapped {
+            ((addr & 0x01) << 1) | ((addr >> 1) & 0x01)
+        } else {
+            addr & 0x03
+        };
+        (addr & 0xF000) | lines
+    }
+
+    fn write_register(&mut self, addr: u16, val: u8) {
+        let reg = self.register(addr);
+        trace!(self.log,
+            "vaddr" => format!("${:04X}", addr),
+            "reg" => format!("${:04X}", reg),
+            "val" => val;
+            "wrote register");
+        let rom_len = self.rom.len() as usize;
+        match reg {
+            0x8000 ... 0x8003 => self.prg_banks[0] = ((val & 0x0F) as usize * PRG_16K_BANK_SIZE) % rom_len,
+            0x9000 ... 0xB002 => self.audio.write(reg, val),
+            0xB003 => {
+                self.mirroring = match (val >> 2) & 0x03 {
+                    0 => nes::Mirroring::Vertical,
+                    1 => nes::Mirroring::Horizontal,
+                    2 => nes::Mirroring::SingleScreenLower,
+                    _ => nes::Mirroring::SingleScreenUpper
+                };
+                self.ram_enabled = val & 0x80 != 0;
+            },
+            0xC000 ... 0xC003 => self.prg_banks[1] = ((val & 0x1F) as usize * PRG_8K_BANK_SIZE) % rom_len,
+            0xD000 ... 0xE003 => {
+                let slot = (((reg >> 12) - 0xD) * 4 + (reg & 0x03)) as usize;
+                self.chr.set_bank(slot, val as usize);
+            },
+            0xF000 => self.irq.write_latch(val),
+            0xF001 => self.irq.write_control(val),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+}
+
+impl nes::Mapper for Vrc6 {
+    fn name(&self) -> &'static str { "Vrc6" }
+
+    fn prg(&self) -> &mem::Memory<u16> {
+        self
+    }
+
+    fn prg_mut(&mut self) -> &mut mem::Memory<u16> {
+        self
+    }
+
+    fn chr(&self) -> &mem::Memory<u16> {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut mem::Memory<u16> {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Option<nes::Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn expansion_audio(&mut self) -> Option<&mut ExpansionAudio> {
+        Some(&mut self.audio)
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn clock_cpu(&mut self) {
+        self.irq.clock();
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        Some(self.ram.contents())
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.ram.contents_mut())
+    }
+}
+
+impl mem::Memory<u16> for Vrc6 {
+    fn len(&self) -> u64 { 0xA000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "read");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on VRC6 cartridge",
+                format!("${:4X} is below the addressable range of 0x6000-0xFFFF", addr)))
+        } else if addr < 0x8000 {
+            if !self.ram_enabled || self.ram.len() == 0 {
+                return Ok(open_bus(addr));
+            }
+            self.ram.get_u8(((addr - 0x6000) as u64) % self.ram.len())
+        } else {
+            let rom_len = self.rom.len() as usize;
+            let last = bank_from_end(rom_len, PRG_8K_BANK_SIZE, 0) * PRG_8K_BANK_SIZE;
+            let eaddr = match addr {
+                0x8000 ... 0xBFFF => self.prg_banks[0] + (addr as usize & (PRG_16K_BANK_SIZE - 1)),
+                0xC000 ... 0xDFFF => self.prg_banks[1] + (addr as usize & (PRG_8K_BANK_SIZE - 1)),
+                _ => last + (addr as usize & (PRG_8K_BANK_SIZE - 1))
+            } % rom_len;
+            trace!(self.log,
+                "read";
+                "vaddr" => format!("${:04X}", addr),
+                "paddr" => format!("${:05X}", eaddr),
+                "target" => "ROM",
+                "action" => "read");
+            self.rom.get_u8(eaddr as u64)
+        }
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "write");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on VRC6 cartridge",
+                format!("${:4X} is below the addressable range on VRC6 cartridge", addr)))
+        } else if addr < 0x8000 {
+            if !self.ram_enabled || self.ram.len() == 0 {
+                trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to disabled RAM");
+                return Ok(());
+            }
+            let eaddr = ((addr - 0x6000) as u64) % self.ram.len();
+            self.ram.set_u8(eaddr, val)
+        } else {
+            self.write_register(addr, val);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mem::Memory;
+    use systems::nes::{Mapper,Mirroring};
+    use systems::nes::cart::Vrc6;
+    use systems::nes::cart::testing::numbered_banks;
+
+    #[test]
+    pub fn prg_banks_are_16k_and_8k() {
+        let mut cart = Vrc6::new(false, numbered_banks(16, 0x2000), numbered_banks(32, 0x0400), 0x2000, None);
+        cart.set_u8(0x8000, 2).unwrap();
+        cart.set_u8(0xC000, 7).unwrap();
+        assert_eq!(Ok(4), cart.get_u8(0x8000));
+        assert_eq!(Ok(5), cart.get_u8(0xA000));
+        assert_eq!(Ok(7), cart.get_u8(0xC000));
+        assert_eq!(Ok(15), cart.get_u8(0xE000));
+    }
+
+    #[test]
+    pub fn vrc6b_swaps_register_lines() {
+        let mut cart = Vrc6::new(true, numbered_banks(16, 0x2000), numbered_banks(32, 0x0400), 0x2000, None);
+        cart.set_u8(0xD001, 9).unwrap();
+        cart.set_u8(0xD002, 5).unwrap();
+        assert_eq!(Ok(5), cart.chr().get_u8(0x0400));
+        assert_eq!(Ok(9), cart.chr().get_u8(0x0800));
+
+        cart.set_u8(0xB003, 0x84).unwrap();
+        assert_eq!(Some(Mirroring::Horizontal), cart.mirroring());
+        cart.set_u8(0x6000, 42).unwrap();
+        assert_eq!(Ok(42), cart.get_u8(0x6000));
+    }
+
+    #[test]
+    pub fn irq_counts_cpu_cycles() {
+        let mut cart = Vrc6::new(false, numbered_banks(16, 0x2000), numbered_banks(32, 0x0400), 0x2000, None);
+        cart.set_u8(0xF000, 0xFF).unwrap();
+        cart.set_u8(0xF001, 0x06).unwrap();
+        cart.clock_cpu();
+        assert!(cart.irq());
+        cart.set_u8(0xF002, 0).unwrap();
+        assert!(!cart.irq());
+    }
+}
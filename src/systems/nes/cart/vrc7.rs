@@ -0,0 +1,261 @@
+use slog;
+
+use mem;
+use hw::expansion_audio::{ExpansionAudio,Vrc7Audio};
+use systems::nes;
+use systems::nes::cart::chr::Chr;
+use systems::nes::cart::vrc_irq::VrcIrq;
+use systems::nes::cart::{bank_from_end,open_bus};
+
+/// The size of a switchable PRG ROM bank
+const PRG_BANK_SIZE: usize = 0x2000;
+
+/// The size of a switchable CHR bank
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// Emulates the Konami VRC7
+///
+/// The second register at each address is selected by A4 on VRC7a boards and A3 on VRC7b boards,
+/// which is given as a mask when creating the mapper. Addresses are translated to the VRC7a
+/// layout before being decoded.
+///
+/// * $8000/$8010/$9000: PRG banks at $8000, $A000 and $C000
+/// * $9010/$9030: Audio register select and data
+/// * $A000-$D010: 1KB CHR banks
+/// * $E000: Mirroring, audio reset and PRG RAM enable
+/// * $E010/$F000/$F010: IRQ latch, control and acknowledge
+///
+/// The last PRG bank is fixed at $E000.
+pub struct Vrc7 {
+    select: u16,
+    rom: mem::Fixed,
+    ram: mem::Fixed,
+    chr: Chr,
+    prg_banks: [usize; 4],
+    mirroring: nes::Mirroring,
+    ram_enabled: bool,
+    irq: VrcIrq,
+    audio: Vrc7Audio,
+    log: slog::Logger
+}
+
+impl Vrc7 {
+    /// Creates a new VRC7 cartridge, with the register select pin connected to the address lines
+    /// in `select`
+    ///
+    /// If `chr` is empty, the cartridge uses 8KB of CHR RAM instead
+    pub fn new(select: u16, prg: Vec<u8>, chr: Vec<u8>, ram_size: usize, logger: Option<slog::Logger>) -> Vrc7 {
+        let log = unwrap_logger!(logger).new(o!("mapper" => "Vrc7", "cartridge" => true));
+        let rom_len = prg.len();
+
+        Vrc7 {
+            select: select,
+            rom: mem::Fixed::from_contents(prg),
+            ram: mem::Fixed::new(ram_size),
+            chr: Chr::new(chr, 0x2000, CHR_BANK_SIZE, log.clone()),
+            prg_banks: [
+                0,
+                PRG_BANK_SIZE % rom_len,
+                (PRG_BANK_SIZE * 2) % rom_len,
+                bank_from_end(rom_len, PRG_BANK_SIZE, 0) * PRG_BANK_SIZE
+            ],
+            mirroring: nes::Mirroring::Vertical,
+            ram_enabled: false,
+            irq: VrcIrq::new(),
+            audio: Vrc7Audio::new(),
+            log: log
+        }
+    }
+
+    /// Translates an address to the register it selects, as $x000 or $x010
+    ///
+    /// The audio data port also decodes A5, so it is kept at $9030
+    fn register(&self, addr: u16) -> u16 {
+        let reg = addr & 0xF000;
+        match (addr & self.select != 0, reg) {
+            (false, _) => reg,
+            (true, 0x9000) => reg | 0x10 | (addr & 0x20),
+            (true, _) => reg | 0x10
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, val: u8) {
+        let reg = self.register(addr);
+        trace!(self.log,
+            "vaddr" => format!("${:04X}", addr),
+            "reg" => format!("${:04X}", reg),
+            "val" => val;
+            "wrote register");
+        let bank = ((val & 0x3F) as usize * PRG_BANK_SIZE) % (self.rom.len() as usize);
+        match reg {
+            0x8000 => self.prg_banks[0] = bank,
+            0x8010 => self.prg_banks[1] = bank,
+            0x9000 => self.prg_banks[2] = bank,
+            0x9010 | 0x9030 => self.audio.write(reg, val),
+            0xA000 ... 0xD010 => {
+                let slot = (((reg >> 12) - 0xA) * 2 + ((reg >> 4) & 0x01)) as usize;
+                self.chr.set_bank(slot, val as usize);
+            },
+            0xE000 => {
+                self.mirroring = match val & 0x03 {
+                    0 => nes::Mirroring::Vertical,
+                    1 => nes::Mirroring::Horizontal,
+                    2 => nes::Mirroring::SingleScreenLower,
+                    _ => nes::Mirroring::SingleScreenUpper
+                };
+                self.ram_enabled = val & 0x80 != 0;
+            },
+            0xE010 => self.irq.write_latch(val),
+            0xF000 => self.irq.write_control(val),
+            0xF010 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+}
+
+impl nes::Mapper for Vrc7 {
+    fn name(&self) -> &'static str { "Vrc7" }
+
+    fn prg(&self) -> &mem::Memory<u16> {
+        self
+    }
+
+    fn prg_mut(&mut self) -> &mut mem::Memory<u16> {
+        self
+    }
+
+    fn chr(&self) -> &mem::Memory<u16> {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut mem::Memory<u16> {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Option<nes::Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn expansion_audio(&mut self) -> Option<&mut ExpansionAudio> {
+        Some(&mut self.audio)
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn clock_cpu(&mut self) {
+        self.irq.clock();
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        Some(self.ram.contents())
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.ram.contents_mut())
+    }
+}
+
+impl mem::Memory<u16> for Vrc7 {
+    fn len(&self) -> u64 { 0xA000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "read");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on VRC7 cartridge",
+                format!("${:4X} is below the addressable range of 0x6000-0xFFFF", addr)))
+        } else if addr < 0x8000 {
+            if !self.ram_enabled || self.ram.len() == 0 {
+                return Ok(open_bus(addr));
+            }
+            self.ram.get_u8(((addr - 0x6000) as u64) % self.ram.len())
+        } else {
+            let bank = self.prg_banks[((addr >> 13) & 0x03) as usize];
+            let eaddr = (bank + (addr as usize & (PRG_BANK_SIZE - 1))) % (self.rom.len() as usize);
+            trace!(self.log,
+                "read";
+                "vaddr" => format!("${:04X}", addr),
+                "paddr" => format!("${:05X}", eaddr),
+                "target" => "ROM",
+                "action" => "read");
+            self.rom.get_u8(eaddr as u64)
+        }
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        if addr < 0x6000 {
+            error!(self.log,
+                "error";
+                "vaddr" => format!("${:04X}", addr),
+                "error" => stringify!(mem::ErrorKind::OutOfBounds),
+                "action" => "write");
+            Err(mem::Error::with_detail(
+                mem::ErrorKind::OutOfBounds,
+                "memory access out of range addressable on VRC7 cartridge",
+                format!("${:4X} is below the addressable range on VRC7 cartridge", addr)))
+        } else if addr < 0x8000 {
+            if !self.ram_enabled || self.ram.len() == 0 {
+                trace!(self.log, "vaddr" => format!("${:04X}", addr); "dropped write to disabled RAM");
+                return Ok(());
+            }
+            let eaddr = ((addr - 0x6000) as u64) % self.ram.len();
+            self.ram.set_u8(eaddr, val)
+        } else {
+            self.write_register(addr, val);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mem::Memory;
+    use systems::nes::{Mapper,Mirroring};
+    use systems::nes::cart::Vrc7;
+    use systems::nes::cart::testing::numbered_banks;
+
+    #[test]
+    pub fn vrc7a_and_vrc7b_select_registers_differently() {
+        let mut vrc7a = Vrc7::new(0x10, numbered_banks(16, 0x2000), numbered_banks(32, 0x0400), 0x2000, None);
+        vrc7a.set_u8(0x8010, 5).unwrap();
+        vrc7a.set_u8(0xA010, 9).unwrap();
+        assert_eq!(Ok(5), vrc7a.get_u8(0xA000));
+        assert_eq!(Ok(9), vrc7a.chr().get_u8(0x0400));
+
+        let mut vrc7b = Vrc7::new(0x08, numbered_banks(16, 0x2000), numbered_banks(32, 0x0400), 0x2000, None);
+        vrc7b.set_u8(0x8008, 5).unwrap();
+        vrc7b.set_u8(0xD008, 9).unwrap();
+        assert_eq!(Ok(5), vrc7b.get_u8(0xA000));
+        assert_eq!(Ok(9), vrc7b.chr().get_u8(0x1C00));
+        assert_eq!(Ok(15), vrc7b.get_u8(0xE000));
+    }
+
+    #[test]
+    pub fn control_register_sets_mirroring_and_ram() {
+        let mut cart = Vrc7::new(0x10, numbered_banks(16, 0x2000), numbered_banks(32, 0x0400), 0x2000, None);
+        cart.set_u8(0x6000, 42).unwrap();
+        assert_eq!(Ok(0x60), cart.get_u8(0x6000));
+        cart.set_u8(0xE000, 0x83).unwrap();
+        cart.set_u8(0x6000, 42).unwrap();
+        assert_eq!(Ok(42), cart.get_u8(0x6000));
+        assert_eq!(Some(Mirroring::SingleScreenUpper), cart.mirroring());
+    }
+
+    #[test]
+    pub fn irq_counts_cpu_cycles() {
+        let mut cart = Vrc7::new(0x10, numbered_banks(16, 0x2000), numbered_banks(32, 0x0400), 0x2000, None);
+        cart.set_u8(0xE010, 0xFF).unwrap();
+        cart.set_u8(0xF000, 0x06).unwrap();
+        cart.clock_cpu();
+        assert!(cart.irq());
+        cart.set_u8(0xF010, 0).unwrap();
+        assert!(!cart.irq());
+    }
+}
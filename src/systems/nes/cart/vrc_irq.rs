@@ -0,0 +1,147 @@
+/// The number of PPU dots in a scanline, which the prescaler counts down from
+const PRESCALER_PERIOD: i16 = 341;
+
+/// The number of PPU dots in each CPU cycle, which the prescaler counts down by
+const DOTS_PER_CYCLE: i16 = 3;
+
+/// The IRQ counter shared by the Konami VRC4, VRC6 and VRC7
+///
+/// The counter is an 8-bit up counter that signals an IRQ and reloads from the latch when it
+/// overflows. In cycle mode it is clocked every CPU cycle. In scanline mode a prescaler divides
+/// the CPU clock down to roughly once per scanline, as the VRCs have no way of watching the PPU.
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pending: bool
+}
+
+impl VrcIrq {
+    pub fn new() -> VrcIrq {
+        VrcIrq {
+            latch: 0,
+            counter: 0,
+            prescaler: PRESCALER_PERIOD,
+            enabled: false,
+            enable_after_ack: false,
+            cycle_mode: false,
+            pending: false
+        }
+    }
+
+    /// Writes the whole reload value
+    pub fn write_latch(&mut self, val: u8) {
+        self.latch = val;
+    }
+
+    /// Writes the low 4 bits of the reload value, the VRC4 has separate registers for each half
+    pub fn write_latch_low(&mut self, val: u8) {
+        self.latch = (self.latch & 0xF0) | (val & 0x0F);
+    }
+
+    /// Writes the high 4 bits of the reload value
+    pub fn write_latch_high(&mut self, val: u8) {
+        self.latch = (self.latch & 0x0F) | (val << 4);
+    }
+
+    /// Writes the control register, which acknowledges any pending IRQ
+    ///
+    /// * Bit 0: Enable the counter again once the IRQ is acknowledged
+    /// * Bit 1: Enable the counter, reloading it and the prescaler
+    /// * Bit 2: Count CPU cycles rather than scanlines
+    pub fn write_control(&mut self, val: u8) {
+        self.enable_after_ack = val & 0x01 != 0;
+        self.enabled = val & 0x02 != 0;
+        self.cycle_mode = val & 0x04 != 0;
+        self.pending = false;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_PERIOD;
+        }
+    }
+
+    /// Acknowledges a pending IRQ, and re-enables the counter if requested by the control register
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    /// Clocks the counter for a CPU cycle
+    pub fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.cycle_mode {
+            self.tick();
+        } else {
+            self.prescaler -= DOTS_PER_CYCLE;
+            if self.prescaler <= 0 {
+                self.prescaler += PRESCALER_PERIOD;
+                self.tick();
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+
+    /// Checks if the counter is signalling an IRQ
+    pub fn pending(&self) -> bool {
+        self.pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use systems::nes::cart::vrc_irq::VrcIrq;
+
+    #[test]
+    pub fn cycle_mode_counts_up_to_overflow() {
+        let mut irq = VrcIrq::new();
+        irq.write_latch(0xFD);
+        irq.write_control(0x06);
+        irq.clock();
+        irq.clock();
+        assert!(!irq.pending());
+        irq.clock();
+        assert!(irq.pending());
+    }
+
+    #[test]
+    pub fn scanline_mode_counts_every_341_dots() {
+        let mut irq = VrcIrq::new();
+        irq.write_latch_low(0x0F);
+        irq.write_latch_high(0x0F);
+        irq.write_control(0x02);
+        for _ in 0 .. 113 {
+            irq.clock();
+        }
+        assert!(!irq.pending());
+        irq.clock();
+        assert!(irq.pending());
+    }
+
+    #[test]
+    pub fn acknowledge_restores_enable_flag() {
+        let mut irq = VrcIrq::new();
+        irq.write_latch(0xFF);
+        irq.write_control(0x06);
+        irq.clock();
+        assert!(irq.pending());
+        irq.acknowledge();
+        assert!(!irq.pending());
+        for _ in 0 .. 0x200 {
+            irq.clock();
+        }
+        assert!(!irq.pending());
+    }
+}
@@ -0,0 +1,92 @@
+use std::any::Any;
+use std::cell::Cell;
+
+use hw::rp2C02;
+use systems::nes::input::InputDevice;
+
+/// Emulates the NES version of the Arkanoid "Vaus" paddle controller
+///
+/// Setting the strobe latches the position of the paddle's potentiometer, which is then shifted
+/// out on D4, most significant bit first and inverted. The fire button is reported on D3. The
+/// range of positions the original controller produces is roughly $62 to $F2.
+pub struct Arkanoid {
+    position: u8,
+    fire: bool,
+    strobe: bool,
+    shift: Cell<u8>
+}
+
+impl Arkanoid {
+    /// Creates a new `Arkanoid` controller in the centre of its range, with the button released
+    pub fn new() -> Arkanoid {
+        Arkanoid {
+            position: 0xAA,
+            fire: false,
+            strobe: false,
+            shift: Cell::new(0)
+        }
+    }
+
+    /// Sets the position of the paddle
+    pub fn set_position(&mut self, position: u8) {
+        self.position = position;
+        if self.strobe {
+            self.shift.set(!position);
+        }
+    }
+
+    /// Presses or releases the fire button
+    pub fn set_fire(&mut self, pressed: bool) {
+        self.fire = pressed;
+    }
+}
+
+impl InputDevice for Arkanoid {
+    fn name(&self) -> &'static str { "Arkanoid" }
+
+    fn write(&mut self, val: u8) {
+        self.strobe = val & 0x01 != 0;
+        if self.strobe {
+            self.shift.set(!self.position);
+        }
+    }
+
+    fn read(&self, _ppu: &rp2C02::Rp2C02) -> u8 {
+        let shift = self.shift.get();
+        if !self.strobe {
+            self.shift.set(shift << 1);
+        }
+        let data = if shift & 0x80 != 0 { 0x10 } else { 0x00 };
+        let fire = if self.fire { 0x08 } else { 0x00 };
+        data | fire
+    }
+
+    fn as_any(&self) -> &Any { self }
+    fn as_any_mut(&mut self) -> &mut Any { self }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::rp2C02;
+    use systems::nes::input::{Arkanoid,InputDevice};
+
+    #[test]
+    pub fn position_is_shifted_out_inverted_msb_first() {
+        let ppu = rp2C02::Rp2C02::new(None);
+        let mut paddle = Arkanoid::new();
+        paddle.set_position(0xA5);
+        paddle.write(1);
+        paddle.write(0);
+
+        let position = (0 .. 8).fold(0, |acc, _| (acc << 1) | ((paddle.read(&ppu) >> 4) & 0x01));
+        assert_eq!(!0xA5u8, position);
+    }
+
+    #[test]
+    pub fn reports_fire_button_on_d3() {
+        let ppu = rp2C02::Rp2C02::new(None);
+        let mut paddle = Arkanoid::new();
+        paddle.set_fire(true);
+        assert_eq!(0x08, paddle.read(&ppu) & 0x08);
+    }
+}
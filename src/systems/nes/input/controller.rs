@@ -0,0 +1,119 @@
+use std::any::Any;
+use std::cell::Cell;
+
+use hw::rp2C02;
+use systems::nes::input::{Buttons,InputDevice};
+
+/// Emulates a standard NES controller
+///
+/// While the strobe bit (bit 0 of writes to $4016) is set, the controller continuously latches
+/// the state of its buttons in to an 8-bit shift register. Once it is cleared, each read shifts
+/// out one button on D0, in the order `A`, `B`, `SELECT`, `START`, `UP`, `DOWN`, `LEFT`, `RIGHT`.
+/// After all 8 buttons have been read, an official controller returns 1 on every read.
+pub struct Controller {
+    buttons: Buttons,
+    strobe: bool,
+    shift: Cell<u8>
+}
+
+impl Controller {
+    /// Creates a new `Controller` with no buttons pressed
+    pub fn new() -> Controller {
+        Controller {
+            buttons: Buttons::NONE(),
+            strobe: false,
+            shift: Cell::new(0)
+        }
+    }
+
+    /// Gets the buttons that are currently pressed
+    pub fn buttons(&self) -> Buttons {
+        self.buttons
+    }
+
+    /// Sets the buttons that are currently pressed
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.buttons = buttons;
+        if self.strobe {
+            self.shift.set(buttons.bits);
+        }
+    }
+}
+
+impl InputDevice for Controller {
+    fn name(&self) -> &'static str { "Controller" }
+
+    fn write(&mut self, val: u8) {
+        self.strobe = val & 0x01 != 0;
+        if self.strobe {
+            self.shift.set(self.buttons.bits);
+        }
+    }
+
+    fn read(&self, _ppu: &rp2C02::Rp2C02) -> u8 {
+        if self.strobe {
+            // The shift register is being reloaded constantly, so only A is ever reported
+            return self.buttons.bits & 0x01;
+        }
+
+        let shift = self.shift.get();
+        self.shift.set((shift >> 1) | 0x80);
+        shift & 0x01
+    }
+
+    fn as_any(&self) -> &Any { self }
+    fn as_any_mut(&mut self) -> &mut Any { self }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::rp2C02;
+    use systems::nes::input::{Buttons,Controller,InputDevice};
+
+    fn read_all(controller: &Controller) -> Vec<u8> {
+        let ppu = rp2C02::Rp2C02::new(None);
+        (0 .. 10).map(|_| controller.read(&ppu)).collect()
+    }
+
+    #[test]
+    pub fn reads_shift_out_buttons_after_strobe() {
+        let mut controller = Controller::new();
+        controller.set_buttons(Buttons::A() | Buttons::START() | Buttons::RIGHT());
+        controller.write(1);
+        controller.write(0);
+
+        assert_eq!(vec![1, 0, 0, 1, 0, 0, 0, 1, 1, 1], read_all(&controller));
+    }
+
+    #[test]
+    pub fn reads_report_a_while_strobe_is_set() {
+        let mut controller = Controller::new();
+        controller.set_buttons(Buttons::A());
+        controller.write(1);
+
+        assert_eq!(vec![1; 10], read_all(&controller));
+    }
+
+    #[test]
+    pub fn buttons_changed_after_strobe_is_cleared_are_not_reported() {
+        let mut controller = Controller::new();
+        controller.write(1);
+        controller.write(0);
+        controller.set_buttons(Buttons::A());
+
+        assert_eq!(0, read_all(&controller)[0]);
+    }
+
+    #[test]
+    pub fn strobe_reloads_shift_register() {
+        let mut controller = Controller::new();
+        controller.set_buttons(Buttons::B());
+        controller.write(1);
+        controller.write(0);
+        read_all(&controller);
+        controller.write(1);
+        controller.write(0);
+
+        assert_eq!(vec![0, 1], read_all(&controller)[0 .. 2].to_vec());
+    }
+}
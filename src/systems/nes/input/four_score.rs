@@ -0,0 +1,123 @@
+use std::any::Any;
+use std::cell::Cell;
+
+use hw::rp2C02;
+use systems::nes::input::{Buttons,InputDevice,Port};
+
+/// Emulates one port of the NES Four Score multitap
+///
+/// The Four Score plugs in to both ports, so one `FourScore` should be connected to each port.
+/// Port one reports players 1 and 3 and port two reports players 2 and 4. After the 8 buttons of
+/// each controller, a port shifts out an 8-bit signature identifying the Four Score, and then
+/// returns 1 on every read.
+pub struct FourScore {
+    port: Port,
+    buttons: [Buttons; 2],
+    strobe: bool,
+    shift: Cell<u32>
+}
+
+impl FourScore {
+    /// Creates a new `FourScore` for the provided port, with no buttons pressed
+    pub fn new(port: Port) -> FourScore {
+        FourScore {
+            port: port,
+            buttons: [Buttons::NONE(); 2],
+            strobe: false,
+            shift: Cell::new(0)
+        }
+    }
+
+    /// Sets the buttons pressed on one of the controllers attached to this port
+    ///
+    /// `player` is 1 or 3 for port one, and 2 or 4 for port two
+    pub fn set_buttons(&mut self, player: usize, buttons: Buttons) {
+        let index = match (self.port, player) {
+            (Port::One, 1) | (Port::Two, 2) => 0,
+            (Port::One, 3) | (Port::Two, 4) => 1,
+            _ => panic!("player {} is not attached to port {:?}", player, self.port)
+        };
+        self.buttons[index] = buttons;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    /// Gets the signature, in the order it is shifted out (first read in the lowest bit)
+    ///
+    /// Games usually collect it most significant bit first, which gives the $10 and $20 values
+    /// the signature is better known by
+    fn signature(&self) -> u32 {
+        match self.port {
+            Port::One => 0x08,
+            Port::Two => 0x04
+        }
+    }
+
+    fn reload(&self) {
+        self.shift.set(
+            (self.buttons[0].bits as u32) |
+            ((self.buttons[1].bits as u32) << 8) |
+            (self.signature() << 16) |
+            0xFF000000);
+    }
+}
+
+impl InputDevice for FourScore {
+    fn name(&self) -> &'static str { "Four Score" }
+
+    fn write(&mut self, val: u8) {
+        self.strobe = val & 0x01 != 0;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    fn read(&self, _ppu: &rp2C02::Rp2C02) -> u8 {
+        if self.strobe {
+            return self.buttons[0].bits & 0x01;
+        }
+
+        let shift = self.shift.get();
+        self.shift.set((shift >> 1) | 0x80000000);
+        (shift & 0x01) as u8
+    }
+
+    fn as_any(&self) -> &Any { self }
+    fn as_any_mut(&mut self) -> &mut Any { self }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::rp2C02;
+    use systems::nes::input::{Buttons,FourScore,InputDevice,Port};
+
+    fn read_bytes(device: &FourScore) -> Vec<u8> {
+        let ppu = rp2C02::Rp2C02::new(None);
+        (0 .. 4).map(|_| {
+            (0 .. 8).fold(0, |acc, bit| acc | (device.read(&ppu) << bit))
+        }).collect()
+    }
+
+    #[test]
+    pub fn port_one_reports_players_1_and_3_then_signature() {
+        let mut four_score = FourScore::new(Port::One);
+        four_score.set_buttons(1, Buttons::A());
+        four_score.set_buttons(3, Buttons::START());
+        four_score.write(1);
+        four_score.write(0);
+
+        assert_eq!(vec![0x01, 0x08, 0x08, 0xFF], read_bytes(&four_score));
+    }
+
+    #[test]
+    pub fn port_two_reports_players_2_and_4_then_signature() {
+        let mut four_score = FourScore::new(Port::Two);
+        four_score.set_buttons(2, Buttons::B());
+        four_score.set_buttons(4, Buttons::RIGHT());
+        four_score.write(1);
+        four_score.write(0);
+
+        assert_eq!(vec![0x02, 0x80, 0x04, 0xFF], read_bytes(&four_score));
+    }
+}
@@ -0,0 +1,111 @@
+use std::{fmt,ops};
+use std::any::Any;
+
+use hw::rp2C02;
+
+pub use self::controller::Controller;
+pub use self::four_score::FourScore;
+pub use self::zapper::Zapper;
+pub use self::arkanoid::Arkanoid;
+pub use self::power_pad::{PowerPad,PowerPadSide};
+
+mod controller;
+mod four_score;
+mod zapper;
+mod arkanoid;
+mod power_pad;
+
+/// Identifies one of the two controller ports on the front of the NES
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum Port {
+    /// The port for player 1, read through $4016
+    One,
+
+    /// The port for player 2, read through $4017
+    Two
+}
+
+/// Represents a device that can be plugged in to one of the controller ports
+///
+/// All devices attached to the NES share the strobe line driven by bit 0 of writes to $4016, and
+/// each port reports data on lines D0-D4 of reads from $4016 or $4017. The upper bits of those
+/// reads are open bus and are provided by the memory map.
+pub trait InputDevice {
+    fn name(&self) -> &'static str;
+
+    /// Handles a write to $4016
+    fn write(&mut self, val: u8);
+
+    /// Handles a read from the port's data register, returning the values of data lines D0-D4
+    ///
+    /// The PPU is provided for devices that sense the picture, such as the Zapper. This takes a
+    /// shared reference because it is called from `mem::Memory::get_u8`, so devices that shift
+    /// out data on each read must keep that state in a `Cell`.
+    fn read(&self, ppu: &rp2C02::Rp2C02) -> u8;
+
+    /// Gets the device as an `Any`, so it can be downcast to the concrete device type
+    fn as_any(&self) -> &Any;
+
+    /// Gets the device as a mutable `Any`, so it can be downcast to the concrete device type
+    fn as_any_mut(&mut self) -> &mut Any;
+}
+
+/// Creates the devices to plug in to each port for a NES 2.0 "default expansion device" value
+///
+/// Returns `None` if the value is unspecified, or identifies a device that isn't supported
+pub fn default_devices(id: u8) -> Option<(Box<InputDevice>, Box<InputDevice>)> {
+    match id {
+        0x01 => Some((Box::new(Controller::new()), Box::new(Controller::new()))),
+        0x02 => Some((Box::new(FourScore::new(Port::One)), Box::new(FourScore::new(Port::Two)))),
+        0x08 => Some((Box::new(Controller::new()), Box::new(Zapper::new()))),
+        0x09 => Some((Box::new(Zapper::new()), Box::new(Zapper::new()))),
+        0x0B => Some((Box::new(Controller::new()), Box::new(PowerPad::new(PowerPadSide::A)))),
+        0x0C => Some((Box::new(Controller::new()), Box::new(PowerPad::new(PowerPadSide::B)))),
+        0x0F => Some((Box::new(Controller::new()), Box::new(Arkanoid::new()))),
+        _ => None
+    }
+}
+
+/// Represents the state of the buttons on a standard NES controller
+///
+/// The bits are in the order the controller reports them, with `A` in bit 0 and `RIGHT` in bit 7
+#[derive(Copy,Clone,Eq,PartialEq)]
+pub struct Buttons {
+    pub bits: u8
+}
+
+impl Buttons {
+    #[inline] #[allow(non_snake_case)] pub fn A() -> Buttons         { Buttons::new(0b00000001) }
+    #[inline] #[allow(non_snake_case)] pub fn B() -> Buttons         { Buttons::new(0b00000010) }
+    #[inline] #[allow(non_snake_case)] pub fn SELECT() -> Buttons    { Buttons::new(0b00000100) }
+    #[inline] #[allow(non_snake_case)] pub fn START() -> Buttons     { Buttons::new(0b00001000) }
+    #[inline] #[allow(non_snake_case)] pub fn UP() -> Buttons        { Buttons::new(0b00010000) }
+    #[inline] #[allow(non_snake_case)] pub fn DOWN() -> Buttons      { Buttons::new(0b00100000) }
+    #[inline] #[allow(non_snake_case)] pub fn LEFT() -> Buttons      { Buttons::new(0b01000000) }
+    #[inline] #[allow(non_snake_case)] pub fn RIGHT() -> Buttons     { Buttons::new(0b10000000) }
+    #[inline] #[allow(non_snake_case)] pub fn NONE() -> Buttons      { Buttons::new(0b00000000) }
+
+    /// Creates a new `Buttons` structure from the provided 8-bit value
+    pub fn new(bits: u8) -> Buttons {
+        Buttons { bits: bits }
+    }
+
+    /// Returns a value indicating if all of the specified buttons are pressed
+    pub fn contains(&self, other: Buttons) -> bool {
+        self.bits & other.bits == other.bits
+    }
+}
+
+impl ops::BitOr for Buttons {
+    type Output = Buttons;
+
+    fn bitor(self, rhs: Buttons) -> Buttons {
+        Buttons::new(self.bits | rhs.bits)
+    }
+}
+
+impl fmt::Debug for Buttons {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Buttons(0b{:08b})", self.bits)
+    }
+}
@@ -0,0 +1,136 @@
+use std::any::Any;
+use std::cell::Cell;
+
+use hw::rp2C02;
+use systems::nes::input::InputDevice;
+
+/// The order the buttons (numbered 1-12) are shifted out on D3
+const D3_ORDER: [usize; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+
+/// The order the buttons (numbered 1-12) are shifted out on D4
+const D4_ORDER: [usize; 4] = [4, 3, 12, 8];
+
+/// The side B button (numbered 1-12) under each of the buttons on side A (numbered 1-8)
+const SIDE_A_BUTTONS: [usize; 8] = [3, 2, 8, 7, 6, 5, 11, 10];
+
+/// Identifies which side of the Power Pad mat is face up
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum PowerPadSide {
+    /// Side A, with 8 buttons numbered 1-8 over the middle of the mat
+    A,
+
+    /// Side B, with all 12 buttons numbered 1-12
+    B
+}
+
+/// Emulates the Power Pad floor mat
+///
+/// The Power Pad has 12 buttons, numbered 1-12 as they are on side B of the mat. Side A is the
+/// same mat flipped over, so its 8 buttons are mirrored left to right on to the side B buttons.
+/// Setting the strobe latches the buttons in to two shift registers, 8 buttons shifted out on D3
+/// and 4 on D4. Pressed buttons read as 1, and each register returns 1 once it has been emptied.
+pub struct PowerPad {
+    side: PowerPadSide,
+    buttons: u16,
+    strobe: bool,
+    shift: Cell<(u8, u8)>
+}
+
+impl PowerPad {
+    /// Creates a new `PowerPad` with the provided side face up, and no buttons pressed
+    pub fn new(side: PowerPadSide) -> PowerPad {
+        PowerPad {
+            side: side,
+            buttons: 0,
+            strobe: false,
+            shift: Cell::new((0, 0))
+        }
+    }
+
+    /// Presses or releases one of the buttons, numbered as they are on the side that is face up
+    pub fn set_button(&mut self, button: usize, pressed: bool) {
+        let count = if self.side == PowerPadSide::A { SIDE_A_BUTTONS.len() } else { 12 };
+        if button < 1 || button > count {
+            panic!("side {:?} of the Power Pad has no button {}", self.side, button);
+        }
+        let button = if self.side == PowerPadSide::A { SIDE_A_BUTTONS[button - 1] } else { button };
+        let mask = 1 << (button - 1);
+        if pressed {
+            self.buttons |= mask;
+        } else {
+            self.buttons &= !mask;
+        }
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    fn pressed(&self, button: usize) -> bool {
+        self.buttons & (1 << (button - 1)) != 0
+    }
+
+    fn reload(&self) {
+        let d3 = D3_ORDER.iter().enumerate()
+            .fold(0u8, |acc, (i, b)| if self.pressed(*b) { acc | (1 << i) } else { acc });
+        let d4 = D4_ORDER.iter().enumerate()
+            .fold(0xF0u8, |acc, (i, b)| if self.pressed(*b) { acc | (1 << i) } else { acc });
+        self.shift.set((d3, d4));
+    }
+}
+
+impl InputDevice for PowerPad {
+    fn name(&self) -> &'static str { "Power Pad" }
+
+    fn write(&mut self, val: u8) {
+        self.strobe = val & 0x01 != 0;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    fn read(&self, _ppu: &rp2C02::Rp2C02) -> u8 {
+        let (d3, d4) = self.shift.get();
+        if !self.strobe {
+            self.shift.set(((d3 >> 1) | 0x80, (d4 >> 1) | 0x80));
+        }
+        ((d3 & 0x01) << 3) | ((d4 & 0x01) << 4)
+    }
+
+    fn as_any(&self) -> &Any { self }
+    fn as_any_mut(&mut self) -> &mut Any { self }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::rp2C02;
+    use systems::nes::input::{InputDevice,PowerPad,PowerPadSide};
+
+    #[test]
+    pub fn buttons_are_shifted_out_on_d3_and_d4() {
+        let ppu = rp2C02::Rp2C02::new(None);
+        let mut pad = PowerPad::new(PowerPadSide::B);
+        pad.set_button(1, true);
+        pad.set_button(3, true);
+        pad.write(1);
+        pad.write(0);
+
+        let reads: Vec<u8> = (0 .. 9).map(|_| pad.read(&ppu)).collect();
+
+        // Button 1 is the second bit on D3, button 3 is the second bit on D4
+        assert_eq!(vec![0x00, 0x18, 0x00, 0x00, 0x10, 0x10, 0x10, 0x10, 0x18], reads);
+    }
+
+    #[test]
+    pub fn side_a_buttons_are_mirrored_on_to_side_b() {
+        let ppu = rp2C02::Rp2C02::new(None);
+        let mut pad = PowerPad::new(PowerPadSide::A);
+        pad.set_button(1, true);
+        pad.write(1);
+        pad.write(0);
+
+        let reads: Vec<u8> = (0 .. 8).map(|_| pad.read(&ppu)).collect();
+
+        // Side A button 1 is over side B button 3, the second bit on D4
+        assert_eq!(vec![0x00, 0x10, 0x00, 0x00, 0x10, 0x10, 0x10, 0x10], reads);
+    }
+}
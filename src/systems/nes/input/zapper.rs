@@ -0,0 +1,112 @@
+use std::any::Any;
+
+use hw::rp2C02;
+use hw::rp2C02::ppu::{BYTES_PER_PIXEL,PIXELS_PER_SCANLINE,SCANLINES_PER_FRAME};
+use systems::nes::input::InputDevice;
+
+/// The number of scanlines after the beam passes a pixel that the Zapper's photodiode still
+/// senses its light
+const LIGHT_SCANLINES: usize = 20;
+
+/// The minimum sum of the red, green and blue components that the Zapper senses as light
+const LIGHT_THRESHOLD: u16 = 0x1E0;
+
+/// Emulates the NES Zapper light gun
+///
+/// The Zapper reports the trigger on D4 (1 when pulled) and the light sensor on D3 (0 when light
+/// is detected). Light is detected when the pixel the Zapper is aimed at is bright and the PPU has
+/// drawn it within the last few scanlines, so games can find where the Zapper is aimed by
+/// flashing targets and timing the response.
+pub struct Zapper {
+    aim: Option<(usize, usize)>,
+    trigger: bool
+}
+
+impl Zapper {
+    /// Creates a new `Zapper` aimed away from the screen, with the trigger released
+    pub fn new() -> Zapper {
+        Zapper {
+            aim: None,
+            trigger: false
+        }
+    }
+
+    /// Aims the Zapper at the provided pixel, or away from the screen if `None`
+    pub fn aim(&mut self, target: Option<(usize, usize)>) {
+        self.aim = target;
+    }
+
+    /// Pulls or releases the trigger
+    pub fn set_trigger(&mut self, pulled: bool) {
+        self.trigger = pulled;
+    }
+
+    /// Returns a value indicating if the light sensor detects light from the provided PPU
+    pub fn senses_light(&self, ppu: &rp2C02::Rp2C02) -> bool {
+        let (x, y) = match self.aim {
+            Some((x, y)) if x < PIXELS_PER_SCANLINE && y < SCANLINES_PER_FRAME => (x, y),
+            _ => return false
+        };
+
+        // The beam must have drawn the pixel recently, so the light hasn't faded
+        let scanline = ppu.scanline();
+        let drawn = scanline > y || (scanline == y && ppu.dot() > x + 1);
+        if !drawn || scanline - y >= LIGHT_SCANLINES {
+            return false;
+        }
+
+        let offset = (y * PIXELS_PER_SCANLINE + x) * BYTES_PER_PIXEL;
+        let brightness = ppu.screen()[offset .. offset + BYTES_PER_PIXEL].iter()
+            .fold(0, |acc, c| acc + (*c as u16));
+        brightness >= LIGHT_THRESHOLD
+    }
+}
+
+impl InputDevice for Zapper {
+    fn name(&self) -> &'static str { "Zapper" }
+
+    fn write(&mut self, _val: u8) {
+        // The Zapper ignores the strobe
+    }
+
+    fn read(&self, ppu: &rp2C02::Rp2C02) -> u8 {
+        let light = if self.senses_light(ppu) { 0x00 } else { 0x08 };
+        let trigger = if self.trigger { 0x10 } else { 0x00 };
+        light | trigger
+    }
+
+    fn as_any(&self) -> &Any { self }
+    fn as_any_mut(&mut self) -> &mut Any { self }
+}
+
+#[cfg(test)]
+mod test {
+    use hw::rp2C02;
+    use systems::nes::input::{InputDevice,Zapper};
+
+    #[test]
+    pub fn reports_trigger_on_d4() {
+        let ppu = rp2C02::Rp2C02::new(None);
+        let mut zapper = Zapper::new();
+        assert_eq!(0x08, zapper.read(&ppu));
+        zapper.set_trigger(true);
+        assert_eq!(0x18, zapper.read(&ppu));
+    }
+
+    #[test]
+    pub fn does_not_sense_light_when_aimed_off_screen() {
+        let ppu = rp2C02::Rp2C02::new(None);
+        let mut zapper = Zapper::new();
+        zapper.aim(Some((300, 20)));
+        assert!(!zapper.senses_light(&ppu));
+    }
+
+    #[test]
+    pub fn does_not_sense_light_from_pixels_not_yet_drawn() {
+        // The PPU starts at the top of the screen, so nothing has been drawn yet
+        let ppu = rp2C02::Rp2C02::new(None);
+        let mut zapper = Zapper::new();
+        zapper.aim(Some((10, 10)));
+        assert!(!zapper.senses_light(&ppu));
+    }
+}
@@ -1,12 +1,27 @@
+use std::cell::{Cell,RefCell};
+
 use slog;
 
+use clock;
 use mem;
+use hw::expansion_audio::ExpansionAudio;
+use hw::rp2C02;
+use hw::rp2A03;
 use systems::nes;
+use systems::nes::input;
+use systems::nes::ppumap::PpuMemoryMap;
 
 /// Represents the memory map for a Nintendo Entertainment System
 pub struct MemoryMap {
     ram: mem::Fixed,
+    ciram: mem::Fixed,
+    ppu: rp2C02::Rp2C02,
+    apu: rp2A03::Apu,
+    pending_dma: Option<u8>,
+    ports: [Box<input::InputDevice>; 2],
+    port_read: Option<(usize, u64)>,
     cart: Option<nes::Cartridge>,
+    cart_cycle: Option<u64>,
     log: slog::Logger,
     memlog: slog::Logger
 }
@@ -18,12 +33,145 @@ impl MemoryMap {
         let memlog = log.new(o!("cartridge" => false));
         MemoryMap {
             ram: mem::Fixed::new(0x0800),
+            // The NES itself only has 2KB of nametable RAM, the other 2KB are only used by
+            // cartridges that provide four-screen mirroring
+            ciram: mem::Fixed::new(0x1000),
+            ppu: rp2C02::Rp2C02::new(Some(log.clone())),
+            apu: rp2A03::Apu::new(Some(log.clone())),
+            pending_dma: None,
+            ports: [Box::new(input::Controller::new()), Box::new(input::Controller::new())],
+            port_read: None,
             cart: None,
+            cart_cycle: None,
             log: log,
             memlog: memlog
         }
     }
 
+    /// Gets a reference to the PPU
+    pub fn ppu(&self) -> &rp2C02::Rp2C02 {
+        &self.ppu
+    }
+
+    /// Gets a mutable reference to the PPU
+    pub fn ppu_mut(&mut self) -> &mut rp2C02::Rp2C02 {
+        &mut self.ppu
+    }
+
+    /// Gets a reference to the APU
+    pub fn apu(&self) -> &rp2A03::Apu {
+        &self.apu
+    }
+
+    /// Gets a mutable reference to the APU
+    pub fn apu_mut(&mut self) -> &mut rp2A03::Apu {
+        &mut self.apu
+    }
+
+    /// Runs the APU until it has caught up with the provided CPU cycle, performing any DMC DMA
+    /// reads it requires
+    ///
+    /// Returns the number of cycles the CPU was stalled by the DMA reads. The caller is responsible
+    /// for advancing the CPU clock by that amount.
+    pub fn step_apu(&mut self, cpu_cycle: u64) -> mem::Result<u64> {
+        rp2A03::step_with_dma(self, cpu_cycle)
+    }
+
+    /// Runs the APU as `step_apu` does, when the CPU is about to read `addr` on the provided cycle
+    ///
+    /// If a DMC DMA halts the CPU on the cycle it reads one of the controller ports, the device
+    /// sees the halted read as well as the CPU's read once the DMA completes, and skips a bit.
+    pub fn step_apu_before_read(&mut self, addr: u16, cpu_cycle: u64) -> mem::Result<u64> {
+        self.port_read = match addr {
+            0x4016 | 0x4017 => Some(((addr - 0x4016) as usize, cpu_cycle)),
+            _ => None
+        };
+        let stall = self.step_apu(cpu_cycle);
+        self.port_read = None;
+        stall
+    }
+
+    /// Gets the nametable mirroring currently in effect
+    ///
+    /// Without a cartridge, vertical mirroring is used
+    pub fn mirroring(&self) -> nes::Mirroring {
+        match self.cart {
+            Some(ref cart) => cart.mirroring(),
+            None => nes::Mirroring::Vertical
+        }
+    }
+
+    /// Runs the PPU until it has caught up with the provided CPU cycle
+    pub fn step_ppu(&mut self, cpu_cycle: u64) -> rp2C02::Result<()> {
+        let mirroring = self.mirroring();
+        let mut bus = PpuMemoryMap::new(self.cart.as_mut(), &mut self.ciram, mirroring);
+        self.ppu.step(cpu_cycle, &mut bus)
+    }
+
+    /// Checks if the cartridge is asserting the CPU's IRQ line
+    pub fn cart_irq(&self) -> bool {
+        self.cart.as_ref().map_or(false, |c| c.irq())
+    }
+
+    /// Clocks the cartridge until it has caught up with the provided CPU cycle
+    ///
+    /// A newly loaded cartridge starts counting from the first cycle it is stepped to
+    pub fn step_cart(&mut self, cpu_cycle: u64) {
+        let start = self.cart_cycle.unwrap_or(cpu_cycle);
+        if let Some(ref mut cart) = self.cart {
+            for _ in start .. cpu_cycle {
+                cart.mapper.clock_cpu();
+            }
+        }
+        self.cart_cycle = Some(cpu_cycle);
+    }
+
+    /// Plugs the provided device in to the provided port, replacing the device plugged in to it
+    pub fn connect(&mut self, port: input::Port, device: Box<input::InputDevice>) {
+        info!(self.log,
+            "port" => format!("{:?}", port),
+            "device" => device.name();
+            "connected {} to port {:?}", device.name(), port);
+        match port {
+            input::Port::One => self.ports[0] = device,
+            input::Port::Two => self.ports[1] = device
+        }
+    }
+
+    /// Gets a reference to the device plugged in to the provided port
+    pub fn device(&self, port: input::Port) -> &input::InputDevice {
+        match port {
+            input::Port::One => &*self.ports[0],
+            input::Port::Two => &*self.ports[1]
+        }
+    }
+
+    /// Gets a mutable reference to the device plugged in to the provided port
+    pub fn device_mut(&mut self, port: input::Port) -> &mut input::InputDevice {
+        match port {
+            input::Port::One => &mut *self.ports[0],
+            input::Port::Two => &mut *self.ports[1]
+        }
+    }
+
+    /// Returns the page requested by a write to the OAM DMA register ($4014), if one has been
+    /// written since the last call, and clears the request
+    pub fn take_dma(&mut self) -> Option<u8> {
+        self.pending_dma.take()
+    }
+
+    /// Copies the 256 bytes in CPU page `page` in to the PPU's OAM, starting at the current OAMADDR
+    ///
+    /// The caller is responsible for suspending the CPU for the duration of the transfer
+    pub fn oam_dma(&mut self, page: u8) -> mem::Result<()> {
+        use mem::Memory;
+        let base = (page as u16) << 8;
+        for offset in 0 .. 0x100 {
+            let val = try!(self.get_u8(base | offset));
+            self.ppu.write_oam(val);
+        }
+        Ok(())
+    }
 
     /// Loads the provided cartridge into the `MemoryMap`, releasing the cartridge previously
     /// loaded, if any
@@ -32,10 +180,11 @@ impl MemoryMap {
             "mapper" => cart.mapper.name();
             "Loaded {} cartridge", cart.mapper.name());
         self.cart = Some(cart);
+        self.cart_cycle = None;
     }
 
-    /// Releases the cartridge currently loaded, if any
-    pub fn eject(&mut self) {
+    /// Releases and returns the cartridge currently loaded
+    pub fn eject(&mut self) -> nes::Cartridge {
         let old_cart = self.cart.take();
         if old_cart.is_none() {
             panic!("Can't eject cartridge, there is no cartridge loaded!");
@@ -45,13 +194,125 @@ impl MemoryMap {
         info!(self.log,
             "mapper" => old_cart.mapper.name();
             "Ejecting {} cartridge", old_cart.mapper.name());
+        old_cart
+    }
+
+    /// Gets the cartridge currently loaded, if any
+    pub fn cartridge(&self) -> Option<&nes::Cartridge> {
+        self.cart.as_ref()
+    }
+}
+
+/// Represents the memory map as seen by the CPU while it executes an instruction
+///
+/// The CPU runs a whole instruction at a time, and the PPU is normally only caught up once the
+/// instruction has finished. Reads and writes of the PPU's registers ($2000-$3FFF) would see the
+/// PPU as it was before the instruction started, so the PPU is first run until it has caught up
+/// with the CPU's clock.
+///
+/// The CPU's clock is advanced by the whole instruction before it accesses memory, so the PPU is
+/// caught up to the end of the instruction. Loads and stores access their operand on their last
+/// cycle, so they see the PPU as it is on the cycle of the access, but the accesses made by
+/// read-modify-write instructions see it up to two cycles late.
+///
+/// The APU is likewise run until it has caught up with the last cycle of the instruction before
+/// each access, so a DMC DMA that lands on the cycle a controller port is read conflicts with the
+/// read. The cycles the DMA reads stall the CPU for are added to the cycles of the accesses after
+/// them, and must be added to the CPU's clock once the instruction has finished.
+///
+/// The CPU reads memory through a shared reference, so the memory map is kept in a `RefCell` to
+/// allow the PPU and APU to be run before memory is read.
+pub struct CpuBus<'a> {
+    map: RefCell<&'a mut MemoryMap>,
+    clock: clock::ClockReader,
+    stall: Cell<u64>
+}
+
+impl<'a> CpuBus<'a> {
+    /// Constructs a new `CpuBus` over the provided memory map, for the CPU with the provided clock
+    pub fn new(map: &'a mut MemoryMap, clock: clock::ClockReader) -> CpuBus<'a> {
+        CpuBus {
+            map: RefCell::new(map),
+            clock: clock,
+            stall: Cell::new(0)
+        }
+    }
+
+    /// Gets the number of cycles the CPU has been stalled for by DMC DMA reads
+    ///
+    /// The caller is responsible for advancing the CPU clock by that amount.
+    pub fn stall(&self) -> u64 {
+        self.stall.get()
+    }
+
+    /// Gets the CPU cycle the instruction ends on, including any stall
+    fn cycle(&self) -> u64 {
+        self.clock.get() + self.stall.get()
+    }
+
+    /// Runs the APU until it has caught up with the cycle of an access to `addr`, performing any
+    /// DMC DMA reads it requires
+    fn step_apu(&self, addr: u16, read: bool) -> mem::Result<()> {
+        // The access is on the last cycle of the instruction
+        let cycle = self.cycle().saturating_sub(1);
+        let mut map = self.map.borrow_mut();
+        let stall = if read {
+            try!(map.step_apu_before_read(addr, cycle))
+        } else {
+            try!(map.step_apu(cycle))
+        };
+        self.stall.set(self.stall.get() + stall);
+        Ok(())
+    }
+
+    /// Runs the PPU until it has caught up with the CPU, if `addr` is one of the PPU's registers
+    fn step_ppu(&self, addr: u16) -> mem::Result<()> {
+        if addr < 0x2000 || addr >= 0x4000 {
+            return Ok(());
+        }
+        match self.map.borrow_mut().step_ppu(self.cycle()) {
+            Ok(()) => Ok(()),
+            Err(rp2C02::Error::ErrorAccessingMemory(e)) => Err(e)
+        }
+    }
+}
+
+impl<'a> mem::Memory<u16> for CpuBus<'a> {
+    fn len(&self) -> u64 { 0x10000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        try!(self.step_apu(addr, true));
+        try!(self.step_ppu(addr));
+        self.map.borrow().get_u8(addr)
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        try!(self.step_apu(addr, false));
+        try!(self.step_ppu(addr));
+        self.map.get_mut().set_u8(addr, val)
     }
 }
 
-impl mem::Memory for MemoryMap {
-    fn len(&self) -> u64 { 0xFFFF }
+impl rp2A03::DmcBus for MemoryMap {
+    fn apu_and_expansion(&mut self) -> (&mut rp2A03::Apu, Option<&mut ExpansionAudio>) {
+        (&mut self.apu, self.cart.as_mut().and_then(|c| c.expansion_audio()))
+    }
 
-    fn get_u8(&self, addr: u64) -> mem::Result<u8> {
+    /// Reads the controller port the CPU is reading on the cycle the DMA halted it, if any
+    fn dmc_dma_halted(&mut self, cpu_cycle: u64) {
+        if let Some((port, cycle)) = self.port_read {
+            if cycle == cpu_cycle {
+                trace!(self.log, "port" => port; "dmc dma conflicts with controller read");
+                self.ports[port].read(&self.ppu);
+            }
+        }
+    }
+}
+
+impl mem::Memory<u16> for MemoryMap {
+    fn len(&self) -> u64 { 0x10000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
         if addr < 0x2000 {
             let eaddr = addr % 0x0800;
             trace!(self.memlog,
@@ -70,8 +331,7 @@ impl mem::Memory for MemoryMap {
                 "paddr" => format!("${:04X}", eaddr),
                 "target" => "PPU",
                 "action" => "read");
-            // Todo: Do something!
-            Ok(0)
+            Ok(self.ppu.read_register(rp2C02::Register::from_offset(eaddr)))
         }
         else if addr < 0x4200 {
             let eaddr = addr - 0x4000;
@@ -81,8 +341,17 @@ impl mem::Memory for MemoryMap {
                 "paddr" => format!("${:04X}", eaddr),
                 "target" => "APU/IO",
                 "action" => "read");
-            // Todo: Do something!
-            Ok(0)
+            match addr {
+                // The upper bits are open bus, left over from the high byte of the address
+                0x4016 | 0x4017 => {
+                    let port = (addr - 0x4016) as usize;
+                    Ok(0x40 | (self.ports[port].read(&self.ppu) & 0x1F))
+                },
+                0x4015 => Ok(self.apu.read_status()),
+
+                // The remaining registers are write-only
+                _ => Ok(0)
+            }
         } else {
             match self.cart {
                 None => {
@@ -104,7 +373,7 @@ impl mem::Memory for MemoryMap {
         }
     }
 
-    fn set_u8(&mut self, addr: u64, val: u8) -> mem::Result<()> {
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
         if addr < 0x2000 {
             let eaddr = addr % 0x0800;
             trace!(self.memlog,
@@ -123,8 +392,19 @@ impl mem::Memory for MemoryMap {
                 "paddr" => format!("${:04X}", eaddr),
                 "target" => "PPU",
                 "action" => "write");
-            // Todo: Do something!
-            Ok(())
+            let reg = rp2C02::Register::from_offset(eaddr);
+            let mirroring = self.mirroring();
+            let result = {
+                let mut bus = PpuMemoryMap::new(self.cart.as_mut(), &mut self.ciram, mirroring);
+                self.ppu.write_register(reg, val, &mut bus)
+            };
+            if let Some(ref mut cart) = self.cart {
+                cart.mapper.ppu_register_written(reg, val);
+            }
+            match result {
+                Ok(()) => Ok(()),
+                Err(rp2C02::Error::ErrorAccessingMemory(e)) => Err(e)
+            }
         }
         else if addr < 0x4200 {
             let eaddr = addr - 0x4000;
@@ -134,7 +414,16 @@ impl mem::Memory for MemoryMap {
                 "paddr" => format!("${:04X}", eaddr),
                 "target" => "APU/IO",
                 "action" => "write");
-            // Todo: Do something!
+            match addr {
+                0x4014 => self.pending_dma = Some(val),
+                0x4016 => {
+                    // Both ports share the strobe line
+                    self.ports[0].write(val);
+                    self.ports[1].write(val);
+                },
+                0x4000 ... 0x4013 | 0x4015 | 0x4017 => self.apu.write_register(addr, val),
+                _ => {}
+            }
             Ok(())
         } else {
             match self.cart {
@@ -157,3 +446,145 @@ impl mem::Memory for MemoryMap {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use clock;
+    use mem::{self,Memory};
+    use hw::mos6502::{self,Instruction,Mos6502,Operand};
+    use systems::nes::{self,Cartridge,Mapper};
+    use systems::nes::input::{Buttons,Controller,Port};
+    use systems::nes::memmap::{CpuBus,MemoryMap};
+
+    /// A mapper that asserts IRQ once it has been clocked 10 times
+    struct CountingMapper {
+        mem: mem::Empty,
+        clocks: u64
+    }
+
+    impl Mapper for CountingMapper {
+        fn name(&self) -> &'static str { "Counting" }
+        fn prg(&self) -> &mem::Memory<u16> { &self.mem }
+        fn prg_mut(&mut self) -> &mut mem::Memory<u16> { &mut self.mem }
+        fn chr(&self) -> &mem::Memory<u16> { &self.mem }
+        fn chr_mut(&mut self) -> &mut mem::Memory<u16> { &mut self.mem }
+        fn clock_cpu(&mut self) { self.clocks += 1; }
+        fn irq(&self) -> bool { self.clocks >= 10 }
+    }
+
+    #[test]
+    pub fn cartridge_is_clocked_from_when_it_is_loaded() {
+        let mut image = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        image.extend(vec![0; 0x4000]);
+        let rom = nes::load_rom(&mut &image[..]).unwrap();
+        let mapper = CountingMapper { mem: mem::Empty, clocks: 0 };
+
+        let mut mem = MemoryMap::new(None);
+        mem.load(Cartridge::new(rom.header, Box::new(mapper)));
+        mem.step_cart(100);
+        mem.step_cart(109);
+        assert!(!mem.cart_irq());
+        mem.step_cart(110);
+        assert!(mem.cart_irq());
+    }
+
+    #[test]
+    pub fn cpu_bus_catches_ppu_up_before_register_access() {
+        let mut mem = MemoryMap::new(None);
+        let mut clock = clock::Clock::new();
+        clock.set(100);
+
+        CpuBus::new(&mut mem, clock.reader()).get_u8(0x0000).unwrap();
+        assert_eq!(0, mem.ppu().cycles());
+        CpuBus::new(&mut mem, clock.reader()).get_u8(0x2002).unwrap();
+        assert_eq!(300, mem.ppu().cycles());
+
+        clock.tick(10);
+        CpuBus::new(&mut mem, clock.reader()).set_u8(0x2005, 0).unwrap();
+        assert_eq!(330, mem.ppu().cycles());
+    }
+
+    #[test]
+    pub fn ppu_register_reads_see_the_ppu_at_the_end_of_the_instruction() {
+        // Vertical blank starts on the first dot of CPU cycle 27394. An LDA $2002 reads on its
+        // last cycle, so it only sees the flag if that is cycle 27394 or later.
+        for &(start, vblank) in [(27390, false), (27391, true)].iter() {
+            let mut mem = MemoryMap::new(None);
+            let mut cpu = Mos6502::new();
+            cpu.clock.set(start);
+            {
+                let mut bus = CpuBus::new(&mut mem, cpu.clock.reader());
+                mos6502::dispatch(Instruction::LDA(Operand::Absolute(0x2002)), &mut cpu, &mut bus, None).unwrap();
+            }
+            assert_eq!(vblank, cpu.registers.a & 0x80 != 0);
+        }
+    }
+
+    #[test]
+    pub fn controller_registers_read_from_each_port() {
+        let mut mem = MemoryMap::new(None);
+        let mut one = Controller::new();
+        one.set_buttons(Buttons::A());
+        let mut two = Controller::new();
+        two.set_buttons(Buttons::B());
+        mem.connect(Port::One, Box::new(one));
+        mem.connect(Port::Two, Box::new(two));
+        mem.set_u8(0x4016, 1).unwrap();
+        mem.set_u8(0x4016, 0).unwrap();
+
+        assert_eq!(0x41, mem.get_u8(0x4016).unwrap());
+        assert_eq!(0x40, mem.get_u8(0x4017).unwrap());
+        assert_eq!(0x40, mem.get_u8(0x4016).unwrap());
+        assert_eq!(0x41, mem.get_u8(0x4017).unwrap());
+    }
+
+    #[test]
+    pub fn dmc_dma_on_a_controller_read_makes_the_controller_skip_a_bit() {
+        // Once enabled, the DMC needs its first byte on the next cycle the APU runs, and the DMA
+        // halts the CPU on the cycle after that. $4016 is read on the last cycle of the
+        // instruction, the cycle before it ends.
+        let tests = [(0, 2, 0x40, 3), (0, 3, 0x41, 3), (1, 3, 0x40, 4)];
+        for &(enabled, end, val, stall) in tests.iter() {
+            let mut image = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+            image.extend(vec![0; 0x4000]);
+            let mut mem = MemoryMap::new(None);
+            mem.load(Cartridge::load(nes::load_rom(&mut &image[..]).unwrap(), None).unwrap());
+            let mut controller = Controller::new();
+            controller.set_buttons(Buttons::A());
+            mem.connect(Port::One, Box::new(controller));
+            mem.set_u8(0x4016, 1).unwrap();
+            mem.set_u8(0x4016, 0).unwrap();
+            mem.step_apu(enabled).unwrap();
+            mem.set_u8(0x4015, 0x10).unwrap();
+
+            let mut clock = clock::Clock::new();
+            clock.set(end);
+            let bus = CpuBus::new(&mut mem, clock.reader());
+            assert_eq!(Ok(val), bus.get_u8(0x4016));
+            assert_eq!(stall, bus.stall());
+        }
+    }
+
+    #[test]
+    pub fn writing_oam_dma_register_requests_dma() {
+        let mut mem = MemoryMap::new(None);
+        mem.set_u8(0x4014, 0x02).unwrap();
+        assert_eq!(Some(0x02), mem.take_dma());
+        assert_eq!(None, mem.take_dma());
+    }
+
+    #[test]
+    pub fn oam_dma_copies_page_to_oam_starting_at_oamaddr() {
+        let mut mem = MemoryMap::new(None);
+        for i in 0 .. 0x100 {
+            mem.set_u8(0x0200 + i, i as u8).unwrap();
+        }
+        mem.set_u8(0x2003, 0x10).unwrap();
+        mem.oam_dma(0x02).unwrap();
+
+        // The copy wraps around OAM, leaving OAMADDR where it started
+        assert_eq!(0x00, mem.get_u8(0x2004).unwrap());
+        mem.set_u8(0x2003, 0x11).unwrap();
+        assert_eq!(0x01, mem.get_u8(0x2004).unwrap());
+    }
+}
@@ -1,4 +1,4 @@
-pub use self::cart::{Mapper,Cartridge};
+pub use self::cart::{Mapper,MapperRegistry,Mirroring,Cartridge};
 pub use self::rom::{Rom,RomHeader,load_rom};
 
 use slog;
@@ -7,7 +7,7 @@ use mem;
 use hw::mos6502::{self,exec};
 use hw::mos6502::instr::decoder;
 
-//use hw::rp2C02;
+use hw::rp2C02;
 
 /// Contains code to load and manipulate ROMs in the iNES and NES 2.0 formats
 pub mod rom;
@@ -15,18 +15,22 @@ pub mod rom;
 /// Contains code to emulate cartridge hardware (Mappers, etc.)
 pub mod cart;
 
+/// Contains code to emulate input devices plugged in to the controller ports
+pub mod input;
+
 mod memmap;
+mod ppumap;
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
 pub struct Error {
     kind: ErrorKind,
-    address: u64,
+    address: u16,
     instruction: Option<mos6502::Instruction>
 }
 
 impl Error {
-    pub fn new(kind: ErrorKind, address: u64, instruction: Option<mos6502::Instruction>) -> Error {
+    pub fn new(kind: ErrorKind, address: u16, instruction: Option<mos6502::Instruction>) -> Error {
         Error {
             kind: kind,
             address: address,
@@ -48,7 +52,10 @@ impl ::std::fmt::Debug for Error {
 #[derive(Debug)]
 pub enum ErrorKind {
     InstructionDecodeError(decoder::Error),
-    ExecutionError(exec::Error)
+    ExecutionError(exec::Error),
+    PpuError(rp2C02::Error),
+    InterruptError(mem::Error),
+    DmaError(mem::Error)
 }
 
 /// Represents a complete NES system, including all necessary hardware and memory
@@ -82,31 +89,95 @@ impl Nes {
     pub fn reset(&mut self) -> mem::Result<()> {
         use mem::MemoryExt;
         let addr = try_log!(self.mem.get_u16::<::byteorder::LittleEndian>(0xFFFC), self.log);
-        self.cpu.pc.set(addr as u64);
+        self.cpu.pc.set(addr);
         Ok(())
     }
 
     /// Gets a mutable reference to the current memory
-    pub fn mem_mut(&mut self) -> &mut mem::Memory {
+    pub fn mem_mut(&mut self) -> &mut mem::Memory<u16> {
         &mut self.mem
     }
 
     /// Gets an immutable reference to the current memory
-    pub fn mem(&self) -> &mem::Memory {
+    pub fn mem(&self) -> &mem::Memory<u16> {
         &self.mem
     }
 
+    /// Plugs the provided device in to the provided controller port
+    pub fn connect(&mut self, port: input::Port, device: Box<input::InputDevice>) {
+        self.mem.connect(port, device);
+    }
+
+    /// Gets the device plugged in to the provided port, if it is a `D`
+    pub fn device<D>(&self, port: input::Port) -> Option<&D> where D: input::InputDevice + 'static {
+        self.mem.device(port).as_any().downcast_ref::<D>()
+    }
+
+    /// Gets the device plugged in to the provided port, if it is a `D`
+    pub fn device_mut<D>(&mut self, port: input::Port) -> Option<&mut D> where D: input::InputDevice + 'static {
+        self.mem.device_mut(port).as_any_mut().downcast_mut::<D>()
+    }
+
+    /// Sets the buttons currently pressed on the standard controller plugged in to the provided
+    /// port
+    ///
+    /// This has no effect if the device in the port is not a standard controller
+    pub fn set_buttons(&mut self, port: input::Port, buttons: input::Buttons) {
+        if let Some(controller) = self.device_mut::<input::Controller>(port) {
+            controller.set_buttons(buttons);
+        }
+    }
+
     /// Loads a cartridge into the NES
+    ///
+    /// If the ROM header names a supported default input device, it is connected as well
     pub fn load(&mut self, cart: Cartridge) {
+        if let Some((one, two)) = input::default_devices(cart.header().default_expansion_device) {
+            self.connect(input::Port::One, one);
+            self.connect(input::Port::Two, two);
+        }
         self.mem.load(cart);
     }
 
     /// Ejects the cartridge from the NES
-    pub fn eject(&mut self) {
-        self.mem.eject();
+    ///
+    /// The cartridge is returned so that its save data can be written with `Cartridge::save`
+    pub fn eject(&mut self) -> Cartridge {
+        self.mem.eject()
+    }
+
+    /// Gets the cartridge loaded in to the NES, if any
+    pub fn cartridge(&self) -> Option<&Cartridge> {
+        self.mem.cartridge()
+    }
+
+    /// Gets the most recently completed frame rendered by the PPU, as 8-bit RGB triples
+    pub fn screen(&self) -> &[u8] {
+        self.mem.ppu().screen()
     }
 
-    /// Runs a single frame of the system
+    /// Removes and returns the audio samples produced by the APU since the last call
+    ///
+    /// The samples are produced at the APU's sample rate, which is 44.1KHz unless changed through
+    /// `mem.apu_mut().set_sample_rate`
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.mem.apu_mut().take_samples()
+    }
+
+    /// Runs the system until the PPU has completed a frame
+    pub fn run_frame(&mut self) -> Result<()> {
+        let frame = self.mem.ppu().frame();
+        while self.mem.ppu().frame() == frame {
+            try!(self.step());
+        }
+        Ok(())
+    }
+
+    /// Runs a single instruction of the system, and runs the PPU until it has caught up
+    ///
+    /// The APU is also caught up during the instruction whenever it accesses memory, and the PPU
+    /// whenever it reads or writes one of the PPU's registers, though only to the cycle the
+    /// instruction ends on (see `memmap::CpuBus`)
     pub fn step(&mut self) -> Result<()> {
         // Fetch next instruction
         let addr = self.cpu.pc.get();
@@ -124,7 +195,13 @@ impl Nes {
             "instr" => instr,
             "cycle" => self.cpu.clock.get();
             "dispatching");
-        match mos6502::dispatch(instr, &mut self.cpu, &mut self.mem, Some(self.log.clone())) {
+        let (result, stall) = {
+            let mut bus = memmap::CpuBus::new(&mut self.mem, self.cpu.clock.reader());
+            let result = mos6502::dispatch(instr, &mut self.cpu, &mut bus, Some(self.log.clone()));
+            (result, bus.stall())
+        };
+        self.cpu.clock.tick(stall);
+        match result {
             Ok(_) => {},
             Err(e) => return Err(Error::new(
                 ErrorKind::ExecutionError(e),
@@ -137,9 +214,46 @@ impl Nes {
             "cycle" => self.cpu.clock.get();
             "dispatched");
 
+        // Perform any OAM DMA requested by the instruction. The CPU is suspended for 513 cycles,
+        // plus one more if the transfer started on an odd cycle.
+        if let Some(page) = self.mem.take_dma() {
+            trace!(self.log, "page" => page, "cycle" => self.cpu.clock.get(); "performing oam dma");
+            if let Err(e) = self.mem.oam_dma(page) {
+                return Err(Error::new(ErrorKind::DmaError(e), addr, Some(instr)));
+            }
+            let stall = if self.cpu.clock.get() % 2 == 1 { 514 } else { 513 };
+            self.cpu.clock.tick(stall);
+        }
+
+        // Run the APU as necessary. DMC DMA reads stall the CPU, so the APU must be run first.
+        match self.mem.step_apu(self.cpu.clock.get()) {
+            Ok(stall) => self.cpu.clock.tick(stall),
+            Err(e) => return Err(Error::new(ErrorKind::DmaError(e), addr, Some(instr)))
+        }
+
         // Run the PPU as necessary
-        //let cycles = self.cpu.clock.get();
-        //self.ppu.step(cycles);
+        let cycles = self.cpu.clock.get();
+        if let Err(e) = self.mem.step_ppu(cycles) {
+            return Err(Error::new(ErrorKind::PpuError(e), addr, Some(instr)));
+        }
+
+        // Run the cartridge, which may raise an IRQ
+        self.mem.step_cart(cycles);
+
+        // Deliver the vertical blank NMI, if the PPU requested one
+        if self.mem.ppu_mut().take_nmi() {
+            trace!(self.log, "cycle" => self.cpu.clock.get(); "servicing nmi");
+            if let Err(e) = self.cpu.nmi(&mut self.mem) {
+                return Err(Error::new(ErrorKind::InterruptError(e), addr, Some(instr)));
+            }
+        }
+        else if self.mem.apu().irq() || self.mem.cart_irq() {
+            match self.cpu.irq(&mut self.mem) {
+                Ok(true) => trace!(self.log, "cycle" => self.cpu.clock.get(); "serviced irq"),
+                Ok(false) => {},
+                Err(e) => return Err(Error::new(ErrorKind::InterruptError(e), addr, Some(instr)))
+            }
+        }
 
         Ok(())
     }
@@ -0,0 +1,177 @@
+use mem;
+use systems::nes;
+
+/// Represents the memory map seen by the PPU of a Nintendo Entertainment System
+///
+/// The PPU memory map is only needed while the PPU is running, so it borrows the memory it maps
+/// from the `MemoryMap` rather than owning it.
+///
+/// * $0000-$1FFF is mapped to the CHR banks of the cartridge
+/// * $2000-$2FFF is mapped to nametable RAM (CIRAM), arranged according to the mirroring, unless
+///   the cartridge provides the nametable itself
+/// * $3000-$3EFF mirrors $2000-$2EFF
+///
+/// Palette memory ($3F00-$3FFF) is internal to the PPU and is never accessed through this map.
+pub struct PpuMemoryMap<'a> {
+    cart: Option<&'a mut nes::Cartridge>,
+    ciram: &'a mut mem::Fixed,
+    mirroring: nes::Mirroring
+}
+
+impl<'a> PpuMemoryMap<'a> {
+    /// Constructs a new `PpuMemoryMap` over the provided cartridge and nametable RAM
+    ///
+    /// `ciram` must be large enough to hold every nametable used by `mirroring`, which is 4KB for
+    /// `Mirroring::FourScreen` and 2KB otherwise
+    pub fn new(cart: Option<&'a mut nes::Cartridge>, ciram: &'a mut mem::Fixed, mirroring: nes::Mirroring) -> PpuMemoryMap<'a> {
+        PpuMemoryMap {
+            cart: cart,
+            ciram: ciram,
+            mirroring: mirroring
+        }
+    }
+}
+
+impl<'a> mem::Memory<u16> for PpuMemoryMap<'a> {
+    fn len(&self) -> u64 { 0x4000 }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        let addr = addr & 0x3FFF;
+        if let Some(ref cart) = self.cart {
+            cart.mapper.ppu_address(addr);
+        }
+        if addr < 0x2000 {
+            match self.cart {
+                // Reads from missing CHR memory see an open bus
+                Some(ref cart) if cart.mapper.chr().len() > 0 => cart.mapper.chr().get_u8(addr),
+                _ => Ok(0)
+            }
+        } else {
+            if let Some(val) = self.cart.as_ref().and_then(|c| c.mapper.read_nametable(addr)) {
+                return Ok(val);
+            }
+            self.ciram.get_u8(self.mirroring.nametable_addr(addr))
+        }
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        let addr = addr & 0x3FFF;
+        if let Some(ref cart) = self.cart {
+            cart.mapper.ppu_address(addr);
+        }
+        if addr < 0x2000 {
+            match self.cart {
+                // Writes to missing CHR memory are dropped
+                Some(ref mut cart) if cart.mapper.chr().len() > 0 => cart.mapper.chr_mut().set_u8(addr, val),
+                _ => Ok(())
+            }
+        } else {
+            if let Some(ref mut cart) = self.cart {
+                if cart.mapper.write_nametable(addr, val) {
+                    return Ok(());
+                }
+            }
+            self.ciram.set_u8(self.mirroring.nametable_addr(addr), val)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use mem::{self,Memory};
+    use systems::nes::{self,Cartridge,Mapper,Mirroring};
+    use systems::nes::ppumap::PpuMemoryMap;
+
+    /// A mapper that records every address the PPU puts on its bus, and selects single-screen
+    /// mirroring
+    struct WatchingMapper {
+        mem: mem::Fixed,
+        addresses: Rc<RefCell<Vec<u16>>>
+    }
+
+    impl Mapper for WatchingMapper {
+        fn name(&self) -> &'static str { "Watching" }
+        fn prg(&self) -> &mem::Memory<u16> { &self.mem }
+        fn prg_mut(&mut self) -> &mut mem::Memory<u16> { &mut self.mem }
+        fn chr(&self) -> &mem::Memory<u16> { &self.mem }
+        fn chr_mut(&mut self) -> &mut mem::Memory<u16> { &mut self.mem }
+        fn mirroring(&self) -> Option<Mirroring> { Some(Mirroring::SingleScreenUpper) }
+        fn ppu_address(&self, addr: u16) { self.addresses.borrow_mut().push(addr); }
+    }
+
+    fn watched_cartridge(addresses: Rc<RefCell<Vec<u16>>>) -> Cartridge {
+        let mut image = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        image.extend(vec![0; 0x4000]);
+        let rom = nes::load_rom(&mut &image[..]).unwrap();
+        Cartridge::new(rom.header, Box::new(WatchingMapper {
+            mem: mem::Fixed::new(0x2000),
+            addresses: addresses
+        }))
+    }
+
+    fn write_each_nametable(mirroring: Mirroring) -> Vec<u8> {
+        let mut ciram = mem::Fixed::new(0x1000);
+        {
+            let mut bus = PpuMemoryMap::new(None, &mut ciram, mirroring);
+            for table in 0 .. 4 {
+                bus.set_u8(0x2000 + table * 0x0400, table as u8 + 1).unwrap();
+            }
+        }
+        (0 .. 4).map(|table| ciram.get_u8(table * 0x0400u16).unwrap()).collect()
+    }
+
+    #[test]
+    pub fn horizontal_mirroring_shares_nametables_vertically() {
+        assert_eq!(vec![2, 4, 0, 0], write_each_nametable(Mirroring::Horizontal));
+    }
+
+    #[test]
+    pub fn vertical_mirroring_shares_nametables_horizontally() {
+        assert_eq!(vec![3, 4, 0, 0], write_each_nametable(Mirroring::Vertical));
+    }
+
+    #[test]
+    pub fn single_screen_mirroring_uses_one_nametable() {
+        assert_eq!(vec![4, 0, 0, 0], write_each_nametable(Mirroring::SingleScreenLower));
+        assert_eq!(vec![0, 4, 0, 0], write_each_nametable(Mirroring::SingleScreenUpper));
+    }
+
+    #[test]
+    pub fn four_screen_mirroring_uses_four_nametables() {
+        assert_eq!(vec![1, 2, 3, 4], write_each_nametable(Mirroring::FourScreen));
+    }
+
+    #[test]
+    pub fn mapped_mirroring_uses_provided_pages() {
+        assert_eq!(vec![4, 3, 0, 0], write_each_nametable(Mirroring::Mapped([1, 0, 1, 0])));
+    }
+
+    #[test]
+    pub fn region_above_3000_mirrors_nametables() {
+        let mut ciram = mem::Fixed::new(0x1000);
+        let mut bus = PpuMemoryMap::new(None, &mut ciram, Mirroring::FourScreen);
+        bus.set_u8(0x3123, 42).unwrap();
+        assert_eq!(Ok(42), bus.get_u8(0x2123));
+    }
+
+    #[test]
+    pub fn mapper_sees_every_address() {
+        let addresses = Rc::new(RefCell::new(Vec::new()));
+        let mut cart = watched_cartridge(addresses.clone());
+        let mut ciram = mem::Fixed::new(0x1000);
+        let mut bus = PpuMemoryMap::new(Some(&mut cart), &mut ciram, Mirroring::Vertical);
+        bus.get_u8(0x1234).unwrap();
+        bus.set_u8(0x2345, 1).unwrap();
+        bus.get_u8(0x7F00).unwrap();
+        assert_eq!(vec![0x1234, 0x2345, 0x3F00], *addresses.borrow());
+    }
+
+    #[test]
+    pub fn mapper_selects_mirroring() {
+        let cart = watched_cartridge(Rc::new(RefCell::new(Vec::new())));
+        assert_eq!(Mirroring::SingleScreenUpper, cart.mirroring());
+    }
+}
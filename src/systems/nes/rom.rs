@@ -1,4 +1,4 @@
-use std::{error,io,fmt};
+use std::{cmp,error,io,fmt};
 
 const HEADER_SIZE: usize = 16;
 const PRG_BANK_SIZE: usize = 16384;
@@ -91,10 +91,10 @@ pub enum Version {
 #[derive(Debug)]
 pub struct RamSize {
     /// Indicates the amount of RAM that is battery-backed
-    pub battery_backed: u16,
+    pub battery_backed: u32,
 
     /// Indicates the total amount of RAM (sum of battery-backed and non-battery-backed RAM)
-    pub total: u16
+    pub total: u32
 }
 
 impl RamSize {
@@ -110,8 +110,8 @@ impl RamSize {
             Version::INES => RamSize::empty(),
 
             Version::NES2 => {
-                let bat = get_full_size(((val & 0xF0) >> 4) as u16);
-                let non_bat = get_full_size((val & 0x0F) as u16);
+                let bat = get_full_size(((val & 0xF0) >> 4) as u32);
+                let non_bat = get_full_size((val & 0x0F) as u32);
 
                 RamSize {
                     battery_backed: bat,
@@ -120,12 +120,24 @@ impl RamSize {
             }
         }
     }
+
+    /// Creates a `RamSize` based on the PRG RAM size byte of an iNES header
+    ///
+    /// The size is given in 8KB units, with 0 also meaning 8KB for compatibility with ROMs that
+    /// predate the field
+    pub fn from_ines_byte(val: u8, battery_backed: bool) -> RamSize {
+        let total = cmp::max(val, 1) as u32 * 0x2000;
+        RamSize {
+            battery_backed: if battery_backed { total } else { 0 },
+            total: total
+        }
+    }
 }
 
-fn get_full_size(inp: u16) -> u16 {
+fn get_full_size(inp: u32) -> u32 {
     match inp {
         0 => 0,
-        x => (2 as u16).pow(6 + x as u32)
+        x => (2 as u32).pow(6 + x)
     }
 }
 
@@ -139,6 +151,9 @@ pub struct CartridgeInfo {
     pub submapper: u8,
 
     /// Indicates if there are bus conflicts on the cartridge
+    ///
+    /// This is only set by the unofficial flag in byte 10 of iNES headers, which NES 2.0 uses for
+    /// the PRG RAM size
     pub bus_conflicts: bool
 }
 
@@ -197,6 +212,9 @@ pub struct RomHeader {
 
     /// Indicates the TV system that this ROM was designed for
     pub tv_system: TvSystem,
+
+    /// Identifies the input device the ROM expects to be connected (NES 2.0 only, 0 if unspecified)
+    pub default_expansion_device: u8,
 }
 
 /// Represents an NES ROM, loaded from the iNES/NES2.0 format
@@ -261,12 +279,13 @@ fn read_header<R>(input: &mut R) -> Result<RomHeader> where R: io::Read {
 
     // Detect version
     // Based on algorithm in http://wiki.nesdev.com/w/index.php/INES#Variant_comparison
+    // Archaic headers have junk (such as "DiskDude!") in bytes 7-15, where iNES has zeroes
     let version = if header[7] & 0x0C == 0x08 {
         Version::NES2
-    } else if header[12..15].iter().all(|i| { *i == 0 }) {
-        Version::ArchaicINES
-    } else {
+    } else if header[7] & 0x0C == 0x00 && header[12..16].iter().all(|i| { *i == 0 }) {
         Version::INES
+    } else {
+        Version::ArchaicINES
     };
 
     // Read ROM sizes 
@@ -288,15 +307,15 @@ fn read_header<R>(input: &mut R) -> Result<RomHeader> where R: io::Read {
     let mut mapper = ((header[6] & 0xF0) >> 4) as u16;
     let mut submapper : u8 = 0;
 
-    // If this is iNES, read the second nybble
-    if version == Version::INES {
+    // If this is iNES or NES 2.0, read the second nybble
+    if version != Version::ArchaicINES {
         mapper = (mapper | ((header[7] as u16 & 0xF0))) as u16;
     }
 
     // If this is NES 2.0, read the third nybble and submapper
     if version == Version::NES2 {
         mapper = (mapper | ((header[8] as u16 & 0x0F) << 8)) as u16;
-        submapper = (header[8] & 0xF0) << 4;
+        submapper = (header[8] & 0xF0) >> 4;
     }
 
     // Read TV System
@@ -313,7 +332,13 @@ fn read_header<R>(input: &mut R) -> Result<RomHeader> where R: io::Read {
     };
 
     // Read Ram Sizes
-    let prg_ram = RamSize::from_header_byte(header[10], version);
+    let prg_ram = match version {
+        // Archaic headers have junk in byte 8, so the size from before the field is used
+        Version::ArchaicINES => RamSize::from_ines_byte(0, (header[6] & 0x02) != 0),
+        Version::INES => RamSize::from_ines_byte(header[8], (header[6] & 0x02) != 0),
+
+        Version::NES2 => RamSize::from_header_byte(header[10], version)
+    };
     let chr_ram = RamSize::from_header_byte(header[11], version);
 
     Ok(RomHeader {
@@ -321,7 +346,7 @@ fn read_header<R>(input: &mut R) -> Result<RomHeader> where R: io::Read {
         chr_rom_size: chr_size,
         prg_ram_size: prg_ram,
         chr_ram_size: chr_ram,
-        cartridge: CartridgeInfo::new(mapper, submapper, (header[10] & 0x20) != 0),
+        cartridge: CartridgeInfo::new(mapper, submapper, version == Version::INES && (header[10] & 0x20) != 0),
         version: version,
         vertical_arrangement: (header[6] & 0x01) == 0,
         four_screen_vram: (header[6] & 0x08) != 0,
@@ -330,7 +355,8 @@ fn read_header<R>(input: &mut R) -> Result<RomHeader> where R: io::Read {
         trainer_present: (header[6] & 0x04) != 0,
         vs_unisystem: (header[7] & 0x01) != 0,
         playchoice_10: (header[7] & 0x02) != 0,
-        tv_system: tv_system
+        tv_system: tv_system,
+        default_expansion_device: if version == Version::NES2 { header[15] & 0x3F } else { 0 }
     })
 }
 
@@ -341,3 +367,101 @@ fn verify_signature(sig: &[u8]) -> bool {
         sig[2] == 0x53 && // 'S'
         sig[3] == 0x1A    // EOF
 }
+
+#[cfg(test)]
+mod test {
+    use systems::nes::rom::{self,Version};
+
+    fn header(bytes: [u8; 12]) -> Vec<u8> {
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A];
+        data.extend_from_slice(&bytes);
+        data
+    }
+
+    #[test]
+    pub fn reads_ines_mapper_from_both_nybbles() {
+        // Mapper 0x45 (69)
+        let data = header([1, 1, 0x50, 0x40, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let header = rom::read_header(&mut &data[..]).unwrap();
+
+        assert_eq!(Version::INES, header.version);
+        assert_eq!(0x45, header.cartridge.mapper);
+        assert_eq!(0, header.cartridge.submapper);
+    }
+
+    #[test]
+    pub fn reads_clean_header_as_ines() {
+        let data = header([1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let header = rom::read_header(&mut &data[..]).unwrap();
+
+        assert_eq!(Version::INES, header.version);
+    }
+
+    #[test]
+    pub fn reads_header_with_junk_as_archaic_ines() {
+        // "DiskDude!" over bytes 7-15, which would otherwise add 0x40 to the mapper number
+        let data = header([1, 1, 0x10, b'D', b'i', b's', b'k', b'D', b'u', b'd', b'e', b'!']);
+        let header = rom::read_header(&mut &data[..]).unwrap();
+
+        assert_eq!(Version::ArchaicINES, header.version);
+        assert_eq!(1, header.cartridge.mapper);
+    }
+
+    #[test]
+    pub fn reads_nes2_mapper_and_submapper() {
+        // Mapper 0x123, submapper 5
+        let data = header([1, 1, 0x30, 0x28, 0x51, 0, 0, 0, 0, 0, 0, 0]);
+        let header = rom::read_header(&mut &data[..]).unwrap();
+
+        assert_eq!(Version::NES2, header.version);
+        assert_eq!(0x123, header.cartridge.mapper);
+        assert_eq!(5, header.cartridge.submapper);
+    }
+
+    #[test]
+    pub fn ines_prg_ram_defaults_to_8k() {
+        let data = header([1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let header = rom::read_header(&mut &data[..]).unwrap();
+
+        assert_eq!(0x2000, header.prg_ram_size.total);
+        assert_eq!(0, header.prg_ram_size.battery_backed);
+    }
+
+    #[test]
+    pub fn ines_prg_ram_is_read_from_byte_8() {
+        let data = header([1, 1, 0x02, 0, 4, 0, 0, 0, 0, 0, 0, 0]);
+        let header = rom::read_header(&mut &data[..]).unwrap();
+
+        assert_eq!(0x8000, header.prg_ram_size.total);
+        assert_eq!(0x8000, header.prg_ram_size.battery_backed);
+    }
+
+    #[test]
+    pub fn archaic_ines_prg_ram_defaults_to_8k() {
+        // "DiskDude!" puts 'i' in byte 8, which would otherwise be read as 105 8KB units
+        let data = header([1, 1, 0x02, b'D', b'i', b's', b'k', b'D', b'u', b'd', b'e', b'!']);
+        let header = rom::read_header(&mut &data[..]).unwrap();
+
+        assert_eq!(Version::ArchaicINES, header.version);
+        assert_eq!(0x2000, header.prg_ram_size.total);
+        assert_eq!(0x2000, header.prg_ram_size.battery_backed);
+    }
+
+    #[test]
+    pub fn nes2_prg_ram_can_be_absent() {
+        let data = header([1, 1, 0, 0x08, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let header = rom::read_header(&mut &data[..]).unwrap();
+
+        assert_eq!(0, header.prg_ram_size.total);
+    }
+
+    #[test]
+    pub fn nes2_prg_ram_is_read_from_byte_10() {
+        // 8KB battery-backed and 2KB volatile
+        let data = header([1, 1, 0, 0x08, 0, 0, 0x75, 0, 0, 0, 0, 0]);
+        let header = rom::read_header(&mut &data[..]).unwrap();
+
+        assert_eq!(0x2800, header.prg_ram_size.total);
+        assert_eq!(0x2000, header.prg_ram_size.battery_backed);
+    }
+}
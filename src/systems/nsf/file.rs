@@ -0,0 +1,444 @@
+use std::{error,io,fmt,ops};
+
+use byteorder::{ByteOrder,LittleEndian};
+
+use systems::nes::rom::TvSystem;
+
+const HEADER_SIZE: usize = 0x80;
+
+/// The default play rate, in microseconds (roughly 60.1Hz)
+pub const DEFAULT_NTSC_SPEED: u16 = 16639;
+
+/// The default play rate on PAL systems, in microseconds (roughly 50Hz)
+pub const DEFAULT_PAL_SPEED: u16 = 19997;
+
+/// Represents the result of an operation performed on an NSF file
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// Represents an error that occurs while loading an NSF or NSFe file
+#[derive(Debug)]
+pub enum Error {
+    /// Indicates that the header of the NSF file is invalid
+    InvalidHeader,
+
+    /// Indicates that the signature of the file is neither an NSF nor an NSFe signature
+    InvalidSignature,
+
+    /// Indicates that a chunk in an NSFe file is truncated or malformed
+    InvalidChunk([u8; 4]),
+
+    /// Indicates that an NSFe file contains a required chunk that isn't supported
+    UnsupportedChunk([u8; 4]),
+
+    /// Indicates that an NSFe file is missing the required INFO or DATA chunk
+    MissingChunk(&'static str),
+
+    /// Indicates that an I/O error occurred while reading the file
+    IoError(io::Error),
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match self {
+            &Error::InvalidHeader         => "NSF file header is invalid",
+            &Error::InvalidSignature      => "NSF file signature is invalid",
+            &Error::InvalidChunk(_)       => "NSFe chunk is invalid",
+            &Error::UnsupportedChunk(_)   => "NSFe file contains an unsupported required chunk",
+            &Error::MissingChunk(_)       => "NSFe file is missing a required chunk",
+            &Error::IoError(_)            => "i/o error"
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            &Error::IoError(ref err) => Some(err),
+            _                        => None
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Error::IoError(ref err) => write!(fmt, "i/o error: {}", err),
+            &Error::InvalidChunk(ref id) | &Error::UnsupportedChunk(ref id) =>
+                write!(fmt, "{}: {}", error::Error::description(self), String::from_utf8_lossy(id)),
+            &Error::MissingChunk(id) => write!(fmt, "{}: {}", error::Error::description(self), id),
+            _ => error::Error::description(self).fmt(fmt)
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::IoError(err)
+    }
+}
+
+/// Identifies the expansion sound chips used by a tune
+#[derive(Copy,Clone,Eq,PartialEq)]
+pub struct ExpansionChips {
+    pub bits: u8
+}
+
+impl ExpansionChips {
+    #[inline] #[allow(non_snake_case)] pub fn VRC6() -> ExpansionChips   { ExpansionChips::new(0b00000001) }
+    #[inline] #[allow(non_snake_case)] pub fn VRC7() -> ExpansionChips   { ExpansionChips::new(0b00000010) }
+    #[inline] #[allow(non_snake_case)] pub fn FDS() -> ExpansionChips    { ExpansionChips::new(0b00000100) }
+    #[inline] #[allow(non_snake_case)] pub fn MMC5() -> ExpansionChips   { ExpansionChips::new(0b00001000) }
+    #[inline] #[allow(non_snake_case)] pub fn N163() -> ExpansionChips   { ExpansionChips::new(0b00010000) }
+    #[inline] #[allow(non_snake_case)] pub fn S5B() -> ExpansionChips    { ExpansionChips::new(0b00100000) }
+    #[inline] #[allow(non_snake_case)] pub fn NONE() -> ExpansionChips   { ExpansionChips::new(0b00000000) }
+
+    /// Creates a new `ExpansionChips` structure from the provided 8-bit value
+    pub fn new(bits: u8) -> ExpansionChips {
+        ExpansionChips { bits: bits }
+    }
+
+    /// Returns a value indicating if all of the specified chips are used
+    pub fn intersects(&self, other: ExpansionChips) -> bool {
+        self.bits & other.bits == other.bits
+    }
+}
+
+impl ops::BitOr for ExpansionChips {
+    type Output = ExpansionChips;
+
+    fn bitor(self, rhs: ExpansionChips) -> ExpansionChips {
+        ExpansionChips::new(self.bits | rhs.bits)
+    }
+}
+
+impl fmt::Debug for ExpansionChips {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "ExpansionChips(0b{:08b})", self.bits)
+    }
+}
+
+/// Describes an NSF tune, from the header of an NSF file or the chunks of an NSFe file
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct NsfHeader {
+    /// The version of the NSF format (always 1 for NSFe files)
+    pub version: u8,
+
+    /// The number of songs in the tune
+    pub total_songs: u8,
+
+    /// The song to play first (1-based)
+    pub starting_song: u8,
+
+    /// The address the tune data is loaded at (when not bankswitched)
+    pub load_addr: u16,
+
+    /// The address of the routine that initializes a song
+    pub init_addr: u16,
+
+    /// The address of the routine called at the play rate
+    pub play_addr: u16,
+
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+
+    /// The interval between calls to the play routine on NTSC systems, in microseconds
+    pub ntsc_speed: u16,
+
+    /// The interval between calls to the play routine on PAL systems, in microseconds
+    pub pal_speed: u16,
+
+    /// The initial values of the bankswitching registers ($5FF8-$5FFF). The tune is only
+    /// bankswitched if at least one of them is non-zero.
+    pub banks: [u8; 8],
+
+    pub tv_system: TvSystem,
+    pub expansion_chips: ExpansionChips,
+
+    /// The length of each song in milliseconds, if known (NSFe only)
+    pub track_times: Vec<Option<u32>>,
+
+    /// The fade out time of each song in milliseconds, if known (NSFe only)
+    pub track_fades: Vec<Option<u32>>
+}
+
+impl NsfHeader {
+    /// Returns a value indicating if the tune uses the bankswitching registers
+    pub fn is_bankswitched(&self) -> bool {
+        self.banks.iter().any(|b| *b != 0)
+    }
+
+    /// Gets the length of the provided song (0-based) in milliseconds, if known
+    pub fn track_time(&self, song: u8) -> Option<u32> {
+        self.track_times.get(song as usize).and_then(|t| *t)
+    }
+
+    /// Gets the fade out time of the provided song (0-based) in milliseconds, if known
+    pub fn track_fade(&self, song: u8) -> Option<u32> {
+        self.track_fades.get(song as usize).and_then(|t| *t)
+    }
+}
+
+/// Represents an NSF tune loaded from an NSF or NSFe file
+pub struct Nsf {
+    pub header: NsfHeader,
+    pub data: Vec<u8>
+}
+
+/// Loads an NSF or NSFe file from the provided input
+pub fn load_nsf<R>(input: &mut R) -> Result<Nsf> where R: io::Read {
+    let mut contents = Vec::new();
+    try!(input.read_to_end(&mut contents));
+
+    if contents.len() >= 5 && &contents[0..5] == b"NESM\x1A" {
+        load_nsf_contents(&contents)
+    } else if contents.len() >= 4 && &contents[0..4] == b"NSFE" {
+        load_nsfe_contents(&contents[4..])
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
+fn load_nsf_contents(contents: &[u8]) -> Result<Nsf> {
+    if contents.len() < HEADER_SIZE {
+        return Err(Error::InvalidHeader);
+    }
+    let header = &contents[0..HEADER_SIZE];
+
+    let mut banks = [0u8; 8];
+    banks.copy_from_slice(&header[0x70..0x78]);
+
+    // NSF2 files can specify the length of the program data, with metadata following it
+    let data_len = (header[0x7D] as usize) | ((header[0x7E] as usize) << 8) | ((header[0x7F] as usize) << 16);
+    let data_end = if header[0x05] >= 2 && data_len > 0 {
+        ::std::cmp::min(contents.len(), HEADER_SIZE + data_len)
+    } else {
+        contents.len()
+    };
+
+    Ok(Nsf {
+        header: NsfHeader {
+            version: header[0x05],
+            total_songs: header[0x06],
+            starting_song: header[0x07],
+            load_addr: LittleEndian::read_u16(&header[0x08..0x0A]),
+            init_addr: LittleEndian::read_u16(&header[0x0A..0x0C]),
+            play_addr: LittleEndian::read_u16(&header[0x0C..0x0E]),
+            title: read_string(&header[0x0E..0x2E]),
+            artist: read_string(&header[0x2E..0x4E]),
+            copyright: read_string(&header[0x4E..0x6E]),
+            ntsc_speed: LittleEndian::read_u16(&header[0x6E..0x70]),
+            pal_speed: LittleEndian::read_u16(&header[0x78..0x7A]),
+            banks: banks,
+            tv_system: read_tv_system(header[0x7A]),
+            expansion_chips: ExpansionChips::new(header[0x7B]),
+            track_times: Vec::new(),
+            track_fades: Vec::new()
+        },
+        data: contents[HEADER_SIZE..data_end].to_vec()
+    })
+}
+
+fn load_nsfe_contents(mut contents: &[u8]) -> Result<Nsf> {
+    let mut header = NsfHeader {
+        version: 1,
+        total_songs: 1,
+        starting_song: 1,
+        load_addr: 0,
+        init_addr: 0,
+        play_addr: 0,
+        title: String::new(),
+        artist: String::new(),
+        copyright: String::new(),
+        ntsc_speed: DEFAULT_NTSC_SPEED,
+        pal_speed: DEFAULT_PAL_SPEED,
+        banks: [0; 8],
+        tv_system: TvSystem::NTSC,
+        expansion_chips: ExpansionChips::NONE(),
+        track_times: Vec::new(),
+        track_fades: Vec::new()
+    };
+    let mut data = None;
+    let mut has_info = false;
+
+    while contents.len() >= 8 {
+        let len = LittleEndian::read_u32(&contents[0..4]) as usize;
+        let mut id = [0u8; 4];
+        id.copy_from_slice(&contents[4..8]);
+        if contents.len() < 8 + len {
+            return Err(Error::InvalidChunk(id));
+        }
+        let chunk = &contents[8..8 + len];
+        contents = &contents[8 + len..];
+
+        match &id {
+            b"INFO" => {
+                if chunk.len() < 9 {
+                    return Err(Error::InvalidChunk(id));
+                }
+                header.load_addr = LittleEndian::read_u16(&chunk[0..2]);
+                header.init_addr = LittleEndian::read_u16(&chunk[2..4]);
+                header.play_addr = LittleEndian::read_u16(&chunk[4..6]);
+                header.tv_system = read_tv_system(chunk[6]);
+                header.expansion_chips = ExpansionChips::new(chunk[7]);
+                header.total_songs = chunk[8];
+
+                // The starting song is 0-based in NSFe files
+                header.starting_song = chunk.get(9).map(|s| s + 1).unwrap_or(1);
+                has_info = true;
+            },
+            b"DATA" => data = Some(chunk.to_vec()),
+            b"BANK" => {
+                for (i, bank) in chunk.iter().take(8).enumerate() {
+                    header.banks[i] = *bank;
+                }
+            },
+            b"RATE" => {
+                if chunk.len() >= 2 {
+                    header.ntsc_speed = LittleEndian::read_u16(&chunk[0..2]);
+                }
+                if chunk.len() >= 4 {
+                    header.pal_speed = LittleEndian::read_u16(&chunk[2..4]);
+                }
+            },
+            b"auth" => {
+                let mut strings = chunk.split(|b| *b == 0).map(read_string);
+                header.title = strings.next().unwrap_or_default();
+                header.artist = strings.next().unwrap_or_default();
+                header.copyright = strings.next().unwrap_or_default();
+            },
+            b"time" => header.track_times = read_track_times(chunk),
+            b"fade" => header.track_fades = read_track_times(chunk),
+            b"NEND" => break,
+            _ => {
+                // Chunks starting with an upper case letter are required to play the tune
+                if id[0] >= b'A' && id[0] <= b'Z' {
+                    return Err(Error::UnsupportedChunk(id));
+                }
+            }
+        }
+    }
+
+    if !has_info {
+        return Err(Error::MissingChunk("INFO"));
+    }
+    match data {
+        Some(data) => Ok(Nsf { header: header, data: data }),
+        None => Err(Error::MissingChunk("DATA"))
+    }
+}
+
+fn read_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[0..end]).into_owned()
+}
+
+fn read_tv_system(val: u8) -> TvSystem {
+    if val & 0x02 != 0 {
+        TvSystem::Dual
+    } else if val & 0x01 != 0 {
+        TvSystem::PAL
+    } else {
+        TvSystem::NTSC
+    }
+}
+
+/// Reads a list of signed 32-bit times, where negative values indicate the default should be used
+fn read_track_times(chunk: &[u8]) -> Vec<Option<u32>> {
+    chunk.chunks(4)
+        .filter(|c| c.len() == 4)
+        .map(|c| {
+            let time = LittleEndian::read_i32(c);
+            if time < 0 { None } else { Some(time as u32) }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use systems::nes::rom::TvSystem;
+    use systems::nsf::file::{self,Error,ExpansionChips};
+
+    #[test]
+    pub fn loads_nsf_header_and_data() {
+        let mut contents = vec![0u8; 0x80];
+        contents[0..5].copy_from_slice(b"NESM\x1A");
+        contents[0x05] = 1;
+        contents[0x06] = 3;
+        contents[0x07] = 2;
+        contents[0x08..0x0E].copy_from_slice(&[0x00, 0x80, 0x03, 0x80, 0x06, 0x80]);
+        contents[0x0E..0x13].copy_from_slice(b"Title");
+        contents[0x2E..0x34].copy_from_slice(b"Artist");
+        contents[0x6E..0x70].copy_from_slice(&[0x1A, 0x41]);
+        contents[0x71] = 1;
+        contents[0x7A] = 0x01;
+        contents[0x7B] = 0x21;
+        contents.extend_from_slice(&[0xEA, 0xEA, 0xEA]);
+
+        let nsf = file::load_nsf(&mut Cursor::new(contents)).unwrap();
+        assert_eq!(3, nsf.header.total_songs);
+        assert_eq!(2, nsf.header.starting_song);
+        assert_eq!(0x8000, nsf.header.load_addr);
+        assert_eq!(0x8003, nsf.header.init_addr);
+        assert_eq!(0x8006, nsf.header.play_addr);
+        assert_eq!("Title", nsf.header.title);
+        assert_eq!("Artist", nsf.header.artist);
+        assert_eq!("", nsf.header.copyright);
+        assert_eq!(16666, nsf.header.ntsc_speed);
+        assert!(nsf.header.is_bankswitched());
+        assert_eq!(TvSystem::PAL, nsf.header.tv_system);
+        assert!(nsf.header.expansion_chips.intersects(ExpansionChips::VRC6() | ExpansionChips::S5B()));
+        assert_eq!(vec![0xEA, 0xEA, 0xEA], nsf.data);
+    }
+
+    #[test]
+    pub fn loads_nsfe_chunks() {
+        let mut contents = b"NSFE".to_vec();
+        add_chunk(&mut contents, b"INFO", &[0x00, 0x80, 0x10, 0x80, 0x20, 0x80, 0x00, 0x00, 0x02, 0x01]);
+        add_chunk(&mut contents, b"DATA", &[0x60]);
+        add_chunk(&mut contents, b"auth", b"Song\0Someone\0\0");
+        add_chunk(&mut contents, b"time", &[0x10, 0x27, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
+        add_chunk(&mut contents, b"xtra", &[0x01, 0x02]);
+        add_chunk(&mut contents, b"NEND", &[]);
+
+        let nsf = file::load_nsf(&mut Cursor::new(contents)).unwrap();
+        assert_eq!(0x8010, nsf.header.init_addr);
+        assert_eq!(0x8020, nsf.header.play_addr);
+        assert_eq!(2, nsf.header.total_songs);
+        assert_eq!(2, nsf.header.starting_song);
+        assert_eq!("Song", nsf.header.title);
+        assert_eq!("Someone", nsf.header.artist);
+        assert_eq!(Some(10000), nsf.header.track_time(0));
+        assert_eq!(None, nsf.header.track_time(1));
+        assert_eq!(file::DEFAULT_NTSC_SPEED, nsf.header.ntsc_speed);
+        assert!(!nsf.header.is_bankswitched());
+        assert_eq!(vec![0x60], nsf.data);
+    }
+
+    #[test]
+    pub fn rejects_unknown_required_nsfe_chunk() {
+        let mut contents = b"NSFE".to_vec();
+        add_chunk(&mut contents, b"INFO", &[0x00, 0x80, 0x10, 0x80, 0x20, 0x80, 0x00, 0x00, 0x01]);
+        add_chunk(&mut contents, b"ZZZZ", &[]);
+
+        match file::load_nsf(&mut Cursor::new(contents)) {
+            Err(Error::UnsupportedChunk(id)) => assert_eq!(b"ZZZZ", &id),
+            _ => panic!("expected an unsupported chunk error")
+        }
+    }
+
+    #[test]
+    pub fn rejects_invalid_signature() {
+        match file::load_nsf(&mut Cursor::new(b"NES\x1A".to_vec())) {
+            Err(Error::InvalidSignature) => {},
+            _ => panic!("expected an invalid signature error")
+        }
+    }
+
+    fn add_chunk(contents: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+        let len = data.len() as u32;
+        contents.extend_from_slice(&[len as u8, (len >> 8) as u8, (len >> 16) as u8, (len >> 24) as u8]);
+        contents.extend_from_slice(id);
+        contents.extend_from_slice(data);
+    }
+}
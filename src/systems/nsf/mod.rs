@@ -0,0 +1,8 @@
+pub use self::file::{Nsf,NsfHeader,ExpansionChips,load_nsf};
+pub use self::player::Player;
+
+/// Contains code to load tunes in the NSF and NSFe formats
+pub mod file;
+
+/// Contains code to play NSF tunes through the APU
+pub mod player;
@@ -0,0 +1,493 @@
+use std::{error,fmt};
+
+use slog;
+
+use mem;
+use hw::mos6502::{self,exec};
+use hw::mos6502::instr::decoder;
+use hw::rp2A03::{self,Apu};
+use hw::expansion_audio::{self,ExpansionAudio};
+use systems::nes::rom::TvSystem;
+use systems::nsf::file::{self,Nsf,NsfHeader,ExpansionChips};
+
+/// The address the INIT and PLAY routines return to. Nothing is mapped here, the player stops
+/// the CPU when the program counter reaches it.
+const RETURN_ADDR: u16 = 0x4100;
+
+/// The number of CPU cycles INIT or PLAY may run for before the player gives up on them
+const ROUTINE_CYCLE_LIMIT: u64 = rp2A03::apu::CPU_FREQUENCY as u64;
+
+const BANK_SIZE: usize = 0x1000;
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// Represents an error that occurs while playing a tune
+#[derive(Debug)]
+pub enum Error {
+    /// Indicates that the requested song (0-based) is not in the tune
+    InvalidSong(u8),
+
+    /// Indicates that an instruction could not be decoded
+    InstructionDecodeError(u16, decoder::Error),
+
+    /// Indicates that an instruction could not be executed
+    ExecutionError(u16, exec::Error),
+
+    /// Indicates that memory could not be accessed outside of an instruction (during a DMC DMA,
+    /// or while setting up a routine call)
+    MemoryError(mem::Error),
+
+    /// Indicates that the routine at the provided address did not return
+    RoutineTimeout(u16),
+
+    /// Indicates that the tune only plays on a TV system the player doesn't emulate
+    UnsupportedTvSystem(TvSystem)
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match self {
+            &Error::InvalidSong(_)               => "the requested song is not in the tune",
+            &Error::InstructionDecodeError(_, _) => "error decoding instruction",
+            &Error::ExecutionError(_, _)         => "error executing instruction",
+            &Error::MemoryError(_)               => "error accessing memory",
+            &Error::RoutineTimeout(_)            => "routine did not return",
+            &Error::UnsupportedTvSystem(_)       => "the tune requires an unsupported TV system"
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Error::InvalidSong(song) => write!(fmt, "song {} is not in the tune", song),
+            &Error::InstructionDecodeError(addr, ref err) => write!(fmt, "error decoding instruction at ${:04X}: {}", addr, err),
+            &Error::ExecutionError(addr, ref err) => write!(fmt, "error executing instruction at ${:04X}: {}", addr, err),
+            &Error::MemoryError(ref err) => write!(fmt, "error accessing memory: {}", err),
+            &Error::RoutineTimeout(addr) => write!(fmt, "routine at ${:04X} did not return", addr),
+            &Error::UnsupportedTvSystem(system) => write!(fmt, "the tune requires the {:?} TV system, which isn't supported", system)
+        }
+    }
+}
+
+impl From<mem::Error> for Error {
+    fn from(err: mem::Error) -> Error {
+        Error::MemoryError(err)
+    }
+}
+
+/// Maps the CPU address space for an NSF tune
+///
+/// RAM ($0000-$07FF, mirrored) and PRG RAM ($6000-$7FFF) are attached to a `mem::Virtual`, in the
+/// same layout as a cartridge in the NES. The APU, the expansion sound chips, the bankswitching
+/// registers and the tune data are handled directly.
+///
+/// FDS tunes expect $8000-$DFFF to be RAM, so writes there change the tune data. Loading FDS tunes
+/// in to $6000-$7FFF isn't supported.
+struct NsfMemory {
+    ram: mem::Virtual<'static, u16>,
+    apu: Apu,
+    expansion: expansion_audio::Combined,
+    rom: Vec<u8>,
+    banks: [u8; 8],
+    bankswitched: bool,
+    writable_rom: bool
+}
+
+impl NsfMemory {
+    fn new(nsf: &Nsf, logger: Option<slog::Logger>) -> NsfMemory {
+        let mut ram = mem::Virtual::new();
+        ram.attach(0x0000, Box::new(mem::Mirrored::new(mem::Fixed::new(0x0800), 0x2000))).unwrap();
+        ram.attach(0x6000, Box::new(mem::Fixed::new(0x2000))).unwrap();
+
+        // Bankswitched tunes are padded so the load address lands at the same offset within the
+        // first bank. Other tunes are padded so it lands at the right place in $8000-$FFFF, which
+        // can then be treated as banks 0-7.
+        let bankswitched = nsf.header.is_bankswitched();
+        let padding = if bankswitched {
+            (nsf.header.load_addr as usize) & (BANK_SIZE - 1)
+        } else {
+            (nsf.header.load_addr as usize).saturating_sub(0x8000)
+        };
+        let mut rom = vec![0; padding];
+        rom.extend_from_slice(&nsf.data);
+
+        let chips = nsf.header.expansion_chips;
+        let mut expansion = expansion_audio::Combined::new();
+        if chips.intersects(ExpansionChips::VRC6()) {
+            expansion.add(Box::new(expansion_audio::Vrc6Audio::new()));
+        }
+        if chips.intersects(ExpansionChips::VRC7()) {
+            expansion.add(Box::new(expansion_audio::Vrc7Audio::new()));
+        }
+        if chips.intersects(ExpansionChips::FDS()) {
+            expansion.add(Box::new(expansion_audio::FdsAudio::new()));
+        }
+        if chips.intersects(ExpansionChips::MMC5()) {
+            expansion.add(Box::new(expansion_audio::Mmc5Audio::new()));
+        }
+        if chips.intersects(ExpansionChips::N163()) {
+            expansion.add(Box::new(expansion_audio::N163Audio::new()));
+        }
+        if chips.intersects(ExpansionChips::S5B()) {
+            expansion.add(Box::new(expansion_audio::Sunsoft5bAudio::new()));
+        }
+
+        NsfMemory {
+            ram: ram,
+            apu: Apu::new(logger),
+            expansion: expansion,
+            rom: rom,
+            banks: [0, 1, 2, 3, 4, 5, 6, 7],
+            bankswitched: bankswitched,
+            writable_rom: chips.intersects(ExpansionChips::FDS())
+        }
+    }
+
+    /// Translates an address in $8000-$FFFF in to an offset in the tune data
+    fn rom_offset(&self, addr: u16) -> usize {
+        let bank = self.banks[((addr - 0x8000) as usize) / BANK_SIZE] as usize;
+        bank * BANK_SIZE + (addr as usize & (BANK_SIZE - 1))
+    }
+
+    /// Restores memory, the APU and the banks to the state expected by INIT
+    fn reset(&mut self, header: &NsfHeader) -> mem::Result<()> {
+        use mem::Memory;
+
+        for addr in 0x0000..0x0800 {
+            try!(self.ram.set_u8(addr, 0));
+        }
+        for addr in 0x6000..0x8000 {
+            try!(self.ram.set_u8(addr, 0));
+        }
+        for addr in 0x4000..0x4014 {
+            self.apu.write_register(addr, 0x00);
+        }
+        self.apu.write_register(0x4015, 0x00);
+        self.apu.write_register(0x4015, 0x0F);
+        self.apu.write_register(0x4017, 0x40);
+
+        if self.bankswitched {
+            self.banks = header.banks;
+        }
+        Ok(())
+    }
+}
+
+impl rp2A03::DmcBus for NsfMemory {
+    fn apu_and_expansion(&mut self) -> (&mut Apu, Option<&mut ExpansionAudio>) {
+        (&mut self.apu, Some(&mut self.expansion))
+    }
+}
+
+impl mem::Memory<u16> for NsfMemory {
+    fn len(&self) -> u64 {
+        0x10000
+    }
+
+    fn get_u8(&self, addr: u16) -> mem::Result<u8> {
+        match addr {
+            0x4015 => Ok(self.apu.read_status()),
+            0x2000 ... 0x5FFF => Ok(self.expansion.read(addr).unwrap_or(0)),
+            0x8000 ... 0xFFFF => Ok(self.rom.get(self.rom_offset(addr)).cloned().unwrap_or(0)),
+            _ => self.ram.get_u8(addr)
+        }
+    }
+
+    fn set_u8(&mut self, addr: u16, val: u8) -> mem::Result<()> {
+        if addr >= 0x4000 {
+            self.expansion.write(addr, val);
+        }
+        match addr {
+            0x4000 ... 0x4013 | 0x4015 | 0x4017 => self.apu.write_register(addr, val),
+            0x5FF8 ... 0x5FFF => {
+                if self.bankswitched {
+                    self.banks[(addr - 0x5FF8) as usize] = val;
+                }
+            },
+            0x8000 ... 0xDFFF if self.writable_rom => {
+                let offset = self.rom_offset(addr);
+                if offset >= self.rom.len() {
+                    self.rom.resize(offset + 1, 0);
+                }
+                self.rom[offset] = val;
+            },
+            0x2000 ... 0x5FFF | 0x8000 ... 0xFFFF => {},
+            _ => try!(self.ram.set_u8(addr, val))
+        }
+        Ok(())
+    }
+}
+
+/// Plays the songs in an NSF tune
+///
+/// The player runs the tune's code on a `Mos6502`, with the APU mapped at $4000-$4017 as it is in
+/// the NES. `init` calls the INIT routine to start a song, then each call to `run_frame` calls the
+/// PLAY routine and runs the APU until it is time to call PLAY again.
+///
+/// Only the NTSC NES is emulated. Tunes that support both systems are played as NTSC, and tunes
+/// that only support PAL are rejected by `init`.
+pub struct Player {
+    cpu: mos6502::Mos6502,
+    mem: NsfMemory,
+    header: NsfHeader,
+    play_period: f64,
+    next_play: f64,
+    sample_rate: u32,
+    log: slog::Logger
+}
+
+impl Player {
+    /// Creates a new `Player` for the provided tune
+    pub fn new(nsf: Nsf, logger: Option<slog::Logger>) -> Player {
+        let log = unwrap_logger!(logger).new(o!("component" => "nsf"));
+        let speed = if nsf.header.ntsc_speed == 0 { file::DEFAULT_NTSC_SPEED } else { nsf.header.ntsc_speed };
+
+        Player {
+            cpu: mos6502::Mos6502::without_bcd(),
+            mem: NsfMemory::new(&nsf, Some(log.clone())),
+            header: nsf.header,
+            play_period: (speed as f64) * rp2A03::apu::CPU_FREQUENCY / 1000000.0,
+            next_play: 0.0,
+            sample_rate: rp2A03::apu::DEFAULT_SAMPLE_RATE,
+            log: log
+        }
+    }
+
+    /// Gets the header of the tune being played
+    pub fn header(&self) -> &NsfHeader {
+        &self.header
+    }
+
+    /// Gets the CPU memory map
+    pub fn mem(&self) -> &mem::Memory<u16> {
+        &self.mem
+    }
+
+    /// Sets the rate, in Hz, of the samples produced by the player
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        self.sample_rate = rate;
+        self.mem.apu.set_sample_rate(rate);
+    }
+
+    /// Removes and returns the samples produced since the last call
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.mem.apu.take_samples()
+    }
+
+    /// Starts playing the provided song (0-based) by resetting the system and calling INIT
+    pub fn init(&mut self, song: u8) -> Result<()> {
+        if song >= self.header.total_songs {
+            return Err(Error::InvalidSong(song));
+        }
+        if self.header.tv_system == TvSystem::PAL {
+            error!(self.log, "song" => song; "PAL-only tunes aren't supported");
+            return Err(Error::UnsupportedTvSystem(TvSystem::PAL));
+        }
+        info!(self.log, "song" => song; "initializing song");
+
+        try!(self.mem.reset(&self.header));
+        self.cpu.registers.sp = 0xFD;
+        self.cpu.flags.replace(mos6502::Flags::new(0x24));
+
+        // INIT takes the song in A and the TV system in X (0 for NTSC)
+        self.cpu.registers.a = song;
+        self.cpu.registers.x = 0;
+        let init_addr = self.header.init_addr;
+        try!(self.call(init_addr));
+
+        self.next_play = self.cpu.clock.get() as f64;
+        Ok(())
+    }
+
+    /// Calls PLAY, then runs the APU until it is time to call PLAY again
+    pub fn run_frame(&mut self) -> Result<()> {
+        let play_addr = self.header.play_addr;
+        try!(self.call(play_addr));
+
+        self.next_play += self.play_period;
+        let target = self.next_play as u64;
+        if self.cpu.clock.get() < target {
+            let idle = target - self.cpu.clock.get();
+            self.cpu.clock.tick(idle);
+        }
+        try!(self.step_apu());
+        Ok(())
+    }
+
+    /// Plays the current song for `length` seconds, fading it out over the last `fade` seconds,
+    /// and returns the samples produced
+    pub fn render(&mut self, length: f64, fade: f64) -> Result<Vec<f32>> {
+        let total = (length * (self.sample_rate as f64)) as usize;
+        let mut samples = self.take_samples();
+        while samples.len() < total {
+            try!(self.run_frame());
+            samples.extend(self.take_samples());
+        }
+        samples.truncate(total);
+
+        let fade_len = ::std::cmp::min((fade * (self.sample_rate as f64)) as usize, total);
+        let fade_start = total - fade_len;
+        for (i, sample) in samples[fade_start..].iter_mut().enumerate() {
+            *sample *= 1.0 - (i as f32) / (fade_len as f32);
+        }
+        Ok(samples)
+    }
+
+    /// Calls the routine at the provided address, and runs until it returns
+    fn call(&mut self, addr: u16) -> Result<()> {
+        // Push the return address as JSR would, so RTS lands on RETURN_ADDR
+        let ret = RETURN_ADDR - 1;
+        try!(self.cpu.push(&mut self.mem, (ret >> 8) as u8));
+        try!(self.cpu.push(&mut self.mem, (ret & 0x00FF) as u8));
+        self.cpu.pc.set(addr);
+
+        let start = self.cpu.clock.get();
+        while self.cpu.pc.get() != RETURN_ADDR {
+            if self.cpu.clock.get() - start > ROUTINE_CYCLE_LIMIT {
+                error!(self.log, "addr" => format!("${:04X}", addr); "routine did not return");
+                return Err(Error::RoutineTimeout(addr));
+            }
+            try!(self.step());
+        }
+        Ok(())
+    }
+
+    /// Runs a single instruction, and runs the APU until it has caught up
+    fn step(&mut self) -> Result<()> {
+        let addr = self.cpu.pc.get();
+        let instr: mos6502::Instruction = match self.cpu.pc.decode(&self.mem) {
+            Ok(i) => i,
+            Err(e) => return Err(Error::InstructionDecodeError(addr, e))
+        };
+
+        trace!(self.log,
+            "instr" => instr,
+            "cycle" => self.cpu.clock.get();
+            "dispatching");
+        if let Err(e) = mos6502::dispatch(instr, &mut self.cpu, &mut self.mem, Some(self.log.clone())) {
+            return Err(Error::ExecutionError(addr, e));
+        }
+
+        self.step_apu()
+    }
+
+    fn step_apu(&mut self) -> Result<()> {
+        let stall = try!(rp2A03::step_with_dma(&mut self.mem, self.cpu.clock.get()));
+        self.cpu.clock.tick(stall);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mem::Memory;
+    use systems::nes::rom::TvSystem;
+    use systems::nsf::{Nsf,NsfHeader,ExpansionChips,Player};
+
+    #[test]
+    pub fn init_receives_song_number() {
+        let mut player = Player::new(tune(), None);
+        player.init(1).unwrap();
+        assert_eq!(0x01, player.mem().get_u8(0x0000).unwrap());
+        assert_eq!(0x00, player.mem().get_u8(0x0001).unwrap());
+    }
+
+    #[test]
+    pub fn run_frame_calls_play() {
+        let mut player = Player::new(tune(), None);
+        player.init(0).unwrap();
+        for _ in 0..3 {
+            player.run_frame().unwrap();
+        }
+        assert_eq!(0x03, player.mem().get_u8(0x0001).unwrap());
+    }
+
+    #[test]
+    pub fn init_rejects_invalid_song() {
+        let mut player = Player::new(tune(), None);
+        assert!(player.init(2).is_err());
+    }
+
+    #[test]
+    pub fn init_rejects_pal_only_tunes() {
+        let mut nsf = tune();
+        nsf.header.tv_system = TvSystem::PAL;
+        let mut player = Player::new(nsf, None);
+        assert!(player.init(0).is_err());
+
+        let mut nsf = tune();
+        nsf.header.tv_system = TvSystem::Dual;
+        let mut player = Player::new(nsf, None);
+        assert!(player.init(0).is_ok());
+    }
+
+    #[test]
+    pub fn bank_registers_switch_tune_data() {
+        let mut nsf = tune();
+        nsf.header.banks = [0, 1, 0, 0, 0, 0, 0, 0];
+        nsf.data.resize(0x2000, 0);
+        nsf.data[0x1000] = 0x42;
+        let mut player = Player::new(nsf, None);
+        player.init(0).unwrap();
+
+        assert_eq!(0x42, player.mem().get_u8(0x9000).unwrap());
+        assert_eq!(0x85, player.mem().get_u8(0xA000).unwrap());
+        player.mem.set_u8(0x5FFA, 0x01).unwrap();
+        assert_eq!(0x42, player.mem().get_u8(0xA000).unwrap());
+    }
+
+    #[test]
+    pub fn expansion_chips_are_mapped() {
+        let mut nsf = tune();
+        nsf.header.expansion_chips = ExpansionChips::N163();
+        let mut player = Player::new(nsf, None);
+        player.init(0).unwrap();
+
+        player.mem.set_u8(0xF800, 0x20).unwrap();
+        player.mem.set_u8(0x4800, 0x5A).unwrap();
+        player.mem.set_u8(0xF800, 0x20).unwrap();
+        assert_eq!(0x5A, player.mem().get_u8(0x4800).unwrap());
+    }
+
+    #[test]
+    pub fn render_produces_faded_samples() {
+        let mut player = Player::new(tune(), None);
+        player.set_sample_rate(48000);
+        player.init(0).unwrap();
+        let samples = player.render(0.5, 0.25).unwrap();
+
+        assert_eq!(24000, samples.len());
+        assert!(samples[20000..].iter().all(|s| s.abs() <= 0.2));
+        assert!(samples[23999].abs() < 0.001);
+    }
+
+    fn tune() -> Nsf {
+        Nsf {
+            header: NsfHeader {
+                version: 1,
+                total_songs: 2,
+                starting_song: 1,
+                load_addr: 0x8000,
+                init_addr: 0x8000,
+                play_addr: 0x8008,
+                title: String::new(),
+                artist: String::new(),
+                copyright: String::new(),
+                ntsc_speed: 16639,
+                pal_speed: 19997,
+                banks: [0; 8],
+                tv_system: TvSystem::NTSC,
+                expansion_chips: ExpansionChips::NONE(),
+                track_times: Vec::new(),
+                track_fades: Vec::new()
+            },
+            data: vec![
+                // INIT: STA $00; LDA #$BF; STA $4000; RTS
+                0x85, 0x00, 0xA9, 0xBF, 0x8D, 0x00, 0x40, 0x60,
+                // PLAY: INC $01; LDA #$FD; STA $4002; LDA #$08; STA $4003; RTS
+                0xE6, 0x01, 0xA9, 0xFD, 0x8D, 0x02, 0x40, 0xA9, 0x08, 0x8D, 0x03, 0x40, 0x60
+            ]
+        }
+    }
+}
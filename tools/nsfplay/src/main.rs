@@ -0,0 +1,64 @@
+//! Renders a song from an NSF or NSFe file to a WAV file
+extern crate remy;
+
+#[macro_use]
+extern crate slog;
+extern crate slog_term;
+
+use slog::DrainExt;
+
+use std::{env,fs};
+
+use remy::audio::WavWriter;
+use remy::hw::rp2A03;
+use remy::systems::nsf;
+
+/// The length used for songs that don't specify one, in seconds
+const DEFAULT_LENGTH: f64 = 150.0;
+
+/// The fade out time used for songs that don't specify one, in seconds
+const DEFAULT_FADE: f64 = 5.0;
+
+pub fn main() {
+    // Set up console logging
+    let drain = slog_term::streamer().build().fuse();
+    let log = slog::Logger::root(slog::level_filter(slog::Level::Info, drain), o!());
+
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        println!("usage: nsfplay [path to NSF file] [path to WAV file] [song number] [length in seconds] [fade in seconds]");
+        return;
+    }
+
+    let tune = nsf::load_nsf(&mut fs::File::open(&args[1]).expect("failed to open NSF file")).expect("failed to load NSF file");
+    println!("Title: {}", tune.header.title);
+    println!("Artist: {}", tune.header.artist);
+    println!("Copyright: {}", tune.header.copyright);
+    println!("Songs: {}", tune.header.total_songs);
+
+    // Song numbers on the command line are 1-based, like the starting song in the header
+    let song = match args.get(3) {
+        Some(s) => s.parse::<u8>().expect("invalid song number"),
+        None => tune.header.starting_song
+    }.saturating_sub(1);
+
+    let length = match args.get(4) {
+        Some(l) => l.parse::<f64>().expect("invalid length"),
+        None => tune.header.track_time(song).map(|t| (t as f64) / 1000.0).unwrap_or(DEFAULT_LENGTH)
+    };
+    let fade = match args.get(5) {
+        Some(f) => f.parse::<f64>().expect("invalid fade"),
+        None => tune.header.track_fade(song).map(|t| (t as f64) / 1000.0).unwrap_or(DEFAULT_FADE)
+    };
+
+    let mut player = nsf::Player::new(tune, Some(log.clone()));
+    player.init(song).expect("failed to initialize song");
+
+    println!("Rendering song {} ({} seconds, {} second fade)", song + 1, length, fade);
+    let samples = player.render(length + fade, fade).expect("error playing song");
+
+    let wav_file = fs::File::create(&args[2]).expect("failed to create WAV file");
+    let mut wav = WavWriter::new(wav_file, rp2A03::apu::DEFAULT_SAMPLE_RATE).expect("failed to write WAV file");
+    wav.write_samples(&samples).expect("failed to write WAV file");
+    wav.finish().expect("failed to write WAV file");
+}